@@ -0,0 +1,130 @@
+//! Measures the decoding speed of representative data, in MB/s of decoded data.
+//!
+//! Run with `cargo bench`. The names of the data to decode can be passed after `--`, for example
+//! `cargo bench -- text`, which also makes it easy to profile a single kind of data.
+
+extern crate flate3;
+
+use std::env;
+use std::hint::black_box;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Size of the decoded data of each benchmark.
+const SIZE: usize = 1 << 20;
+
+/// Minimum time spent decoding each kind of data.
+const DURATION: Duration = Duration::from_secs(2);
+
+/// Xorshift generator, so that the data is the same for each run.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Long runs of a few different bytes, which are mostly encoded as matches of the maximum length.
+fn rle_data() -> Vec<u8> {
+    let mut rng = Rng(0x9e3779b97f4a7c15);
+    let mut data = Vec::with_capacity(SIZE);
+
+    while data.len() < SIZE {
+        let byte = b"abcd"[(rng.next() % 4) as usize];
+        let run = 1 + (rng.next() % 512) as usize;
+        data.extend((0 .. run).map(|_| byte));
+    }
+
+    data.truncate(SIZE);
+    data
+}
+
+/// Text made of words of various frequencies, which is encoded in dynamic blocks.
+fn text_data() -> Vec<u8> {
+    const WORDS: &[&str] = &["the", "of", "and", "to", "in", "a", "is", "that", "for", "it",
+                             "as", "was", "with", "be", "by", "on", "not", "he", "this", "are",
+                             "decoder", "stream", "block", "Huffman", "window", "compressed",
+                             "literal", "distance", "length", "symbol", "table", "header"];
+
+    let mut rng = Rng(0xd1b54a32d192ed03);
+    let mut data = Vec::with_capacity(SIZE);
+
+    while data.len() < SIZE {
+        // the first words of the list are the most frequent ones
+        let index = (rng.next() % WORDS.len() as u64) * (rng.next() % WORDS.len() as u64);
+        data.extend_from_slice(WORDS[index as usize / WORDS.len()].as_bytes());
+        data.extend_from_slice(match rng.next() % 16 {
+            0 => b", ",
+            1 => b".\n",
+            _ => b" ",
+        });
+    }
+
+    data.truncate(SIZE);
+    data
+}
+
+/// Random bytes, which are encoded in uncompressed blocks.
+fn stored_data() -> Vec<u8> {
+    let mut rng = Rng(0x2545f4914f6cdd1d);
+    let mut data = Vec::with_capacity(SIZE);
+
+    while data.len() < SIZE {
+        data.extend_from_slice(&rng.next().to_le_bytes());
+    }
+
+    data.truncate(SIZE);
+    data
+}
+
+/// Compresses `data` at level 6.
+fn compress(data: &[u8]) -> Vec<u8> {
+    flate3::deflate(data, 6)
+}
+
+/// Compresses `data` with a flush every 100 bytes, as a protocol that sends small messages does,
+/// so that it is made of many short blocks with fixed codes.
+fn compress_flushed(data: &[u8]) -> Vec<u8> {
+    let mut deflater = flate3::Deflater::new(Vec::new(), 6);
+    for chunk in data.chunks(100) {
+        deflater.write_all(chunk).unwrap();
+        deflater.flush().unwrap();
+    }
+    deflater.finish().unwrap()
+}
+
+/// Decodes the compressed `data` repeatedly for at least `DURATION` and prints the speed.
+fn bench(name: &str, data: &[u8], compress: fn(&[u8]) -> Vec<u8>) {
+    let compressed = compress(data);
+    assert!(flate3::inflate(&compressed).unwrap() == data);
+
+    let mut iterations = 0u32;
+    let start = Instant::now();
+    while start.elapsed() < DURATION {
+        black_box(flate3::inflate(black_box(&compressed)).unwrap());
+        iterations += 1;
+    }
+
+    let secs = start.elapsed().as_secs_f64();
+    let speed = data.len() as f64 * iterations as f64 / secs / 1_000_000.0;
+    println!("{:<8} {:>8} -> {:>8} bytes {:>10.1} MB/s", name, compressed.len(), data.len(),
+             speed);
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`, which isn't the name of a benchmark
+    let filters = env::args().skip(1).filter(|arg| !arg.starts_with("--")).collect::<Vec<_>>();
+    let benches = [("rle", rle_data as fn() -> Vec<u8>, compress as fn(&[u8]) -> Vec<u8>),
+                   ("text", text_data, compress), ("stored", stored_data, compress),
+                   ("fixed", text_data, compress_flushed)];
+
+    for &(name, data, compress) in &benches {
+        if filters.is_empty() || filters.iter().any(|filter| name.contains(filter.as_str())) {
+            bench(name, &data(), compress);
+        }
+    }
+}
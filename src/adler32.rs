@@ -1,5 +1,14 @@
 //! Implementation of the Adler32 hashing algorithm.
 
+use core::hash::Hasher;
+
+/// Largest prime smaller than 65536.
+const MOD: u32 = 65521;
+
+/// Largest number of bytes that can be processed before `s2` could overflow a `u32`, assuming
+/// that `s1` and `s2` start below `MOD`.
+const NMAX: usize = 5552;
+
 /// An Implementation of the Adler-32 checksum
 #[derive(Clone, Copy)]
 pub struct Adler32 {
@@ -15,12 +24,33 @@ impl Adler32 {
 
     /// Update the internal hasher with the bytes from `buf`.
     pub fn feed(&mut self, buf: &[u8]) {
-        for &byte in buf {
-            self.s1 = self.s1 + byte as u32;
-            self.s2 = self.s1 + self.s2;
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("avx2") {
+                unsafe { simd::feed_avx2(self, buf) };
+                return;
+            }
 
-            self.s1 %= 65521;
-            self.s2 %= 65521;
+            if is_x86_feature_detected!("ssse3") {
+                unsafe { simd::feed_ssse3(self, buf) };
+                return;
+            }
+        }
+
+        self.feed_scalar(buf);
+    }
+
+    /// Portable implementation of `feed`.
+    fn feed_scalar(&mut self, buf: &[u8]) {
+        // the modulo only needs to be applied once every `NMAX` bytes
+        for chunk in buf.chunks(NMAX) {
+            for &byte in chunk {
+                self.s1 += byte as u32;
+                self.s2 += self.s1;
+            }
+
+            self.s1 %= MOD;
+            self.s2 %= MOD;
         }
     }
 
@@ -28,4 +58,256 @@ impl Adler32 {
     pub fn checksum(self) -> u32 {
         (self.s2 << 16) | self.s1
     }
+
+    /// Returns the hasher that would be obtained by feeding the data fed to `self`, followed by
+    /// the data fed to `other`, whose length is `other_len`.
+    pub fn combine(self, other: Adler32, other_len: u64) -> Adler32 {
+        // `s1` is 1 plus the sum of the bytes, and each byte of the first data is added to `s2`
+        // of the second data `other_len` more times
+        let rem = (other_len % MOD as u64) as u32;
+        let s1 = (self.s1 + other.s1 + MOD - 1) % MOD;
+        let s2 = ((self.s2 + other.s2 + MOD - rem) as u64 +
+                  rem as u64 * self.s1 as u64) % MOD as u64;
+
+        Adler32 { s1, s2: s2 as u32 }
+    }
+}
+
+impl Default for Adler32 {
+    fn default() -> Adler32 {
+        Adler32::new()
+    }
+}
+
+/// Feeds the data written to the hasher with `feed`. `finish` returns `checksum`.
+impl Hasher for Adler32 {
+    fn write(&mut self, bytes: &[u8]) {
+        self.feed(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        self.checksum() as u64
+    }
+}
+
+/// Implementations of `feed` that use x86_64 vector instructions.
+///
+/// The bytes are processed in blocks. For a block of `N` bytes, `s1` is increased by the sum of
+/// the bytes and `s2` is increased by `N * s1` plus the sum of the bytes weighted by `N`, `N - 1`,
+/// ..., `1`. The terms are accumulated in vectors and reduced once per `NMAX` bytes.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd {
+    use std::arch::x86_64::*;
+    use super::{Adler32, MOD, NMAX};
+
+    #[target_feature(enable = "ssse3")]
+    pub unsafe fn feed_ssse3(hasher: &mut Adler32, buf: &[u8]) {
+        let weights = _mm_setr_epi8(16, 15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1);
+        let ones = _mm_set1_epi16(1);
+        let zero = _mm_setzero_si128();
+
+        let blocks_len = buf.len() - buf.len() % 16;
+        let (blocks, remaining) = buf.split_at(blocks_len);
+
+        for chunk in blocks.chunks(NMAX - NMAX % 16) {
+            // sum of the `s1` at the start of each block, not including the initial `s1`
+            let mut v_ps = zero;
+            let mut v_s1 = zero;
+            let mut v_s2 = zero;
+
+            for block in chunk.chunks(16) {
+                let bytes = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+                v_ps = _mm_add_epi32(v_ps, v_s1);
+                v_s1 = _mm_add_epi32(v_s1, _mm_sad_epu8(bytes, zero));
+                let weighted = _mm_maddubs_epi16(bytes, weights);
+                v_s2 = _mm_add_epi32(v_s2, _mm_madd_epi16(weighted, ones));
+            }
+
+            let s1 = hasher.s1;
+            hasher.s2 += s1 * chunk.len() as u32 + 16 * sum_ssse3(v_ps) + sum_ssse3(v_s2);
+            hasher.s1 += sum_ssse3(v_s1);
+            hasher.s1 %= MOD;
+            hasher.s2 %= MOD;
+        }
+
+        hasher.feed_scalar(remaining);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn feed_avx2(hasher: &mut Adler32, buf: &[u8]) {
+        let weights = _mm256_setr_epi8(32, 31, 30, 29, 28, 27, 26, 25, 24, 23, 22, 21, 20, 19,
+                                       18, 17, 16, 15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3,
+                                       2, 1);
+        let ones = _mm256_set1_epi16(1);
+        let zero = _mm256_setzero_si256();
+
+        let blocks_len = buf.len() - buf.len() % 32;
+        let (blocks, remaining) = buf.split_at(blocks_len);
+
+        for chunk in blocks.chunks(NMAX - NMAX % 32) {
+            let mut v_ps = zero;
+            let mut v_s1 = zero;
+            let mut v_s2 = zero;
+
+            for block in chunk.chunks(32) {
+                let bytes = _mm256_loadu_si256(block.as_ptr() as *const __m256i);
+                v_ps = _mm256_add_epi32(v_ps, v_s1);
+                v_s1 = _mm256_add_epi32(v_s1, _mm256_sad_epu8(bytes, zero));
+                let weighted = _mm256_maddubs_epi16(bytes, weights);
+                v_s2 = _mm256_add_epi32(v_s2, _mm256_madd_epi16(weighted, ones));
+            }
+
+            let s1 = hasher.s1;
+            hasher.s2 += s1 * chunk.len() as u32 + 32 * sum_avx2(v_ps) + sum_avx2(v_s2);
+            hasher.s1 += sum_avx2(v_s1);
+            hasher.s1 %= MOD;
+            hasher.s2 %= MOD;
+        }
+
+        hasher.feed_scalar(remaining);
+    }
+
+    /// Sums the four 32-bits lanes of a vector.
+    #[target_feature(enable = "ssse3")]
+    unsafe fn sum_ssse3(v: __m128i) -> u32 {
+        let mut lanes = [0u32; 4];
+        _mm_storeu_si128(lanes.as_mut_ptr() as *mut __m128i, v);
+        lanes.iter().sum()
+    }
+
+    /// Sums the eight 32-bits lanes of a vector.
+    #[target_feature(enable = "avx2")]
+    unsafe fn sum_avx2(v: __m256i) -> u32 {
+        let mut lanes = [0u32; 8];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, v);
+        lanes.iter().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Adler32, MOD};
+    use std::hash::{Hash, Hasher};
+
+    #[test]
+    fn wikipedia() {
+        let mut hasher = Adler32::new();
+        hasher.feed(b"Wikipedia");
+        assert_eq!(hasher.checksum(), 0x11e60398);
+    }
+
+    #[test]
+    fn combine() {
+        let data = (0 .. 200000).map(|n: u32| (n.wrapping_mul(2654435761) >> 24) as u8)
+                                .collect::<Vec<_>>();
+
+        for &split in &[0, 1, 5552, 65521, 65522, 100000, data.len()] {
+            let mut expected = Adler32::new();
+            expected.feed(&data);
+
+            let (first, second) = data.split_at(split);
+            let mut first_hasher = Adler32::new();
+            first_hasher.feed(first);
+            let mut second_hasher = Adler32::new();
+            second_hasher.feed(second);
+
+            let combined = first_hasher.combine(second_hasher, second.len() as u64);
+            assert_eq!(combined.checksum(), expected.checksum());
+        }
+
+        let empty = Adler32::new();
+        let mut hasher = Adler32::new();
+        hasher.feed(b"Wikipedia");
+        assert_eq!(hasher.combine(empty, 0).checksum(), 0x11e60398);
+        assert_eq!(empty.combine(hasher, 9).checksum(), 0x11e60398);
+
+        // a length that doesn't fit in 32 bits: the hasher of `n` zeros has `s1 == 1` and
+        // `s2 == n % MOD`, so the result must be the same as with `n % MOD` zeros
+        let rem = (1u64 << 40) % MOD as u64;
+        let zeros = Adler32 { s1: 1, s2: rem as u32 };
+        let mut expected = hasher;
+        expected.feed(&vec![0; rem as usize]);
+        assert_eq!(hasher.combine(zeros, 1 << 40).checksum(), expected.checksum());
+    }
+
+    #[test]
+    fn big_buffer_and_small_chunks() {
+        let data = (0 .. 4 * 1024 * 1024).map(|n: u32| (n.wrapping_mul(2654435761) >> 24) as u8)
+                                          .collect::<Vec<_>>();
+
+        let mut one_call = Adler32::new();
+        one_call.feed(&data);
+
+        let mut many_calls = Adler32::new();
+        for chunk in data.chunks(37) {
+            many_calls.feed(chunk);
+        }
+
+        assert_eq!(one_call.checksum(), many_calls.checksum());
+
+        // same data made of only 0xff bytes, which is the worst case for overflows
+        let data = vec![0xff; 4 * 1024 * 1024];
+        let mut one_call = Adler32::new();
+        one_call.feed(&data);
+        let mut many_calls = Adler32::new();
+        for &byte in &data {
+            many_calls.feed(&[byte]);
+        }
+        assert_eq!(one_call.checksum(), many_calls.checksum());
+        assert_eq!(one_call.checksum(), 0x430dbc6e);
+    }
+
+    #[test]
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    fn simd_matches_scalar() {
+        let mut seed = 0x2545f491u32;
+        let data = (0 .. 1024 * 1024 + 13).map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as u8
+        }).collect::<Vec<_>>();
+
+        for &len in &[0, 1, 15, 16, 31, 32, 33, 5551, 5552, 5553, 65536, data.len()] {
+            let mut scalar = Adler32::new();
+            scalar.feed_scalar(&data[..len]);
+
+            if is_x86_feature_detected!("ssse3") {
+                let mut simd = Adler32::new();
+                unsafe { super::simd::feed_ssse3(&mut simd, &data[..len]) };
+                assert_eq!(simd.checksum(), scalar.checksum());
+            }
+
+            if is_x86_feature_detected!("avx2") {
+                let mut simd = Adler32::new();
+                unsafe { super::simd::feed_avx2(&mut simd, &data[..len]) };
+                assert_eq!(simd.checksum(), scalar.checksum());
+            }
+        }
+
+        let ff = vec![0xff; 1024 * 1024];
+        let mut scalar = Adler32::new();
+        scalar.feed_scalar(&ff);
+        let mut dispatched = Adler32::new();
+        dispatched.feed(&ff);
+        assert_eq!(dispatched.checksum(), scalar.checksum());
+    }
+
+    #[test]
+    fn hasher() {
+        let mut hasher = Adler32::new();
+        "hello world".hash(&mut hasher);
+
+        // `str` writes its bytes followed by `0xff`
+        let mut expected = Adler32::new();
+        expected.feed(b"hello world\xff");
+        assert_eq!(hasher.finish(), expected.checksum() as u64);
+
+        let mut hasher = Adler32::new();
+        hasher.write(b"hello ");
+        hasher.write(b"world");
+        let mut expected = Adler32::new();
+        expected.feed(b"hello world");
+        assert_eq!(hasher.finish(), expected.checksum() as u64);
+    }
 }
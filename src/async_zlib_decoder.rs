@@ -0,0 +1,185 @@
+//! Decoding of zlib data read from an asynchronous source.
+
+use std::io::{ErrorKind, Read, Result as IoResult};
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use tokio::io::{AsyncRead, ReadBuf};
+use zlib_decoder::ZlibDecoder;
+
+/// A tokio `AsyncRead` that decodes zlib data from an underlying `AsyncRead`.
+///
+/// This is a `ZlibDecoder` whose reader returns `WouldBlock` when the underlying reader isn't
+/// ready, which the decoder continues from on the next call. The decoding is therefore the same
+/// as with a blocking reader, and the underlying reader is woken up as usual once there is more
+/// data.
+pub struct AsyncZlibDecoder<R> where R: AsyncRead + Unpin {
+    decoder: ZlibDecoder<PollReader<R>>,
+}
+
+/// Blocking reader over an `AsyncRead`, which returns `WouldBlock` when it is pending.
+struct PollReader<R> {
+    inner: R,
+
+    /// Waker of the task that is polling the decoder, set before each call to the decoder.
+    waker: Option<Waker>,
+}
+
+impl<R> Read for PollReader<R> where R: AsyncRead + Unpin {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let waker = self.waker.as_ref().expect("the waker is set before reading");
+        let mut cx = Context::from_waker(waker);
+        let mut buf = ReadBuf::new(buf);
+
+        match Pin::new(&mut self.inner).poll_read(&mut cx, &mut buf) {
+            Poll::Ready(Ok(())) => Ok(buf.filled().len()),
+            Poll::Ready(Err(err)) => Err(err),
+            Poll::Pending => Err(ErrorKind::WouldBlock.into()),
+        }
+    }
+}
+
+impl<R> AsyncZlibDecoder<R> where R: AsyncRead + Unpin {
+    /// Builds a new decoder by taking ownership of a reader where the data will be read from.
+    pub fn new(reader: R) -> AsyncZlibDecoder<R> {
+        AsyncZlibDecoder {
+            decoder: ZlibDecoder::new(PollReader { inner: reader, waker: None }),
+        }
+    }
+
+    /// Destroys the decoder and returns the underlying reader. See `ZlibDecoder::into_inner`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if a previous call to `poll_read` returned an error.
+    ///
+    pub fn into_inner(self) -> R {
+        self.decoder.into_inner().inner
+    }
+}
+
+impl<R> AsyncRead for AsyncZlibDecoder<R> where R: AsyncRead + Unpin {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>)
+                 -> Poll<IoResult<()>>
+    {
+        let this = self.get_mut();
+
+        // after an error, the decoder doesn't read anything and returns an error again
+        if let Some(reader) = this.decoder.try_get_mut() {
+            match reader.waker {
+                Some(ref waker) if waker.will_wake(cx.waker()) => (),
+                _ => reader.waker = Some(cx.waker().clone()),
+            }
+        }
+
+        match this.decoder.read(buf.initialize_unfilled()) {
+            Ok(len) => {
+                buf.advance(len);
+                Poll::Ready(Ok(()))
+            },
+            // the underlying reader has registered the waker
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => Poll::Pending,
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AsyncZlibDecoder;
+    use std::future::Future;
+    use std::mem;
+    use std::io::{ErrorKind, Result as IoResult, Write};
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{self, AsyncRead, AsyncWrite, DuplexStream, ReadBuf};
+    use tokio::runtime::Builder;
+    use zlib_encoder::ZlibEncoder;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), 6);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Writes `data` a few bytes at a time to one end of a pipe, while decoding what comes
+    /// out of the other end.
+    struct Transfer {
+        data: Vec<u8>,
+        written: usize,
+        // `None` once everything has been written, which closes the pipe
+        writer: Option<DuplexStream>,
+        decoder: AsyncZlibDecoder<DuplexStream>,
+        output: Vec<u8>,
+    }
+
+    impl Future for Transfer {
+        type Output = IoResult<Vec<u8>>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<Vec<u8>>> {
+            let this = self.get_mut();
+            loop {
+                let mut progress = false;
+
+                if let Some(ref mut writer) = this.writer {
+                    let end = this.data.len().min(this.written + 7);
+                    let chunk = &this.data[this.written .. end];
+                    if let Poll::Ready(len) = Pin::new(writer).poll_write(cx, chunk)? {
+                        this.written += len;
+                        progress = true;
+                    }
+                }
+                if this.written == this.data.len() {
+                    this.writer = None;
+                }
+
+                let mut buf = [0; 100];
+                let mut buf = ReadBuf::new(&mut buf);
+                if let Poll::Ready(()) = Pin::new(&mut this.decoder).poll_read(cx, &mut buf)? {
+                    if buf.filled().is_empty() {
+                        return Poll::Ready(Ok(mem::take(&mut this.output)));
+                    }
+                    this.output.extend_from_slice(buf.filled());
+                    progress = true;
+                }
+
+                if !progress {
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+
+    fn transfer(data: Vec<u8>) -> IoResult<Vec<u8>> {
+        // the pipe only holds a few bytes, so the decoder often has to wait for the writer
+        let (writer, reader) = io::duplex(16);
+        let transfer = Transfer {
+            data,
+            written: 0,
+            writer: Some(writer),
+            decoder: AsyncZlibDecoder::new(reader),
+            output: Vec::new(),
+        };
+
+        Builder::new_current_thread().build().unwrap().block_on(transfer)
+    }
+
+    #[test]
+    fn duplex() {
+        let data = b"Lorem ipsum dolor sit amet, lorem ipsum dolor sit amet".repeat(1000);
+        assert!(transfer(compress(&data)).unwrap() == data);
+        assert_eq!(transfer(compress(b"")).unwrap(), b"");
+    }
+
+    #[test]
+    fn errors() {
+        let mut compressed = compress(b"hello world");
+        let len = compressed.len();
+
+        // truncated data
+        assert!(transfer(compressed[.. len - 6].to_vec()).is_err());
+
+        compressed[len - 1] ^= 1;
+        let err = transfer(compressed).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,213 @@
+//! Decoding of data whose framing isn't known in advance.
+
+use core::cmp;
+use io::{ErrorKind, Read};
+use io::Error as IoError;
+use gzip_decoder::GzipDecoder;
+use inflate::Inflater;
+use zlib_decoder::{self, ZlibDecoder};
+
+/// A reader that decodes gzip, zlib or raw DEFLATE data from an underlying reader, depending on
+/// what the data starts with.
+///
+/// This is useful for example for the `deflate` HTTP content encoding, which is supposed to
+/// designate zlib data but is sent as raw DEFLATE data by some servers.
+///
+/// The first two bytes of the data are checked: `1f 8b` is the start of a gzip header, and two
+/// bytes that form a valid zlib header are the start of zlib data. Anything else is decoded as
+/// raw DEFLATE data. This can't be mistaken in the case of gzip, as no DEFLATE data can start
+/// with `1f`, but some raw DEFLATE data happens to start like a zlib header.
+pub struct AutoDecoder<R> where R: Read {
+    state: Option<AutoDecoderState<R>>,
+}
+
+/// Framing of the data decoded by an `AutoDecoder`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    /// Gzip data, decoded with a `GzipDecoder`.
+    Gzip,
+    /// Zlib data, decoded with a `ZlibDecoder`.
+    Zlib,
+    /// Raw DEFLATE data, decoded with an `Inflater`.
+    Raw,
+}
+
+enum AutoDecoderState<R> where R: Read {
+    // we haven't read the first two bytes yet, of which the first `len` are in `peeked`
+    Start {
+        reader: R,
+        peeked: [u8; 2],
+        len: usize,
+    },
+
+    Gzip(GzipDecoder<Peeked<R>>),
+    Zlib(ZlibDecoder<Peeked<R>>),
+    Raw(Inflater<Peeked<R>>),
+}
+
+/// Reader that returns the bytes read to detect the format, then the rest of the data.
+struct Peeked<R> {
+    peeked: [u8; 2],
+    start: usize,
+    end: usize,
+    inner: R,
+}
+
+impl<R> Read for Peeked<R> where R: Read {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        if self.start == self.end {
+            return self.inner.read(buf);
+        }
+
+        let len = cmp::min(buf.len(), self.end - self.start);
+        buf[.. len].copy_from_slice(&self.peeked[self.start .. self.start + len]);
+        self.start += len;
+        Ok(len)
+    }
+}
+
+impl<R> AutoDecoder<R> where R: Read {
+    /// Builds a new decoder by taking ownership of a reader where the data will be read from.
+    ///
+    /// Nothing is read until the first call to `read`.
+    pub fn new(reader: R) -> AutoDecoder<R> {
+        AutoDecoder {
+            state: Some(AutoDecoderState::Start { reader, peeked: [0, 0], len: 0 }),
+        }
+    }
+
+    /// Returns the format of the data, or `None` if the start of the data hasn't been read yet.
+    pub fn format(&self) -> Option<Format> {
+        match self.state {
+            Some(AutoDecoderState::Gzip(_)) => Some(Format::Gzip),
+            Some(AutoDecoderState::Zlib(_)) => Some(Format::Zlib),
+            Some(AutoDecoderState::Raw(_)) => Some(Format::Raw),
+            Some(AutoDecoderState::Start { .. }) | None => None,
+        }
+    }
+}
+
+impl<R> Read for AutoDecoder<R> where R: Read {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        match self.state {
+            Some(AutoDecoderState::Gzip(ref mut decoder)) => return decoder.read(buf),
+            Some(AutoDecoderState::Zlib(ref mut decoder)) => return decoder.read(buf),
+            Some(AutoDecoderState::Raw(ref mut inflater)) => return inflater.read(buf),
+            Some(AutoDecoderState::Start { .. }) => (),
+            None => {
+                return Err(IoError::new(ErrorKind::InvalidInput,
+                                        "I/O errors in the inflater are unrecoverable"));
+            },
+        }
+
+        let (mut reader, mut peeked, mut len) = match self.state.take() {
+            Some(AutoDecoderState::Start { reader, peeked, len }) => (reader, peeked, len),
+            _ => unreachable!(),
+        };
+
+        // the data can be shorter than two bytes, in which case it is handled as raw data
+        while len != peeked.len() {
+            match reader.read(&mut peeked[len ..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => {
+                    // the bytes already peeked are kept for the next call
+                    if ::is_retryable(&e) {
+                        self.state = Some(AutoDecoderState::Start { reader, peeked, len });
+                    }
+                    return Err(e);
+                },
+            }
+        }
+
+        let peeked_reader = Peeked { peeked, start: 0, end: len, inner: reader };
+        self.state = Some(if len == 2 && peeked == [0x1f, 0x8b] {
+            AutoDecoderState::Gzip(GzipDecoder::new(peeked_reader))
+        } else if len == 2 && zlib_decoder::is_zlib_header(peeked[0], peeked[1]) {
+            AutoDecoderState::Zlib(ZlibDecoder::new(peeked_reader))
+        } else {
+            AutoDecoderState::Raw(Inflater::new(peeked_reader))
+        });
+
+        self.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AutoDecoder, Format};
+    use std::io::{Read, Write};
+    use deflate::Deflater;
+    use gzip_encoder::GzipEncoder;
+    use zlib_encoder::ZlibEncoder;
+
+    const DATA: &[u8] = b"Lorem ipsum dolor sit amet, lorem ipsum dolor sit amet";
+
+    fn decode(data: &[u8]) -> (Vec<u8>, Option<Format>) {
+        let mut decoder = AutoDecoder::new(data);
+        assert_eq!(decoder.format(), None);
+
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        (output, decoder.format())
+    }
+
+    #[test]
+    fn empty_read() {
+        let mut decoder = AutoDecoder::new(DATA);
+        assert_eq!(decoder.read(&mut []).unwrap(), 0);
+        assert_eq!(decoder.format(), None);
+    }
+
+    #[test]
+    fn gzip() {
+        let mut encoder = GzipEncoder::new(Vec::new());
+        encoder.write_all(DATA).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(decode(&compressed), (DATA.to_vec(), Some(Format::Gzip)));
+    }
+
+    #[test]
+    fn zlib() {
+        for level in &[0, 1, 6, 9] {
+            let mut encoder = ZlibEncoder::new(Vec::new(), *level);
+            encoder.write_all(DATA).unwrap();
+            let compressed = encoder.finish().unwrap();
+
+            assert_eq!(decode(&compressed), (DATA.to_vec(), Some(Format::Zlib)));
+        }
+    }
+
+    #[test]
+    fn raw() {
+        let mut deflater = Deflater::new(Vec::new(), 6);
+        deflater.write_all(DATA).unwrap();
+        let compressed = deflater.finish().unwrap();
+
+        assert_eq!(decode(&compressed), (DATA.to_vec(), Some(Format::Raw)));
+    }
+
+    #[test]
+    fn raw_starting_like_zlib() {
+        // non-final uncompressed block containing `hello` whose first byte is a valid zlib
+        // `CMF`, but whose second byte doesn't match the zlib header checksum, followed by an
+        // empty final uncompressed block
+        let data = [0x78, 0x05, 0x00, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o',
+                    0x01, 0x00, 0x00, 0xff, 0xff];
+
+        assert_eq!(decode(&data), (b"hello".to_vec(), Some(Format::Raw)));
+    }
+
+    #[test]
+    fn short_input() {
+        let mut decoder = AutoDecoder::new(&[0x03][..]);
+        assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+        assert_eq!(decoder.format(), Some(Format::Raw));
+    }
+}
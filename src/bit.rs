@@ -1,70 +1,347 @@
-use std::io::{self, Read};
-use std::io::Error as IoError;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use io::{self, Read, Write};
+use io::Error as IoError;
 
 /// Reads some data bit per bit.
-pub struct BitRead<R> where R: Read {
+///
+/// `O` is the order in which the bits of each byte are read. DEFLATE uses `Lsb`.
+#[derive(Clone)]
+pub struct BitRead<R, O = Lsb> where R: Read {
     /// The `Read` object that the bytes are read from.
     inner: R,
 
-    /// The current cached data being read. This is right-shifted when you call `read`.
+    /// The current cached data being read. The bits are removed from it by `O::take_bits`.
     data: u16,
 
     /// Number of bits remaining to read in `data`. Must be between 0 and 7.
     bits: u8,
+
+    /// Number of bits read so far. See `bit_position`.
+    position: u64,
+
+    /// Bytes read from `inner` since the last call to `checkpoint`, or empty if `checkpoint`
+    /// has never been called. After a `rollback`, the last `replay` bytes are read again before
+    /// the next bytes of `inner`.
+    ///
+    /// The last of these bytes, `recent_len` of them, are in `recent` instead, the last one
+    /// being the least significant byte. They are only moved to `journal` when there are more
+    /// than 8 of them, as that is much faster than pushing each byte to `journal`.
+    journal: Vec<u8>,
+    recent: u64,
+    recent_len: u8,
+    replay: usize,
+
+    /// True if `checkpoint` has been called, in which case the bytes are kept in `journal`.
+    journaling: bool,
+
+    /// Values of `data`, `bits` and `position` at the last checkpoint.
+    saved: (u16, u8, u64),
+
+    order: PhantomData<O>,
+}
+
+/// Order in which a `BitRead` reads the bits of each byte.
+pub trait BitOrder {
+    /// Adds a byte that has been read from the stream to `data`, which contains `bits` bits
+    /// that haven't been read yet.
+    fn push_byte(data: u16, bits: u8, byte: u8) -> u16;
+
+    /// Removes the next `count` bits from `data`, which contains `bits` bits, and returns them.
+    fn take_bits(data: &mut u16, bits: u8, count: u8) -> u8;
+
+    /// Assembles a value from two consecutive reads of `first_bits` then `second_bits` bits.
+    fn join(first: u8, first_bits: u8, second: u8, second_bits: u8) -> u16;
+}
+
+/// Reads the least significant bit of each byte first. The first bit that is read is the least
+/// significant bit of the value.
+#[derive(Debug, Copy, Clone)]
+pub struct Lsb;
+
+/// Reads the most significant bit of each byte first. The first bit that is read is the most
+/// significant bit of the value.
+#[derive(Debug, Copy, Clone)]
+pub struct Msb;
+
+impl BitOrder for Lsb {
+    fn push_byte(data: u16, bits: u8, byte: u8) -> u16 {
+        data | ((byte as u16) << bits)
+    }
+
+    fn take_bits(data: &mut u16, _: u8, count: u8) -> u8 {
+        let result = *data & ((1 << count) - 1);
+        *data >>= count;
+        result as u8
+    }
+
+    fn join(first: u8, first_bits: u8, second: u8, _: u8) -> u16 {
+        first as u16 | ((second as u16) << first_bits)
+    }
+}
+
+impl BitOrder for Msb {
+    fn push_byte(data: u16, _: u8, byte: u8) -> u16 {
+        (data << 8) | byte as u16
+    }
+
+    fn take_bits(data: &mut u16, bits: u8, count: u8) -> u8 {
+        let rest = bits - count;
+        let result = (*data >> rest) & ((1 << count) - 1);
+        *data &= (1 << rest) - 1;
+        result as u8
+    }
+
+    fn join(first: u8, _: u8, second: u8, second_bits: u8) -> u16 {
+        ((first as u16) << second_bits) | second as u16
+    }
 }
 
 impl<R> BitRead<R> where R: Read {
+    /// Builds a reader that reads the least significant bits of each byte first, as DEFLATE does.
     pub fn new(inner: R) -> BitRead<R> {
+        BitRead::with_order(inner)
+    }
+
+    /// Removes the bits that have been read from the underlying reader but not returned yet
+    /// and returns them, the next one being the least significant, with their number. The bits
+    /// after them are 0.
+    ///
+    /// The caller can then read whole bytes with `read_raw_byte`, and must give back the bits
+    /// that it hasn't used with `restore_cache`.
+    pub(crate) fn take_cache(&mut self) -> (u16, u8) {
+        let result = (self.data, self.bits);
+        self.data = 0;
+        self.bits = 0;
+        result
+    }
+
+    /// Gives back the `bits` bits of `data` that haven't been used, after `consumed` bits have
+    /// been used. See `take_cache`.
+    pub(crate) fn restore_cache(&mut self, data: u16, bits: u8, consumed: u8) {
+        debug_assert!(bits <= 7 && self.bits == 0);
+        self.data = data & ((1 << bits) - 1);
+        self.bits = bits;
+        self.position += consumed as u64;
+    }
+}
+
+impl<R, O> BitRead<R, O> where R: Read, O: BitOrder {
+    /// Builds a reader that reads the bits of each byte in the order `O`.
+    pub fn with_order(inner: R) -> BitRead<R, O> {
         BitRead {
-            inner: inner,
+            inner,
             data: 0,
             bits: 0,
+            position: 0,
+            journal: Vec::new(),
+            recent: 0,
+            recent_len: 0,
+            replay: 0,
+            journaling: false,
+            saved: (0, 0, 0),
+            order: PhantomData,
         }
     }
 
+    /// Builds a reader that continues reading a bitstream in the middle of a byte, as saved by
+    /// `into_state`.
+    ///
+    /// `bits`, which must be between 0 and 7, is the number of bits of the current byte that
+    /// haven't been read yet, and `partial` contains them in its `bits` least significant bits.
+    /// With `Lsb` the next bit to read is the least significant one, and with `Msb` it is the
+    /// most significant of them. The other bits of `partial` are ignored.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `bits` is superior to 7.
+    ///
+    pub fn with_state(inner: R, partial: u8, bits: u8) -> BitRead<R, O> {
+        assert!(bits <= 7, "A partial byte contains at most 7 bits");
+
+        BitRead {
+            inner,
+            data: partial as u16 & ((1 << bits) - 1),
+            bits,
+            position: 0,
+            journal: Vec::new(),
+            recent: 0,
+            recent_len: 0,
+            replay: 0,
+            journaling: false,
+            saved: (0, 0, 0),
+            order: PhantomData,
+        }
+    }
+
+    /// Destroys the reader and returns the underlying reader, the bits of the current byte that
+    /// haven't been read yet and their number. See `with_state`.
+    pub fn into_state(self) -> (R, u8, u8) {
+        debug_assert!(self.bits <= 7);
+        debug_assert!(self.replay == 0);
+        (self.inner, self.data as u8, self.bits)
+    }
+
     /// Reads some bits.
     ///
-    /// If the stream reaches EOF, returns an `InvalidInput` error.
+    /// If the stream reaches EOF, returns an `InvalidInput` error. Reads that are interrupted are
+    /// retried.
     ///
     /// **Warning**: reading two bits can be different from reading one bit then one bit.
-    /// For example, if the data is `0b10`, then reading one bit then one bit would give `0` then
-    /// `1`, while reading two bits would give `0b10`.
+    /// For example with `Lsb`, if the data is `0b10`, then reading one bit then one bit would
+    /// give `0` then `1`, while reading two bits would give `0b10`.
     ///
     pub fn read(&mut self, bits: u8) -> Result<u8, IoError> {
         assert!(bits <= 8);
 
-        if bits > self.bits {
-            // making sure that there is enough data in `data`
-            let mut data = [0];
-            if try!(self.inner.read(&mut data)) == 0 {
-                return Err(IoError::new(io::ErrorKind::InvalidInput, "Unexpected EOF in bits \
-                                                                      stream"));
-            }
-
-            assert!(self.bits <= 8);
-            self.data |= (data[0] as u16) << self.bits;
-            self.bits += 8;
+        // making sure that there is enough data in `data`
+        if bits > self.bits && !self.fill()? {
+            return Err(IoError::new(io::ErrorKind::InvalidInput,
+                                    "Unexpected EOF in bits stream"));
         }
 
         Ok(self.read_from_cache(bits))
     }
 
+    /// Returns true if all the bits have been read and the underlying reader has reached EOF.
+    pub fn is_at_eof(&mut self) -> Result<bool, IoError> {
+        if self.bits != 0 {
+            return Ok(false);
+        }
+
+        Ok(!self.fill()?)
+    }
+
+    /// Same as `read`, but can read up to 16 bits.
     ///
-    pub fn read_bit_per_bit(&mut self, bits: u8) -> Result<u8, IoError> {
-        let mut buffer = 0;
-        for _ in (0 .. bits) {
-            buffer <<= 1;
-            buffer |= try!(self.read(1));
+    /// The bits are assembled in the same order as by `read`, for example with `Lsb` the first
+    /// bits that are read are the least significant bits of the result.
+    pub fn read_u16(&mut self, bits: u8) -> Result<u16, IoError> {
+        assert!(bits <= 16);
+
+        if bits <= 8 {
+            return Ok(self.read(bits)? as u16);
         }
-        Ok(buffer)
+
+        let first = self.read(8)?;
+        let second = self.read(bits - 8)?;
+        Ok(O::join(first, 8, second, bits - 8))
+    }
+
+    /// Returns the number of bits read since the reader has been built, unless another starting
+    /// position has been set with `set_bit_position`.
+    pub fn bit_position(&self) -> u64 {
+        self.position
+    }
+
+    /// Changes the value returned by `bit_position`, for example to the position of the reader
+    /// in a stream that doesn't start at the beginning of the underlying reader.
+    pub(crate) fn set_bit_position(&mut self, position: u64) {
+        self.position = position;
+    }
+
+    /// Returns a reference to the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
     }
 
     /// Aligns to the next byte and returns the wrapper reader.
     pub fn byte_align_unwrap(self) -> R {
         debug_assert!(self.bits <= 7);
+        debug_assert!(self.replay == 0);
         self.inner
     }
 
+    /// Marks the current position as the one that `rollback` goes back to.
+    ///
+    /// From then on, the bytes read from the underlying reader are kept until the next
+    /// checkpoint, so this must be called regularly.
+    pub(crate) fn checkpoint(&mut self) {
+        if self.replay == 0 {
+            self.journal.clear();
+        } else {
+            let len = self.journal.len() - self.replay;
+            self.journal.drain(.. len);
+        }
+        self.recent_len = 0;
+        self.journaling = true;
+        self.saved = (self.data, self.bits, self.position);
+    }
+
+    /// Goes back to the position of the last call to `checkpoint`, for example after the
+    /// underlying reader has returned an error of kind `WouldBlock` in the middle of a symbol.
+    /// The bytes read since then are read again before the next bytes of the underlying reader.
+    pub(crate) fn rollback(&mut self) {
+        debug_assert!(self.journaling);
+        self.move_recent_to_journal();
+        let (data, bits, position) = self.saved;
+        self.data = data;
+        self.bits = bits;
+        self.position = position;
+        self.replay = self.journal.len();
+    }
+
+    /// Reads a byte from the underlying reader and adds it to `data`. Returns false on EOF.
+    fn fill(&mut self) -> Result<bool, IoError> {
+        let byte = match self.read_raw_byte()? {
+            Some(byte) => byte,
+            None => return Ok(false),
+        };
+
+        assert!(self.bits <= 8);
+        self.data = O::push_byte(self.data, self.bits, byte);
+        self.bits += 8;
+        Ok(true)
+    }
+
+    /// Reads the next byte of the underlying reader, without going through `data`. Returns
+    /// `None` on EOF.
+    pub(crate) fn read_raw_byte(&mut self) -> Result<Option<u8>, IoError> {
+        if self.replay != 0 {
+            return Ok(Some(self.replay_byte()));
+        }
+
+        let mut data = [0];
+        loop {
+            match self.inner.read(&mut data) {
+                Ok(0) => return Ok(None),
+                Ok(_) => {
+                    if self.journaling {
+                        if self.recent_len == 8 {
+                            self.move_recent_to_journal();
+                        }
+                        self.recent = (self.recent << 8) | data[0] as u64;
+                        self.recent_len += 1;
+                    }
+                    return Ok(Some(data[0]));
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Moves the bytes of `recent` to the end of `journal`.
+    #[cold]
+    fn move_recent_to_journal(&mut self) {
+        for n in (0 .. self.recent_len).rev() {
+            self.journal.push((self.recent >> (8 * n)) as u8);
+        }
+        self.recent_len = 0;
+    }
+
+    /// Returns the next byte of `journal` to read again after a `rollback`.
+    #[cold]
+    fn replay_byte(&mut self) -> u8 {
+        self.replay -= 1;
+        self.journal[self.journal.len() - self.replay - 1]
+    }
+
     /// Reads a number of bits from `data`.
     ///
     /// # Panic
@@ -73,18 +350,143 @@ impl<R> BitRead<R> where R: Read {
     fn read_from_cache(&mut self, bits: u8) -> u8 {
         assert!(bits <= self.bits);
 
-        let result = self.data & ((1 << bits) - 1);
-        self.data >>= bits;
+        let result = O::take_bits(&mut self.data, self.bits, bits);
         self.bits -= bits;
-        result as u8
+        self.position += bits as u64;
+        result
+    }
+}
+
+/// Writes some data bit per bit, in the same order as `BitRead` reads it.
+pub struct BitWrite<W> where W: Write {
+    /// The `Write` object that the bytes are written to.
+    inner: W,
+
+    /// Complete bytes that haven't been written to `inner` yet.
+    buffer: Vec<u8>,
+
+    /// Bits that don't form a complete byte yet. New bits are added on the most significant side.
+    data: u64,
+
+    /// Number of bits in `data`. Must be between 0 and 7 between calls.
+    bits: u8,
+}
+
+/// Number of bytes in `buffer` above which they are written to the inner writer.
+const WRITE_BUFFER_LEN: usize = 8192;
+
+impl<W> BitWrite<W> where W: Write {
+    pub fn new(inner: W) -> BitWrite<W> {
+        BitWrite::with_prefix(inner, &[])
+    }
+
+    /// Same as `new`, but `prefix` is written to the underlying writer before any other data.
+    pub fn with_prefix(inner: W, prefix: &[u8]) -> BitWrite<W> {
+        let mut buffer = Vec::with_capacity(WRITE_BUFFER_LEN + prefix.len());
+        buffer.extend_from_slice(prefix);
+
+        BitWrite {
+            inner,
+            buffer,
+            data: 0,
+            bits: 0,
+        }
+    }
+
+    /// Writes the `bits` lowest bits of `value`.
+    ///
+    /// The least significant bit is written first, which means that writing `value` with
+    /// `bits` bits then reading `bits` bits with a `BitRead` gives back `value`.
+    pub fn write(&mut self, value: u32, bits: u8) -> Result<(), IoError> {
+        assert!(bits <= 32);
+
+        let value = value as u64 & ((1 << bits) - 1);
+        self.data |= value << self.bits;
+        self.bits += bits;
+
+        while self.bits >= 8 {
+            self.buffer.push(self.data as u8);
+            self.data >>= 8;
+            self.bits -= 8;
+        }
+
+        if self.buffer.len() >= WRITE_BUFFER_LEN {
+            self.write_buffer()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes bytes as they are. The writer must be aligned on a byte boundary.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), IoError> {
+        assert!(self.is_aligned());
+
+        if self.buffer.len() + bytes.len() >= WRITE_BUFFER_LEN {
+            self.write_buffer()?;
+            self.inner.write_all(bytes)
+        } else {
+            self.buffer.extend_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    /// Returns true if the next bit to be written is the first bit of a byte.
+    pub fn is_aligned(&self) -> bool {
+        self.bits == 0
+    }
+
+    /// Pads with zero bits until the next byte boundary.
+    pub fn byte_align(&mut self) -> Result<(), IoError> {
+        if self.bits != 0 {
+            let padding = 8 - self.bits;
+            self.write(0, padding)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes all the complete bytes to the underlying writer and flushes it.
+    pub fn flush(&mut self) -> Result<(), IoError> {
+        self.write_buffer()?;
+        self.inner.flush()
+    }
+
+    /// Pads with zero bits until the next byte boundary, writes everything to the underlying
+    /// writer and returns it.
+    pub fn into_inner(mut self) -> Result<W, IoError> {
+        self.byte_align()?;
+        self.write_buffer()?;
+        Ok(self.inner)
+    }
+
+    fn write_buffer(&mut self) -> Result<(), IoError> {
+        let result = self.inner.write_all(&self.buffer);
+        self.buffer.clear();
+        result
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::Cursor;
+    use std::io::{self, Cursor};
     use std::io::Read;
-    use super::BitRead;
+    use super::{BitRead, BitWrite, Lsb, Msb};
+
+    /// Reader that returns an `Interrupted` error before each successful read.
+    struct Interrupting<R> {
+        inner: R,
+        interrupt: bool,
+    }
+
+    impl<R> Read for Interrupting<R> where R: Read {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "interrupted"));
+            }
+            self.inner.read(buf)
+        }
+    }
 
     #[test]
     fn test() {
@@ -114,6 +516,18 @@ mod tests {
         assert_eq!(data.read(1).unwrap(), 0b0);
     }
 
+    #[test]
+    fn interrupted() {
+        let data = Interrupting { inner: Cursor::new(vec![0b01001110, 0b11011000]),
+                                  interrupt: false };
+        let mut data = BitRead::new(data);
+
+        assert_eq!(data.read(2).unwrap(), 0b10);
+        assert_eq!(data.read(8).unwrap(), 0b00010011);
+        assert_eq!(data.read(6).unwrap(), 0b110110);
+        assert!(data.read(1).is_err());
+    }
+
     #[test]
     #[should_panic]
     fn too_much() {
@@ -122,6 +536,127 @@ mod tests {
         data.read(9).unwrap();
     }
 
+    #[test]
+    fn read_u16() {
+        let data = Cursor::new(vec![0b01001110, 0b11011000]);
+        let mut data = BitRead::new(data);
+
+        assert_eq!(data.read(2).unwrap(), 0b10);
+        assert_eq!(data.read_u16(13).unwrap(), 0b1011000010011);
+    }
+
+    #[test]
+    fn msb() {
+        let data = Cursor::new(vec![0b01001110, 0b11011000]);
+        let mut data = BitRead::<_, Msb>::with_order(data);
+
+        assert_eq!(data.read(2).unwrap(), 0b01);
+        assert_eq!(data.read(3).unwrap(), 0b001);
+        assert_eq!(data.read(1).unwrap(), 0b1);
+        assert_eq!(data.read(3).unwrap(), 0b101);
+        assert_eq!(data.read(3).unwrap(), 0b101);
+        assert_eq!(data.read(4).unwrap(), 0b1000);
+    }
+
+    #[test]
+    fn msb_bit_by_bit() {
+        let data = Cursor::new(vec![0b01001110]);
+        let mut data = BitRead::<_, Msb>::with_order(data);
+
+        for &bit in &[0, 1, 0, 0, 1, 1, 1, 0] {
+            assert_eq!(data.read(1).unwrap(), bit);
+        }
+        assert!(data.read(1).is_err());
+    }
+
+    #[test]
+    fn msb_read_u16() {
+        let data = Cursor::new(vec![0b01001110, 0b11011000, 0b10100000]);
+        let mut data = BitRead::<_, Msb>::with_order(data);
+
+        assert_eq!(data.read(2).unwrap(), 0b01);
+        assert_eq!(data.read_u16(13).unwrap(), 0b0011101101100);
+        assert!(data.read_u16(16).is_err());
+    }
+
+    #[test]
+    fn msb_byte_align() {
+        let data = Cursor::new(vec![0b01001110, 0xaa]);
+
+        let mut data = BitRead::<_, Msb>::with_order(data);
+        assert_eq!(data.read(8).unwrap(), 0b01001110);
+        assert_eq!(data.read(3).unwrap(), 0b101);
+
+        let data = data.byte_align_unwrap();
+        assert!(data.bytes().next().is_none());
+    }
+
+    #[test]
+    fn bit_position() {
+        let data = Cursor::new(vec![0b01001110, 0b11011000]);
+        let mut data = BitRead::new(data);
+        assert_eq!(data.bit_position(), 0);
+
+        data.read(3).unwrap();
+        data.read_u16(10).unwrap();
+        assert_eq!(data.bit_position(), 13);
+        assert!(data.read(4).is_err());
+        assert_eq!(data.bit_position(), 13);
+    }
+
+    #[test]
+    fn checkpoint_and_rollback() {
+        let data = Cursor::new(vec![0b01001110, 0b11011000, 0b10100101]);
+        let mut data = BitRead::new(data);
+
+        assert_eq!(data.read(3).unwrap(), 0b110);
+        data.checkpoint();
+        let value = data.read_u16(10).unwrap();
+        data.rollback();
+        assert_eq!(data.bit_position(), 3);
+        assert_eq!(data.read_u16(10).unwrap(), value);
+
+        // the bytes that have been read again are forgotten at the next checkpoint
+        data.checkpoint();
+        assert_eq!(data.read(8).unwrap(), 0b00101110);
+        data.rollback();
+        assert_eq!(data.read(8).unwrap(), 0b00101110);
+        assert_eq!(data.bit_position(), 21);
+    }
+
+    #[test]
+    fn save_and_restore_state() {
+        let bytes = vec![0b01001110, 0b11011000, 0b10100101];
+
+        // reading the bits with a new reader after each read must give the same result
+        let mut expected = BitRead::new(Cursor::new(bytes.clone()));
+        let mut state = (Cursor::new(bytes.clone()), 0, 0);
+        for &len in &[3, 1, 7, 2, 8, 3] {
+            let mut data = BitRead::<_, Lsb>::with_state(state.0, state.1, state.2);
+            assert_eq!(data.read(len).unwrap(), expected.read(len).unwrap());
+            state = data.into_state();
+        }
+        assert_eq!(state.2, 0);
+
+        let mut expected = BitRead::<_, Msb>::with_order(Cursor::new(bytes.clone()));
+        let mut state = (Cursor::new(bytes), 0, 0);
+        for &len in &[3, 1, 7, 2, 8, 3] {
+            let mut data = BitRead::<_, Msb>::with_state(state.0, state.1, state.2);
+            assert_eq!(data.read(len).unwrap(), expected.read(len).unwrap());
+            state = data.into_state();
+        }
+    }
+
+    #[test]
+    fn with_state() {
+        // the high bits of the partial byte are ignored
+        let mut data = BitRead::<_, Lsb>::with_state(Cursor::new(vec![0xff]), 0b11110101, 3);
+        assert_eq!(data.read(4).unwrap(), 0b1101);
+
+        let mut data = BitRead::<_, Msb>::with_state(Cursor::new(vec![0x00]), 0b11110101, 3);
+        assert_eq!(data.read(4).unwrap(), 0b1010);
+    }
+
     #[test]
     fn byte_align() {
         let data = Cursor::new(vec![0b01001110, 0xaa]);
@@ -156,4 +691,100 @@ mod tests {
         let data = data.byte_align_unwrap();
         assert_eq!(data.bytes().next().unwrap().unwrap(), 0xaa);
     }
+
+    #[test]
+    fn write_then_read() {
+        let mut output = BitWrite::new(Vec::new());
+        output.write(0b10, 2).unwrap();
+        output.write(0b011, 3).unwrap();
+        output.write(0b0, 1).unwrap();
+        output.write(0b001, 3).unwrap();
+        output.write(0b100, 3).unwrap();
+        output.write(0b1101, 4).unwrap();
+        let output = output.into_inner().unwrap();
+        assert_eq!(output, [0b01001110, 0b11011000]);
+
+        let mut data = BitRead::new(Cursor::new(output));
+        assert_eq!(data.read(2).unwrap(), 0b10);
+        assert_eq!(data.read(3).unwrap(), 0b011);
+        assert_eq!(data.read(1).unwrap(), 0b0);
+        assert_eq!(data.read(3).unwrap(), 0b001);
+        assert_eq!(data.read(3).unwrap(), 0b100);
+        assert_eq!(data.read(4).unwrap(), 0b1101);
+    }
+
+    #[test]
+    fn write_bit_by_bit() {
+        let mut output = BitWrite::new(Vec::new());
+        for &bit in &[0, 1, 1, 1, 0, 0, 1, 0] {
+            output.write(bit, 1).unwrap();
+        }
+        let output = output.into_inner().unwrap();
+        assert_eq!(output, [0b01001110]);
+
+        let mut data = BitRead::new(Cursor::new(output));
+        for &bit in &[0, 1, 1, 1, 0, 0, 1, 0] {
+            assert_eq!(data.read(1).unwrap(), bit);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn write_too_much() {
+        let mut output = BitWrite::new(Vec::new());
+        output.write(0, 33).unwrap();
+    }
+
+    #[test]
+    fn write_wide_then_read_u16() {
+        let mut output = BitWrite::new(Vec::new());
+        output.write(0b10, 2).unwrap();
+        output.write(0b1011000010011, 13).unwrap();
+        let output = output.into_inner().unwrap();
+
+        let mut data = BitRead::new(Cursor::new(output));
+        assert_eq!(data.read(2).unwrap(), 0b10);
+        assert_eq!(data.read_u16(13).unwrap(), 0b1011000010011);
+    }
+
+    #[test]
+    fn write_byte_align() {
+        let mut output = BitWrite::new(Vec::new());
+        output.write(0b10, 2).unwrap();
+        output.byte_align().unwrap();
+        assert!(output.is_aligned());
+        output.write_bytes(&[0xaa]).unwrap();
+        let output = output.into_inner().unwrap();
+        assert_eq!(output, [0b10, 0xaa]);
+
+        let mut data = BitRead::new(Cursor::new(output));
+        assert_eq!(data.read(2).unwrap(), 0b10);
+        let data = data.byte_align_unwrap();
+        assert_eq!(data.bytes().next().unwrap().unwrap(), 0xaa);
+    }
+
+    #[test]
+    fn write_byte_align_0() {
+        let mut output = BitWrite::new(Vec::new());
+        output.write(0, 0).unwrap();
+        output.byte_align().unwrap();
+        output.write_bytes(&[0xcc, 0xaa]).unwrap();
+        assert_eq!(output.into_inner().unwrap(), [0xcc, 0xaa]);
+    }
+
+    #[test]
+    fn write_byte_align_8() {
+        let mut output = BitWrite::new(Vec::new());
+        output.write(0b01001110, 8).unwrap();
+        output.byte_align().unwrap();
+        output.write_bytes(&[0xaa]).unwrap();
+        assert_eq!(output.into_inner().unwrap(), [0b01001110, 0xaa]);
+    }
+
+    #[test]
+    fn write_with_prefix() {
+        let mut output = BitWrite::with_prefix(Vec::new(), &[0x78, 0x9c]);
+        output.write(0b101, 3).unwrap();
+        assert_eq!(output.into_inner().unwrap(), [0x78, 0x9c, 0b101]);
+    }
 }
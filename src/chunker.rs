@@ -0,0 +1,266 @@
+//! Splitting of decompressed data into content-defined chunks, for deduplication.
+//!
+//! The boundaries are found with a gear hash, a rolling hash where each byte shifts the hash by
+//! one bit and adds a random value that depends on the byte. The hash therefore only depends on
+//! the last 64 bytes, and a boundary is placed after each byte where some of its bits are zero.
+//! Inserting or removing data only moves the boundaries around the change, so the other chunks
+//! stay the same.
+
+use alloc::vec::Vec;
+use core::mem;
+use io::{Read, Result as IoResult};
+use inflate::Inflater;
+
+/// Random value added to the hash for each possible byte.
+const GEAR: [u64; 256] = make_gear();
+
+const fn make_gear() -> [u64; 256] {
+    // splitmix64, with a fixed seed so that the boundaries never change
+    let mut table = [0; 256];
+    let mut state: u64 = 0x0123456789abcdef;
+    let mut n = 0;
+    while n < 256 {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        table[n] = z ^ (z >> 31);
+        n += 1;
+    }
+    table
+}
+
+/// Sizes of the chunks returned by a `ChunkedInflater`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChunkerParams {
+    min_size: usize,
+    max_size: usize,
+    // a boundary is placed after the bytes where `hash & mask == 0`
+    mask: u64,
+}
+
+impl ChunkerParams {
+    /// Builds the parameters of chunks that are between `min_size` and `max_size` bytes long.
+    ///
+    /// After the first `min_size` bytes of a chunk, each byte ends it with a probability of
+    /// `1 / avg_size`, so that the chunks are about `min_size + avg_size` bytes long on average.
+    /// Only the last chunk of the data can be shorter than `min_size`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `avg_size` isn't a power of two, or if `min_size` is 0 or larger than
+    /// `max_size`.
+    ///
+    pub fn new(min_size: usize, avg_size: usize, max_size: usize) -> ChunkerParams {
+        assert!(avg_size.is_power_of_two(), "The average size must be a power of two");
+        assert!(min_size != 0 && min_size <= max_size, "Invalid chunk sizes");
+
+        ChunkerParams {
+            min_size,
+            max_size,
+            // the high bits of the hash depend on more bytes than the low ones
+            mask: !(u64::MAX >> avg_size.trailing_zeros()),
+        }
+    }
+}
+
+impl Default for ChunkerParams {
+    /// Chunks of 2 kiB to 64 kiB, of about 10 kiB on average.
+    fn default() -> ChunkerParams {
+        ChunkerParams::new(2048, 8192, 65536)
+    }
+}
+
+/// An iterator over the decompressed data of raw DEFLATE data, split into chunks whose
+/// boundaries only depend on their content. See `ChunkerParams` for their sizes.
+///
+/// The same decompressed data always gives the same chunks, whatever the way it has been
+/// compressed, so that storage systems can deduplicate them.
+///
+/// After an error, the iterator doesn't return anything else, unless the error is of kind
+/// `WouldBlock` or `Interrupted`, in which case the next call continues the chunk.
+pub struct ChunkedInflater<R> where R: Read {
+    inflater: Inflater<R>,
+    params: ChunkerParams,
+
+    /// Decompressed data that hasn't been looked at yet is `input[input_pos ..]`.
+    input: Vec<u8>,
+    input_pos: usize,
+
+    /// Data of the chunk being built, and hash of its last bytes.
+    chunk: Vec<u8>,
+    hash: u64,
+
+    /// True at the end of the data or after an error that can't be retried.
+    finished: bool,
+}
+
+impl<R> ChunkedInflater<R> where R: Read {
+    /// Builds an iterator over the chunks of the DEFLATE data read from `reader`.
+    pub fn new(reader: R, params: ChunkerParams) -> ChunkedInflater<R> {
+        ChunkedInflater {
+            inflater: Inflater::new(reader),
+            params,
+            input: Vec::new(),
+            input_pos: 0,
+            chunk: Vec::new(),
+            hash: 0,
+            finished: false,
+        }
+    }
+
+    /// Destroys the iterator and returns the underlying inflater.
+    pub fn into_inner(self) -> Inflater<R> {
+        self.inflater
+    }
+
+    /// Looks for the end of the current chunk in the data that has already been decompressed.
+    /// Returns true if it has been found.
+    fn find_boundary(&mut self) -> bool {
+        let params = self.params;
+        let start = self.input_pos;
+        let mut found = false;
+
+        for &byte in &self.input[start ..] {
+            self.input_pos += 1;
+            self.hash = (self.hash << 1).wrapping_add(GEAR[byte as usize]);
+
+            let len = self.chunk.len() + self.input_pos - start;
+            if len >= params.max_size || (len >= params.min_size && self.hash & params.mask == 0)
+            {
+                found = true;
+                break;
+            }
+        }
+
+        self.chunk.extend_from_slice(&self.input[start .. self.input_pos]);
+        found
+    }
+
+    /// Returns the next chunk, or `None` at the end of the data.
+    fn next_chunk(&mut self) -> IoResult<Option<Vec<u8>>> {
+        while !self.find_boundary() {
+            self.input.resize(32768, 0);
+            self.input_pos = 0;
+            let len = match self.inflater.read(&mut self.input) {
+                Ok(len) => len,
+                Err(err) => {
+                    self.input.clear();
+                    return Err(err);
+                },
+            };
+            self.input.truncate(len);
+
+            if len == 0 {
+                self.finished = true;
+                if self.chunk.is_empty() {
+                    return Ok(None);
+                }
+                break;
+            }
+        }
+
+        // the hash starts over, so that the boundaries only depend on the chunk
+        self.hash = 0;
+        Ok(Some(mem::take(&mut self.chunk)))
+    }
+}
+
+impl<R> Iterator for ChunkedInflater<R> where R: Read {
+    type Item = IoResult<Vec<u8>>;
+
+    fn next(&mut self) -> Option<IoResult<Vec<u8>>> {
+        if self.finished {
+            return None;
+        }
+
+        match self.next_chunk() {
+            Ok(chunk) => chunk.map(Ok),
+            Err(err) => {
+                self.finished = !::is_retryable(&err);
+                Some(Err(err))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChunkedInflater, ChunkerParams};
+    use std::io::Write;
+    use deflate::Deflater;
+
+    /// Data made of pseudo-random words, so that the chunks don't all have the same content.
+    fn data(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed;
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            data.extend_from_slice(format!("{} ", state % 1000).as_bytes());
+        }
+        data.truncate(len);
+        data
+    }
+
+    fn compress(data: &[u8], level: u32) -> Vec<u8> {
+        let mut deflater = Deflater::new(Vec::new(), level);
+        deflater.write_all(data).unwrap();
+        deflater.finish().unwrap()
+    }
+
+    fn chunks(compressed: &[u8], params: ChunkerParams) -> Vec<Vec<u8>> {
+        ChunkedInflater::new(compressed, params).collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn deterministic_boundaries() {
+        let data = data(0x9e3779b97f4a7c15, 200_000);
+        let params = ChunkerParams::new(512, 1024, 4096);
+
+        let first = chunks(&compress(&data, 6), params);
+        assert_eq!(first.concat(), data);
+        assert!(first.iter().all(|chunk| chunk.len() <= 4096));
+        assert!(first[.. first.len() - 1].iter().all(|chunk| chunk.len() >= 512));
+        assert!(first.len() > 50);
+
+        // the boundaries don't depend on how the data is compressed
+        assert_eq!(chunks(&compress(&data, 0), params), first);
+        assert_eq!(chunks(&compress(&data, 9), params), first);
+
+        let lengths = first.iter().map(|chunk| chunk.len()).collect::<Vec<_>>();
+        assert_eq!(&lengths[.. 4], &[911, 796, 523, 910][..]);
+    }
+
+    #[test]
+    fn insertion() {
+        let data = data(0xd1b54a32d192ed03, 100_000);
+        let mut modified = data[.. 50_000].to_vec();
+        modified.extend_from_slice(b"inserted data");
+        modified.extend_from_slice(&data[50_000 ..]);
+
+        let params = ChunkerParams::default();
+        let original = chunks(&compress(&data, 6), params);
+        let modified = chunks(&compress(&modified, 6), params);
+
+        // only the chunks around the insertion are different
+        let common = modified.iter().filter(|chunk| original.contains(chunk)).count();
+        assert!(common + 2 >= original.len());
+    }
+
+    #[test]
+    fn short_data() {
+        let params = ChunkerParams::default();
+        assert_eq!(chunks(&compress(b"hello", 6), params), vec![b"hello".to_vec()]);
+        assert!(chunks(&compress(b"", 6), params).is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let compressed = compress(&data(0x2545f4914f6cdd1d, 10_000), 6);
+        let mut iter = ChunkedInflater::new(&compressed[.. 1000], ChunkerParams::default());
+        assert!(iter.by_ref().any(|chunk| chunk.is_err()));
+        assert!(iter.next().is_none());
+    }
+}
@@ -1,86 +1,302 @@
-use std::io::{self, Read, Cursor};
+use alloc::vec::Vec;
+use core::cmp;
+use core::iter;
+use io::{self, Read};
+#[cfg(feature = "std")]
+use std::sync::OnceLock;
 use bit::BitRead;
-use huffman::HuffmanTable;
+use error::DecodeError;
+use huffman::{CODE_LENGTH_ORDER, HuffmanTable};
+use window::Window;
 
 /// A reader that allows reading from a compressed block.
+#[derive(Clone)]
 pub struct CompressedBlockReader<R> where R: Read {
     data: BitRead<R>,
     eof: bool,
     lit_len_table: HuffmanTable<LitLenSymbol>,
-    dist_table: HuffmanTable<u8>,
+
+    /// `None` if the block doesn't define any distance code, in which case it can't contain
+    /// pointers.
+    dist_table: Option<HuffmanTable<DistSymbol>>,
+
+    /// Number of bytes remaining to copy from a previous pointer. A pointer can require more
+    /// bytes than what fits in the output buffer, in which case we continue on the next read.
+    copy_length: u16,
+
+    /// Distance of the pointer that is being copied.
+    copy_distance: u16,
+
+    /// Number of bytes written by literals and by pointers since the last call to
+    /// `take_output_counts`.
+    literal_bytes: u64,
+    match_bytes: u64,
+
+    /// Number of bytes that the last call to `read` that returned an error has written to its
+    /// buffer before the error.
+    written_before_error: usize,
+}
+
+/// A symbol of compressed data, as returned by `SymbolIter`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Symbol {
+    /// A byte that is copied to the output.
+    Literal(u8),
+
+    /// A copy of `length` bytes, starting `distance` bytes before the end of the output.
+    Match {
+        length: u16,
+        distance: u16,
+    },
+
+    /// The end of the block.
+    EndOfBlock,
 }
 
 #[derive(Debug, Copy, Clone)]
 enum LitLenSymbol {
     Byte(u8),
     Eof,
-    Pointer(u8),
+    Pointer(LengthCode),
+
+    /// Symbols 286 and 287, which can be decoded from the fixed table and from dynamic tables
+    /// but are reserved by the format.
+    Reserved,
 }
 
-impl<R> CompressedBlockReader<R> where R: Read {
-    /// Reads dynamic tables from the input stream and builds a reader for this block.
-    pub fn from_dynamic_tables(mut inner: BitRead<R>) -> io::Result<CompressedBlockReader<R>> {
-        let (lit_len_table, dist_table) = try!(read_dynamic_tables(&mut inner));
+/// Base length of a pointer, to which `extra_bits` bits read from the stream are added.
+#[derive(Debug, Copy, Clone)]
+struct LengthCode {
+    base: u16,
+    extra_bits: u8,
+}
+
+/// Base distance of a pointer, to which `extra_bits` bits read from the stream are added.
+#[derive(Debug, Copy, Clone)]
+struct DistCode {
+    base: u16,
+    extra_bits: u8,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum DistSymbol {
+    Distance(DistCode),
 
-        Ok(CompressedBlockReader {
+    /// Symbols 30 and 31, which are reserved by the format.
+    Reserved,
+}
+
+impl LitLenSymbol {
+    /// Returns the symbol corresponding to a value between 0 and 287 of the lit/len alphabet.
+    fn from_value(value: usize) -> LitLenSymbol {
+        match value {
+            n @ 0 ..= 255 => LitLenSymbol::Byte(n as u8),
+            256 => LitLenSymbol::Eof,
+            n if n - 257 < LENGTHS.len() => LitLenSymbol::Pointer(LengthCode {
+                base: LENGTHS[n - 257],
+                extra_bits: EXTRA_LENGTHS[n - 257],
+            }),
+            _ => LitLenSymbol::Reserved,
+        }
+    }
+}
+
+impl DistSymbol {
+    /// Returns the symbol corresponding to a value between 0 and 31 of the distance alphabet.
+    fn from_value(value: usize) -> DistSymbol {
+        if value < DISTANCES.len() {
+            DistSymbol::Distance(DistCode {
+                base: DISTANCES[value],
+                extra_bits: EXTRA_DISTANCES[value],
+            })
+        } else {
+            DistSymbol::Reserved
+        }
+    }
+}
+
+/// Huffman tables of the blocks with fixed codes.
+type FixedTables = (HuffmanTable<LitLenSymbol>, HuffmanTable<DistSymbol>);
+
+/// Returns the tables of the blocks with fixed codes. They are only built once, and cloned for
+/// each block, which is much faster than building them again.
+#[cfg(feature = "std")]
+fn fixed_tables() -> FixedTables {
+    static TABLES: OnceLock<FixedTables> = OnceLock::new();
+    TABLES.get_or_init(build_fixed_tables).clone()
+}
+
+/// Returns the tables of the blocks with fixed codes. Without `std`, there is no `OnceLock` to
+/// keep them, so they are built for each block.
+#[cfg(not(feature = "std"))]
+fn fixed_tables() -> FixedTables {
+    build_fixed_tables()
+}
+
+/// Builds the tables of the blocks with fixed codes, defined by the RFC.
+fn build_fixed_tables() -> FixedTables {
+    let lit_len_table = HuffmanTable::from_lengths((0usize .. 288).map(|i| {
+        let len = match i {
+            0 ..= 143 => 8,
+            144 ..= 255 => 9,
+            256 ..= 279 => 7,
+            280 ..= 287 => 8,
+            _ => unreachable!()
+        };
+
+        (LitLenSymbol::from_value(i), len)
+    }));
+
+    let dist_table = HuffmanTable::from_lengths((0 .. 32).map(|val| {
+        (DistSymbol::from_value(val), 5)
+    }));
+
+    (lit_len_table, dist_table)
+}
+
+/// Huffman tables defined at the start of a block with dynamic codes.
+pub struct DynamicTables {
+    lit_len_table: HuffmanTable<LitLenSymbol>,
+    dist_table: Option<HuffmanTable<DistSymbol>>,
+}
+
+impl DynamicTables {
+    /// Reads dynamic tables from the input stream. If `accept_incomplete` is false, the codes
+    /// must be complete, see `HuffmanTable::try_from_lengths`.
+    pub fn read<R>(inner: &mut BitRead<R>, accept_incomplete: bool) -> io::Result<DynamicTables>
+        where R: Read
+    {
+        let (lit_len_table, dist_table) = read_dynamic_tables(inner, accept_incomplete)?;
+        Ok(DynamicTables { lit_len_table, dist_table })
+    }
+}
+
+impl<R> CompressedBlockReader<R> where R: Read {
+    /// Builds a reader for this block that uses the tables read by `DynamicTables::read`.
+    pub fn from_dynamic_tables(inner: BitRead<R>, tables: DynamicTables)
+                               -> CompressedBlockReader<R>
+    {
+        CompressedBlockReader {
             data: inner,
             eof: false,
-            lit_len_table: lit_len_table,
-            dist_table: dist_table,
-        })
+            lit_len_table: tables.lit_len_table,
+            dist_table: tables.dist_table,
+            copy_length: 0,
+            copy_distance: 0,
+            literal_bytes: 0,
+            match_bytes: 0,
+            written_before_error: 0,
+        }
     }
 
     /// Builds a reader for this block that uses fixed huffman tables.
     pub fn from_fixed_tables(inner: BitRead<R>) -> CompressedBlockReader<R> {
-        let lit_len_table = HuffmanTable::from_lengths((0u32..288).map(|i| {
-            let sym = match i {
-                n @ 0 ... 255 => LitLenSymbol::Byte(n as u8),
-                256 => LitLenSymbol::Eof,
-                n => LitLenSymbol::Pointer((n - 257) as u8)
-            };
-
-            let len = match i {
-                0 ... 143 => 8,
-                144 ... 255 => 9,
-                256 ... 279 => 7,
-                280 ... 287 => 8,
-                _ => unreachable!()
-            };
-
-            (sym, len)
-        }));
-
-        let dist_table = HuffmanTable::from_lengths((0 .. 32).map(|val| (val, 5)));
+        let (lit_len_table, dist_table) = fixed_tables();
 
         CompressedBlockReader {
             data: inner,
             eof: false,
-            lit_len_table: lit_len_table,
-            dist_table: dist_table,
+            lit_len_table,
+            dist_table: Some(dist_table),
+            copy_length: 0,
+            copy_distance: 0,
+            literal_bytes: 0,
+            match_bytes: 0,
+            written_before_error: 0,
         }
     }
 
+    /// Decodes the next symbol of the block, without copying anything for the pointers, whose
+    /// distance isn't checked either. Must not be called after the end of the block.
+    ///
+    /// If the underlying reader returns `WouldBlock` or `Interrupted` before the end of the
+    /// symbol, the bits of the symbol that have been read are lost.
+    pub fn next_symbol(&mut self) -> io::Result<Symbol> {
+        // this symbol doesn't necessarly mean a byte, it can also be an EOF marker or a
+        // pointer to a previous element of the output buffer
+        let at_bit = self.data.bit_position();
+
+        match self.lit_len_table.decode(&mut self.data)? {
+            LitLenSymbol::Byte(val) => Ok(Symbol::Literal(val)),
+
+            LitLenSymbol::Eof => {
+                self.eof = true;
+                Ok(Symbol::EndOfBlock)
+            },
+
+            LitLenSymbol::Reserved => Err(DecodeError::InvalidSymbol { at_bit }.into()),
+
+            LitLenSymbol::Pointer(code) => {
+                let length = code.base + self.data.read(code.extra_bits)? as u16;
+                let dist_at_bit = self.data.bit_position();
+                let distance = match self.dist_table {
+                    Some(ref table) => table.decode(&mut self.data)?,
+                    None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                      "Pointer in a block without distance \
+                                                       codes")),
+                };
+                let distance = match distance {
+                    DistSymbol::Distance(code) => code.base + self.data.read_u16(code.extra_bits)?,
+                    DistSymbol::Reserved => {
+                        return Err(DecodeError::InvalidSymbol { at_bit: dist_at_bit }.into());
+                    },
+                };
+
+                Ok(Symbol::Match { length, distance })
+            },
+        }
+    }
+
+    /// Returns a reference to the underlying bits reader.
+    pub fn get_ref(&self) -> &BitRead<R> {
+        &self.data
+    }
+
+    /// Returns a mutable reference to the underlying bits reader.
+    pub fn get_mut(&mut self) -> &mut BitRead<R> {
+        &mut self.data
+    }
+
     /// Stops decoding and returns the underlying bits reader.
     pub fn into_inner(self) -> BitRead<R> {
         self.data
     }
 
+    /// Returns the number of bytes that the last call to `read` that returned an error has
+    /// decoded before the error. They are valid, but the rest of the buffer isn't.
+    pub fn written_before_error(&self) -> usize {
+        self.written_before_error
+    }
+
+    /// Returns the number of bytes written by literals and the number of bytes copied by
+    /// pointers since the last call, and resets them.
+    pub fn take_output_counts(&mut self) -> (u64, u64) {
+        let counts = (self.literal_bytes, self.match_bytes);
+        self.literal_bytes = 0;
+        self.match_bytes = 0;
+        counts
+    }
+
     /// Starts reading from the block. We need to pass the data previously read from the stream
-    /// in case of a pointer in the uncompressed data.
-    pub fn with_previous_data<'a>(&'a mut self, cache: &'a [u8]) -> ReadContext<'a, R> {
+    /// in case of a pointer in the uncompressed data, and the maximum distance of a pointer.
+    pub fn with_previous_data<'a, W>(&'a mut self, cache: &'a W, window_size: usize)
+                                     -> ReadContext<'a, R, W> where W: Window
+    {
         ReadContext {
             reader: self,
             data_cache: cache,
+            window_size,
         }
     }
 }
 
-pub struct ReadContext<'a, R: 'a> where R: Read {
+pub struct ReadContext<'a, R: 'a, W: 'a> where R: Read, W: Window {
     reader: &'a mut CompressedBlockReader<R>,
-    data_cache: &'a [u8],
+    data_cache: &'a W,
+    window_size: usize,
 }
 
-impl<'a, R: 'a> Read for ReadContext<'a, R> where R: Read {
+impl<'a, R: 'a, W: 'a> Read for ReadContext<'a, R, W> where R: Read, W: Window {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         if self.reader.eof {
             return Ok(0);
@@ -89,59 +305,104 @@ impl<'a, R: 'a> Read for ReadContext<'a, R> where R: Read {
         // number of bytes already written to `buf`
         let mut written = 0;
 
+        match self.decode(buf, &mut written) {
+            Ok(()) => Ok(written),
+            // the symbol that was being decoded is decoded again by the next call
+            Err(ref err) if ::is_retryable(err) && written != 0 => {
+                Ok(written)
+            },
+            Err(err) => {
+                self.reader.written_before_error = written;
+                Err(err)
+            },
+        }
+    }
+}
+
+impl<'a, R: 'a, W: 'a> ReadContext<'a, R, W> where R: Read, W: Window {
+    /// Decodes symbols into `buf[*written ..]` until it is full or the block ends, and updates
+    /// `written`.
+    fn decode(&mut self, buf: &mut [u8], written: &mut usize) -> io::Result<()> {
         loop {
-            if written == buf.len() {
-                return Ok(written);
+            if *written == buf.len() {
+                return Ok(());
             }
 
-            // reading a symbol from the input data
-            // this symbol doesn't necessarly mean a byte, it can also be an EOF marker or a
-            // pointer to a previous element of the output buffer
-            let symbol = try!(self.reader.lit_len_table.decode(&mut self.reader.data));
-
-            match symbol {
-                LitLenSymbol::Byte(val) => {
-                    // byte to copy to the output
-                    buf[written] = val;
-                    written += 1;
-                },
-
-                LitLenSymbol::Eof => {
-                    // we reached the end of the block
-                    self.reader.eof = true;
-                    return Ok(written);
-                },
-
-                LitLenSymbol::Pointer(ptr) => {
-                    // this means that we need to copy some existing data
-                    let length = LENGTHS[ptr as usize] +
-                                 try!(self.reader.data.read(EXTRA_LENGTHS[ptr as usize])) as u16;
-                    let distance = try!(self.reader.dist_table.decode(&mut self.reader.data));
-                    let distance = DISTANCES[distance as usize] +
-                                   try!(self.reader.data.read(EXTRA_DISTANCES[distance as usize]))
-                                   as u16;
-
-                    let (src, dest) = buf.split_at_mut(written);
-                    let (nb, remaining_data) = try!(read_behind(length, distance, src,
-                                                                self.data_cache, dest));
-                    assert!(remaining_data.len() == 0);     // FIXME: 
-                    written += nb;
-
-                    // FIXME: not totally implemented, there's a repeating thingy
+            // finishing a copy started by a pointer before reading the next symbol
+            if self.reader.copy_length != 0 {
+                let nb = read_behind(self.reader.copy_length, self.reader.copy_distance,
+                                     self.data_cache, buf, *written);
+                self.reader.copy_length -= nb as u16;
+                self.reader.match_bytes += nb as u64;
+                *written += nb;
+                continue;
+            }
+
+            // if the underlying reader returns a retryable error before the end of the symbol, we
+            // go back to its start so that it can be decoded again once there is more data
+            self.reader.data.checkpoint();
+            if let Err(err) = self.decode_symbol(buf, written) {
+                if ::is_retryable(&err) {
+                    self.reader.data.rollback();
                 }
+                return Err(err);
+            }
+
+            if self.reader.eof {
+                return Ok(());
             }
         }
     }
+
+    /// Decodes a symbol, and writes it to `buf[*written]` if it is a byte or prepares the copy
+    /// if it is a pointer.
+    fn decode_symbol(&mut self, buf: &mut [u8], written: &mut usize) -> io::Result<()> {
+        let at_bit = self.reader.data.bit_position();
+
+        match self.reader.next_symbol()? {
+            Symbol::Literal(val) => {
+                // byte to copy to the output
+                buf[*written] = val;
+                *written += 1;
+                self.reader.literal_bytes += 1;
+            },
+
+            // `next_symbol` has marked the end of the block
+            Symbol::EndOfBlock => (),
+
+            Symbol::Match { length, distance } => {
+                // the pointer can't go further back than what we have decoded so far, nor
+                // further than the window
+                if distance as usize > self.data_cache.len() + *written ||
+                   distance as usize > self.window_size
+                {
+                    return Err(DecodeError::InvalidBackReference { at_bit }.into());
+                }
+
+                self.reader.copy_length = length;
+                self.reader.copy_distance = distance;
+            },
+        }
+
+        Ok(())
+    }
 }
 
-fn read_dynamic_tables<R>(inner: &mut BitRead<R>)
-                          -> io::Result<(HuffmanTable<LitLenSymbol>, HuffmanTable<u8>)>
+fn read_dynamic_tables<R>(inner: &mut BitRead<R>, accept_incomplete: bool)
+                          -> io::Result<(HuffmanTable<LitLenSymbol>,
+                                         Option<HuffmanTable<DistSymbol>>)>
                           where R: Read
 {
     // the dynamic tables start with the number of elements that are following
-    let hlit = try!(inner.read(5)) as u16 + 257;
-    let hdist = try!(inner.read(5)) + 1;
-    let hclen = try!(inner.read(4)) + 4;
+    let header_start = inner.bit_position();
+    let hlit = inner.read(5)? as u16 + 257;
+    let hdist = inner.read(5)? + 1;
+    let hclen = inner.read(4)? + 4;
+
+    // the fields can describe up to 288 and 32 codes, but the last two of each are reserved
+    if hlit > 286 || hdist > 30 {
+        return Err(DecodeError::InvalidHeader { at_bit: header_start }.into());
+    }
 
     // The second and third tables are the lit/len table and the distances table. They contain
     // the lengths that we need to pass to `HuffmanTable::from_lengths`.
@@ -162,17 +423,19 @@ fn read_dynamic_tables<R>(inner: &mut BitRead<R>)
     }
 
     // However these commands are themselves encoded using a huffman table. This huffman table
-    // is the first table and we are going to read it now.
+    // is the first table and we are going to read it now. The lengths of the commands that
+    // aren't part of the `hclen` first ones in `CODE_LENGTH_ORDER` are 0. If all of them are 0,
+    // or if they are over-subscribed, `try_from_lengths` returns an error. A stream that uses a
+    // single command can give it a length of 1, which is accepted even if `accept_incomplete`
+    // is false.
     let decoding_table = {
         // This table contains the code length of each decoding command.
-        let mut decoding_codes = vec![0; 19];
-        for (_, &code) in (0 .. hclen).zip(&[16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3,
-                                             13, 2, 14, 1, 15])
-        {
-            decoding_codes[code] = try!(inner.read(3));
+        let mut decoding_codes = [0; 19];
+        for (_, &code) in (0 .. hclen).zip(&CODE_LENGTH_ORDER) {
+            decoding_codes[code] = inner.read(3)?;
         }
 
-        HuffmanTable::from_lengths(
+        HuffmanTable::try_from_lengths(
             [
                 (DecodingCommand::CodeLength(0), decoding_codes[0]),
                 (DecodingCommand::CodeLength(1), decoding_codes[1]),
@@ -193,109 +456,131 @@ fn read_dynamic_tables<R>(inner: &mut BitRead<R>)
                 (DecodingCommand::RepeatPrevious, decoding_codes[16]),
                 (DecodingCommand::RepeatZeroSmall, decoding_codes[17]),
                 (DecodingCommand::RepeatZeroLarge, decoding_codes[18]),
-            ].iter().filter(|&&(_, len)| len != 0).cloned()
-        )
+            ].iter().filter(|&&(_, len)| len != 0).cloned(),
+            accept_incomplete
+        )?
     };
 
     // Now that we have the decoding table, we can decode the two real tables with it.
-    // This is a macro that decodes a table.
-    macro_rules! decode {
-        ($inner:expr, $len:expr, $map:expr) => ({
-            let mut code = None;
-            let mut result = Vec::new();
-
-            for _ in (0 .. $len) {
-                match try!(decoding_table.decode($inner)) {
-                    DecodingCommand::CodeLength(c) => {
-                        code = Some(c);
-                        result.push(c);
-                    },
-                    DecodingCommand::RepeatPrevious => {
-                        let code = match code {
-                            Some(c) => c,
-                            None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
-                                               "Can't repeat the previous code length as there is \
-                                                none"))
-                        };
-
-                        for _ in (0 .. 3 + try!($inner.read(2))) {
-                            result.push(code);
-                        }
-                    },
-                    DecodingCommand::RepeatZeroSmall => {
-                        for _ in (0 .. 3 + try!($inner.read(3))) {
-                            result.push(0);
-                        }
-                    },
-                    DecodingCommand::RepeatZeroLarge => {
-                        for _ in (0 .. 11 + try!($inner.read(7))) {
-                            result.push(0);
-                        }
-                    },
-                }
-            }
+    // The code lengths of both tables form a single sequence, and a repeat command can overlap
+    // the end of the first table and the start of the second.
+    let total = hlit as usize + hdist as usize;
+    let mut lengths = Vec::with_capacity(total);
+
+    while lengths.len() < total {
+        let (code, repeat) = match decoding_table.decode(inner)? {
+            DecodingCommand::CodeLength(c) => (c, 1),
+            DecodingCommand::RepeatPrevious => {
+                let code = match lengths.last() {
+                    Some(&c) => c,
+                    None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                      "Can't repeat the previous code length as \
+                                                       there is none"))
+                };
+
+                (code, 3 + inner.read(2)? as usize)
+            },
+            DecodingCommand::RepeatZeroSmall => (0, 3 + inner.read(3)? as usize),
+            DecodingCommand::RepeatZeroLarge => (0, 11 + inner.read(7)? as usize),
+        };
+
+        lengths.extend(iter::repeat_n(code, repeat));
+    }
 
-            HuffmanTable::from_lengths(result.into_iter().filter(|&l| l != 0).enumerate()
-                                             .map(|(num, len)| ($map(num), len)))
-        })
+    if lengths.len() != total {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "Code lengths repetition overflows the tables"));
     }
 
-    let lit_len_table = decode!(inner, hlit, |num: usize| {
-        match num {
-            n @ 0 ... 255 => LitLenSymbol::Byte(n as u8),
-            256 => LitLenSymbol::Eof,
-            n => LitLenSymbol::Pointer((n - 257) as u8)
-        }
-    });
+    let dist_lengths = lengths.split_off(hlit as usize);
 
-    let dist_table = decode!(inner, hdist, |n: usize| n as u8);
+    if lengths[256] == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "Missing end of block code in dynamic tables"));
+    }
+
+    let lit_len_table = HuffmanTable::try_from_lengths(lengths.into_iter().enumerate()
+                                                          .filter(|&(_, len)| len != 0)
+                                                          .map(|(num, len)| {
+        (LitLenSymbol::from_value(num), len)
+    }), accept_incomplete)?;
+
+    // A block that only contains literals can have a single distance code, or even none at
+    // all if all the distance code lengths are 0. In the latter case no pointer can be decoded.
+    let dist_table = if dist_lengths.iter().all(|&len| len == 0) {
+        None
+    } else {
+        Some(HuffmanTable::try_from_lengths(dist_lengths.into_iter().enumerate()
+                                                        .filter(|&(_, len)| len != 0)
+                                                        .map(|(n, len)| {
+                                                            (DistSymbol::from_value(n), len)
+                                                        }), accept_incomplete)?)
+    };
 
     Ok((lit_len_table, dist_table))
 }
 
-/// Reads from the previous data into the destination.
+/// Copies `length` bytes starting `distance` bytes behind position `written` of `buf` to
+/// `buf[written..]`. The first `written` bytes of `buf` are considered to directly follow
+/// the data of `previous_cache`.
 ///
-/// Returns the size that was written in `dest`, plus any remaining data.
-fn read_behind(length: u16, distance: u16, immediate_cache: &[u8], previous_cache: &[u8],
-               dest: &mut [u8]) -> io::Result<(usize, Vec<u8>)>
+/// Returns the number of bytes that were written, which is inferior to `length` if there is not
+/// enough space in `buf`. In that case the rest of the data must be read by calling this function
+/// again once `buf` has been processed. The distance must have been checked against the
+/// available data beforehand.
+fn read_behind<W>(length: u16, distance: u16, previous_cache: &W, buf: &mut [u8],
+                  written: usize) -> usize where W: Window
 {
-    let mut written = 0;
-
-    // building an iterator of the input data
-    // FIXME: check overflow
-    let reader = Cursor::new(previous_cache).chain(Cursor::new(immediate_cache));
-    let mut reader = reader.bytes()
-                           .skip(previous_cache.len() + immediate_cache.len() - distance as usize)
-                           .take(length as usize)
-                           .map(|b| b.unwrap());
-
-    for (src, dest) in reader.by_ref().zip(dest.iter_mut()) {
-        *dest = src;
-        written += 1;
+    let distance = distance as usize;
+    let length = cmp::min(length as usize, buf.len() - written);
+    debug_assert!(distance <= previous_cache.len() + written);
+
+    let mut dest = written;
+    let end = written + length;
+
+    // the start of the data to copy can be in `previous_cache`
+    if distance > written {
+        let len = cmp::min(length, distance - written);
+        previous_cache.copy_back(distance - written, &mut buf[dest .. dest + len]);
+        dest += len;
+    }
+
+    // the rest of the data is in `buf`
+    if dest == end {
+        return length;
+    }
+
+    if end - dest <= distance {
+        buf.copy_within(dest - distance .. end - distance, dest);
+    } else {
+        // the source and destination overlap, which repeats the last `distance` bytes
+        for n in dest .. end {
+            buf[n] = buf[n - distance];
+        }
     }
 
-    Ok((written, reader.collect()))
+    length
 }
 
-const LENGTHS: [u16; 29] = [
+pub const LENGTHS: [u16; 29] = [
     3,  4,  5,   6,   7,   8,   9,  10,  11, 13,
     15, 17, 19,  23,  27,  31,  35,  43,  51, 59,
     67, 83, 99, 115, 131, 163, 195, 227, 258
 ];
 
-const EXTRA_LENGTHS: [u8; 29] = [
+pub const EXTRA_LENGTHS: [u8; 29] = [
     0, 0, 0, 0, 0, 0, 0, 0, 1, 1,
     1, 1, 2, 2, 2, 2, 3, 3, 3, 3,
     4, 4, 4, 4, 5, 5, 5, 5, 0
 ];
 
-const DISTANCES: [u16; 30] = [
+pub const DISTANCES: [u16; 30] = [
     1,    2,      3,    4,    5,    7,    9,    13,    17,    25,
     33,   49,     65,   97,  129,  193,  257,   385,   513,   769,
     1025,  1537,  2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577
 ];
 
-const EXTRA_DISTANCES: [u8; 30] = [
+pub const EXTRA_DISTANCES: [u8; 30] = [
     0, 0,  0,  0,  1,  1,  2,  2,  3,  3,
     4, 4,  5,  5,  6,  6,  7,  7,  8,  8,
     9, 9, 10, 10, 11, 11, 12, 12, 13, 13
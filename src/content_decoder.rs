@@ -0,0 +1,113 @@
+//! Decoding of HTTP bodies according to their `Content-Encoding`.
+
+use io::{ErrorKind, Read};
+use io::Error as IoError;
+use auto_decoder::AutoDecoder;
+use gzip_decoder::GzipDecoder;
+
+/// A reader that decodes the body of an HTTP message whose `Content-Encoding` is `gzip`,
+/// `deflate` or `identity`.
+///
+/// The `deflate` encoding is supposed to designate zlib data, but some servers send raw DEFLATE
+/// data instead. Like browsers, the data is decoded as zlib data if its first two bytes form a
+/// valid zlib header, and as raw DEFLATE data otherwise. See `AutoDecoder`, which implements
+/// this heuristic and also accepts gzip data.
+pub struct ContentDecoder<R> where R: Read {
+    inner: ContentDecoderInner<R>,
+}
+
+enum ContentDecoderInner<R> where R: Read {
+    Gzip(GzipDecoder<R>),
+    Deflate(AutoDecoder<R>),
+    Identity(R),
+}
+
+impl<R> ContentDecoder<R> where R: Read {
+    /// Builds a decoder for the given value of the `Content-Encoding` header by taking
+    /// ownership of a reader where the body will be read from.
+    ///
+    /// The name isn't case sensitive, and `x-gzip` is the same as `gzip`. Other encodings, and
+    /// lists of several encodings, return an error of kind `InvalidInput`.
+    pub fn new(encoding: &str, reader: R) -> Result<ContentDecoder<R>, IoError> {
+        let encoding = encoding.trim();
+        let inner = if encoding.eq_ignore_ascii_case("gzip") ||
+                       encoding.eq_ignore_ascii_case("x-gzip")
+        {
+            ContentDecoderInner::Gzip(GzipDecoder::new(reader))
+        } else if encoding.eq_ignore_ascii_case("deflate") {
+            ContentDecoderInner::Deflate(AutoDecoder::new(reader))
+        } else if encoding.eq_ignore_ascii_case("identity") {
+            ContentDecoderInner::Identity(reader)
+        } else {
+            return Err(IoError::new(ErrorKind::InvalidInput, "Unsupported content encoding"));
+        };
+
+        Ok(ContentDecoder { inner })
+    }
+}
+
+impl<R> Read for ContentDecoder<R> where R: Read {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        match self.inner {
+            ContentDecoderInner::Gzip(ref mut decoder) => decoder.read(buf),
+            ContentDecoderInner::Deflate(ref mut decoder) => decoder.read(buf),
+            ContentDecoderInner::Identity(ref mut reader) => reader.read(buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ContentDecoder;
+    use std::io::{ErrorKind, Read};
+    use LOREM_IPSUM as DATA;
+
+    fn decode(encoding: &str, data: &[u8]) -> Vec<u8> {
+        let mut decoder = ContentDecoder::new(encoding, data).unwrap();
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn gzip() {
+        let compressed = ::compress_gzip(DATA);
+
+        assert_eq!(decode("gzip", &compressed), DATA);
+        assert_eq!(decode("x-gzip", &compressed), DATA);
+        assert_eq!(decode(" GZip ", &compressed), DATA);
+
+        // this is not gzip data
+        assert!(ContentDecoder::new("gzip", DATA).unwrap().read_to_end(&mut Vec::new())
+                                                          .is_err());
+    }
+
+    #[test]
+    fn deflate() {
+        let compressed = ::compress_zlib(DATA, 6);
+
+        assert_eq!(decode("deflate", &compressed), DATA);
+        assert_eq!(decode("Deflate", &compressed), DATA);
+    }
+
+    #[test]
+    fn raw_deflate() {
+        let compressed = ::deflate(DATA, 6);
+
+        assert_eq!(decode("deflate", &compressed), DATA);
+    }
+
+    #[test]
+    fn identity() {
+        assert_eq!(decode("identity", DATA), DATA);
+        assert_eq!(decode("identity", b""), b"");
+    }
+
+    #[test]
+    fn unsupported() {
+        for encoding in &["br", "compress", "gzip, deflate", ""] {
+            let err = ContentDecoder::new(encoding, DATA).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+    }
+}
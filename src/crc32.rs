@@ -0,0 +1,303 @@
+//! Implementation of the CRC-32 checksum used by gzip.
+
+use core::hash::Hasher;
+
+/// Reversed representation of the CRC-32 polynomial.
+const POLYNOMIAL: u32 = 0xedb88320;
+
+/// Precomputed value of the CRC for each possible byte.
+const TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { POLYNOMIAL ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+    table
+}
+
+/// An implementation of the CRC-32 checksum.
+#[derive(Clone, Copy)]
+pub struct Crc32 {
+    // The value of the CRC, before the final inversion.
+    state: u32,
+}
+
+impl Crc32 {
+    /// Create a new hasher.
+    pub fn new() -> Crc32 {
+        Crc32 { state: !0 }
+    }
+
+    /// Update the internal hasher with the bytes from `buf`.
+    pub fn feed(&mut self, buf: &[u8]) {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        {
+            if buf.len() >= 64 && is_x86_feature_detected!("pclmulqdq") &&
+               is_x86_feature_detected!("sse4.1")
+            {
+                let (blocks, remaining) = buf.split_at(buf.len() - buf.len() % 16);
+                self.state = unsafe { simd::update(self.state, blocks) };
+                self.feed_scalar(remaining);
+                return;
+            }
+        }
+
+        self.feed_scalar(buf);
+    }
+
+    /// Portable implementation of `feed`.
+    fn feed_scalar(&mut self, buf: &[u8]) {
+        for &byte in buf {
+            self.state = TABLE[((self.state ^ byte as u32) & 0xff) as usize] ^ (self.state >> 8);
+        }
+    }
+
+    /// Return the computed hash.
+    pub fn checksum(self) -> u32 {
+        !self.state
+    }
+
+    /// Returns the hasher that would be obtained by feeding the data fed to `self`, followed by
+    /// the data fed to `other`, whose length is `other_len`.
+    pub fn combine(self, other: Crc32, other_len: u64) -> Crc32 {
+        // appending `other_len` bytes multiplies the CRC of the first data by `x^(8 * other_len)`
+        // modulo the polynomial, and the CRC of the second data is added to the result
+        let checksum = multiply_mod(x_pow_8n_mod(other_len), self.checksum()) ^ other.checksum();
+        Crc32 { state: !checksum }
+    }
+}
+
+/// Multiplies two polynomials modulo the CRC-32 polynomial, in the reversed representation where
+/// the most significant bit is the coefficient of `x^0`.
+fn multiply_mod(a: u32, mut b: u32) -> u32 {
+    let mut product = 0;
+    let mut bit = 1 << 31;
+
+    while bit != 0 {
+        if a & bit != 0 {
+            product ^= b;
+        }
+
+        // multiplying `b` by `x`
+        b = if b & 1 != 0 { POLYNOMIAL ^ (b >> 1) } else { b >> 1 };
+        bit >>= 1;
+    }
+
+    product
+}
+
+/// Returns `x^(8 * n)` modulo the CRC-32 polynomial.
+fn x_pow_8n_mod(mut n: u64) -> u32 {
+    // `x^8`, which is squared for each bit of `n`
+    let mut power = 1 << (31 - 8);
+    let mut result = 1 << 31;
+
+    while n != 0 {
+        if n & 1 != 0 {
+            result = multiply_mod(power, result);
+        }
+
+        power = multiply_mod(power, power);
+        n >>= 1;
+    }
+
+    result
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32::new()
+    }
+}
+
+/// Feeds the data written to the hasher with `feed`. `finish` returns `checksum`.
+impl Hasher for Crc32 {
+    fn write(&mut self, bytes: &[u8]) {
+        self.feed(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        self.checksum() as u64
+    }
+}
+
+/// Implementation of `feed` that uses carry-less multiplications.
+///
+/// The data is folded 64 bytes at a time into four 128-bits accumulators, which are then folded
+/// into a single one and reduced to 32 bits with a Barrett reduction. See Intel's "Fast CRC
+/// Computation for Generic Polynomials Using PCLMULQDQ Instruction" for the constants.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd {
+    use std::arch::x86_64::*;
+
+    static K1K2: [u64; 2] = [0x0154442bd4, 0x01c6e41596];
+    static K3K4: [u64; 2] = [0x01751997d0, 0x00ccaa009e];
+    static K5K0: [u64; 2] = [0x0163cd6124, 0x0000000000];
+    static POLY: [u64; 2] = [0x01db710641, 0x01f7011641];
+
+    /// Updates the non-inverted `crc` with `buf`, whose length must be a multiple of 16 and at
+    /// least 64.
+    #[target_feature(enable = "pclmulqdq", enable = "sse4.1")]
+    pub unsafe fn update(crc: u32, buf: &[u8]) -> u32 {
+        debug_assert!(buf.len() >= 64 && buf.len().is_multiple_of(16));
+
+        let mut ptr = buf.as_ptr() as *const __m128i;
+        let mut len = buf.len();
+
+        let mut x1 = _mm_loadu_si128(ptr);
+        let mut x2 = _mm_loadu_si128(ptr.add(1));
+        let mut x3 = _mm_loadu_si128(ptr.add(2));
+        let mut x4 = _mm_loadu_si128(ptr.add(3));
+        x1 = _mm_xor_si128(x1, _mm_cvtsi32_si128(crc as i32));
+        let mut x0 = _mm_loadu_si128(K1K2.as_ptr() as *const __m128i);
+        ptr = ptr.add(4);
+        len -= 64;
+
+        // folding 64 bytes at a time
+        while len >= 64 {
+            let x5 = _mm_clmulepi64_si128(x1, x0, 0x00);
+            let x6 = _mm_clmulepi64_si128(x2, x0, 0x00);
+            let x7 = _mm_clmulepi64_si128(x3, x0, 0x00);
+            let x8 = _mm_clmulepi64_si128(x4, x0, 0x00);
+
+            x1 = _mm_clmulepi64_si128(x1, x0, 0x11);
+            x2 = _mm_clmulepi64_si128(x2, x0, 0x11);
+            x3 = _mm_clmulepi64_si128(x3, x0, 0x11);
+            x4 = _mm_clmulepi64_si128(x4, x0, 0x11);
+
+            x1 = _mm_xor_si128(_mm_xor_si128(x1, x5), _mm_loadu_si128(ptr));
+            x2 = _mm_xor_si128(_mm_xor_si128(x2, x6), _mm_loadu_si128(ptr.add(1)));
+            x3 = _mm_xor_si128(_mm_xor_si128(x3, x7), _mm_loadu_si128(ptr.add(2)));
+            x4 = _mm_xor_si128(_mm_xor_si128(x4, x8), _mm_loadu_si128(ptr.add(3)));
+
+            ptr = ptr.add(4);
+            len -= 64;
+        }
+
+        // folding the four accumulators into one
+        x0 = _mm_loadu_si128(K3K4.as_ptr() as *const __m128i);
+        for &next in &[x2, x3, x4] {
+            let x5 = _mm_clmulepi64_si128(x1, x0, 0x00);
+            x1 = _mm_clmulepi64_si128(x1, x0, 0x11);
+            x1 = _mm_xor_si128(_mm_xor_si128(x1, next), x5);
+        }
+
+        // folding the remaining 16 bytes blocks
+        while len >= 16 {
+            let x5 = _mm_clmulepi64_si128(x1, x0, 0x00);
+            x1 = _mm_clmulepi64_si128(x1, x0, 0x11);
+            x1 = _mm_xor_si128(_mm_xor_si128(x1, _mm_loadu_si128(ptr)), x5);
+            ptr = ptr.add(1);
+            len -= 16;
+        }
+
+        // folding 128 bits into 64 bits
+        let mask = _mm_setr_epi32(!0, 0, !0, 0);
+        x2 = _mm_clmulepi64_si128(x1, x0, 0x10);
+        x1 = _mm_xor_si128(_mm_srli_si128(x1, 8), x2);
+        x0 = _mm_loadl_epi64(K5K0.as_ptr() as *const __m128i);
+        x2 = _mm_srli_si128(x1, 4);
+        x1 = _mm_clmulepi64_si128(_mm_and_si128(x1, mask), x0, 0x00);
+        x1 = _mm_xor_si128(x1, x2);
+
+        // Barrett reduction to 32 bits
+        x0 = _mm_loadu_si128(POLY.as_ptr() as *const __m128i);
+        x2 = _mm_clmulepi64_si128(_mm_and_si128(x1, mask), x0, 0x10);
+        x2 = _mm_clmulepi64_si128(_mm_and_si128(x2, mask), x0, 0x00);
+        x1 = _mm_xor_si128(x1, x2);
+
+        _mm_extract_epi32(x1, 1) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Crc32;
+    use std::hash::{Hash, Hasher};
+
+    #[test]
+    fn check_value() {
+        let mut hasher = Crc32::new();
+        hasher.feed(b"123456789");
+        assert_eq!(hasher.checksum(), 0xcbf43926);
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(Crc32::new().checksum(), 0);
+    }
+
+    #[test]
+    fn combine() {
+        let data = (0 .. 200000).map(|n: u32| (n.wrapping_mul(2654435761) >> 24) as u8)
+                                .collect::<Vec<_>>();
+
+        for &split in &[0, 1, 9, 64, 65536, 100001, data.len()] {
+            let mut expected = Crc32::new();
+            expected.feed(&data);
+
+            let (first, second) = data.split_at(split);
+            let mut first_hasher = Crc32::new();
+            first_hasher.feed(first);
+            let mut second_hasher = Crc32::new();
+            second_hasher.feed(second);
+
+            let combined = first_hasher.combine(second_hasher, second.len() as u64);
+            assert_eq!(combined.checksum(), expected.checksum());
+        }
+
+        let mut hasher = Crc32::new();
+        hasher.feed(b"123456789");
+        assert_eq!(hasher.combine(Crc32::new(), 0).checksum(), 0xcbf43926);
+        assert_eq!(Crc32::new().combine(hasher, 9).checksum(), 0xcbf43926);
+    }
+
+    #[test]
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    fn simd_matches_scalar() {
+        let mut seed = 0x2545f491u32;
+        let data = (0 .. 1024 * 1024 + 13).map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as u8
+        }).collect::<Vec<_>>();
+
+        for &len in &[0, 1, 63, 64, 65, 80, 127, 128, 1000, 65536, data.len()] {
+            let mut scalar = Crc32::new();
+            scalar.feed_scalar(&data[..len]);
+
+            let mut dispatched = Crc32::new();
+            dispatched.feed(&data[..len]);
+
+            assert_eq!(dispatched.checksum(), scalar.checksum());
+        }
+    }
+
+    #[test]
+    fn hasher() {
+        let mut hasher = Crc32::new();
+        "hello world".hash(&mut hasher);
+
+        // `str` writes its bytes followed by `0xff`
+        let mut expected = Crc32::new();
+        expected.feed(b"hello world\xff");
+        assert_eq!(hasher.finish(), expected.checksum() as u64);
+
+        let mut hasher = Crc32::new();
+        hasher.write(b"hello ");
+        hasher.write(b"world");
+        let mut expected = Crc32::new();
+        expected.feed(b"hello world");
+        assert_eq!(hasher.finish(), expected.checksum() as u64);
+    }
+}
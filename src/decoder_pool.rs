@@ -0,0 +1,133 @@
+use std::io::{Read, Result as IoResult};
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+use zlib_decoder::ZlibDecoder;
+
+/// Hands out zlib decoders that reuse the buffers of the decoders that have been dropped.
+///
+/// Each decoder needs a buffer of at least 64 kiB to store the window of decoded data that
+/// back-references can point to. Decoding many small payloads with `ZlibDecoder::new` allocates
+/// and frees such a buffer every time, while the decoders returned by `get` take one from the
+/// pool and put it back when they are dropped.
+///
+/// The pool can be shared between threads. It keeps as many buffers as there were decoders
+/// alive at the same time.
+pub struct DecoderPool {
+    /// Buffers that aren't used by any decoder.
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+/// A zlib decoder returned by `DecoderPool::get`. Its buffer goes back to the pool when it is
+/// dropped, unless `read` has returned an error.
+pub struct PooledDecoder<'a, R> where R: Read {
+    decoder: ZlibDecoder<R>,
+    pool: &'a DecoderPool,
+}
+
+impl DecoderPool {
+    /// Builds an empty pool.
+    pub fn new() -> DecoderPool {
+        DecoderPool {
+            buffers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns a decoder that reads zlib data from `reader`, reusing a buffer of the pool if
+    /// there is one.
+    pub fn get<R>(&self, reader: R) -> PooledDecoder<'_, R> where R: Read {
+        let buffer = self.buffers.lock().ok().and_then(|mut buffers| buffers.pop());
+        let decoder = match buffer {
+            Some(buffer) => ZlibDecoder::with_output_cache(reader, buffer),
+            None => ZlibDecoder::new(reader),
+        };
+
+        PooledDecoder {
+            decoder,
+            pool: self,
+        }
+    }
+
+    /// Returns the number of buffers that aren't used by any decoder.
+    pub fn available(&self) -> usize {
+        self.buffers.lock().map(|buffers| buffers.len()).unwrap_or(0)
+    }
+}
+
+impl Default for DecoderPool {
+    fn default() -> DecoderPool {
+        DecoderPool::new()
+    }
+}
+
+impl<'a, R> Deref for PooledDecoder<'a, R> where R: Read {
+    type Target = ZlibDecoder<R>;
+
+    fn deref(&self) -> &ZlibDecoder<R> {
+        &self.decoder
+    }
+}
+
+impl<'a, R> DerefMut for PooledDecoder<'a, R> where R: Read {
+    fn deref_mut(&mut self) -> &mut ZlibDecoder<R> {
+        &mut self.decoder
+    }
+}
+
+impl<'a, R> Read for PooledDecoder<'a, R> where R: Read {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.decoder.read(buf)
+    }
+}
+
+impl<'a, R> Drop for PooledDecoder<'a, R> where R: Read {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.decoder.take_output_cache() {
+            if let Ok(mut buffers) = self.pool.buffers.lock() {
+                buffers.push(buffer);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DecoderPool;
+    use std::io::Read;
+
+    // `hello world` compressed with zlib
+    const HELLO_WORLD: &[u8] = &[0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf,
+                                 0x2f, 0xca, 0x49, 0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+
+    #[test]
+    fn reuse() {
+        let pool = DecoderPool::new();
+        assert_eq!(pool.available(), 0);
+
+        for _ in 0 .. 3 {
+            let mut output = Vec::new();
+            pool.get(HELLO_WORLD).read_to_end(&mut output).unwrap();
+            assert_eq!(output, b"hello world");
+            assert_eq!(pool.available(), 1);
+        }
+
+        let first = pool.get(HELLO_WORLD);
+        let second = pool.get(HELLO_WORLD);
+        assert_eq!(pool.available(), 0);
+        drop((first, second));
+
+        // the second decoder hasn't allocated anything
+        assert_eq!(pool.available(), 1);
+    }
+
+    #[test]
+    fn error() {
+        let pool = DecoderPool::new();
+        let mut decoder = pool.get(&HELLO_WORLD[.. 10]);
+        assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+        drop(decoder);
+
+        let mut output = Vec::new();
+        pool.get(HELLO_WORLD).read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+    }
+}
@@ -0,0 +1,145 @@
+//! Interface shared by the decoders of the different framings.
+
+use io::Read;
+
+use gzip_decoder::GzipDecoder;
+use inflate::Inflater;
+use window::Window;
+use zlib_decoder::ZlibDecoder;
+
+/// A reader that decodes compressed data from an underlying reader.
+///
+/// Implemented by `Inflater`, `ZlibDecoder` and `GzipDecoder`, so that code can accept any of
+/// them, for example as a `&mut dyn Decompressor<Reader = R>`.
+pub trait Decompressor: Read {
+    /// Type of the underlying reader.
+    type Reader;
+
+    /// Returns true if all the data has been decoded, which is the case once `read` has
+    /// returned 0.
+    fn is_finished(&self) -> bool;
+
+    /// Returns the number of bytes read from the underlying reader so far.
+    fn total_in(&self) -> u64;
+
+    /// Returns the number of bytes decoded so far.
+    fn total_out(&self) -> u64;
+
+    /// Destroys the decoder and returns the underlying reader.
+    ///
+    /// # Panic
+    ///
+    /// Panics if a previous call to `read` returned an error.
+    ///
+    fn into_inner(self) -> Self::Reader where Self: Sized;
+}
+
+impl<R, W> Decompressor for Inflater<R, W> where R: Read, W: Window {
+    type Reader = R;
+
+    fn is_finished(&self) -> bool {
+        Inflater::is_finished(self)
+    }
+
+    fn total_in(&self) -> u64 {
+        Inflater::total_in(self)
+    }
+
+    fn total_out(&self) -> u64 {
+        Inflater::total_out(self)
+    }
+
+    fn into_inner(self) -> R {
+        Inflater::into_inner(self)
+    }
+}
+
+impl<R> Decompressor for ZlibDecoder<R> where R: Read {
+    type Reader = R;
+
+    fn is_finished(&self) -> bool {
+        ZlibDecoder::is_finished(self)
+    }
+
+    fn total_in(&self) -> u64 {
+        ZlibDecoder::total_in(self)
+    }
+
+    fn total_out(&self) -> u64 {
+        ZlibDecoder::total_out(self)
+    }
+
+    fn into_inner(self) -> R {
+        ZlibDecoder::into_inner(self)
+    }
+}
+
+impl<R> Decompressor for GzipDecoder<R> where R: Read {
+    type Reader = R;
+
+    fn is_finished(&self) -> bool {
+        GzipDecoder::is_finished(self)
+    }
+
+    fn total_in(&self) -> u64 {
+        GzipDecoder::total_in(self)
+    }
+
+    fn total_out(&self) -> u64 {
+        GzipDecoder::total_out(self)
+    }
+
+    fn into_inner(self) -> R {
+        GzipDecoder::into_inner(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Decompressor;
+    use std::io::Read;
+    use gzip_decoder::GzipDecoder;
+    use inflate::Inflater;
+    use zlib_decoder::ZlibDecoder;
+    use LOREM_IPSUM as DATA;
+
+    // decodes everything and returns what is left in the reader
+    fn finish<'a, D>(mut decoder: D) -> &'a [u8] where D: Decompressor<Reader = &'a [u8]> {
+        decoder.read_to_end(&mut Vec::new()).unwrap();
+        decoder.into_inner()
+    }
+
+    #[test]
+    fn boxed() {
+        let raw = ::deflate(DATA, 6);
+        let zlib = ::compress_zlib(DATA, 6);
+        let gzip = ::compress_gzip(DATA);
+
+        let mut decoders: Vec<Box<dyn Decompressor<Reader = &[u8]>>> = vec![
+            Box::new(Inflater::new(&raw[..])),
+            Box::new(ZlibDecoder::new(&zlib[..])),
+            Box::new(GzipDecoder::new(&gzip[..])),
+        ];
+
+        for (decoder, len) in decoders.iter_mut().zip(&[raw.len(), zlib.len(), gzip.len()]) {
+            assert!(!decoder.is_finished());
+            assert_eq!((decoder.total_in(), decoder.total_out()), (0, 0));
+
+            let mut output = Vec::new();
+            decoder.read_to_end(&mut output).unwrap();
+            assert_eq!(output, DATA);
+            assert!(decoder.is_finished());
+            assert_eq!(decoder.total_in(), *len as u64);
+            assert_eq!(decoder.total_out(), DATA.len() as u64);
+        }
+    }
+
+    #[test]
+    fn into_inner() {
+        let mut zlib = ::compress_zlib(DATA, 6);
+        zlib.extend_from_slice(b"next");
+
+        assert_eq!(finish(ZlibDecoder::new(&zlib[..])), b"next");
+        assert_eq!(finish(Inflater::new(&[0x03, 0x00, b'n', b'e', b'x', b't'][..])), b"next");
+    }
+}
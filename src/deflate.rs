@@ -0,0 +1,1000 @@
+//! An Implementation of RFC 1951 compression.
+
+use alloc::vec::Vec;
+use core::cmp;
+use io::{self, Write};
+
+use bit::BitWrite;
+use compressed_block_reader::{LENGTHS, EXTRA_LENGTHS, DISTANCES, EXTRA_DISTANCES};
+use huffman::HuffmanEncoder;
+use lz77::{self, MatchFinder, Token, WINDOW_SIZE};
+use optimal;
+
+/// Maximum number of bytes in a stored block.
+const MAX_STORED_BLOCK_LEN: usize = 65535;
+
+/// Number of bytes of input that are compressed in a single compressed block.
+const COMPRESSED_BLOCK_LEN: usize = 65536;
+
+/// Minimum length of the matches of the `Filtered` strategy, the same as zlib.
+const FILTERED_MIN_MATCH: usize = 6;
+
+/// Compression level used when the user doesn't have any preference.
+pub const DEFAULT_LEVEL: u32 = 6;
+
+/// Parameters of the match finder for each compression level: maximum length of the hash
+/// chains to examine, length of a match that is good enough to stop searching, length below
+/// which lazy matching is performed (`0` for greedy matching), and length of a match above which
+/// the lazy search is shortened.
+const LEVELS: [(usize, usize, usize, usize); 9] = [
+    (4, 8, 0, 0),
+    (8, 16, 0, 0),
+    (16, 16, 4, 4),
+    (32, 32, 4, 4),
+    (64, 64, 16, 16),
+    (128, 128, 16, 8),
+    (256, 128, 32, 8),
+    (1024, 258, 128, 32),
+    (4096, 258, 258, 32),
+];
+
+/// Writes DEFLATE data to an underlying writer.
+///
+/// The data written to the deflater is buffered and written to the underlying writer as blocks.
+/// `finish` must be called once all the data has been written in order to write the last block.
+pub struct Deflater<W> where W: Write {
+    /// The writer where the compressed data is written.
+    output: BitWrite<W>,
+
+    /// Contains the data that has already been compressed and that can be referenced by
+    /// back-references, followed by the data that has been written by the user but not
+    /// compressed yet.
+    window: Vec<u8>,
+
+    /// Number of bytes at the start of `window` that have already been compressed.
+    history_len: usize,
+
+    /// Position in the stream of the first byte of `window`.
+    window_start: usize,
+
+    /// Used to find back-references. `None` if the data is not compressed.
+    match_finder: Option<MatchFinder>,
+
+    /// How the back-references are chosen. `Greedy`, `Lazy` and `Filtered` only differ by the
+    /// parameters of `match_finder`, and `Fixed` by the type of the blocks.
+    strategy: DeflateStrategy,
+}
+
+/// Builds a `Deflater` with a finer control of the match finder than the compression level.
+#[derive(Debug, Clone)]
+pub struct DeflaterBuilder {
+    level: u32,
+    strategy: Option<DeflateStrategy>,
+    max_chain_length: Option<usize>,
+    nice_match: Option<usize>,
+}
+
+/// How the compressor chooses the back-references among the matches that it finds. See
+/// `DeflaterBuilder::strategy`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeflateStrategy {
+    /// Always uses the longest match found at the current position. This is the fastest
+    /// strategy, used by levels 1 and 2.
+    Greedy,
+
+    /// Writes a literal instead of a match if the next position starts a longer match, like
+    /// zlib does. Used by levels 3 to 9.
+    Lazy,
+
+    /// Finds all the matches of a block first, then chooses the back-references that give the
+    /// smallest block according to the Huffman codes of a first attempt. This is several times
+    /// slower than `Lazy`, for an output that is usually a few percents smaller.
+    Optimal,
+
+    /// Only uses back-references to the previous byte, which encode runs of the same byte, like
+    /// zlib's `Z_RLE`. This is about as fast as storing the data, and close to `Lazy` for
+    /// images, such as the scanlines of PNG files once filtered.
+    Rle,
+
+    /// Same as `Lazy`, but matches shorter than 6 bytes are written as literals, like zlib's
+    /// `Z_FILTERED`. This is better for data that is mostly small values with a somewhat
+    /// random distribution, such as filtered PNG scanlines, whose short matches are mostly
+    /// coincidences that cost more than the literals.
+    Filtered,
+
+    /// Chooses the back-references like the default strategy of the level, but always writes
+    /// blocks compressed with the fixed huffman codes of the RFC, like zlib's `Z_FIXED`. This
+    /// avoids the cost of computing and writing the huffman codes of each block, which is
+    /// significant for small messages.
+    Fixed,
+}
+
+impl<W> Deflater<W> where W: Write {
+    /// Initializes a new deflater with a compression level between 0 and 9.
+    ///
+    /// Level 0 doesn't compress the data at all and only produces stored blocks. Higher levels
+    /// spend more time looking for back-references in the previous data, which gives a smaller
+    /// output but is slower. Levels 1 and 2 always use the longest match that is found at the
+    /// current position, while levels 3 to 9 also check whether the next byte starts a longer
+    /// match. `6` (`DEFAULT_LEVEL`) is a good compromise.
+    ///
+    /// Each compressed block uses huffman codes computed from its content, unless the fixed
+    /// huffman codes of the RFC or no compression at all give a smaller result.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `level` is superior to 9.
+    ///
+    pub fn new(inner: W, level: u32) -> Deflater<W> {
+        Deflater::with_output(BitWrite::new(inner), level)
+    }
+
+    /// Same as `new`, but `header` is written to the underlying writer before the compressed
+    /// data. Used by the formats that wrap DEFLATE data.
+    pub(crate) fn with_header(inner: W, level: u32, header: &[u8]) -> Deflater<W> {
+        Deflater::with_output(BitWrite::with_prefix(inner, header), level)
+    }
+
+    fn with_output(output: BitWrite<W>, level: u32) -> Deflater<W> {
+        Deflater::builder().level(level).build_with_output(output)
+    }
+
+    /// Makes the compressed data able to reference `dictionary` as if it had been written just
+    /// before the data. Only the last 32kiB of the dictionary are used.
+    ///
+    /// # Panic
+    ///
+    /// Panics if some data has already been written.
+    ///
+    pub(crate) fn set_dictionary(&mut self, dictionary: &[u8]) {
+        assert!(self.window.is_empty() && self.window_start == 0);
+
+        let start = dictionary.len().saturating_sub(WINDOW_SIZE);
+        self.window.extend_from_slice(&dictionary[start ..]);
+        self.history_len = self.window.len();
+        self.window_start = start;
+
+        if let Some(ref mut match_finder) = self.match_finder {
+            match_finder.insert_range(&self.window, start, start, dictionary.len());
+        }
+    }
+
+    /// Writes the remaining data as the last block and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let len = self.window.len() - self.history_len;
+        self.write_blocks(len, true)?;
+        let mut inner = self.output.into_inner()?;
+        inner.flush()?;
+        Ok(inner)
+    }
+
+    /// Maximum number of bytes to put in a single block.
+    fn block_len(&self) -> usize {
+        if self.match_finder.is_some() {
+            COMPRESSED_BLOCK_LEN
+        } else {
+            MAX_STORED_BLOCK_LEN
+        }
+    }
+
+    /// Encodes the first `len` pending bytes as one or more blocks. If `last` is true, the last
+    /// of these blocks has its `bfinal` bit set. `len` can be 0, in which case an empty block
+    /// is written.
+    fn write_blocks(&mut self, mut len: usize, last: bool) -> io::Result<()> {
+        loop {
+            let block_len = if len > self.block_len() { self.block_len() } else { len };
+            len -= block_len;
+            self.write_block(block_len, last && len == 0)?;
+
+            if len == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Encodes the first `len` pending bytes as a single block.
+    fn write_block(&mut self, len: usize, last: bool) -> io::Result<()> {
+        let start = self.history_len;
+        let end = start + len;
+
+        match self.match_finder {
+            None => {
+                write_stored_block(&mut self.output, &self.window[start .. end], last)?;
+            },
+
+            Some(ref mut match_finder) => {
+                let mut tokens = Vec::new();
+                match self.strategy {
+                    DeflateStrategy::Optimal => {
+                        optimal::tokenize(match_finder, &self.window, self.window_start,
+                                          self.window_start + start, self.window_start + end,
+                                          &mut tokens);
+                    },
+                    DeflateStrategy::Rle => {
+                        lz77::tokenize_runs(&self.window, self.window_start,
+                                            self.window_start + start, self.window_start + end,
+                                            &mut tokens);
+                    },
+                    _ => {
+                        match_finder.tokenize(&self.window, self.window_start,
+                                              self.window_start + start,
+                                              self.window_start + end, &mut tokens);
+                    },
+                }
+                if self.strategy == DeflateStrategy::Fixed {
+                    write_fixed_block(&mut self.output, &tokens, last)?;
+                } else {
+                    write_best_block(&mut self.output, &self.window[start .. end], &tokens,
+                                     last)?;
+                }
+            },
+        }
+
+        self.history_len = end;
+
+        // only keeping the data that can be referenced by the next blocks
+        if self.history_len > 2 * WINDOW_SIZE {
+            let drop = self.history_len - WINDOW_SIZE;
+            self.window.drain(.. drop);
+            self.history_len -= drop;
+            self.window_start += drop;
+        }
+
+        Ok(())
+    }
+}
+
+// The writer is only passed to `DeflaterBuilder::build`, so `builder` is only defined for a
+// single type of writer in order for `Deflater::builder()` to compile.
+impl Deflater<Vec<u8>> {
+    /// Returns a builder that allows tuning the match finder.
+    pub fn builder() -> DeflaterBuilder {
+        DeflaterBuilder {
+            level: DEFAULT_LEVEL,
+            strategy: None,
+            max_chain_length: None,
+            nice_match: None,
+        }
+    }
+}
+
+impl<W> Write for Deflater<W> where W: Write {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.window.extend_from_slice(buf);
+
+        // we always keep some data for the last block, which is written by `finish`
+        let pending = self.window.len() - self.history_len;
+        if pending > self.block_len() {
+            let len = (pending - 1) / self.block_len() * self.block_len();
+            self.write_blocks(len, false)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let pending = self.window.len() - self.history_len;
+        if pending != 0 {
+            self.write_blocks(pending, false)?;
+
+            // an empty stored block aligns the output on a byte boundary so that everything
+            // can be written to the underlying writer
+            if !self.output.is_aligned() {
+                write_stored_block(&mut self.output, &[], false)?;
+            }
+        }
+
+        self.output.flush()
+    }
+}
+
+impl DeflaterBuilder {
+    /// Sets the compression level, between 0 and 9, which gives the default value of the other
+    /// parameters. See `Deflater::new`. The other parameters are ignored at level 0, which
+    /// doesn't compress the data.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `level` is superior to 9.
+    ///
+    pub fn level(mut self, level: u32) -> DeflaterBuilder {
+        assert!(level <= 9, "The compression level must be between 0 and 9");
+        self.level = level;
+        self
+    }
+
+    /// Sets how the back-references are chosen. By default, this depends on the level.
+    ///
+    /// With `Lazy` or `Filtered` at levels 1 and 2, the lazy search is done as at level 3.
+    /// `Rle` ignores the level and the other parameters.
+    pub fn strategy(mut self, strategy: DeflateStrategy) -> DeflaterBuilder {
+        self.strategy = Some(strategy);
+        self
+    }
+
+    /// Sets the maximum number of previous positions examined to find each match, between 4
+    /// at level 1 and 4096 at level 9. Higher values find longer matches but are slower.
+    pub fn max_chain_length(mut self, max_chain_length: usize) -> DeflaterBuilder {
+        self.max_chain_length = Some(max_chain_length);
+        self
+    }
+
+    /// Sets the length of a match above which the search for a longer one stops, between 8 at
+    /// level 1 and 258, the maximum length of a match, at levels 8 and 9.
+    pub fn nice_match(mut self, nice_match: usize) -> DeflaterBuilder {
+        self.nice_match = Some(nice_match);
+        self
+    }
+
+    /// Builds the deflater by taking ownership of the writer where the data will be written to.
+    pub fn build<W>(self, writer: W) -> Deflater<W> where W: Write {
+        self.build_with_output(BitWrite::new(writer))
+    }
+
+    fn build_with_output<W>(self, output: BitWrite<W>) -> Deflater<W> where W: Write {
+        let match_finder = if self.level == 0 {
+            None
+        } else {
+            let (mut max_chain, mut nice_length, mut max_lazy, mut good_length) =
+                LEVELS[self.level as usize - 1];
+            max_chain = self.max_chain_length.unwrap_or(max_chain);
+            nice_length = self.nice_match.unwrap_or(nice_length);
+
+            match self.strategy {
+                Some(DeflateStrategy::Greedy) => max_lazy = 0,
+                Some(DeflateStrategy::Lazy) | Some(DeflateStrategy::Filtered) if max_lazy == 0 => {
+                    max_lazy = LEVELS[2].2;
+                    good_length = LEVELS[2].3;
+                },
+                _ => (),
+            }
+
+            let mut match_finder = MatchFinder::new(max_chain, nice_length, max_lazy,
+                                                    good_length);
+            if self.strategy == Some(DeflateStrategy::Filtered) {
+                match_finder.set_min_length(FILTERED_MIN_MATCH);
+            }
+            Some(match_finder)
+        };
+
+        let strategy = self.strategy.unwrap_or(match self.level {
+            0 ..= 2 => DeflateStrategy::Greedy,
+            _ => DeflateStrategy::Lazy,
+        });
+
+        Deflater {
+            output,
+            window: Vec::with_capacity(WINDOW_SIZE + COMPRESSED_BLOCK_LEN),
+            history_len: 0,
+            window_start: 0,
+            strategy,
+            match_finder,
+        }
+    }
+}
+
+/// Writes a stored block.
+fn write_stored_block<W>(out: &mut BitWrite<W>, data: &[u8], last: bool) -> io::Result<()>
+                         where W: Write
+{
+    debug_assert!(data.len() <= MAX_STORED_BLOCK_LEN);
+
+    // the `bfinal` bit, then `0b00` for the block type, then padding up to the next byte
+    out.write(if last { 1 } else { 0 }, 3)?;
+    out.byte_align()?;
+
+    let len = data.len() as u16;
+    out.write_bytes(&[len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8])?;
+    out.write_bytes(data)
+}
+
+/// Writes a block compressed with the fixed huffman codes.
+fn write_fixed_block<W>(out: &mut BitWrite<W>, tokens: &[Token], last: bool) -> io::Result<()>
+                        where W: Write
+{
+    // the `bfinal` bit, then `0b01` for the block type
+    out.write(if last { 0b011 } else { 0b010 }, 3)?;
+
+    let (lit_len, dist) = fixed_encoders();
+    write_tokens(out, tokens, &lit_len, &dist)
+}
+
+/// Writes `data`, whose tokens are `tokens`, as a dynamic, fixed or stored block depending on
+/// which one is the smallest.
+fn write_best_block<W>(out: &mut BitWrite<W>, data: &[u8], tokens: &[Token], last: bool)
+                       -> io::Result<()> where W: Write
+{
+    let (lit_len_freqs, dist_freqs) = frequencies(tokens);
+    let extra_bits = extra_bits(tokens);
+
+    let dynamic = DynamicHeader::new(&lit_len_freqs, &dist_freqs);
+    let dynamic_size = 3 + dynamic.size() + extra_bits +
+                       symbols_size(&lit_len_freqs, &dynamic.lit_len) +
+                       symbols_size(&dist_freqs, &dynamic.dist);
+
+    let (fixed_lit_len, fixed_dist) = fixed_encoders();
+    let fixed_size = 3 + extra_bits + symbols_size(&lit_len_freqs, &fixed_lit_len) +
+                     symbols_size(&dist_freqs, &fixed_dist);
+
+    // each stored block has a header of 3 bits, a padding of up to 7 bits, and LEN and NLEN
+    let stored_blocks = cmp::max(1, data.len().div_ceil(MAX_STORED_BLOCK_LEN));
+    let stored_size = stored_blocks * (3 + 7 + 32) + data.len() * 8;
+
+    if stored_size <= fixed_size && stored_size <= dynamic_size {
+        let mut chunks = data.chunks(MAX_STORED_BLOCK_LEN).peekable();
+        if chunks.peek().is_none() {
+            return write_stored_block(out, &[], last);
+        }
+        while let Some(chunk) = chunks.next() {
+            write_stored_block(out, chunk, last && chunks.peek().is_none())?;
+        }
+        Ok(())
+
+    } else if fixed_size <= dynamic_size {
+        out.write(if last { 0b011 } else { 0b010 }, 3)?;
+        write_tokens(out, tokens, &fixed_lit_len, &fixed_dist)
+
+    } else {
+        // the `bfinal` bit, then `0b10` for the block type
+        out.write(if last { 0b101 } else { 0b100 }, 3)?;
+        dynamic.write(out)?;
+        write_tokens(out, tokens, &dynamic.lit_len, &dynamic.dist)
+    }
+}
+
+/// Writes the tokens of a compressed block followed by the end of block symbol.
+fn write_tokens<W>(out: &mut BitWrite<W>, tokens: &[Token], lit_len: &HuffmanEncoder,
+                   dist: &HuffmanEncoder) -> io::Result<()> where W: Write
+{
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => {
+                write_symbol(out, lit_len, byte as usize)?;
+            },
+
+            Token::Match { length, distance } => {
+                let (symbol, extra_bits, extra) = length_symbol(length);
+                write_symbol(out, lit_len, 257 + symbol as usize)?;
+                out.write(extra as u32, extra_bits)?;
+
+                let (symbol, extra_bits, extra) = distance_symbol(distance);
+                write_symbol(out, dist, symbol as usize)?;
+                out.write(extra as u32, extra_bits)?;
+            },
+        }
+    }
+
+    // end of block
+    write_symbol(out, lit_len, 256)
+}
+
+/// Writes the code of a symbol, whose most significant bit comes first in the stream.
+fn write_symbol<W>(out: &mut BitWrite<W>, encoder: &HuffmanEncoder, symbol: usize)
+                   -> io::Result<()> where W: Write
+{
+    let (code, bits) = encoder.code(symbol);
+    debug_assert!(bits != 0);
+    let reversed = code.reverse_bits() >> (16 - bits);
+    out.write(reversed as u32, bits)
+}
+
+/// Returns the literal/length and distance codes defined by the RFC for fixed blocks.
+fn fixed_encoders() -> (HuffmanEncoder, HuffmanEncoder) {
+    let mut lit_len = [8; 288];
+    for len in &mut lit_len[144 .. 256] {
+        *len = 9;
+    }
+    for len in &mut lit_len[256 .. 280] {
+        *len = 7;
+    }
+
+    (HuffmanEncoder::from_lengths(&lit_len), HuffmanEncoder::from_lengths(&[5; 30]))
+}
+
+/// Counts the number of occurences of each literal/length symbol, including the end of block,
+/// and of each distance symbol.
+pub(crate) fn frequencies(tokens: &[Token]) -> ([u32; 286], [u32; 30]) {
+    let mut lit_len = [0; 286];
+    let mut dist = [0; 30];
+
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => lit_len[byte as usize] += 1,
+            Token::Match { length, distance } => {
+                lit_len[257 + length_symbol(length).0 as usize] += 1;
+                dist[distance_symbol(distance).0 as usize] += 1;
+            },
+        }
+    }
+
+    lit_len[256] += 1;
+    (lit_len, dist)
+}
+
+/// Returns the total number of extra bits of the lengths and distances of the tokens.
+fn extra_bits(tokens: &[Token]) -> usize {
+    tokens.iter().map(|token| match *token {
+        Token::Literal(_) => 0,
+        Token::Match { length, distance } => {
+            length_symbol(length).1 as usize + distance_symbol(distance).1 as usize
+        },
+    }).sum()
+}
+
+/// Returns the number of bits needed to write the symbols with the given frequencies.
+fn symbols_size(frequencies: &[u32], encoder: &HuffmanEncoder) -> usize {
+    frequencies.iter().enumerate()
+               .map(|(symbol, &freq)| freq as usize * encoder.code(symbol).1 as usize)
+               .sum()
+}
+
+/// The huffman codes of a dynamic block and the way they are described in its header.
+struct DynamicHeader {
+    lit_len: HuffmanEncoder,
+    dist: HuffmanEncoder,
+
+    /// Number of literal/length codes that are written in the header.
+    hlit: usize,
+
+    /// Number of distance codes that are written in the header.
+    hdist: usize,
+
+    /// Code of the code length alphabet.
+    code_lengths: HuffmanEncoder,
+
+    /// The code lengths of both tables as symbols of the code length alphabet, with the value of
+    /// their extra bits.
+    commands: Vec<(u8, u8)>,
+}
+
+impl DynamicHeader {
+    fn new(lit_len_freqs: &[u32], dist_freqs: &[u32]) -> DynamicHeader {
+        let lit_len = HuffmanEncoder::from_frequencies(lit_len_freqs, 15);
+        let dist = HuffmanEncoder::from_frequencies(dist_freqs, 15);
+
+        let lit_len_lengths = lit_len.lengths();
+        let dist_lengths = dist.lengths();
+        let hlit = cmp::max(257, lit_len_lengths.iter().rposition(|&l| l != 0).unwrap() + 1);
+        let hdist = dist_lengths.iter().rposition(|&l| l != 0).unwrap() + 1;
+
+        let mut lengths = lit_len_lengths[.. hlit].to_vec();
+        lengths.extend_from_slice(&dist_lengths[.. hdist]);
+        let commands = run_length_encode(&lengths);
+
+        let mut command_freqs = [0; 19];
+        for &(symbol, _) in &commands {
+            command_freqs[symbol as usize] += 1;
+        }
+
+        DynamicHeader {
+            lit_len,
+            dist,
+            hlit,
+            hdist,
+            code_lengths: HuffmanEncoder::from_frequencies(&command_freqs, 7),
+            commands,
+        }
+    }
+
+    /// Returns the number of bits of the header, not including the first three bits of the
+    /// block.
+    fn size(&self) -> usize {
+        let commands: usize = self.commands.iter().map(|&(symbol, _)| {
+            self.code_lengths.code(symbol as usize).1 as usize + command_extra_bits(symbol) as usize
+        }).sum();
+
+        5 + 5 + 4 + 3 * self.code_lengths.lengths_in_header_order().len() + commands
+    }
+
+    /// Writes the header, not including the first three bits of the block.
+    fn write<W>(&self, out: &mut BitWrite<W>) -> io::Result<()> where W: Write {
+        let code_lengths = self.code_lengths.lengths_in_header_order();
+
+        out.write((self.hlit - 257) as u32, 5)?;
+        out.write((self.hdist - 1) as u32, 5)?;
+        out.write((code_lengths.len() - 4) as u32, 4)?;
+
+        for &len in &code_lengths {
+            out.write(len as u32, 3)?;
+        }
+
+        for &(symbol, extra) in &self.commands {
+            write_symbol(out, &self.code_lengths, symbol as usize)?;
+            out.write(extra as u32, command_extra_bits(symbol))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Encodes a list of code lengths as symbols of the code length alphabet, plus the value of
+/// their extra bits. This is the inverse of what `read_dynamic_tables` decodes.
+fn run_length_encode(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut commands = Vec::new();
+
+    let mut pos = 0;
+    while pos < lengths.len() {
+        let len = lengths[pos];
+        let run = lengths[pos ..].iter().take_while(|&&l| l == len).count();
+
+        if len == 0 && run >= 11 {
+            let run = cmp::min(run, 138);
+            commands.push((18, (run - 11) as u8));
+            pos += run;
+
+        } else if len == 0 && run >= 3 {
+            commands.push((17, (run - 3) as u8));
+            pos += run;
+
+        } else if len != 0 && run >= 4 {
+            // the first length is written as it is, then repeated
+            commands.push((len, 0));
+            let mut remaining = run - 1;
+            while remaining >= 3 {
+                let repeat = cmp::min(remaining, 6);
+                commands.push((16, (repeat - 3) as u8));
+                remaining -= repeat;
+            }
+            for _ in 0 .. remaining {
+                commands.push((len, 0));
+            }
+            pos += run;
+
+        } else {
+            commands.push((len, 0));
+            pos += 1;
+        }
+    }
+
+    commands
+}
+
+/// Returns the number of extra bits of a symbol of the code length alphabet.
+fn command_extra_bits(symbol: u8) -> u8 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+/// Returns the length symbol (between 0 and 28) of a back-reference length, plus the number and
+/// value of the extra bits.
+pub(crate) fn length_symbol(length: u16) -> (u16, u8, u16) {
+    let symbol = LENGTHS.iter().rposition(|&l| l <= length).unwrap();
+    (symbol as u16, EXTRA_LENGTHS[symbol], length - LENGTHS[symbol])
+}
+
+/// Returns the distance symbol of a back-reference distance, plus the number and value of the
+/// extra bits.
+pub(crate) fn distance_symbol(distance: u16) -> (u16, u8, u16) {
+    let symbol = DISTANCES.iter().rposition(|&d| d <= distance).unwrap();
+    (symbol as u16, EXTRA_DISTANCES[symbol], distance - DISTANCES[symbol])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DEFAULT_LEVEL, DeflateStrategy, Deflater, run_length_encode};
+    use inflate::Inflater;
+    use std::io::{Cursor, Read, Write};
+
+    fn inflate(compressed: Vec<u8>) -> Vec<u8> {
+        let mut inflater = Inflater::new(Cursor::new(compressed));
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        output
+    }
+
+    fn deflate(data: &[u8], level: u32) -> Vec<u8> {
+        let mut deflater = Deflater::new(Vec::new(), level);
+        deflater.write_all(data).unwrap();
+        deflater.finish().unwrap()
+    }
+
+    fn round_trip(data: &[u8], level: u32) -> Vec<u8> {
+        inflate(deflate(data, level))
+    }
+
+    fn deflate_fixed(data: &[u8]) -> Vec<u8> {
+        let mut deflater = Deflater::builder().strategy(DeflateStrategy::Fixed).build(Vec::new());
+        deflater.write_all(data).unwrap();
+        deflater.finish().unwrap()
+    }
+
+    /// Generates pseudo-random data.
+    fn random_data(len: usize, mut seed: u32) -> Vec<u8> {
+        (0 .. len).map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as u8
+        }).collect()
+    }
+
+    /// Generates text-like data with a lot of repetitions.
+    fn text_data(len: usize) -> Vec<u8> {
+        let words: &[&[u8]] = &[b"the ", b"quick ", b"brown ", b"fox ", b"jumps ", b"over ",
+                                b"lazy ", b"dog", b". ", b"\n"];
+        let mut seed = 12345u32;
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            data.extend_from_slice(words[(seed >> 16) as usize % words.len()]);
+        }
+        data.truncate(len);
+        data
+    }
+
+    #[test]
+    fn stored_hello() {
+        let mut deflater = Deflater::new(Vec::new(), 0);
+        deflater.write_all(b"hello").unwrap();
+        let compressed = deflater.finish().unwrap();
+
+        assert_eq!(compressed, [0x1, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o']);
+    }
+
+    #[test]
+    fn stored_empty() {
+        assert!(round_trip(&[], 0).is_empty());
+    }
+
+    #[test]
+    fn stored_multiple_blocks() {
+        let data = (0 .. 200000u32).map(|n| (n % 251) as u8).collect::<Vec<_>>();
+        assert_eq!(round_trip(&data, 0), data);
+
+        // exactly on a block boundary
+        let data = vec![7; 65535 * 2];
+        assert_eq!(round_trip(&data, 0), data);
+    }
+
+    #[test]
+    fn stored_many_small_writes() {
+        let data = (0 .. 150000u32).map(|n| (n % 13) as u8).collect::<Vec<_>>();
+
+        let mut deflater = Deflater::new(Vec::new(), 0);
+        for chunk in data.chunks(1000) {
+            deflater.write_all(chunk).unwrap();
+        }
+        deflater.flush().unwrap();
+        let compressed = deflater.finish().unwrap();
+
+        assert_eq!(inflate(compressed), data);
+    }
+
+    #[test]
+    fn deflate_late() {
+        let compressed = deflate(b"Deflate late", DEFAULT_LEVEL);
+
+        // same as what zlib produces
+        assert_eq!(compressed, [0x73, 0x49, 0x4d, 0xcb, 0x49, 0x2c, 0x49, 0x55, 0x00, 0x11, 0x00]);
+    }
+
+    #[test]
+    fn fixed_deflate_late() {
+        let compressed = deflate_fixed(b"Deflate late");
+
+        // same as what zlib produces
+        assert_eq!(compressed, [0x73, 0x49, 0x4d, 0xcb, 0x49, 0x2c, 0x49, 0x55, 0x00, 0x11, 0x00]);
+    }
+
+    #[test]
+    fn fixed_round_trip() {
+        assert!(inflate(deflate_fixed(&[])).is_empty());
+
+        for &len in &[1, 2, 3, 100, 1000, 65536, 65537, 300000] {
+            let data = random_data(len, len as u32 + 1);
+            assert_eq!(inflate(deflate_fixed(&data)), data);
+
+            let data = text_data(len);
+            assert_eq!(inflate(deflate_fixed(&data)), data);
+        }
+
+        let data = vec![0; 100000];
+        assert_eq!(inflate(deflate_fixed(&data)), data);
+    }
+
+    #[test]
+    fn fixed_compresses_text() {
+        let data = text_data(100000);
+        let compressed = deflate_fixed(&data);
+        assert!(compressed.len() < data.len() / 2);
+
+        // random data is still compressed instead of being stored
+        let compressed = deflate_fixed(&random_data(1000, 1));
+        assert_eq!(compressed[0] & 0b110, 0b010);
+    }
+
+    #[test]
+    fn compressed_flush() {
+        let data = text_data(5000);
+
+        let mut deflater = Deflater::new(Vec::new(), DEFAULT_LEVEL);
+        deflater.write_all(&data[.. 2000]).unwrap();
+        deflater.flush().unwrap();
+        deflater.write_all(&data[2000 ..]).unwrap();
+        let compressed = deflater.finish().unwrap();
+
+        assert_eq!(inflate(compressed), data);
+    }
+
+    #[test]
+    fn run_length_encoding() {
+        let mut lengths = vec![3; 12];
+        lengths.extend_from_slice(&[0; 2]);
+        lengths.extend_from_slice(&[0; 150 - 2]);
+        lengths.extend_from_slice(&[0, 0, 0, 5, 5, 5, 5, 7]);
+
+        assert_eq!(run_length_encode(&lengths), [
+            (3, 0), (16, 3), (16, 2),
+            (18, 127), (18, 4),
+            (5, 0), (16, 0), (7, 0),
+        ]);
+    }
+
+    #[test]
+    fn compressed_round_trip() {
+        for level in 1 .. 10 {
+            assert!(round_trip(&[], level).is_empty());
+
+            for &len in &[1, 2, 3, 100, 1000, 65536, 65537] {
+                let data = random_data(len, len as u32 + 1);
+                assert_eq!(round_trip(&data, level), data);
+
+                let data = text_data(len);
+                assert_eq!(round_trip(&data, level), data);
+            }
+
+            let data = vec![0; 100000];
+            assert_eq!(round_trip(&data, level), data);
+
+            // a single distance symbol
+            let data = b"abcabcabcabc".repeat(20);
+            assert_eq!(round_trip(&data, level), data);
+        }
+
+        let data = text_data(300000);
+        assert_eq!(round_trip(&data, DEFAULT_LEVEL), data);
+    }
+
+    #[test]
+    fn compresses_text() {
+        let data = text_data(100000);
+        assert!(deflate(&data, 1).len() < data.len() / 2);
+    }
+
+    #[test]
+    fn higher_levels_are_smaller() {
+        let corpus = [
+            text_data(100000),
+            include_bytes!("../tests/fixture/6r").to_vec(),
+            include_bytes!("../tests/fixture/8r").to_vec(),
+            include_bytes!("../tests/fixture/7r").to_vec(),
+        ];
+
+        for data in &corpus {
+            let sizes = (0 .. 10).map(|level| deflate(data, level).len()).collect::<Vec<_>>();
+            for level in 1 .. 10 {
+                assert!(sizes[level] <= sizes[level - 1],
+                        "level {} gives {} bytes while level {} gives {} bytes", level,
+                        sizes[level], level - 1, sizes[level - 1]);
+            }
+        }
+    }
+
+    #[test]
+    fn strategies() {
+        let corpus = [
+            text_data(100000),
+            include_bytes!("../tests/fixture/6r").to_vec(),
+            include_bytes!("../tests/fixture/8r").to_vec(),
+            include_bytes!("../tests/fixture/7r").to_vec(),
+        ];
+
+        for data in &corpus {
+            let mut sizes = Vec::new();
+            for &strategy in &[DeflateStrategy::Greedy, DeflateStrategy::Lazy,
+                               DeflateStrategy::Optimal, DeflateStrategy::Rle,
+                               DeflateStrategy::Filtered, DeflateStrategy::Fixed] {
+                let mut deflater = Deflater::builder().strategy(strategy).build(Vec::new());
+                deflater.write_all(data).unwrap();
+                let compressed = deflater.finish().unwrap();
+                sizes.push(compressed.len());
+                assert!(&inflate(compressed) == data);
+            }
+
+            assert!(sizes[2] <= sizes[0], "optimal gives {} bytes while greedy gives {} bytes",
+                    sizes[2], sizes[0]);
+        }
+    }
+
+    #[test]
+    fn rle() {
+        let compress = |data: &[u8]| {
+            let mut deflater = Deflater::builder().strategy(DeflateStrategy::Rle)
+                                                  .build(Vec::new());
+            deflater.write_all(data).unwrap();
+            deflater.finish().unwrap()
+        };
+
+        let mut runs = Vec::new();
+        for i in 0 .. 1000 {
+            runs.extend_from_slice(&[(i % 7) as u8; 100]);
+        }
+        let compressed = compress(&runs);
+        assert!(compressed.len() < 3000);
+        assert_eq!(inflate(compressed), runs);
+
+        // a pattern that repeats every two bytes isn't compressed as it requires a distance
+        // of 2, while each byte still takes at least one bit
+        let pattern = b"ab".repeat(50000);
+        let compressed = compress(&pattern);
+        assert!(compressed.len() >= pattern.len() / 8);
+        assert!(deflate(&pattern, DEFAULT_LEVEL).len() < 1000);
+        assert_eq!(inflate(compressed), pattern);
+    }
+
+    #[test]
+    fn match_finder_parameters() {
+        let data = text_data(100000);
+        let mut sizes = Vec::new();
+        for &(max_chain_length, nice_match) in &[(1, 3), (4, 8), (4096, 258)] {
+            let mut deflater = Deflater::builder().level(1).max_chain_length(max_chain_length)
+                                                  .nice_match(nice_match).build(Vec::new());
+            deflater.write_all(&data).unwrap();
+            let compressed = deflater.finish().unwrap();
+            sizes.push(compressed.len());
+            assert_eq!(inflate(compressed), data);
+        }
+        assert!(sizes[2] <= sizes[1] && sizes[1] <= sizes[0], "{:?}", sizes);
+
+        // same as the level
+        let mut deflater = Deflater::builder().level(1).max_chain_length(4).nice_match(8)
+                                              .strategy(DeflateStrategy::Greedy)
+                                              .build(Vec::new());
+        deflater.write_all(&data).unwrap();
+        assert_eq!(deflater.finish().unwrap(), deflate(&data, 1));
+
+        // the parameters don't matter without compression
+        let mut deflater = Deflater::builder().level(0).strategy(DeflateStrategy::Optimal)
+                                              .build(Vec::new());
+        deflater.write_all(&data).unwrap();
+        assert_eq!(deflater.finish().unwrap(), deflate(&data, 0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn level_too_high() {
+        Deflater::new(Vec::new(), 10);
+    }
+
+    #[test]
+    fn random_data_is_stored() {
+        let data = random_data(200000, 5);
+        let compressed = deflate(&data, DEFAULT_LEVEL);
+
+        // blocks of 65536 bytes are split in two stored blocks
+        assert!(compressed.len() <= data.len() + 5 * 2 * (data.len() / 65536 + 1));
+        assert_eq!(inflate(compressed), data);
+    }
+
+    #[test]
+    fn compared_to_zlib() {
+        // the fixtures were compressed with zlib's default level, with a 2 bytes header and a
+        // 4 bytes trailer around the deflate data
+        let corpus: &[(&[u8], &[u8])] = &[
+            (include_bytes!("../tests/fixture/1r"), include_bytes!("../tests/fixture/1")),
+            (include_bytes!("../tests/fixture/6r"), include_bytes!("../tests/fixture/6")),
+            (include_bytes!("../tests/fixture/7r"), include_bytes!("../tests/fixture/7")),
+            (include_bytes!("../tests/fixture/8r"), include_bytes!("../tests/fixture/8")),
+        ];
+
+        for &(data, zlib) in corpus {
+            let compressed = deflate(data, DEFAULT_LEVEL);
+            assert!(compressed.len() <= (zlib.len() - 6) * 105 / 100);
+            assert_eq!(inflate(compressed), data);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Errors that can happen while decoding a stream.
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error::Error;
+#[cfg(feature = "std")]
+use std::io;
+
+/// An error caused by malformed compressed data.
+///
+/// The `Read` implementations of this crate return these errors wrapped inside an `io::Error`
+/// of kind `InvalidInput`. They can be retrieved with `io::Error::get_ref`, or with
+/// `io::Error::decode_error` without the `std` feature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// A back-reference points further back than the start of the decoded data.
+    InvalidBackReference {
+        /// Position in bits of the length symbol of the back-reference, see `bit_position`.
+        at_bit: u64,
+    },
+
+    /// The code lengths of a Huffman table don't describe a valid code, either because there
+    /// are too many short codes or because there is no code at all.
+    InvalidHuffmanTable,
+
+    /// A length or distance symbol that is reserved by the format was decoded.
+    InvalidSymbol {
+        /// Position in bits of the symbol, see `bit_position`.
+        at_bit: u64,
+    },
+
+    /// The bits don't match any code of a Huffman table.
+    BadHuffmanCode {
+        /// Position in bits of the start of the code, see `bit_position`.
+        at_bit: u64,
+    },
+
+    /// The header of a dynamic block declares more than 286 literal and length codes or more
+    /// than 30 distance codes. The codes after these are reserved by the format.
+    InvalidHeader {
+        /// Position in bits of the number of literal and length codes, see `bit_position`.
+        at_bit: u64,
+    },
+
+    /// The data ended between two blocks, before a block marked as the last one.
+    UnexpectedEof,
+
+    /// The bits between the header of an uncompressed block and the next byte boundary aren't
+    /// all zero. Only returned if `Inflater::set_check_padding` has been enabled.
+    NonZeroPadding {
+        /// Position in bits of the first of these bits, see `bit_position`.
+        at_bit: u64,
+    },
+
+    /// The two first bytes of a zlib header aren't a multiple of 31. They are most likely not
+    /// a zlib header at all.
+    InvalidZlibHeaderChecksum,
+
+    /// The compression method of a zlib header isn't DEFLATE.
+    UnsupportedCompressionMethod {
+        /// Value of the `CM` field, which is 8 for DEFLATE.
+        method: u8,
+    },
+
+    /// The zlib data requires a preset dictionary and none has been provided. See
+    /// `ZlibDecoder::required_dictionary_id`.
+    DictionaryRequired {
+        /// Adler32 checksum of the dictionary, as found in the `DICTID` field.
+        id: u32,
+    },
+
+    /// The dictionary passed to `ZlibDecoder::set_dictionary` isn't the one that the zlib data
+    /// requires.
+    WrongDictionary {
+        /// Adler32 checksum of the dictionary that the data requires.
+        expected: u32,
+        /// Adler32 checksum of the dictionary that has been passed.
+        found: u32,
+    },
+
+    /// `ZlibDecoder::set_dictionary` has been called before the header has been read, after
+    /// decoding has started, or for zlib data that doesn't require a dictionary.
+    UnexpectedDictionary,
+
+    /// The window size of a zlib header is larger than 32 kiB.
+    InvalidWindowSize {
+        /// Value of the `CINFO` field, which is the base-2 logarithm of the window size minus
+        /// eight and must be at most 7.
+        cinfo: u8,
+    },
+}
+
+impl DecodeError {
+    /// Returns the position of the error in the DEFLATE data, in bits from the start of the data
+    /// read by the `Inflater`, if known.
+    ///
+    /// For zlib and gzip data, the position is counted from the end of the header.
+    pub fn bit_position(&self) -> Option<u64> {
+        match *self {
+            DecodeError::InvalidBackReference { at_bit } |
+            DecodeError::InvalidSymbol { at_bit } |
+            DecodeError::BadHuffmanCode { at_bit } |
+            DecodeError::InvalidHeader { at_bit } |
+            DecodeError::NonZeroPadding { at_bit } => Some(at_bit),
+            DecodeError::InvalidHuffmanTable |
+            DecodeError::UnexpectedEof |
+            DecodeError::InvalidZlibHeaderChecksum |
+            DecodeError::UnsupportedCompressionMethod { .. } |
+            DecodeError::DictionaryRequired { .. } |
+            DecodeError::WrongDictionary { .. } |
+            DecodeError::UnexpectedDictionary |
+            DecodeError::InvalidWindowSize { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::InvalidBackReference { at_bit } => {
+                write!(fmt, "Back-reference points before the start of the data at bit {}",
+                       at_bit)
+            },
+            DecodeError::InvalidHuffmanTable => write!(fmt, "Invalid Huffman code lengths"),
+            DecodeError::InvalidSymbol { at_bit } => {
+                write!(fmt, "Reserved length or distance symbol at bit {}", at_bit)
+            },
+            DecodeError::BadHuffmanCode { at_bit } => {
+                write!(fmt, "Bits that don't match any Huffman code at bit {}", at_bit)
+            },
+            DecodeError::InvalidHeader { at_bit } => {
+                write!(fmt, "Too many literal, length or distance codes in dynamic block header \
+                             at bit {}", at_bit)
+            },
+            DecodeError::UnexpectedEof => write!(fmt, "Unexpected EOF before the last block"),
+            DecodeError::NonZeroPadding { at_bit } => {
+                write!(fmt, "Non-zero padding after uncompressed block header at bit {}", at_bit)
+            },
+            DecodeError::InvalidZlibHeaderChecksum => {
+                write!(fmt, "Wrong value for zlib header checksum")
+            },
+            DecodeError::UnsupportedCompressionMethod { method } => {
+                write!(fmt, "Unsupported zlib compression method {}", method)
+            },
+            DecodeError::DictionaryRequired { id } => {
+                write!(fmt, "The zlib data requires the preset dictionary {:#010x}", id)
+            },
+            DecodeError::WrongDictionary { expected, found } => {
+                write!(fmt, "Wrong preset dictionary {:#010x} for zlib data that requires {:#010x}",
+                       found, expected)
+            },
+            DecodeError::UnexpectedDictionary => {
+                write!(fmt, "The zlib data doesn't expect a preset dictionary at this point")
+            },
+            DecodeError::InvalidWindowSize { cinfo } => {
+                write!(fmt, "Invalid value {} for CInfo in zlib header", cinfo)
+            },
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for DecodeError {}
+
+#[cfg(feature = "std")]
+impl From<DecodeError> for io::Error {
+    fn from(err: DecodeError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
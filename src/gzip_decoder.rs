@@ -0,0 +1,1108 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::{cmp, mem};
+use io::{ErrorKind, Read};
+use io::Error as IoError;
+use crc32::Crc32;
+use inflate::Inflater;
+#[cfg(feature = "std")]
+use std::io::{Seek, SeekFrom};
+
+/// A reader that decodes gzip data from an underlying reader.
+///
+/// A gzip stream can contain multiple members one after another, like the result of
+/// `cat a.gz b.gz`. Their decompressed content is concatenated.
+pub struct GzipDecoder<R> where R: Read {
+    state: Option<GzipDecoderState<R>>,
+
+    /// Header of the member currently being decoded, once it has been read.
+    header: Option<GzipHeader>,
+
+    /// Number of bytes read before the data of the current member, including its header.
+    total_in: u64,
+
+    /// Number of bytes decoded from the previous members.
+    total_out: u64,
+
+    /// If false, the CRC16 of the headers isn't checked. See `new_lenient`.
+    check_header_crc: bool,
+
+    /// Bytes of the header or of the trailer being read, which are kept if the reader returns
+    /// a retryable error in the middle of them. See `Replay`.
+    pending: Vec<u8>,
+}
+
+/// Metadata found in the header of a gzip member.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GzipHeader {
+    /// Name of the original file, if any.
+    pub filename: Option<String>,
+
+    /// Comment about the file, if any.
+    pub comment: Option<String>,
+
+    /// Modification time of the original file, as a Unix timestamp. `0` if not available.
+    pub mtime: u32,
+
+    /// Value of the `XFL` field, which describes the compression method. For example `2` for
+    /// the maximum compression and `4` for the fastest one. Other values are kept as they are.
+    pub xfl: u8,
+
+    /// Type of filesystem on which the compression took place. For example `3` for Unix and
+    /// `255` if unknown. Values that aren't defined by the RFC are kept as they are.
+    pub os: u8,
+
+    /// Content of the extra field, if any. See `extra_subfields`.
+    pub extra: Option<Vec<u8>>,
+}
+
+/// Iterator over the subfields of the extra field of a gzip header, returned by
+/// `GzipHeader::extra_subfields`.
+#[derive(Debug, Clone)]
+pub struct ExtraSubfields<'a> {
+    remaining: &'a [u8],
+}
+
+impl GzipHeader {
+    /// Returns the subfields of the extra field, as their two identification bytes and their
+    /// data.
+    ///
+    /// The structure of the extra field isn't checked when the header is read. The iterator
+    /// ends at the first subfield that doesn't fit in the extra field, whose content is still
+    /// available in `extra`.
+    pub fn extra_subfields(&self) -> ExtraSubfields<'_> {
+        ExtraSubfields {
+            remaining: self.extra.as_ref().map_or(&[], |extra| &extra[..]),
+        }
+    }
+}
+
+impl<'a> Iterator for ExtraSubfields<'a> {
+    type Item = ([u8; 2], &'a [u8]);
+
+    fn next(&mut self) -> Option<([u8; 2], &'a [u8])> {
+        // SI1, SI2 and LEN, followed by the data
+        if self.remaining.len() < 4 {
+            self.remaining = &[];
+            return None;
+        }
+
+        let len = ((self.remaining[3] as usize) << 8) | self.remaining[2] as usize;
+        if self.remaining.len() < 4 + len {
+            self.remaining = &[];
+            return None;
+        }
+
+        let id = [self.remaining[0], self.remaining[1]];
+        let data = &self.remaining[4 .. 4 + len];
+        self.remaining = &self.remaining[4 + len ..];
+        Some((id, data))
+    }
+}
+
+// The inflater is much larger than the other states, but it is the state that the decoder is in
+// almost all the time, and boxing it would only add an allocation.
+#[allow(clippy::large_enum_variant)]
+enum GzipDecoderState<R> where R: Read {
+    // we are at the start of a member and need to read its header
+    Header {
+        // naked reader where we will read the header from
+        reader: R,
+
+        // true if this is the first member, in which case reaching EOF is an error
+        first_member: bool,
+    },
+
+    // we are currently reading compressed data
+    CompressedData {
+        // reader wrapper around the inflater
+        reader: Inflater<R>,
+
+        // checksum of the data decompressed so far for this member
+        crc: Crc32,
+
+        // number of bytes decompressed so far for this member, modulo 2^32
+        size: u32,
+    },
+
+    // the data of the member has been decoded, and we need to read its trailer
+    Trailer {
+        reader: R,
+        crc: Crc32,
+        size: u32,
+    },
+
+    // we have read the last member
+    Eof {
+        reader: R,
+    },
+}
+
+impl<R> GzipDecoder<R> where R: Read {
+    /// Builds a new gzip decoder by taking ownership of a reader where the data will be read from.
+    pub fn new(reader: R) -> GzipDecoder<R> {
+        GzipDecoder {
+            state: Some(GzipDecoderState::Header {
+                reader,
+                first_member: true,
+            }),
+            header: None,
+            total_in: 0,
+            total_out: 0,
+            check_header_crc: true,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Same as `new`, but the CRC16 that protects the headers which have the `FHCRC` flag
+    /// isn't checked, and is skipped instead.
+    ///
+    /// This is meant for data produced by encoders that write something else than the CRC16,
+    /// such as the part number that old versions of GNU gzip wrote there for multi-part
+    /// archives. The CRC32 of the decompressed data is still checked.
+    pub fn new_lenient(reader: R) -> GzipDecoder<R> {
+        let mut decoder = GzipDecoder::new(reader);
+        decoder.check_header_crc = false;
+        decoder
+    }
+
+    /// Returns the header of the member currently being decoded, or `None` if no header has
+    /// been read yet.
+    ///
+    /// For streams containing multiple members, this is the header of the last member that
+    /// was encountered.
+    pub fn header(&self) -> Option<&GzipHeader> {
+        self.header.as_ref()
+    }
+
+    /// Reads the header of the first member if it hasn't been read yet, and returns the header
+    /// of the member currently being decoded.
+    ///
+    /// Calling this is optional, as `read` reads the headers when needed. Does not start
+    /// decompressing the data.
+    pub fn read_header(&mut self) -> Result<&GzipHeader, IoError> {
+        match self.state.take() {
+            Some(GzipDecoderState::Header { reader, first_member: true }) => {
+                self.start_member(reader, true)?;
+            },
+            Some(state) => self.state = Some(state),
+            None => {
+                return Err(IoError::new(ErrorKind::InvalidInput,
+                                        "I/O errors in the inflater are unrecoverable"));
+            }
+        }
+
+        Ok(self.header.as_ref().expect("the header is read before anything else"))
+    }
+
+    /// Returns true if all the members have been decoded and their trailers checked.
+    ///
+    /// This is the case once `read` has returned 0.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, Some(GzipDecoderState::Eof { .. }))
+    }
+
+    /// Returns the number of bytes read from the underlying reader so far, including the
+    /// headers and the trailers of the members.
+    pub fn total_in(&self) -> u64 {
+        match self.state {
+            Some(GzipDecoderState::CompressedData { ref reader, .. }) => {
+                self.total_in + reader.total_in()
+            },
+            _ => self.total_in,
+        }
+    }
+
+    /// Returns the number of bytes decoded so far.
+    pub fn total_out(&self) -> u64 {
+        match self.state {
+            Some(GzipDecoderState::CompressedData { ref reader, .. }) => {
+                self.total_out + reader.total_out()
+            },
+            _ => self.total_out,
+        }
+    }
+
+    /// Destroys the decoder and returns the underlying reader.
+    ///
+    /// Once `read` has returned 0, all the data of the reader has been consumed. Otherwise it
+    /// is positioned somewhere in the middle of the gzip data.
+    ///
+    /// # Panic
+    ///
+    /// Panics if a previous call to `read` returned an error.
+    ///
+    pub fn into_inner(self) -> R {
+        match self.state {
+            Some(GzipDecoderState::Header { reader, .. }) => reader,
+            Some(GzipDecoderState::CompressedData { reader, .. }) => reader.into_inner(),
+            Some(GzipDecoderState::Trailer { reader, .. }) => reader,
+            Some(GzipDecoderState::Eof { reader }) => reader,
+            None => panic!("I/O errors in the inflater are unrecoverable"),
+        }
+    }
+
+    /// Returns an iterator that decodes the members one by one, instead of concatenating their
+    /// data. If some data of the current member has already been read, the first item only
+    /// contains the rest of it.
+    pub fn members(self) -> GzipMembers<R> {
+        GzipMembers {
+            decoder: self,
+            failed: false,
+            data: Vec::new(),
+        }
+    }
+
+    /// Decodes some data of the current member into `buf`. Returns 0 once the end of the
+    /// member has been reached and its trailer checked, in which case the decoder is before
+    /// the next member, if any.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the decoder isn't inside of a member.
+    ///
+    fn decode_member_data(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        let (mut reader, mut crc, size) = match self.state.take() {
+            Some(GzipDecoderState::CompressedData { reader, crc, size }) => (reader, crc, size),
+            Some(GzipDecoderState::Trailer { reader, crc, size }) => {
+                self.finish_member(reader, crc, size)?;
+                return Ok(0);
+            },
+            _ => panic!("The decoder isn't inside of a member"),
+        };
+
+        let result = match reader.read(buf) {
+            Ok(result) => result,
+            Err(err) => {
+                // the inflater continues from where it stopped on the next call
+                if ::is_retryable(&err) {
+                    self.state = Some(GzipDecoderState::CompressedData { reader, crc, size });
+                }
+                return Err(err);
+            },
+        };
+
+        if result == 0 {
+            self.total_in += reader.total_in();
+            self.total_out += reader.total_out();
+            self.finish_member(reader.into_inner(), crc, size)?;
+
+        } else {
+            crc.feed(&buf[..result]);
+            self.state = Some(GzipDecoderState::CompressedData {
+                reader,
+                crc,
+                size: size.wrapping_add(result as u32),
+            });
+        }
+
+        Ok(result)
+    }
+
+    /// Reads the header of the next member, if any, and prepares to decompress its data.
+    fn start_member(&mut self, mut reader: R, first_member: bool) -> Result<(), IoError> {
+        let result = consume_next_gzip_header(&mut Replay::new(&mut reader, &mut self.pending),
+                                              self.check_header_crc);
+        let result = match result {
+            Ok(result) => result,
+            Err(err) => {
+                if ::is_retryable(&err) {
+                    self.state = Some(GzipDecoderState::Header { reader, first_member });
+                }
+                return Err(err);
+            },
+        };
+        self.pending.clear();
+
+        let (header, header_len) = match result {
+            Some(header) => header,
+            None if first_member => {
+                return Err(IoError::new(ErrorKind::InvalidInput, "Unexpected EOF"));
+            },
+            None => {
+                self.state = Some(GzipDecoderState::Eof { reader });
+                return Ok(());
+            },
+        };
+
+        self.header = Some(header);
+        self.total_in += header_len;
+        self.state = Some(GzipDecoderState::CompressedData {
+            reader: Inflater::new(reader),
+            crc: Crc32::new(),
+            size: 0,
+        });
+        Ok(())
+    }
+
+    /// Reads the trailer of the member whose data has been decoded, and checks it against
+    /// the data.
+    fn finish_member(&mut self, mut reader: R, crc: Crc32, size: u32) -> Result<(), IoError> {
+        let result = consume_gzip_trailer(&mut Replay::new(&mut reader, &mut self.pending), crc,
+                                          size);
+        if let Err(err) = result {
+            if ::is_retryable(&err) {
+                self.state = Some(GzipDecoderState::Trailer { reader, crc, size });
+            }
+            return Err(err);
+        }
+        self.pending.clear();
+
+        self.total_in += 8;
+        self.state = Some(GzipDecoderState::Header {
+            reader,
+            first_member: false,
+        });
+        Ok(())
+    }
+}
+
+impl<R> Read for GzipDecoder<R> where R: Read {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // a loop rather than recursion, as the data can contain any number of empty members
+        loop {
+            match self.state.take() {
+                Some(GzipDecoderState::Header { reader, first_member }) => {
+                    self.start_member(reader, first_member)?;
+                },
+
+                Some(state @ GzipDecoderState::CompressedData { .. }) |
+                Some(state @ GzipDecoderState::Trailer { .. }) => {
+                    self.state = Some(state);
+
+                    match self.decode_member_data(buf)? {
+                        0 => (),
+                        len => return Ok(len),
+                    }
+                },
+
+                Some(GzipDecoderState::Eof { reader }) => {
+                    self.state = Some(GzipDecoderState::Eof { reader });
+                    return Ok(0);
+                },
+
+                None => {
+                    return Err(IoError::new(ErrorKind::InvalidInput,
+                                            "I/O errors in the inflater are unrecoverable"));
+                }
+            }
+        }
+    }
+}
+
+/// Iterator over the members of gzip data, returned by `GzipDecoder::members`.
+///
+/// After an error, the iterator doesn't return anything else, unless the error is of kind
+/// `WouldBlock` or `Interrupted`, in which case the next call continues the member.
+pub struct GzipMembers<R> where R: Read {
+    decoder: GzipDecoder<R>,
+
+    /// True if an error that can't be retried has been returned.
+    failed: bool,
+
+    /// Data of the current member decoded so far, which is kept if the reader returns a
+    /// retryable error.
+    data: Vec<u8>,
+}
+
+/// A member of gzip data, returned by `GzipMembers`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GzipMember {
+    /// Header of the member.
+    pub header: GzipHeader,
+
+    /// Decompressed data of the member.
+    pub data: Vec<u8>,
+}
+
+impl<R> GzipMembers<R> where R: Read {
+    /// Decodes the next member. Returns `None` at the end of the data.
+    fn next_member(&mut self) -> Result<Option<GzipMember>, IoError> {
+        let decoder = &mut self.decoder;
+
+        match decoder.state.take() {
+            Some(GzipDecoderState::Header { reader, first_member }) => {
+                decoder.start_member(reader, first_member)?;
+            },
+            Some(state) => decoder.state = Some(state),
+            None => {
+                return Err(IoError::new(ErrorKind::InvalidInput,
+                                        "I/O errors in the inflater are unrecoverable"));
+            },
+        }
+
+        if decoder.is_finished() {
+            return Ok(None);
+        }
+
+        let mut buf = [0; 32768];
+        loop {
+            match decoder.decode_member_data(&mut buf)? {
+                0 => break,
+                len => self.data.extend_from_slice(&buf[.. len]),
+            }
+        }
+
+        let header = decoder.header.clone().expect("the header is read before anything else");
+        Ok(Some(GzipMember { header, data: mem::take(&mut self.data) }))
+    }
+}
+
+impl<R> Iterator for GzipMembers<R> where R: Read {
+    type Item = Result<GzipMember, IoError>;
+
+    fn next(&mut self) -> Option<Result<GzipMember, IoError>> {
+        if self.failed {
+            return None;
+        }
+
+        match self.next_member() {
+            Ok(member) => member.map(Ok),
+            Err(err) => {
+                self.failed = !::is_retryable(&err);
+                Some(Err(err))
+            },
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R> GzipDecoder<R> where R: Read + Seek {
+    /// Returns the `ISIZE` field of the trailer at the end of the data, which is the size of
+    /// the decompressed data, without decoding anything. The position of the reader is
+    /// restored afterwards, so this can be called at any time.
+    ///
+    /// The size is only a hint, for example to allocate the output or to display the progress:
+    /// it is stored modulo 2^32, so it is wrong for data larger than 4GiB, and for multiple
+    /// members only the size of the last one is returned. The trailer is found at the end of
+    /// the reader, which must not contain anything after the gzip data.
+    ///
+    /// Returns `None` if the reader can't seek, for example if it is a pipe, or if it is too
+    /// short to contain a gzip member.
+    pub fn uncompressed_size_hint(&mut self) -> Result<Option<u32>, IoError> {
+        let reader = match self.state {
+            Some(GzipDecoderState::Header { ref mut reader, .. }) => reader,
+            Some(GzipDecoderState::CompressedData { ref mut reader, .. }) => reader.get_mut(),
+            Some(GzipDecoderState::Trailer { ref mut reader, .. }) => reader,
+            Some(GzipDecoderState::Eof { ref mut reader }) => reader,
+            None => {
+                return Err(IoError::new(ErrorKind::InvalidInput,
+                                        "I/O errors in the inflater are unrecoverable"));
+            },
+        };
+
+        let position = match reader.stream_position() {
+            Ok(position) => position,
+            Err(_) => return Ok(None),
+        };
+
+        let result = read_isize(reader);
+        reader.seek(SeekFrom::Start(position))?;
+        result
+    }
+}
+
+/// Reads the last four bytes of `reader` as an `ISIZE` field. See
+/// `GzipDecoder::uncompressed_size_hint`.
+#[cfg(feature = "std")]
+fn read_isize<R>(reader: &mut R) -> Result<Option<u32>, IoError> where R: Read + Seek {
+    // smallest possible member: 10 bytes of header, 2 bytes of DEFLATE and 8 bytes of trailer
+    if reader.seek(SeekFrom::End(0))? < 20 {
+        return Ok(None);
+    }
+
+    reader.seek(SeekFrom::End(-4))?;
+    ::read_u32_le(reader).map(Some)
+}
+
+/// Decompresses the whole gzip data of `data`, which can contain multiple members.
+///
+/// Instead of growing the output as it is decoded, this allocates it at once using the size of
+/// the decompressed data stored in the trailer of the last member. The size is only a hint:
+/// it is stored modulo 2^32, so it can't be trusted for data larger than 4GiB, and it only
+/// covers the last member of the data. The output grows as usual in these cases.
+pub fn decompress_gzip(data: &[u8]) -> Result<Vec<u8>, IoError> {
+    let mut output = Vec::with_capacity(isize_hint(data));
+    let mut decoder = GzipDecoder::new(data);
+    let mut buf = [0; 32768];
+
+    loop {
+        match decoder.read(&mut buf) {
+            Ok(0) => return Ok(output),
+            Ok(len) => output.extend_from_slice(&buf[.. len]),
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Returns the `ISIZE` field of the trailer at the end of `data`, or 0 if there is none.
+///
+/// The value is capped to what the compressed data can possibly contain, so that corrupt or
+/// malicious data can't make us allocate gigabytes for nothing.
+fn isize_hint(data: &[u8]) -> usize {
+    // smallest possible member: 10 bytes of header, 2 bytes of DEFLATE and 8 bytes of trailer
+    if data.len() < 20 {
+        return 0;
+    }
+
+    let isize = &data[data.len() - 4 ..];
+    let isize = u32::from_le_bytes([isize[0], isize[1], isize[2], isize[3]]);
+
+    // a pointer takes at least 2 bits and produces at most 258 bytes
+    let max = data.len().saturating_mul(1032);
+    if isize as u64 > max as u64 { max } else { isize as usize }
+}
+
+/// Reader of a gzip header that computes the CRC32 and the length of what has been read.
+struct HeaderReader<'a, R: 'a> {
+    inner: &'a mut R,
+    crc: Crc32,
+    len: u64,
+}
+
+impl<'a, R: 'a> Read for HeaderReader<'a, R> where R: Read {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        let len = self.inner.read(buf)?;
+        self.crc.feed(&buf[.. len]);
+        self.len += len as u64;
+        Ok(len)
+    }
+}
+
+/// Reader that first returns the bytes of `buffer` that haven't been returned yet, then the
+/// bytes of `inner`, which are appended to `buffer`.
+///
+/// Used to read a header or a trailer, which can't be parsed in the middle: if `inner` returns
+/// a retryable error, `buffer` keeps what has been read so far, and it is parsed again from the
+/// start by the next attempt.
+struct Replay<'a, R: 'a> {
+    inner: &'a mut R,
+    buffer: &'a mut Vec<u8>,
+    position: usize,
+}
+
+impl<'a, R: 'a> Replay<'a, R> where R: Read {
+    fn new(inner: &'a mut R, buffer: &'a mut Vec<u8>) -> Replay<'a, R> {
+        Replay { inner, buffer, position: 0 }
+    }
+}
+
+impl<'a, R: 'a> Read for Replay<'a, R> where R: Read {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        if self.position < self.buffer.len() {
+            let len = cmp::min(buf.len(), self.buffer.len() - self.position);
+            buf[.. len].copy_from_slice(&self.buffer[self.position .. self.position + len]);
+            self.position += len;
+            return Ok(len);
+        }
+
+        let len = self.inner.read(buf)?;
+        self.buffer.extend_from_slice(&buf[.. len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// Consumes the gzip header of the next member if there is one, or returns `None` if the
+/// reader is at EOF. See `consume_gzip_header`.
+fn consume_next_gzip_header<R>(reader: &mut R, check_crc: bool)
+                               -> Result<Option<(GzipHeader, u64)>, IoError> where R: Read
+{
+    let mut id1 = [0];
+    if reader.read(&mut id1)? == 0 {
+        return Ok(None);
+    }
+
+    consume_gzip_header(reader, id1[0], check_crc).map(Some)
+}
+
+/// Consumes the gzip header of a member from the reader and checks that nothing is wrong with
+/// it. The first byte of the header has already been read and is passed as `id1`. If
+/// `check_crc` is false, the CRC16 of the header, if any, isn't checked.
+///
+/// Returns the header and its length in bytes.
+fn consume_gzip_header<R>(reader: &mut R, id1: u8, check_crc: bool)
+                          -> Result<(GzipHeader, u64), IoError> where R: Read
+{
+    let mut crc = Crc32::new();
+    crc.feed(&[id1]);
+    let reader = &mut HeaderReader { inner: reader, crc, len: 1 };
+
+    // ID2, CM, FLG, MTIME, XFL and OS
+    let mut header = [0; 9];
+    ::read_all(reader, &mut header)?;
+
+    if id1 != 0x1f || header[0] != 0x8b {
+        return Err(IoError::new(ErrorKind::InvalidInput, "Invalid gzip header"));
+    }
+
+    if header[1] != 8 {
+        return Err(IoError::new(ErrorKind::InvalidInput, "Unsupported gzip compression method"));
+    }
+
+    let flg = header[2];
+    if (flg & 0b11100000) != 0 {
+        return Err(IoError::new(ErrorKind::InvalidInput, "Reserved flags set in gzip header"));
+    }
+
+    let mtime = u32::from_le_bytes([header[3], header[4], header[5], header[6]]);
+    let xfl = header[7];
+    let os = header[8];
+
+    // FEXTRA
+    let extra = if (flg & 0b00000100) != 0 {
+        let mut xlen = [0, 0];
+        ::read_all(reader, &mut xlen)?;
+        let xlen = ((xlen[1] as u16) << 8) | xlen[0] as u16;
+        let mut extra = vec![0; xlen as usize];
+        if !extra.is_empty() {
+            ::read_all(reader, &mut extra)?;
+        }
+        Some(extra)
+    } else {
+        None
+    };
+
+    // FNAME and FCOMMENT
+    let filename = if (flg & 0b00001000) != 0 {
+        Some(read_latin1_string(reader)?)
+    } else {
+        None
+    };
+
+    let comment = if (flg & 0b00010000) != 0 {
+        Some(read_latin1_string(reader)?)
+    } else {
+        None
+    };
+
+    // FHCRC, the two least significant bytes of the CRC32 of the header up to this point
+    if (flg & 0b00000010) != 0 {
+        let expected = reader.crc.checksum() as u16;
+        let mut crc16 = [0, 0];
+        ::read_all(reader, &mut crc16)?;
+        if check_crc && ((crc16[1] as u16) << 8) | crc16[0] as u16 != expected {
+            return Err(IoError::new(ErrorKind::InvalidInput, "Wrong CRC16 in gzip header"));
+        }
+    }
+
+    let header = GzipHeader {
+        filename,
+        comment,
+        mtime,
+        xfl,
+        os,
+        extra,
+    };
+    Ok((header, reader.len))
+}
+
+/// Reads a zero-terminated ISO 8859-1 string.
+fn read_latin1_string<R>(reader: &mut R) -> Result<String, IoError> where R: Read {
+    let mut string = String::new();
+    let mut byte = [0];
+
+    loop {
+        ::read_all(reader, &mut byte)?;
+        if byte[0] == 0 {
+            return Ok(string);
+        }
+
+        // the ISO 8859-1 characters have the same values as the first 256 unicode code points
+        string.push(byte[0] as char);
+    }
+}
+
+/// Consumes the gzip trailer of a member and checks it against the decompressed data.
+fn consume_gzip_trailer<R>(reader: &mut R, crc: Crc32, size: u32) -> Result<(), IoError>
+                           where R: Read
+{
+    // both fields are stored in little endian, contrary to the checksum of zlib
+    let expected_crc = ::read_u32_le(reader)?;
+    let expected_size = ::read_u32_le(reader)?;
+
+    if crc.checksum() != expected_crc {
+        return Err(IoError::new(ErrorKind::InvalidInput, "Wrong CRC32 in gzip trailer"));
+    }
+
+    if size != expected_size {
+        return Err(IoError::new(ErrorKind::InvalidInput, "Wrong size in gzip trailer"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress_gzip, isize_hint, GzipDecoder, GzipHeader};
+    use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+    use crc32::Crc32;
+    use gzip_encoder::GzipEncoder;
+
+    const HELLO_WORLD: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0x57, 0x28, 0xcf, 0x2f, 0xca, 0x49, 0x01, 0x00, 0x85, 0x11, 0x4a, 0x0d, 0x0b, 0x00, 0x00,
+        0x00
+    ];
+
+    const DEFLATE_LATE: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x73, 0x49, 0x4d, 0xcb, 0x49,
+        0x2c, 0x49, 0x55, 0x00, 0x11, 0x00, 0x64, 0x53, 0x6d, 0xdc, 0x0c, 0x00, 0x00, 0x00
+    ];
+
+    #[test]
+    fn empty_reads() {
+        let mut decoder = GzipDecoder::new(HELLO_WORLD);
+        assert_eq!(decoder.read(&mut []).unwrap(), 0);
+        assert!(decoder.header().is_none());
+
+        // a read into an empty buffer in the middle of the data isn't the end of the member
+        let mut buf = [0; 5];
+        decoder.read_exact(&mut buf).unwrap();
+        assert_eq!(decoder.read(&mut []).unwrap(), 0);
+        assert!(!decoder.is_finished());
+
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b" world");
+    }
+
+    #[test]
+    fn hello_world() {
+        let mut decoder = GzipDecoder::new(Cursor::new(HELLO_WORLD));
+
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+    }
+
+    #[test]
+    fn trailer_endianness() {
+        let mut crc = Crc32::new();
+        crc.feed(b"hello world");
+        assert_eq!(crc.checksum(), 0x0d4a1185);
+        assert_eq!(HELLO_WORLD[HELLO_WORLD.len() - 8 ..], [0x85, 0x11, 0x4a, 0x0d, 11, 0, 0, 0]);
+
+        // the CRC32 in big endian
+        let mut data = HELLO_WORLD.to_vec();
+        data[23 .. 27].reverse();
+        let err = GzipDecoder::new(&data[..]).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "Wrong CRC32 in gzip trailer");
+
+        // the size in big endian
+        let mut data = HELLO_WORLD.to_vec();
+        data[27 ..].reverse();
+        let err = GzipDecoder::new(&data[..]).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "Wrong size in gzip trailer");
+    }
+
+    #[test]
+    fn two_members() {
+        let data = [HELLO_WORLD, DEFLATE_LATE].concat();
+        let mut decoder = GzipDecoder::new(Cursor::new(data));
+
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello worldDeflate late");
+    }
+
+    #[test]
+    fn members() {
+        let mut encoder = GzipEncoder::builder().filename("b.txt").build(Vec::new());
+        encoder.write_all(b"second member").unwrap();
+        let second = encoder.finish().unwrap();
+        let data = [HELLO_WORLD, &second].concat();
+
+        let members = GzipDecoder::new(Cursor::new(data.clone())).members()
+                                                                 .collect::<Vec<_>>();
+        assert_eq!(members.len(), 2);
+        let first = members[0].as_ref().unwrap();
+        assert_eq!(first.data, b"hello world");
+        assert_eq!(first.header.filename, None);
+        let second = members[1].as_ref().unwrap();
+        assert_eq!(second.data, b"second member");
+        assert_eq!(second.header.filename.as_ref().map(|f| &f[..]), Some("b.txt"));
+
+        // the rest of the current member
+        let mut decoder = GzipDecoder::new(Cursor::new(data));
+        decoder.read_exact(&mut [0; 6]).unwrap();
+        let mut members = decoder.members();
+        assert_eq!(members.next().unwrap().unwrap().data, b"world");
+        assert_eq!(members.next().unwrap().unwrap().data, b"second member");
+        assert!(members.next().is_none());
+    }
+
+    #[test]
+    fn members_error() {
+        let data = [HELLO_WORLD, b"garbage"].concat();
+        let mut members = GzipDecoder::new(Cursor::new(data)).members();
+        assert_eq!(members.next().unwrap().unwrap().data, b"hello world");
+        assert!(members.next().unwrap().is_err());
+        assert!(members.next().is_none());
+
+        assert!(GzipDecoder::new(Cursor::new(Vec::new())).members().next().unwrap().is_err());
+    }
+
+    #[test]
+    fn totals() {
+        let data = [HELLO_WORLD, DEFLATE_LATE].concat();
+        let mut decoder = GzipDecoder::new(Cursor::new(data.clone()));
+        assert!(!decoder.is_finished());
+
+        let mut buf = [0; 11];
+        decoder.read_exact(&mut buf).unwrap();
+        assert_eq!(decoder.total_out(), 11);
+        assert!(decoder.total_in() < HELLO_WORLD.len() as u64);
+
+        decoder.read_to_end(&mut Vec::new()).unwrap();
+        assert!(decoder.is_finished());
+        assert_eq!(decoder.total_in(), data.len() as u64);
+        assert_eq!(decoder.total_out(), 23);
+        assert_eq!(decoder.into_inner().position(), data.len() as u64);
+    }
+
+    #[test]
+    fn trailing_garbage() {
+        let data = [HELLO_WORLD, b"garbage"].concat();
+        let mut decoder = GzipDecoder::new(Cursor::new(data));
+
+        let mut output = Vec::new();
+        assert!(decoder.read_to_end(&mut output).is_err());
+    }
+
+    #[test]
+    fn wrong_crc() {
+        let mut data = HELLO_WORLD.to_vec();
+        data[23] ^= 1;
+        let mut decoder = GzipDecoder::new(Cursor::new(data));
+
+        let mut output = Vec::new();
+        assert!(decoder.read_to_end(&mut output).is_err());
+    }
+
+    #[test]
+    fn empty_member() {
+        // a fixed block with only the end of block symbol, and a final empty uncompressed block
+        let fixed: &[u8] = &[0x03, 0x00];
+        let stored: &[u8] = &[0x01, 0x00, 0x00, 0xff, 0xff];
+
+        for deflate in &[fixed, stored] {
+            let data = [&HELLO_WORLD[.. 10], deflate, &[0; 8]].concat();
+            let mut decoder = GzipDecoder::new(Cursor::new(data.clone()));
+            let mut output = Vec::new();
+            assert_eq!(decoder.read_to_end(&mut output).unwrap(), 0);
+
+            // followed by another member
+            let data = [&data[..], HELLO_WORLD].concat();
+            let mut decoder = GzipDecoder::new(Cursor::new(data));
+            let mut output = Vec::new();
+            decoder.read_to_end(&mut output).unwrap();
+            assert_eq!(output, b"hello world");
+        }
+    }
+
+    #[test]
+    fn empty_input() {
+        let mut decoder = GzipDecoder::new(Cursor::new(Vec::new()));
+
+        let mut output = Vec::new();
+        assert!(decoder.read_to_end(&mut output).is_err());
+    }
+
+    #[test]
+    fn header() {
+        // header with FEXTRA, FNAME and FCOMMENT, followed by a stored block containing `a`
+        let data = vec![0x1f, 0x8b, 0x08, 0x1c, 0xd2, 0x02, 0x96, 0x49, 0x00, 0x0b,
+                        0x02, 0x00, 0xab, 0xcd,
+                        b'c', b'a', b'f', 0xe9, b'.', b't', b'x', b't', 0x00,
+                        b'h', b'i', 0x00,
+                        0x01, 0x01, 0x00, 0xfe, 0xff, b'a',
+                        0x43, 0xbe, 0xb7, 0xe8, 0x01, 0x00, 0x00, 0x00];
+        let len = data.len() as u64;
+        let mut decoder = GzipDecoder::new(Cursor::new(data));
+        assert!(decoder.header().is_none());
+
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"a");
+        assert_eq!((decoder.total_in(), decoder.total_out()), (len, 1));
+
+        assert_eq!(decoder.header(), Some(&GzipHeader {
+            filename: Some("caf\u{e9}.txt".to_owned()),
+            comment: Some("hi".to_owned()),
+            mtime: 1234567890,
+            xfl: 0,
+            os: 11,
+            extra: Some(vec![0xab, 0xcd]),
+        }));
+    }
+
+    #[test]
+    fn unusual_header_values() {
+        // header with unknown XFL and OS values, and an extra field with two subfields, the
+        // second one being empty, followed by an empty fixed block and the trailer
+        let data = [0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff,
+                    0x0b, 0x00, b'A', b'p', 0x03, 0x00, 0x01, 0x02, 0x03, b'x', b'y', 0x00, 0x00,
+                    0x03, 0x00,
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let mut decoder = GzipDecoder::new(&data[..]);
+        assert_eq!(decoder.read_to_end(&mut Vec::new()).unwrap(), 0);
+
+        let header = decoder.header().unwrap();
+        assert_eq!((header.xfl, header.os), (0xff, 0xff));
+        assert_eq!(header.extra_subfields().collect::<Vec<_>>(),
+                   vec![(*b"Ap", &[1, 2, 3][..]), (*b"xy", &[][..])]);
+    }
+
+    #[test]
+    fn malformed_extra_subfields() {
+        // extra field whose second subfield claims to be longer than the field
+        let data = [0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03,
+                    0x09, 0x00, b'A', b'p', 0x01, 0x00, 0x01, b'x', b'y', 0x05, 0x00,
+                    0x03, 0x00,
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let mut decoder = GzipDecoder::new(&data[..]);
+        decoder.read_to_end(&mut Vec::new()).unwrap();
+
+        let header = decoder.header().unwrap();
+        assert_eq!(header.extra.as_ref().map(|extra| extra.len()), Some(9));
+        assert_eq!(header.extra_subfields().collect::<Vec<_>>(), vec![(*b"Ap", &[1][..])]);
+
+        // no extra field at all
+        decoder = GzipDecoder::new(HELLO_WORLD);
+        decoder.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(decoder.header().unwrap().extra_subfields().next(), None);
+    }
+
+    #[test]
+    fn read_header() {
+        let data = [HELLO_WORLD, DEFLATE_LATE].concat();
+        let mut decoder = GzipDecoder::new(Cursor::new(data));
+
+        {
+            let header = decoder.read_header().unwrap();
+            assert_eq!(header.filename, None);
+            assert_eq!(header.os, 3);
+        }
+        assert!(decoder.header().is_some());
+
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello worldDeflate late");
+        assert_eq!(decoder.read_header().unwrap().os, 3);
+    }
+
+    #[test]
+    fn decompress_gzip_members() {
+        assert_eq!(decompress_gzip(HELLO_WORLD).unwrap(), b"hello world");
+        assert_eq!(decompress_gzip(&[HELLO_WORLD, DEFLATE_LATE].concat()).unwrap(),
+                   b"hello worldDeflate late");
+        assert!(decompress_gzip(&HELLO_WORLD[.. 20]).is_err());
+    }
+
+    #[test]
+    fn isize_hints() {
+        assert_eq!(isize_hint(HELLO_WORLD), 11);
+        assert_eq!(isize_hint(&HELLO_WORLD[.. 10]), 0);
+
+        // a corrupt trailer announcing 4GiB
+        let mut data = HELLO_WORLD.to_vec();
+        let len = data.len();
+        data[len - 4 ..].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(isize_hint(&data), len * 1032);
+    }
+
+    #[test]
+    fn uncompressed_size_hint() {
+        let mut decoder = GzipDecoder::new(Cursor::new(HELLO_WORLD));
+        assert_eq!(decoder.uncompressed_size_hint().unwrap(), Some(11));
+
+        // the position is restored and the decoding isn't disturbed
+        let mut buf = [0; 5];
+        decoder.read_exact(&mut buf).unwrap();
+        assert_eq!(decoder.uncompressed_size_hint().unwrap(), Some(11));
+        let mut output = buf.to_vec();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+        assert_eq!(decoder.uncompressed_size_hint().unwrap(), Some(11));
+
+        // only the last member is taken into account
+        let data = [HELLO_WORLD, DEFLATE_LATE].concat();
+        let mut decoder = GzipDecoder::new(Cursor::new(&data[..]));
+        assert_eq!(decoder.uncompressed_size_hint().unwrap(), Some(12));
+
+        let mut decoder = GzipDecoder::new(Cursor::new(&HELLO_WORLD[.. 19]));
+        assert_eq!(decoder.uncompressed_size_hint().unwrap(), None);
+    }
+
+    #[test]
+    fn uncompressed_size_hint_fixture() {
+        let expected = include_bytes!("../tests/fixture/6r");
+        let mut encoder = GzipEncoder::new(Vec::new());
+        encoder.write_all(expected).unwrap();
+        let data = encoder.finish().unwrap();
+
+        let mut decoder = GzipDecoder::new(Cursor::new(data));
+        let hint = decoder.uncompressed_size_hint().unwrap().unwrap();
+        let mut output = Vec::with_capacity(hint as usize);
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(hint as usize, expected.len());
+        assert!(output[..] == expected[..]);
+    }
+
+    #[test]
+    fn uncompressed_size_hint_not_seekable() {
+        // reader that can't seek, like a pipe
+        struct Pipe<'a>(&'a [u8]);
+
+        impl<'a> Read for Pipe<'a> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.0.read(buf)
+            }
+        }
+
+        impl<'a> Seek for Pipe<'a> {
+            fn seek(&mut self, _: SeekFrom) -> io::Result<u64> {
+                Err(io::Error::new(io::ErrorKind::Unsupported, "Illegal seek"))
+            }
+        }
+
+        let mut decoder = GzipDecoder::new(Pipe(HELLO_WORLD));
+        assert_eq!(decoder.uncompressed_size_hint().unwrap(), None);
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+    }
+
+    #[test]
+    fn header_crc() {
+        // `HELLO_WORLD` with the `FHCRC` flag, and the CRC16 after the 10 bytes of the header
+        let mut header = HELLO_WORLD[.. 10].to_vec();
+        header[3] |= 0b00000010;
+        let mut crc = Crc32::new();
+        crc.feed(&header);
+        let crc16 = crc.checksum() as u16;
+        let data = [&header[..], &[crc16 as u8, (crc16 >> 8) as u8], &HELLO_WORLD[10 ..]].concat();
+
+        let mut decoder = GzipDecoder::new(&data[..]);
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+        assert_eq!(decoder.total_in(), data.len() as u64);
+
+        let mut corrupted = data.clone();
+        corrupted[10] ^= 1;
+        let mut decoder = GzipDecoder::new(&corrupted[..]);
+        assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+
+        let mut decoder = GzipDecoder::new_lenient(&corrupted[..]);
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+    }
+}
@@ -0,0 +1,249 @@
+use alloc::vec::Vec;
+use io::{self, Write};
+use crc32::Crc32;
+use deflate::{DEFAULT_LEVEL, Deflater};
+
+/// A writer that compresses data in the gzip format to an underlying writer.
+///
+/// `finish` must be called once all the data has been written in order to write the end of the
+/// compressed data and the trailer.
+pub struct GzipEncoder<W> where W: Write {
+    /// Compresses the data and writes the header.
+    deflater: Deflater<W>,
+
+    /// Checksum of the uncompressed data written so far.
+    crc: Crc32,
+
+    /// Number of bytes of uncompressed data written so far, modulo 2^32.
+    size: u32,
+}
+
+/// Builds a `GzipEncoder` and configures the fields of its header.
+#[derive(Debug, Clone)]
+pub struct GzipEncoderBuilder {
+    level: u32,
+    filename: Option<Vec<u8>>,
+    comment: Option<Vec<u8>>,
+    mtime: u32,
+    os: u8,
+}
+
+impl<W> GzipEncoder<W> where W: Write {
+    /// Builds a new gzip encoder with the default compression level and an empty header.
+    pub fn new(writer: W) -> GzipEncoder<W> {
+        GzipEncoder::builder().build(writer)
+    }
+
+    /// Writes the end of the compressed data and the trailer, and returns the underlying
+    /// writer.
+    pub fn finish(self) -> io::Result<W> {
+        let mut writer = self.deflater.finish()?;
+
+        let crc = self.crc.checksum();
+        let size = self.size;
+        writer.write_all(&[crc as u8, (crc >> 8) as u8, (crc >> 16) as u8, (crc >> 24) as u8,
+                           size as u8, (size >> 8) as u8, (size >> 16) as u8,
+                           (size >> 24) as u8])?;
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+// The writer is only passed to `GzipEncoderBuilder::build`, so `builder` is only defined for a
+// single type of writer in order for `GzipEncoder::builder()` to compile.
+impl GzipEncoder<Vec<u8>> {
+    /// Returns a builder that allows setting the compression level and the header fields.
+    pub fn builder() -> GzipEncoderBuilder {
+        GzipEncoderBuilder {
+            level: DEFAULT_LEVEL,
+            filename: None,
+            comment: None,
+            mtime: 0,
+            os: 255,
+        }
+    }
+}
+
+impl<W> Write for GzipEncoder<W> where W: Write {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.deflater.write(buf)?;
+        self.crc.feed(&buf[.. written]);
+        self.size = self.size.wrapping_add(written as u32);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.deflater.flush()
+    }
+}
+
+impl GzipEncoderBuilder {
+    /// Sets the compression level, between 0 and 9. See `Deflater::new`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `level` is superior to 9.
+    ///
+    pub fn level(mut self, level: u32) -> GzipEncoderBuilder {
+        assert!(level <= 9, "The compression level must be between 0 and 9");
+        self.level = level;
+        self
+    }
+
+    /// Sets the name of the original file.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the name contains characters that are not in ISO 8859-1, or a zero character.
+    ///
+    pub fn filename(mut self, filename: &str) -> GzipEncoderBuilder {
+        self.filename = Some(latin1_string(filename));
+        self
+    }
+
+    /// Sets a comment about the file.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the comment contains characters that are not in ISO 8859-1, or a zero
+    /// character.
+    ///
+    pub fn comment(mut self, comment: &str) -> GzipEncoderBuilder {
+        self.comment = Some(latin1_string(comment));
+        self
+    }
+
+    /// Sets the modification time of the original file, as a Unix timestamp. `0` means that
+    /// it is not available, which is the default.
+    pub fn mtime(mut self, mtime: u32) -> GzipEncoderBuilder {
+        self.mtime = mtime;
+        self
+    }
+
+    /// Sets the type of filesystem on which the compression takes place. The default is `255`,
+    /// which means unknown.
+    pub fn os(mut self, os: u8) -> GzipEncoderBuilder {
+        self.os = os;
+        self
+    }
+
+    /// Builds the encoder by taking ownership of the writer where the data will be written to.
+    pub fn build<W>(self, writer: W) -> GzipEncoder<W> where W: Write {
+        let mut flg = 0;
+        if self.filename.is_some() {
+            flg |= 0b00001000;
+        }
+        if self.comment.is_some() {
+            flg |= 0b00010000;
+        }
+
+        // the same values as the reference implementation for the extra flags
+        let xfl = match self.level {
+            1 => 4,
+            9 => 2,
+            _ => 0,
+        };
+
+        let mtime = self.mtime;
+        let mut header = vec![0x1f, 0x8b, 8, flg, mtime as u8, (mtime >> 8) as u8,
+                              (mtime >> 16) as u8, (mtime >> 24) as u8, xfl, self.os];
+
+        for string in self.filename.iter().chain(self.comment.iter()) {
+            header.extend_from_slice(string);
+            header.push(0);
+        }
+
+        GzipEncoder {
+            deflater: Deflater::with_header(writer, self.level, &header),
+            crc: Crc32::new(),
+            size: 0,
+        }
+    }
+}
+
+/// Converts a string to ISO 8859-1.
+fn latin1_string(string: &str) -> Vec<u8> {
+    string.chars().map(|c| {
+        // the ISO 8859-1 characters have the same values as the first 256 unicode code points
+        assert!(c != '\0' && (c as u32) < 256, "Invalid character in a gzip header string");
+        c as u8
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GzipEncoder;
+    use gzip_decoder::{GzipDecoder, GzipHeader};
+    use std::io::{Read, Write};
+
+    fn decode(data: &[u8]) -> (Vec<u8>, GzipHeader) {
+        let mut decoder = GzipDecoder::new(data);
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        let header = decoder.header().unwrap().clone();
+        (output, header)
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = b"Deflate late".repeat(1000);
+
+        for level in 0 .. 10 {
+            let mut encoder = GzipEncoder::builder().level(level).build(Vec::new());
+            encoder.write_all(&data).unwrap();
+            let compressed = encoder.finish().unwrap();
+
+            assert_eq!(decode(&compressed).0, data);
+        }
+    }
+
+    #[test]
+    fn header() {
+        let mut encoder = GzipEncoder::builder().filename("caf\u{e9}.txt").comment("hi")
+                                                .mtime(1234567890).os(3).build(Vec::new());
+        encoder.write_all(b"hello world").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let (output, header) = decode(&compressed);
+        assert_eq!(output, b"hello world");
+        assert_eq!(header, GzipHeader {
+            filename: Some("caf\u{e9}.txt".to_owned()),
+            comment: Some("hi".to_owned()),
+            mtime: 1234567890,
+            xfl: 0,
+            os: 3,
+            extra: None,
+        });
+    }
+
+    #[test]
+    fn empty_header() {
+        let compressed = GzipEncoder::new(Vec::new()).finish().unwrap();
+        assert_eq!(&compressed[.. 10], [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255]);
+
+        let (output, header) = decode(&compressed);
+        assert!(output.is_empty());
+        assert_eq!(header.filename, None);
+        assert_eq!(header.comment, None);
+    }
+
+    #[test]
+    fn trailer_size() {
+        for &len in &[0, 1, 255, 256, 65536, 1 << 20] {
+            let data = vec![7; len];
+            let mut encoder = GzipEncoder::builder().level(1).build(Vec::new());
+            encoder.write_all(&data).unwrap();
+            let compressed = encoder.finish().unwrap();
+
+            let isize = &compressed[compressed.len() - 4 ..];
+            let isize = u32::from_le_bytes([isize[0], isize[1], isize[2], isize[3]]);
+            assert_eq!(isize, len as u32);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn filename_not_latin1() {
+        GzipEncoder::builder().filename("\u{1f600}");
+    }
+}
@@ -3,23 +3,47 @@
 //! Note that this is not a generic implementation, but an implementation that uses the
 //! restrictions in place with the deflate encoding.
 
+use alloc::vec::Vec;
+use core::cmp;
 use bit::BitRead;
-use std::io;
+use error::DecodeError;
+use io;
+
+/// Maximum number of bits of the codes that are decoded with a single lookup. Longer codes need
+/// a second lookup in a sub-table.
+const PRIMARY_BITS: u8 = 9;
 
 /// A huffman table. Contains the code -> symbol decoding system.
 ///
 /// The `S` corresponds to the types of symbols (ie. the result of decoding).
 #[derive(Debug, Clone)]
 pub struct HuffmanTable<S> {
-    // The index of each element corresponds to the pattern that must be matched.
-    // For example element `0` corresponds to the bits pattern `000000000`.
-    //
-    // In addition to this, each element contains the number of bits for this pattern to be
-    // matched.
-    elements: Vec<Option<(u8, S)>>,
-
-    // Minimum number of bits to read before trying to match any pattern.
-    min_bits: u8,
+    // The `2^primary_bits` first entries are the primary table, indexed by the next
+    // `primary_bits` bits of the stream, the first one being the least significant bit of the
+    // index. Codes that are shorter than `primary_bits` fill all the entries that start with
+    // them. They are followed by the sub-tables of the longer codes, see `Entry::Link`.
+    entries: Vec<Entry<S>>,
+
+    // Number of bits of the indices of the primary table, which is the length of the longest
+    // code if it is shorter than `PRIMARY_BITS`.
+    primary_bits: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Entry<S> {
+    // no code starts with these bits
+    Empty,
+
+    // code of the given length in bits, including the bits of the primary table for the entries
+    // of a sub-table
+    Symbol(u8, S),
+
+    // the codes that start with these bits are longer than `primary_bits`; the next `bits` bits
+    // are the index in the sub-table that starts at `start` in `entries`
+    Link {
+        start: usize,
+        bits: u8,
+    },
 }
 
 impl<S> HuffmanTable<S> where S: Clone {
@@ -29,118 +53,356 @@ impl<S> HuffmanTable<S> where S: Clone {
     ///
     /// # Panic
     ///
-    /// Panics if one of the lengths is strictly superior to 15 or equal to 0.
+    /// Panics if the lengths don't describe a valid code, see `try_from_lengths`. Incomplete
+    /// codes are accepted. This is meant for tables that are known in advance.
     ///
     pub fn from_lengths<I>(lengths: I) -> HuffmanTable<S> where I: IntoIterator<Item = (S, u8)> {
+        HuffmanTable::try_from_lengths(lengths, true).expect("Invalid Huffman code lengths")
+    }
+
+    /// Same as `from_lengths`, but returns an error instead of panicking if the lengths don't
+    /// describe a valid code, which is the case if there is no length, if one of the lengths
+    /// is 0 or strictly superior to 15, or if there are too many short codes.
+    ///
+    /// If `accept_incomplete` is false, the code must also be complete, meaning that every
+    /// sequence of bits starts with a code, with the exception of a code made of a single
+    /// symbol of length 1, which the RFC allows for the distances. If it is true, incomplete
+    /// codes are accepted, and decoding the sequences of bits that don't start with a code
+    /// returns an error.
+    pub fn try_from_lengths<I>(lengths: I, accept_incomplete: bool)
+                               -> Result<HuffmanTable<S>, DecodeError>
+        where I: IntoIterator<Item = (S, u8)>
+    {
         let lengths = lengths.into_iter().collect::<Vec<_>>();
-        assert!(!lengths.is_empty());
+        if lengths.is_empty() || lengths.iter().any(|&(_, len)| len == 0 || len > 15) {
+            return Err(DecodeError::InvalidHuffmanTable);
+        }
+
+        // Kraft inequality: the sum of `2^-len` must not exceed 1, otherwise some codes would
+        // be prefixes of others
+        let kraft: u32 = lengths.iter().map(|&(_, len)| 1 << (15 - len)).sum();
+        if kraft > 1 << 15 {
+            return Err(DecodeError::InvalidHuffmanTable);
+        }
+        let single_code = lengths.len() == 1 && lengths[0].1 == 1;
+        if !accept_incomplete && kraft != 1 << 15 && !single_code {
+            return Err(DecodeError::InvalidHuffmanTable);
+        }
 
         // array where indices are lengths and values are number of elements of that length
         let bitlen_count = {
             let mut bl = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
             for &(_, len) in &lengths {
-                assert!(len <= 15);
                 bl[len as usize] += 1;
             }
             bl
         };
 
-        // finding the minimum number of bits of pattern
-        let min_bits = match bitlen_count.iter().position(|&e| e != 0) {
-            Some(pos) => pos as u8,
-            None => panic!(),
-        };
-        assert!(min_bits >= 1);
-
         // array where indices are lengths and values are the starting values for this length
         let mut next_code = {
             let mut code = 0;
             let mut next_code = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
-            for bit in (1 .. next_code.len()) {
+            for bit in 1 .. next_code.len() {
                 code = (code + bitlen_count[bit - 1]) << 1;
                 next_code[bit] = code;
             }
             next_code
         };
 
-        // building the real array of elements
-        let mut elements = Vec::new();
-        for (symbol, len) in lengths {
-            assert!(len != 0);
-
-            let code = next_code[len as usize];
+        // the codes are written with their first bit first, which is the least significant bit
+        // of the indices of the tables
+        let codes = lengths.into_iter().map(|(symbol, len)| {
+            let code = next_code[len as usize] as u16;
             next_code[len as usize] += 1;
+            (symbol, len, code.reverse_bits() >> (16 - len))
+        }).collect::<Vec<_>>();
 
-            if elements.len() <= code as usize {
-                for _ in (0 .. 1 + code as usize - elements.len()) {
-                    elements.push(None);
-                }
+        let max_bits = bitlen_count.iter().rposition(|&e| e != 0).unwrap_or(1) as u8;
+        let primary_bits = cmp::min(max_bits, PRIMARY_BITS);
+        let mut entries = vec![Entry::Empty; 1 << primary_bits];
+
+        // the sub-tables are large enough for the longest code that starts with their prefix
+        for &(_, len, code) in &codes {
+            if len <= primary_bits {
+                continue;
+            }
+
+            let entry = &mut entries[(code & ((1 << primary_bits) - 1)) as usize];
+            let bits = len - primary_bits;
+            match *entry {
+                Entry::Link { bits: ref mut b, .. } => *b = cmp::max(*b, bits),
+                _ => *entry = Entry::Link { start: 0, bits },
             }
+        }
 
-            assert!(elements.len() > code);
-            elements[code] = Some((len, symbol));
+        for index in 0 .. 1 << primary_bits {
+            if let Entry::Link { bits, .. } = entries[index] {
+                let start = entries.len();
+                entries[index] = Entry::Link { start, bits };
+                entries.resize(start + (1 << bits), Entry::Empty);
+            }
         }
 
-        HuffmanTable {
-            elements: elements,
-            min_bits: min_bits,
+        for (symbol, len, code) in codes {
+            let (start, index, bits) = if len <= primary_bits {
+                (0, code as usize, primary_bits)
+            } else {
+                match entries[(code & ((1 << primary_bits) - 1)) as usize] {
+                    Entry::Link { start, bits } => (start, (code >> primary_bits) as usize, bits),
+                    _ => unreachable!(),
+                }
+            };
+
+            // filling all the entries whose index starts with the code
+            let code_bits = if len <= primary_bits { len } else { len - primary_bits };
+            for suffix in 0 .. 1 << (bits - code_bits) {
+                let entry = &mut entries[start + (index | (suffix << code_bits))];
+                *entry = Entry::Symbol(len, symbol.clone());
+            }
         }
+
+        Ok(HuffmanTable {
+            entries,
+            primary_bits,
+        })
     }
 
     /// Reads from a bunch of bits and attempts to decode a next symbol by using the table.
+    ///
+    /// Doesn't read any byte of the underlying reader after the one that contains the last bit
+    /// of the code.
     pub fn decode<R>(&self, input: &mut BitRead<R>) -> io::Result<S> where R: io::Read {
-        // we store the list of bits that have been read in a buffer
-        let mut buffer = 0;
-        for _ in (0 .. self.min_bits) {
-            buffer <<= 1;
-            buffer |= try!(input.read(1)) as u16;
-        }
-        let mut num_bits_in_buffer = self.min_bits;
+        let start = input.bit_position();
+
+        // the bits that are known so far, the next one being the least significant; the bits
+        // after them are 0
+        let (data, available) = input.take_cache();
+        let (mut data, mut available) = (data as u32, available);
 
         loop {
-            // breaking the loop if we have read too much
-            if (1 << num_bits_in_buffer) > self.elements.len() {
-                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Bad huffman data"));
+            // If the entry that corresponds to the known bits followed by zeroes is a code
+            // that fits in the known bits, this is the code. Otherwise the code is longer than
+            // the known bits, and we read one more byte. The entry is definitive once all the
+            // bits of its index are known.
+            let (entry, index_bits) = self.lookup(data);
+            match *entry {
+                Entry::Symbol(len, ref symbol) if len <= available => {
+                    input.restore_cache((data >> len) as u16, available - len, len);
+                    return Ok(symbol.clone());
+                },
+                _ if available >= index_bits => {
+                    return Err(DecodeError::BadHuffmanCode { at_bit: start }.into());
+                },
+                _ => (),
             }
 
-            match &self.elements[buffer as usize] {
-                &None => (),
-                &Some(ref elem) => {
-                    if elem.0 == num_bits_in_buffer {
-                        return Ok(elem.1.clone());
-                    }
+            match input.read_raw_byte()? {
+                Some(byte) => {
+                    data |= (byte as u32) << available;
+                    available += 8;
+                },
+                None => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                              "Unexpected EOF in bits stream"));
                 },
+            }
+        }
+    }
+
+    /// Returns the entry that corresponds to the next bits of the stream, and the number of bits
+    /// that its index is made of.
+    fn lookup(&self, data: u32) -> (&Entry<S>, u8) {
+        let entry = &self.entries[(data & ((1 << self.primary_bits) - 1)) as usize];
+        match *entry {
+            Entry::Link { start, bits } => {
+                let index = (data >> self.primary_bits) & ((1 << bits) - 1);
+                (&self.entries[start + index as usize], self.primary_bits + bits)
+            },
+            _ => (entry, self.primary_bits),
+        }
+    }
+}
+
+/// Order in which the code lengths of the code length alphabet are written in the header of
+/// a dynamic block.
+pub const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13,
+                                            2, 14, 1, 15];
+
+/// A huffman code used for encoding. Contains the symbol -> code system.
+///
+/// Symbols are indices between 0 and the number of symbols of the alphabet.
+#[derive(Debug, Clone)]
+pub struct HuffmanEncoder {
+    // For each symbol, its code and its length in bits. The code is stored with its first bit
+    // as the most significant bit. Symbols that are never used have a length of 0.
+    codes: Vec<(u16, u8)>,
+}
+
+impl HuffmanEncoder {
+    /// Builds the optimal code whose lengths don't exceed `max_bits`, given the number of
+    /// occurences of each symbol.
+    ///
+    /// Symbols with a frequency of 0 don't get a code. However if fewer than two symbols are
+    /// used, the code is completed with unused symbols so that it always has at least two codes.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `max_bits` is 0 or strictly superior to 15, or if there are too many symbols
+    /// to fit in `max_bits` bits.
+    ///
+    pub fn from_frequencies(frequencies: &[u32], max_bits: u8) -> HuffmanEncoder {
+        assert!((1 ..= 15).contains(&max_bits));
+        assert!(frequencies.len() >= 2 && frequencies.len() <= 1 << max_bits);
+
+        let mut used = frequencies.iter().enumerate().filter(|&(_, &f)| f != 0)
+                                  .map(|(symbol, &f)| (f as u64, symbol))
+                                  .collect::<Vec<_>>();
+
+        // a code with a single element would be incomplete
+        for symbol in 0 .. 2 {
+            if used.len() < 2 && !used.iter().any(|&(_, s)| s == symbol) {
+                used.push((1, symbol));
+            }
+        }
+
+        used.sort();
+
+        let mut lengths = vec![0; frequencies.len()];
+        for (symbol, len) in package_merge(&used, max_bits) {
+            lengths[symbol] = len;
+        }
+
+        HuffmanEncoder::from_lengths(&lengths)
+    }
+
+    /// Builds the canonical code corresponding to the given length of each symbol, as described
+    /// in the RFC.
+    ///
+    /// # Panic
+    ///
+    /// Panics if one of the lengths is strictly superior to 15.
+    ///
+    pub fn from_lengths(lengths: &[u8]) -> HuffmanEncoder {
+        // array where indices are lengths and values are number of elements of that length
+        let mut bitlen_count = [0u16; 16];
+        for &len in lengths {
+            assert!(len <= 15);
+            bitlen_count[len as usize] += 1;
+        }
+        bitlen_count[0] = 0;
+
+        // array where indices are lengths and values are the starting codes for this length
+        let mut next_code = [0u16; 16];
+        let mut code = 0u16;
+        for bit in 1 .. next_code.len() {
+            code = (code + bitlen_count[bit - 1]) << 1;
+            next_code[bit] = code;
+        }
+
+        let codes = lengths.iter().map(|&len| {
+            if len == 0 {
+                return (0, 0);
+            }
+
+            let code = next_code[len as usize];
+            next_code[len as usize] += 1;
+            (code, len)
+        }).collect();
+
+        HuffmanEncoder {
+            codes,
+        }
+    }
+
+    /// Returns the code of a symbol and its number of bits. The first bit of the code is its
+    /// most significant bit.
+    ///
+    /// The number of bits is 0 if the symbol doesn't have a code.
+    #[inline]
+    pub fn code(&self, symbol: usize) -> (u16, u8) {
+        self.codes[symbol]
+    }
+
+    /// Returns the length of the code of each symbol.
+    pub fn lengths(&self) -> Vec<u8> {
+        self.codes.iter().map(|&(_, len)| len).collect()
+    }
+
+    /// For a code of the code length alphabet, returns the lengths in the order in which they
+    /// are written in the header of a dynamic block. The trailing zeroes are removed, while
+    /// keeping at least 4 lengths.
+    pub fn lengths_in_header_order(&self) -> Vec<u8> {
+        assert_eq!(self.codes.len(), CODE_LENGTH_ORDER.len());
+
+        let mut lengths = CODE_LENGTH_ORDER.iter().map(|&s| self.codes[s].1).collect::<Vec<_>>();
+        while lengths.len() > 4 && lengths.last() == Some(&0) {
+            lengths.pop();
+        }
+        lengths
+    }
+}
+
+/// Computes the length of the codes of the given `(frequency, symbol)` pairs, which must be
+/// sorted by frequency, with the package-merge algorithm.
+fn package_merge(symbols: &[(u64, usize)], max_bits: u8) -> Vec<(usize, u8)> {
+    // Each item is a weight and the list of symbols it contains. Every time a symbol appears in
+    // one of the items that are selected at the end, its code gets one bit longer.
+    let leaves = symbols.iter().map(|&(weight, symbol)| (weight, vec![symbol]))
+                        .collect::<Vec<_>>();
+
+    let mut items = leaves.clone();
+    for _ in 1 .. max_bits {
+        // packaging the items two by two, and merging the packages with the leaves
+        let mut packages = items.chunks(2).filter(|pair| pair.len() == 2).map(|pair| {
+            let mut content = pair[0].1.clone();
+            content.extend_from_slice(&pair[1].1);
+            (pair[0].0 + pair[1].0, content)
+        }).collect::<Vec<_>>();
+
+        let mut merged = Vec::with_capacity(leaves.len() + packages.len());
+        let mut leaves = leaves.iter().cloned().peekable();
+        let mut packages_iter = packages.drain(..).peekable();
+        loop {
+            let take_leaf = match (leaves.peek(), packages_iter.peek()) {
+                (Some(leaf), Some(package)) => leaf.0 <= package.0,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
             };
 
-            buffer <<= 1;
-            buffer |= try!(input.read(1)) as u16;
-            num_bits_in_buffer += 1;
+            if take_leaf {
+                merged.push(leaves.next().unwrap());
+            } else {
+                merged.push(packages_iter.next().unwrap());
+            }
         }
+
+        items = merged;
     }
+
+    let mut lengths = symbols.iter().map(|&(_, symbol)| (symbol, 0)).collect::<Vec<_>>();
+    for (_, content) in items.iter().take(2 * symbols.len() - 2) {
+        for &symbol in content {
+            let entry = lengths.iter_mut().find(|&&mut (s, _)| s == symbol).unwrap();
+            entry.1 += 1;
+        }
+    }
+    lengths
 }
 
 #[cfg(test)]
 mod test {
-    use bit::BitRead;
+    use bit::{BitRead, BitWrite};
+    use error::DecodeError;
     use std::io::Cursor;
-    use super::HuffmanTable;
+    use core::cmp;
+    use super::{Entry, HuffmanEncoder, HuffmanTable, PRIMARY_BITS};
 
     #[test]
     fn decode_rfc1951() {
         // takes the example from RFC1951
-        let table = HuffmanTable {
-            elements: vec![
-                Some((1, 'B')),
-                None,
-                Some((2, 'A')),
-                None,
-                None,
-                None,
-                Some((3, 'C')),
-                Some((3, 'D')),
-            ],
-            min_bits: 1,
-        };
+        let table = HuffmanTable::from_lengths([('A', 2), ('B', 1), ('C', 3), ('D', 3)]
+                                                   .iter().cloned());
 
         // BAACDC
         let data = vec![0b01101010, 0b00011111];
@@ -162,13 +424,223 @@ mod test {
             ('A', 3), ('B', 3), ('C', 3), ('D', 3), ('E', 3), ('F', 2), ('G', 4), ('H', 4)
         ].iter().cloned());
 
-        assert_eq!(tree.elements[0b010], Some((3, 'A')));
-        assert_eq!(tree.elements[0b011], Some((3, 'B')));
-        assert_eq!(tree.elements[0b100], Some((3, 'C')));
-        assert_eq!(tree.elements[0b101], Some((3, 'D')));
-        assert_eq!(tree.elements[0b110], Some((3, 'E')));
-        assert_eq!(tree.elements[0b00], Some((2, 'F')));
-        assert_eq!(tree.elements[0b1110], Some((4, 'G')));
-        assert_eq!(tree.elements[0b1111], Some((4, 'H')));
+        // the indices are the codes of the RFC with their bits reversed, followed by all the
+        // possible bits up to 4 bits
+        assert_eq!(tree.primary_bits, 4);
+        for &(code, len, symbol) in &[(0b010, 3, 'A'), (0b011, 3, 'B'), (0b100, 3, 'C'),
+                                      (0b101, 3, 'D'), (0b110, 3, 'E'), (0b00, 2, 'F'),
+                                      (0b1110, 4, 'G'), (0b1111, 4, 'H')] {
+            let index = (code as u16).reverse_bits() >> (16 - len);
+            for suffix in 0 .. 1 << (4 - len) {
+                assert_eq!(tree.entries[(index | (suffix << len)) as usize],
+                           Entry::Symbol(len, symbol));
+            }
+        }
+    }
+
+    #[test]
+    fn long_codes() {
+        // symbol `n` has a code of `n + 1` bits, except the last one; codes longer than
+        // `PRIMARY_BITS` are in sub-tables
+        let table = HuffmanTable::from_lengths((0 .. 15).map(|n| (n, cmp::min(n + 1, 14))));
+        assert_eq!(table.primary_bits, PRIMARY_BITS);
+        assert!(table.entries.len() > 1 << PRIMARY_BITS);
+
+        let encoder = HuffmanEncoder::from_lengths(&(0 .. 15).map(|n| cmp::min(n + 1, 14))
+                                                               .collect::<Vec<_>>());
+        let symbols = [14, 0, 13, 9, 8, 10, 1, 12, 14];
+        let mut output = BitWrite::new(Vec::new());
+        for &symbol in &symbols {
+            let (code, bits) = encoder.code(symbol as usize);
+            for bit in (0 .. bits).rev() {
+                output.write(((code >> bit) & 1) as u32, 1).unwrap();
+            }
+        }
+        let mut output = output.into_inner().unwrap();
+        output.push(0xaa);
+
+        let mut data = BitRead::new(Cursor::new(output));
+        for &symbol in &symbols {
+            assert_eq!(table.decode(&mut data).unwrap(), symbol);
+        }
+
+        // the byte after the last code hasn't been read
+        let position = data.get_ref().position();
+        assert_eq!(position, data.get_ref().get_ref().len() as u64 - 1);
+    }
+
+    #[test]
+    fn incomplete_code() {
+        // only the codes `0` and `10` exist
+        let table = HuffmanTable::from_lengths([('A', 1), ('B', 2)].iter().cloned());
+
+        let mut data = BitRead::new(Cursor::new(vec![0b11]));
+        let err = table.decode(&mut data).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()),
+                   Some(&DecodeError::BadHuffmanCode { at_bit: 0 }));
+
+        let mut data = BitRead::new(Cursor::new(vec![0b01]));
+        assert_eq!(table.decode(&mut data).unwrap(), 'B');
+        assert!(table.decode(&mut data).is_ok());
+    }
+
+    #[test]
+    fn incomplete_code_strict() {
+        let incomplete = [('A', 1), ('B', 2)];
+        assert_eq!(HuffmanTable::try_from_lengths(incomplete.iter().cloned(), false).unwrap_err(),
+                   DecodeError::InvalidHuffmanTable);
+        assert!(HuffmanTable::try_from_lengths(incomplete.iter().cloned(), true).is_ok());
+
+        // a literal/length code where a single length is missing
+        let lit_len = (0 .. 286).filter(|&n| n != 100).map(|n| (n, if n < 226 { 8 } else { 9 }));
+        assert!(HuffmanTable::try_from_lengths(lit_len.clone(), false).is_err());
+        assert!(HuffmanTable::try_from_lengths(lit_len, true).is_ok());
+
+        // a single code of one bit, as used by blocks with a single distance, is the only
+        // incomplete code accepted in both modes
+        assert!(HuffmanTable::try_from_lengths([(4, 1)].iter().cloned(), false).is_ok());
+        assert!(HuffmanTable::try_from_lengths([(4, 2)].iter().cloned(), false).is_err());
+        assert!(HuffmanTable::try_from_lengths([(4, 2)].iter().cloned(), true).is_ok());
+    }
+
+    /// Checks that the lengths form a complete code that doesn't exceed `max_bits`.
+    fn check_lengths(lengths: &[u8], max_bits: u8) {
+        assert!(lengths.iter().all(|&len| len <= max_bits));
+
+        // Kraft equality: the sum of `2^-len` over the used symbols is exactly 1
+        let sum: u32 = lengths.iter().filter(|&&len| len != 0).map(|&len| 1 << (15 - len)).sum();
+        assert_eq!(sum, 1 << 15);
+    }
+
+    #[test]
+    fn try_from_lengths() {
+        assert!(HuffmanTable::try_from_lengths([('A', 1), ('B', 2), ('C', 2)].iter().cloned(),
+                                                false)
+                    .is_ok());
+        assert!(HuffmanTable::try_from_lengths([('A', 1)].iter().cloned(), false).is_ok());
+
+        // over-subscribed, empty, or with invalid lengths
+        assert_eq!(HuffmanTable::try_from_lengths([('A', 1), ('B', 1), ('C', 1)].iter().cloned(),
+                                                    true)
+                       .unwrap_err(), DecodeError::InvalidHuffmanTable);
+        assert!(HuffmanTable::<char>::try_from_lengths(Vec::new(), true).is_err());
+        assert!(HuffmanTable::try_from_lengths([('A', 1), ('B', 16)].iter().cloned(), true)
+                    .is_err());
+    }
+
+    #[test]
+    fn try_from_lengths_dynamic_block() {
+        // the kind of length sets that can be found in the tables of a dynamic block
+        let too_many_short = (0 .. 10).map(|n| (n, 3));
+        assert!(HuffmanTable::try_from_lengths(too_many_short, true).is_err());
+
+        let zero_length = [(0, 2), (1, 0), (2, 2)].iter().cloned();
+        assert!(HuffmanTable::try_from_lengths(zero_length, true).is_err());
+
+        // all the lengths are 0, whether they are filtered out or not
+        let all_zero = (0 .. 19).map(|n| (n, 0));
+        assert_eq!(HuffmanTable::try_from_lengths(all_zero.clone(), true).unwrap_err(),
+                   DecodeError::InvalidHuffmanTable);
+        assert_eq!(HuffmanTable::try_from_lengths(all_zero.filter(|&(_, len)| len != 0), true)
+                       .unwrap_err(), DecodeError::InvalidHuffmanTable);
+
+        let full = (0 .. 286).map(|n| (n, if n < 226 { 8 } else { 9 }));
+        assert!(HuffmanTable::try_from_lengths(full, false).is_ok());
+        let over_full = (0 .. 287).map(|n| (n, if n < 226 { 8 } else { 9 }));
+        assert!(HuffmanTable::try_from_lengths(over_full, true).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn from_lengths_invalid() {
+        HuffmanTable::from_lengths([('A', 1), ('B', 1), ('C', 1)].iter().cloned());
+    }
+
+    #[test]
+    fn encoder_from_lengths_rfc1951() {
+        let encoder = HuffmanEncoder::from_lengths(&[3, 3, 3, 3, 3, 2, 4, 4]);
+
+        assert_eq!(encoder.code(0), (0b010, 3));
+        assert_eq!(encoder.code(1), (0b011, 3));
+        assert_eq!(encoder.code(2), (0b100, 3));
+        assert_eq!(encoder.code(3), (0b101, 3));
+        assert_eq!(encoder.code(4), (0b110, 3));
+        assert_eq!(encoder.code(5), (0b00, 2));
+        assert_eq!(encoder.code(6), (0b1110, 4));
+        assert_eq!(encoder.code(7), (0b1111, 4));
+    }
+
+    #[test]
+    fn encoder_from_frequencies() {
+        let encoder = HuffmanEncoder::from_frequencies(&[1, 0, 2, 4, 8, 16, 32], 15);
+
+        assert_eq!(encoder.lengths(), [5, 0, 5, 4, 3, 2, 1]);
+        check_lengths(&encoder.lengths(), 15);
+    }
+
+    #[test]
+    fn encoder_length_limited() {
+        // fibonacci frequencies would need codes of 29 bits without the limit
+        let mut frequencies = vec![1u32, 1];
+        while frequencies.len() < 30 {
+            let next = frequencies[frequencies.len() - 1] + frequencies[frequencies.len() - 2];
+            frequencies.push(next);
+        }
+        frequencies.resize(286, 0);
+
+        let lengths = HuffmanEncoder::from_frequencies(&frequencies, 15).lengths();
+        assert_eq!(lengths.iter().cloned().max(), Some(15));
+        check_lengths(&lengths, 15);
+
+        let lengths = HuffmanEncoder::from_frequencies(&frequencies[.. 19], 7).lengths();
+        check_lengths(&lengths, 7);
+    }
+
+    #[test]
+    fn encoder_single_symbol() {
+        let mut frequencies = [0; 30];
+        frequencies[12] = 50;
+
+        let lengths = HuffmanEncoder::from_frequencies(&frequencies, 15).lengths();
+        assert_eq!(lengths[12], 1);
+        check_lengths(&lengths, 15);
+
+        let lengths = HuffmanEncoder::from_frequencies(&[0; 30], 15).lengths();
+        check_lengths(&lengths, 15);
+    }
+
+    #[test]
+    fn encoder_lengths_in_header_order() {
+        let mut frequencies = [0; 19];
+        frequencies[0] = 5;
+        frequencies[8] = 3;
+        frequencies[18] = 1;
+
+        let encoder = HuffmanEncoder::from_frequencies(&frequencies, 7);
+        assert_eq!(encoder.lengths_in_header_order(), [0, 0, 2, 1, 2]);
+    }
+
+    #[test]
+    fn encoder_then_decode() {
+        let frequencies = (0 .. 286u32).map(|n| (n * 7919) % 113).collect::<Vec<_>>();
+        let encoder = HuffmanEncoder::from_frequencies(&frequencies, 15);
+
+        let symbols = (0 .. 286).filter(|&s| frequencies[s] != 0).collect::<Vec<usize>>();
+
+        let mut output = BitWrite::new(Vec::new());
+        for &symbol in &symbols {
+            let (code, bits) = encoder.code(symbol);
+            for bit in (0 .. bits).rev() {
+                output.write(((code >> bit) & 1) as u32, 1).unwrap();
+            }
+        }
+        let output = output.into_inner().unwrap();
+
+        let table = HuffmanTable::from_lengths(encoder.lengths().into_iter().enumerate()
+                                                      .filter(|&(_, len)| len != 0));
+        let mut data = BitRead::new(Cursor::new(output));
+        for &symbol in &symbols {
+            assert_eq!(table.decode(&mut data).unwrap(), symbol);
+        }
     }
 }
@@ -0,0 +1,369 @@
+use std::cmp;
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use std::io::Error as IoError;
+use inflate::Inflater;
+
+/// Maximum distance of a pointer in DEFLATE data.
+const WINDOW_LEN: u64 = 32768;
+
+/// A reader that decodes DEFLATE data from an underlying reader and supports seeking in the
+/// decompressed data.
+///
+/// While the data is being read, the inflater records the position of each uncompressed
+/// (stored) block in the compressed data, which is the only kind of block whose content can be
+/// located without decoding what precedes it. Seeking then jumps to the nearest recorded point
+/// before the target and decodes forward from there.
+///
+/// This only makes seeks fast in streams that are mostly made of stored blocks. Seeking
+/// directly to a position requires it to be in a stored block whose 32 kiB of preceding data
+/// are stored as well, since later blocks can reference that data. Data in compressed blocks
+/// is always reached by decoding forward from the last usable stored block, or from the start
+/// of the stream if there is none. Seeking to a position that hasn't been read yet, or
+/// relative to the end of the data, first decodes the stream up to there.
+pub struct IndexedInflater<R> where R: Read + Seek {
+    /// If this ever becomes `None`, that means an IoError occured somewhere.
+    inflater: Option<Inflater<PositionedReader<R>>>,
+
+    /// Position of the start of the compressed data in the underlying reader.
+    start: u64,
+
+    /// Position in the decompressed data.
+    position: u64,
+
+    /// Length of the decompressed data, if the end of the stream has been reached.
+    len: Option<u64>,
+
+    /// The non-empty stored blocks that have been read so far, in order.
+    blocks: Vec<StoredBlock>,
+}
+
+/// Location of a stored block.
+#[derive(Debug, Copy, Clone)]
+struct StoredBlock {
+    /// Position of the first byte of data of the block in the underlying reader.
+    compressed_offset: u64,
+
+    /// Position of the first byte of data of the block in the decompressed data.
+    decompressed_offset: u64,
+
+    /// Number of bytes of data in the block.
+    len: u64,
+
+    /// True if this is the last block of the stream.
+    last_block: bool,
+
+    /// Position in the decompressed data of the first byte of the uninterrupted sequence of
+    /// stored blocks that this block is part of.
+    run_start: u64,
+}
+
+impl StoredBlock {
+    /// Returns the position in the decompressed data right after this block.
+    fn end(&self) -> u64 {
+        self.decompressed_offset + self.len
+    }
+}
+
+/// Wrapper around the underlying reader that keeps track of its position.
+struct PositionedReader<R> {
+    inner: R,
+    position: u64,
+}
+
+impl<R> Read for PositionedReader<R> where R: Read {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = self.inner.read(buf)?;
+        self.position += result as u64;
+        Ok(result)
+    }
+}
+
+impl<R> IndexedInflater<R> where R: Read + Seek {
+    /// Builds a new inflater by taking ownership of a reader whose current position is the start
+    /// of the DEFLATE data.
+    pub fn new(mut reader: R) -> io::Result<IndexedInflater<R>> {
+        let start = reader.stream_position()?;
+
+        Ok(IndexedInflater {
+            inflater: Some(Inflater::new(PositionedReader { inner: reader, position: start })),
+            start,
+            position: 0,
+            len: None,
+            blocks: Vec::new(),
+        })
+    }
+
+    /// Finds the point closest to `target`, and not after it, where decoding can restart
+    /// directly. Returns the index of the stored block that contains it and its position in the
+    /// decompressed data, or `None` for the start of the stream.
+    fn restart_point(&self, target: u64) -> Option<(usize, u64)> {
+        let candidates = self.blocks.iter()
+                                    .take_while(|b| b.decompressed_offset <= target)
+                                    .count();
+
+        self.blocks[.. candidates].iter().enumerate().rev().filter_map(|(index, block)| {
+            // the data before the restart point that later blocks can reference must be
+            // available in stored blocks as well
+            let earliest = if block.run_start == 0 {
+                block.decompressed_offset
+            } else {
+                cmp::max(block.decompressed_offset, block.run_start + WINDOW_LEN)
+            };
+
+            let point = cmp::min(target, block.end() - 1);
+            if point >= earliest { Some((index, point)) } else { None }
+        }).next()
+    }
+
+    /// Starts decoding again at the given restart point. See `restart_point`.
+    fn restart(&mut self, point: Option<(usize, u64)>) -> io::Result<()> {
+        let mut reader = match self.inflater.take() {
+            Some(inflater) => inflater.into_inner().inner,
+            None => return Err(IoError::new(ErrorKind::InvalidInput,
+                                            "I/O errors in the inflater are unrecoverable")),
+        };
+
+        let (index, point) = match point {
+            Some(point) => point,
+            None => {
+                reader.seek(SeekFrom::Start(self.start))?;
+                let reader = PositionedReader { inner: reader, position: self.start };
+                self.inflater = Some(Inflater::new(reader));
+                self.position = 0;
+                return Ok(());
+            },
+        };
+
+        // gathering the data that precedes the restart point from the stored blocks
+        let block = self.blocks[index];
+        let history_start = cmp::max(block.run_start, point.saturating_sub(WINDOW_LEN));
+        let mut history = vec![0; (point - history_start) as usize];
+        for previous in self.blocks[..= index].iter().filter(|b| b.end() > history_start) {
+            let from = cmp::max(history_start, previous.decompressed_offset);
+            let to = cmp::min(point, previous.end());
+            if from >= to {
+                continue;
+            }
+
+            reader.seek(SeekFrom::Start(previous.compressed_offset +
+                                        (from - previous.decompressed_offset)))?;
+            reader.read_exact(&mut history[(from - history_start) as usize ..
+                                           (to - history_start) as usize])?;
+        }
+
+        let position = block.compressed_offset + (point - block.decompressed_offset);
+        reader.seek(SeekFrom::Start(position))?;
+        let reader = PositionedReader { inner: reader, position };
+        self.inflater = Some(Inflater::in_stored_block(reader, &history,
+                                                       (block.end() - point) as usize,
+                                                       block.last_block));
+        self.position = point;
+        Ok(())
+    }
+
+    /// Decodes and discards data until `target` or the end of the stream is reached.
+    fn skip_to(&mut self, target: u64) -> io::Result<()> {
+        let mut buf = [0; 8192];
+        while self.position < target {
+            let len = cmp::min(buf.len() as u64, target - self.position) as usize;
+            if self.read(&mut buf[.. len])? == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R> Read for IndexedInflater<R> where R: Read + Seek {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let result = match self.inflater {
+            Some(ref mut inflater) => inflater.read(buf),
+            None => return Err(IoError::new(ErrorKind::InvalidInput,
+                                            "I/O errors in the inflater are unrecoverable")),
+        };
+
+        let result = match result {
+            Ok(result) => result,
+            Err(err) => {
+                if !::is_retryable(&err) {
+                    self.inflater = None;
+                }
+                return Err(err);
+            },
+        };
+
+        let inflater = self.inflater.as_mut().unwrap();
+        if let Some((len, last_block)) = inflater.take_stored_block_start() {
+            // all the data returned by this call comes from the block that has just started
+            let decompressed_offset = self.position;
+            let is_new = self.blocks.last().is_none_or(|b| b.decompressed_offset <
+                                                               decompressed_offset);
+
+            if is_new {
+                let run_start = match self.blocks.last() {
+                    Some(previous) if previous.end() == decompressed_offset => previous.run_start,
+                    _ => decompressed_offset,
+                };
+
+                self.blocks.push(StoredBlock {
+                    compressed_offset: inflater.get_ref().position - result as u64,
+                    decompressed_offset,
+                    len: len as u64,
+                    last_block,
+                    run_start,
+                });
+            }
+        }
+
+        self.position += result as u64;
+        if result == 0 {
+            self.len = Some(self.position);
+        }
+
+        Ok(result)
+    }
+}
+
+impl<R> Seek for IndexedInflater<R> where R: Read + Seek {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => {
+                let len = match self.len {
+                    Some(len) => len,
+                    None => {
+                        self.skip_to(u64::MAX)?;
+                        self.position
+                    },
+                };
+                len.checked_add_signed(offset)
+            },
+        };
+
+        let target = match target {
+            Some(target) => target,
+            None => return Err(IoError::new(ErrorKind::InvalidInput,
+                                            "Invalid seek to a negative or overflowing \
+                                             position")),
+        };
+
+        // going forward from the current position is better than restarting before it
+        let point = self.restart_point(target);
+        let restart_position = point.map_or(0, |(_, point)| point);
+        let can_continue = self.inflater.is_some() && self.position <= target &&
+                           self.position >= restart_position;
+        if !can_continue {
+            self.restart(point)?;
+        }
+
+        self.skip_to(target)?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IndexedInflater;
+    use deflate::Deflater;
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    fn compress(data: &[u8], level: u32) -> Vec<u8> {
+        let mut deflater = Deflater::new(Vec::new(), level);
+        deflater.write_all(data).unwrap();
+        deflater.finish().unwrap()
+    }
+
+    fn random_data(len: usize, mut seed: u32) -> Vec<u8> {
+        (0 .. len).map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as u8
+        }).collect()
+    }
+
+    fn read_at<R>(inflater: &mut IndexedInflater<R>, offset: u64, len: usize) -> Vec<u8>
+        where R: Read + Seek
+    {
+        assert_eq!(inflater.seek(SeekFrom::Start(offset)).unwrap(), offset);
+        let mut output = vec![0; len];
+        inflater.read_exact(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn sequential_read() {
+        let data = random_data(200000, 1);
+        let mut inflater = IndexedInflater::new(Cursor::new(compress(&data, 0))).unwrap();
+
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, data);
+        assert_eq!(inflater.blocks.len(), 4);
+    }
+
+    #[test]
+    fn seek_stored_blocks() {
+        let data = random_data(200000, 2);
+        let mut inflater = IndexedInflater::new(Cursor::new(compress(&data, 0))).unwrap();
+
+        assert_eq!(read_at(&mut inflater, 150000, 100), &data[150000 .. 150100]);
+        assert_eq!(read_at(&mut inflater, 10, 100), &data[10 .. 110]);
+        assert_eq!(read_at(&mut inflater, 65530, 10), &data[65530 .. 65540]);
+        assert_eq!(read_at(&mut inflater, 199990, 10), &data[199990 ..]);
+
+        assert_eq!(inflater.seek(SeekFrom::End(-5)).unwrap(), 199995);
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, &data[199995 ..]);
+
+        inflater.seek(SeekFrom::Start(100000)).unwrap();
+        assert_eq!(inflater.seek(SeekFrom::Current(-50)).unwrap(), 99950);
+        assert_eq!(read_at(&mut inflater, 99950, 50), &data[99950 .. 100000]);
+    }
+
+    #[test]
+    fn seek_mixed_blocks() {
+        let mut data = random_data(100000, 3);
+        for i in 0 .. 20000 {
+            data.extend_from_slice(format!("{} ", i % 1000).as_bytes());
+        }
+        data.extend(random_data(100000, 4));
+
+        let mut inflater = IndexedInflater::new(Cursor::new(compress(&data, 6))).unwrap();
+        let end = data.len() as u64;
+
+        assert_eq!(read_at(&mut inflater, end - 1000, 1000), &data[data.len() - 1000 ..]);
+        assert_eq!(read_at(&mut inflater, 120000, 1000), &data[120000 .. 121000]);
+        assert_eq!(read_at(&mut inflater, 50000, 1000), &data[50000 .. 51000]);
+        assert_eq!(read_at(&mut inflater, end - 50000, 1000),
+                   &data[data.len() - 50000 .. data.len() - 49000]);
+
+        // the random data is stored, while the text is compressed
+        assert!(inflater.blocks.iter().any(|b| b.decompressed_offset < 100000));
+        assert!(inflater.blocks.iter().any(|b| b.decompressed_offset > end - 100000));
+        assert!(inflater.blocks.iter().all(|b| b.end() <= 100000 ||
+                                               b.decompressed_offset >= end - 100000));
+
+        let mut output = Vec::new();
+        inflater.seek(SeekFrom::Start(0)).unwrap();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    fn seek_past_end() {
+        let data = b"hello world";
+        let mut inflater = IndexedInflater::new(Cursor::new(compress(data, 6))).unwrap();
+
+        assert_eq!(inflater.seek(SeekFrom::Start(100)).unwrap(), 11);
+        assert_eq!(inflater.read(&mut [0; 4]).unwrap(), 0);
+        assert!(inflater.seek(SeekFrom::Current(-20)).is_err());
+        assert_eq!(read_at(&mut inflater, 6, 5), b"world");
+    }
+}
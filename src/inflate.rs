@@ -1,23 +1,158 @@
 //! An Implementation of RFC 1951
 
-use std::io::{ErrorKind, Read};
-use std::io::Error as IoError;
-use std::io::Result as IoResult;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::{cmp, mem};
+use io::{ErrorKind, Read, Write};
+use io::Error as IoError;
+use io::Result as IoResult;
 
 use bit::BitRead;
-use compressed_block_reader::CompressedBlockReader;
+use compressed_block_reader::{CompressedBlockReader, DynamicTables};
+use error::DecodeError;
+#[cfg(feature = "metrics")]
+use metrics::{DecodeMetrics, Recorder};
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+use tee::Tee;
+use window::Window;
 
-/// Reads data from an underlying reader and decodes it.
-pub struct Inflater<R> where R: Read {
+/// Maximum distance of a pointer in DEFLATE data, and default window size of the inflater.
+pub const DEFAULT_WINDOW_SIZE: usize = 32768;
+
+/// A reader that decodes raw DEFLATE data, without any zlib or gzip header, from an underlying
+/// reader.
+///
+/// The data that pointers can reference is stored in a `Vec<u8>` by default. See
+/// `with_window_storage` to store it elsewhere.
+///
+/// If the reader can be cloned, so can the inflater, for example to save its state before
+/// decoding ahead and to go back to it afterwards. The clone has its own copy of the window
+/// and of the Huffman tables, and continues from the same point. This only makes sense for
+/// readers whose clones read the same data from the same position, such as `&[u8]` or a
+/// `Cursor`: cloning a reader over a socket or a pipe, if possible, doesn't rewind it.
+#[derive(Clone)]
+pub struct Inflater<R, W = Vec<u8>> where R: Read, W: Window {
     /// Since the algorithm can require us to copy previous data in the stream, we have to
-    /// keep a cache of the already decoded data.
-    output_cache: Vec<u8>,
+    /// keep a cache of the already decoded data. Only the last `window_size` bytes are needed,
+    /// and the rest can be discarded.
+    output_cache: W,
+
+    /// Maximum distance of a pointer to previous data.
+    window_size: usize,
 
     /// If this ever becomes `None`, that means an IoError occured somewhere.
-    state: Option<InflaterState<R>>,
+    state: Option<InflaterState<CountingReader<R>>>,
+
+    /// Number of bytes read from the underlying reader, as of the end of the last call to
+    /// `read` that didn't fail.
+    total_in: u64,
+
+    /// Number of bytes decoded so far.
+    total_out: u64,
+
+    /// Length and `bfinal` value of the non-empty uncompressed block that the last call to
+    /// `read` has started reading, if any. See `take_stored_block_start`. The length of an
+    /// uncompressed block always fits in a `u16`.
+    stored_block_start: Option<(u16, bool)>,
+
+    /// True if the last block that has been read is an empty non-final uncompressed block.
+    sync_boundary: bool,
+
+    /// If true, `read` returns 0 after reading an empty non-final uncompressed block.
+    stop_at_sync_flush: bool,
+
+    /// If true, the padding bits after the header of an uncompressed block must be zero.
+    check_padding: bool,
+
+    /// If true, the dynamic blocks can define incomplete Huffman codes. See
+    /// `set_accept_incomplete_codes`.
+    accept_incomplete_codes: bool,
+
+    /// Number of blocks of each type encountered so far.
+    block_stats: BlockStats,
+
+    /// Number of bytes decoded from literals and from pointers so far. See `literal_bytes`.
+    literal_bytes: u64,
+    match_bytes: u64,
+
+    /// Position and type of each block whose header has been read, if `record_block_offsets`
+    /// is true.
+    block_offsets: Vec<BlockOffset>,
+    record_block_offsets: bool,
+
+    /// Data decoded by the call to `read` that has returned an error. See `recovered_output`.
+    recovered_output: Vec<u8>,
+
+    /// Type and data decoded so far of the block that `next_block` has started decoding, if
+    /// the underlying reader has returned `WouldBlock` or `Interrupted` in the middle of it.
+    pending_block: Option<(BlockType, Vec<u8>)>,
+
+    /// Number of bits skipped after the last block to reach the next byte boundary. See
+    /// `end_padding_bits`.
+    end_padding_bits: u8,
+
+    /// Measurements returned by `metrics`. Boxed, so that the inflater, which is itself stored
+    /// in the states of the other decoders, stays small.
+    #[cfg(feature = "metrics")]
+    metrics: Box<Recorder>,
+}
+
+/// Number of blocks of each type that an inflater has encountered, including the one being
+/// decoded.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct BlockStats {
+    /// Number of uncompressed blocks, including the empty ones.
+    pub stored: u64,
+
+    /// Number of blocks compressed with the fixed Huffman codes.
+    pub fixed: u64,
+
+    /// Number of blocks compressed with Huffman codes defined in the block.
+    pub dynamic: u64,
+}
+
+/// Position of a block in the DEFLATE data, recorded by the inflater. See
+/// `Inflater::block_offsets`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BlockOffset {
+    /// Position in bits of the header of the block, from the start of the data read by the
+    /// inflater. The header starts at byte `bit_position / 8`, at its bit `bit_position % 8`
+    /// counting from the least significant bit.
+    pub bit_position: u64,
+
+    /// How the data of the block is encoded.
+    pub block_type: BlockType,
+}
+
+/// A block decoded by `Inflater::next_block`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockInfo {
+    /// How the data of the block is encoded.
+    pub block_type: BlockType,
+
+    /// True if the `bfinal` bit of the block is set, meaning that this is the last block.
+    pub last_block: bool,
+
+    /// Data decoded from the block.
+    pub data: Vec<u8>,
+}
+
+/// Type of a DEFLATE block.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockType {
+    /// Uncompressed data.
+    Stored,
+
+    /// Data compressed with the Huffman codes defined by the RFC.
+    Fixed,
+
+    /// Data compressed with Huffman codes defined at the start of the block.
+    Dynamic,
 }
 
 /// State of the inflater.
+#[derive(Clone)]
 enum InflaterState<R> where R: Read {
     /// We are outside of any block.
     BeforeBlockStart {
@@ -39,8 +174,9 @@ enum InflaterState<R> where R: Read {
     },
 
     CompressedData {
-        /// The data to read from. Returns EOF at the end of the block.
-        data: CompressedBlockReader<R>,
+        /// The data to read from. Returns EOF at the end of the block. Boxed, as it is much
+        /// larger than the other states.
+        data: Box<CompressedBlockReader<R>>,
 
         /// If true, then we have read a block header whose `bfinal` value is true, meaning that
         /// this is the last block of the stream.
@@ -54,38 +190,741 @@ enum InflaterState<R> where R: Read {
     },
 }
 
+/// Wrapper around the underlying reader that counts the bytes read from it.
+#[derive(Clone)]
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R> Read for CountingReader<R> where R: Read {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let result = self.inner.read(buf)?;
+        self.count += result as u64;
+        Ok(result)
+    }
+}
+
+/// Builds a `BitRead` that starts at the current byte of `data`, so that the positions of
+/// decode errors keep counting from the start of the DEFLATE data.
+fn aligned_bit_reader<R>(data: CountingReader<R>) -> BitRead<CountingReader<R>>
+    where R: Read
+{
+    let position = data.count * 8;
+    let mut data = BitRead::new(data);
+    data.set_bit_position(position);
+    data
+}
+
 impl<R> Inflater<R> where R: Read {
     /// Initializes a new inflater.
     pub fn new(inner: R) -> Inflater<R> {
+        Inflater::with_output_cache(inner, Vec::with_capacity(2 * DEFAULT_WINDOW_SIZE), &[])
+    }
+
+    /// Initializes a new inflater whose pointers can go at most `window_size` bytes back,
+    /// instead of 32768. Decoding a pointer that goes further back returns an error.
+    ///
+    /// This is meant for formats derived from DEFLATE whose window has another size. Standard
+    /// DEFLATE data can't reference more than 32 kiB back, and `ZlibDecoder` and `GzipDecoder`
+    /// always use the size that their format allows.
+    pub fn with_window_size(inner: R, window_size: usize) -> Inflater<R> {
+        let output_cache = Vec::with_capacity(2 * window_size);
+        let mut inflater = Inflater::with_output_cache(inner, output_cache, &[]);
+        inflater.window_size = window_size;
+        inflater
+    }
+
+    /// Initializes a new inflater whose data can reference `dictionary` as if it had been
+    /// decoded just before.
+    pub fn with_dictionary(inner: R, dictionary: &[u8]) -> Inflater<R> {
+        Inflater::with_output_cache(inner, Vec::with_capacity(2 * DEFAULT_WINDOW_SIZE), dictionary)
+    }
+
+    /// Initializes a new inflater whose data can reference the last 32 kiB of `initial` as if
+    /// they had been decoded just before, for example in order to resume an interrupted stream.
+    ///
+    /// Contrary to `with_dictionary`, `initial` isn't copied: it becomes the buffer where the
+    /// decoded data is stored, and the data before its last 32 kiB is discarded.
+    pub fn with_window(inner: R, mut initial: Vec<u8>) -> Inflater<R> {
+        let excess = initial.len().saturating_sub(DEFAULT_WINDOW_SIZE);
+        initial.drain(.. excess);
+
+        let mut inflater = Inflater::with_window_storage(inner, Vec::new());
+        inflater.output_cache = initial;
+        inflater
+    }
+
+    /// Same as `with_dictionary`, but reuses `output_cache` to store the decoded data instead
+    /// of allocating a new buffer. Its content is discarded. See `take_output_cache`.
+    pub(crate) fn with_output_cache(inner: R, output_cache: Vec<u8>, dictionary: &[u8])
+                                    -> Inflater<R>
+    {
+        let mut inflater = Inflater::with_window_storage(inner, output_cache);
+        inflater.output_cache.extend_from_slice(dictionary);
+        inflater
+    }
+
+    /// Initializes an inflater that resumes decoding in the middle of an uncompressed block.
+    ///
+    /// `inner` must be positioned on the data of the block, of which `len` bytes remain, and
+    /// `history` must contain the data decoded before this point, or at least the last 32 kiB of
+    /// it.
+    #[cfg(feature = "std")]
+    pub(crate) fn in_stored_block(inner: R, history: &[u8], len: usize, last_block: bool)
+                                  -> Inflater<R>
+    {
+        assert!(len != 0);
+
+        let mut output_cache = Vec::with_capacity(history.len() + 2 * DEFAULT_WINDOW_SIZE);
+        output_cache.extend_from_slice(history);
+
+        Inflater {
+            output_cache,
+            window_size: DEFAULT_WINDOW_SIZE,
+            state: Some(InflaterState::UncompressedData {
+                data: CountingReader { inner, count: 0 },
+                len,
+                last_block,
+            }),
+            total_in: 0,
+            total_out: 0,
+            stored_block_start: None,
+            sync_boundary: false,
+            stop_at_sync_flush: false,
+            check_padding: false,
+            accept_incomplete_codes: false,
+            block_stats: BlockStats::default(),
+            literal_bytes: 0,
+            match_bytes: 0,
+            block_offsets: Vec::new(),
+            record_block_offsets: false,
+            recovered_output: Vec::new(),
+            pending_block: None,
+            end_padding_bits: 0,
+            #[cfg(feature = "metrics")]
+            metrics: Box::new(Recorder::new()),
+        }
+    }
+
+    /// Returns the data that the next pointers can reference, which is the last `window_size`
+    /// bytes decoded so far, preceded by the dictionary if there is one and nothing has been
+    /// decoded yet.
+    ///
+    /// The slice isn't copied. Its content changes with each call to `read`.
+    pub fn window(&self) -> &[u8] {
+        let start = self.output_cache.len().saturating_sub(self.window_size);
+        &self.output_cache[start ..]
+    }
+
+    /// Takes the buffer where the decoded data is stored, so that another inflater can reuse it
+    /// with `with_output_cache`. The inflater must not be used to decode anything afterwards.
+    pub(crate) fn take_output_cache(&mut self) -> Vec<u8> {
+        mem::take(&mut self.output_cache)
+    }
+}
+
+impl<R, S> Inflater<Tee<R, S>> where R: Read, S: Write {
+    /// Initializes a new inflater that writes to `sink` all the compressed bytes that it reads
+    /// from `inner`, for example to forward the original data while inspecting it. See `Tee`.
+    ///
+    /// Once the last block has been read, the sink contains exactly the compressed data. The
+    /// reader and the sink can be retrieved with `into_inner().into_inner()`.
+    pub fn new_teeing(inner: R, sink: S) -> Inflater<Tee<R, S>> {
+        Inflater::new(Tee::new(inner, sink))
+    }
+}
+
+impl<R, W> Inflater<R, W> where R: Read, W: Window {
+    /// Initializes a new inflater that stores the data that pointers can reference in
+    /// `window` instead of a `Vec<u8>`, for example a `FixedWindow` in order not to allocate
+    /// the window on the heap. The content of `window` is discarded.
+    ///
+    /// The window size is limited to the capacity of `window`, so that decoding a pointer that
+    /// goes further back returns an error.
+    pub fn with_window_storage(inner: R, mut window: W) -> Inflater<R, W> {
+        window.clear();
+
         Inflater {
-            output_cache: Vec::with_capacity(32768 + 258),
+            window_size: cmp::min(DEFAULT_WINDOW_SIZE, window.capacity()),
+            output_cache: window,
             state: Some(InflaterState::BeforeBlockStart {
-                data: BitRead::new(inner)
-            })
+                data: BitRead::new(CountingReader { inner, count: 0 })
+            }),
+            total_in: 0,
+            total_out: 0,
+            stored_block_start: None,
+            sync_boundary: false,
+            stop_at_sync_flush: false,
+            check_padding: false,
+            accept_incomplete_codes: false,
+            block_stats: BlockStats::default(),
+            literal_bytes: 0,
+            match_bytes: 0,
+            block_offsets: Vec::new(),
+            record_block_offsets: false,
+            recovered_output: Vec::new(),
+            pending_block: None,
+            end_padding_bits: 0,
+            #[cfg(feature = "metrics")]
+            metrics: Box::new(Recorder::new()),
+        }
+    }
+
+    /// Sets the maximum distance of the pointers to previous data, which is 32768 by default.
+    /// Decoding a pointer that goes further back returns an error.
+    ///
+    /// The window size is never larger than the capacity of the window storage.
+    pub fn set_window_size(&mut self, window_size: usize) {
+        self.window_size = cmp::min(window_size, self.output_cache.capacity());
+    }
+
+    /// Forgets the data decoded so far, so that the next blocks can't reference it, without
+    /// changing the position in the compressed data.
+    ///
+    /// This is meant to be called between blocks, for example at a sync boundary when each
+    /// message of a protocol must be decodable on its own. Calling it while a block is being
+    /// decoded is a logic error: the rest of the block would most likely fail to decode.
+    pub fn reset_window(&mut self) {
+        self.output_cache.clear();
+    }
+
+    /// If true, `read` returns 0 when it reaches a sync flush, which is an empty non-final
+    /// uncompressed block (`00 00 FF FF` after the block header), instead of continuing with the
+    /// next block. The next call to `read` then decodes the following blocks normally.
+    ///
+    /// This lets a protocol that splits a single stream into messages at sync flushes, which
+    /// never sets `bfinal`, decode one message at a time. Disabled by default.
+    pub fn set_stop_at_sync_flush(&mut self, stop: bool) {
+        self.stop_at_sync_flush = stop;
+    }
+
+    /// If true, the bits between the header of an uncompressed block and the next byte boundary
+    /// must be zero, and decoding returns a `DecodeError::NonZeroPadding` error otherwise.
+    ///
+    /// The RFC says that these bits are ignored, but encoders always set them to zero, so other
+    /// values are a sign of corrupted data. Disabled by default.
+    pub fn set_check_padding(&mut self, check: bool) {
+        self.check_padding = check;
+    }
+
+    /// If true, the Huffman codes defined by dynamic blocks can be incomplete, meaning that
+    /// some sequences of bits don't start with any code. Decoding then only returns a
+    /// `DecodeError::BadHuffmanCode` error if such a sequence is actually found in the data.
+    ///
+    /// By default, these codes are malformed and return a `DecodeError::InvalidHuffmanTable`
+    /// error, except for a code made of a single code of one bit, which the RFC allows for the
+    /// distances of a block that only uses one distance. Over-subscribed codes are always
+    /// rejected. Enabling this is meant for data produced by broken encoders that other
+    /// decoders accept.
+    pub fn set_accept_incomplete_codes(&mut self, accept: bool) {
+        self.accept_incomplete_codes = accept;
+    }
+
+    /// If true, the position and type of each block are recorded as the data is decoded, see
+    /// `block_offsets`. Disabled by default, as the list grows with the number of blocks, which
+    /// isn't bounded.
+    pub fn set_record_block_offsets(&mut self, record: bool) {
+        self.record_block_offsets = record;
+    }
+
+    /// Returns true if the last block that has been consumed is a sync flush, meaning an empty
+    /// non-final uncompressed block.
+    ///
+    /// Uncompressed blocks end on a byte boundary, so in that situation all the bytes read
+    /// from the underlying reader have been entirely consumed, the last four of them being
+    /// `00 00 FF FF`, and the next block starts on the next byte.
+    pub fn at_sync_boundary(&self) -> bool {
+        self.sync_boundary
+    }
+
+    /// Returns the length and the `bfinal` value of the uncompressed block whose header was read
+    /// by the last call to `read`, if any, and resets it.
+    ///
+    /// All the bytes returned by that call to `read` come from this block.
+    #[cfg(feature = "std")]
+    pub(crate) fn take_stored_block_start(&mut self) -> Option<(usize, bool)> {
+        self.stored_block_start.take().map(|(len, last_block)| (len as usize, last_block))
+    }
+
+    /// Returns true if the last block of the data has been entirely decoded.
+    ///
+    /// `read` returns 0 in that situation, but can also return 0 at a sync flush, see
+    /// `set_stop_at_sync_flush`.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, Some(InflaterState::Eof { .. }))
+    }
+
+    /// Returns the number of bits between the end of the last block and the next byte
+    /// boundary, between 0 and 7. These bits are skipped, and the data that follows the
+    /// DEFLATE data, such as the trailer of zlib data, starts at the next byte.
+    ///
+    /// This is 0 until the last block has been decoded, and for data that ends with an
+    /// uncompressed block, which always ends on a byte boundary. Together with `total_in`, it
+    /// gives the exact position in bits of the end of the data.
+    pub fn end_padding_bits(&self) -> u8 {
+        self.end_padding_bits
+    }
+
+    /// Returns the number of bytes read from the underlying reader so far.
+    ///
+    /// The data is read byte by byte, so this is exactly the number of bytes of compressed data
+    /// that have been consumed. If a call to `read` returns an error, this is the value before
+    /// that call.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// Returns the number of bytes decoded so far.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    /// Returns the number of blocks of each type encountered so far. A block is counted as soon
+    /// as its header starts being read.
+    pub fn block_stats(&self) -> &BlockStats {
+        &self.block_stats
+    }
+
+    /// Returns the number of bytes decoded so far from the literals of the compressed blocks.
+    ///
+    /// The rest of the output comes from pointers to previous data, see `match_bytes`, and from
+    /// uncompressed blocks. Together with `block_stats`, this tells how well the data has been
+    /// compressed.
+    pub fn literal_bytes(&self) -> u64 {
+        self.literal_bytes
+    }
+
+    /// Returns the number of bytes decoded so far by copying previous data, as pointed to by
+    /// the compressed blocks. See `literal_bytes`.
+    pub fn match_bytes(&self) -> u64 {
+        self.match_bytes
+    }
+
+    /// Returns the position and type of each block whose header has been read so far, in the
+    /// order of the data.
+    ///
+    /// This is empty unless `set_record_block_offsets` has been called, and only contains the
+    /// blocks whose header has been read after that.
+    pub fn block_offsets(&self) -> &[BlockOffset] {
+        &self.block_offsets
+    }
+
+    /// Returns the measurements of the decoding so far.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> DecodeMetrics {
+        self.metrics.snapshot(self.total_in, self.total_out)
+    }
+
+    /// Decodes the next block entirely and returns it, or returns `None` if the last block has
+    /// already been decoded.
+    ///
+    /// This must not be mixed with calls to `read`, which can stop in the middle of a block. If
+    /// the inflater is in the middle of a block, an error is returned.
+    ///
+    /// If the underlying reader returns `WouldBlock` or `Interrupted`, the data decoded so far is
+    /// kept and the next call continues the block.
+    pub fn next_block(&mut self) -> IoResult<Option<BlockInfo>> {
+        let (block_type, mut output) = match self.pending_block.take() {
+            Some(pending) => pending,
+            None => match self.start_next_block()? {
+                Some(block_type) => (block_type, Vec::new()),
+                None => return Ok(None),
+            },
+        };
+
+        let mut buf = [0; 4096];
+
+        let last_block = loop {
+            match self.state {
+                Some(InflaterState::BeforeBlockStart { .. }) => break false,
+                Some(InflaterState::Eof { .. }) => break true,
+                _ => (),
+            }
+
+            match self.decode_block_data(&mut buf) {
+                Ok(len) => output.extend_from_slice(&buf[.. len]),
+                Err(err) => {
+                    // the next call continues the block
+                    if ::is_retryable(&err) {
+                        self.pending_block = Some((block_type, output));
+                    }
+                    return Err(err);
+                },
+            }
+        };
+
+        self.total_in = self.counting_reader().map_or(self.total_in, |reader| reader.count);
+        self.total_out += output.len() as u64;
+
+        Ok(Some(BlockInfo {
+            block_type,
+            last_block,
+            data: output,
+        }))
+    }
+
+    /// Reads the header of the next block for `next_block`, and returns its type, or `None` at
+    /// the end of the data.
+    fn start_next_block(&mut self) -> IoResult<Option<BlockType>> {
+        let data = match self.state.take() {
+            Some(InflaterState::BeforeBlockStart { data }) => data,
+            Some(state @ InflaterState::Eof { .. }) => {
+                self.state = Some(state);
+                return Ok(None);
+            },
+            Some(state) => {
+                self.state = Some(state);
+                return Err(IoError::new(ErrorKind::InvalidInput,
+                                        "The inflater is in the middle of a block"));
+            },
+            None => return Err(IoError::new(ErrorKind::InvalidInput,
+                                            "I/O errors in the inflater are unrecoverable")),
+        };
+
+        let stats = self.block_stats;
+        self.start_block(data)?;
+
+        Ok(Some(if self.block_stats.stored != stats.stored {
+            BlockType::Stored
+        } else if self.block_stats.fixed != stats.fixed {
+            BlockType::Fixed
+        } else {
+            BlockType::Dynamic
+        }))
+    }
+
+    /// Decodes data into `buf` like `read`, but stops at the end of a block once `max_blocks`
+    /// blocks have ended during this call. Returns the number of bytes written to `buf`.
+    ///
+    /// If the inflater is in the middle of a block, the rest of that block counts as one block.
+    /// Empty blocks count as well, so this returns 0 if `max_blocks` is 1 and the next block is
+    /// empty, in addition to the situations where `read` returns 0. The call also stops once
+    /// `buf` is full, so a block larger than `buf` only advances within that block and doesn't
+    /// count as ended: the next call continues it.
+    ///
+    /// If the underlying reader returns `WouldBlock` or `Interrupted` after some data has been
+    /// decoded, that data is returned and the next call continues where this one stopped.
+    pub fn read_up_to_blocks(&mut self, max_blocks: usize, buf: &mut [u8]) -> IoResult<usize> {
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+
+        let result = self.decode_up_to_blocks(max_blocks, buf);
+
+        #[cfg(feature = "metrics")]
+        self.metrics.add_time(start.elapsed());
+
+        let result = result?;
+        self.total_in = self.counting_reader().map_or(self.total_in, |reader| reader.count);
+        self.total_out += result as u64;
+        Ok(result)
+    }
+
+    /// Returns a reference to the underlying reader.
+    ///
+    /// # Panic
+    ///
+    /// Panics if a previous call to `read` returned an error.
+    pub fn get_ref(&self) -> &R {
+        match self.counting_reader() {
+            Some(reader) => &reader.inner,
+            None => panic!("I/O errors in the inflater are unrecoverable"),
+        }
+    }
+
+    /// Returns a mutable reference to the underlying reader.
+    ///
+    /// Reading from it directly skips data that the inflater expects to decode.
+    ///
+    /// # Panic
+    ///
+    /// Panics if a previous call to `read` returned an error.
+    pub fn get_mut(&mut self) -> &mut R {
+        match self.try_get_mut() {
+            Some(reader) => reader,
+            None => panic!("I/O errors in the inflater are unrecoverable"),
+        }
+    }
+
+    /// Same as `get_mut`, but returns `None` instead of panicking.
+    pub(crate) fn try_get_mut(&mut self) -> Option<&mut R> {
+        let reader = match self.state {
+            Some(InflaterState::BeforeBlockStart { ref mut data }) => data.get_mut(),
+            Some(InflaterState::UncompressedData { ref mut data, .. }) => data,
+            Some(InflaterState::CompressedData { ref mut data, .. }) => {
+                data.get_mut().get_mut()
+            },
+            Some(InflaterState::Eof { ref mut data }) => data,
+            None => return None,
+        };
+
+        Some(&mut reader.inner)
+    }
+
+    /// If the last block has been read, prepares to read another block from the underlying
+    /// reader as if the last block didn't have `bfinal` set. The window is kept.
+    pub(crate) fn continue_after_last_block(&mut self) {
+        self.state = match self.state.take() {
+            Some(InflaterState::Eof { data }) => {
+                Some(InflaterState::BeforeBlockStart { data: aligned_bit_reader(data) })
+            },
+            state => state,
+        };
+    }
+
+    fn counting_reader(&self) -> Option<&CountingReader<R>> {
+        match self.state {
+            Some(InflaterState::BeforeBlockStart { ref data }) => Some(data.get_ref()),
+            Some(InflaterState::UncompressedData { ref data, .. }) => Some(data),
+            Some(InflaterState::CompressedData { ref data, .. }) => Some(data.get_ref().get_ref()),
+            Some(InflaterState::Eof { ref data }) => Some(data),
+            None => None,
         }
     }
+
+    /// Destroys the inflater and returns the data that has been decoded by the call to `read`
+    /// that returned an error, before the error. This data has been written to the buffer
+    /// given to `read`, but isn't reported by the error.
+    ///
+    /// Together with the data returned by the previous calls to `read`, this is all the data
+    /// that precedes the point where the decoding failed. This is meant for tools that salvage
+    /// what they can from corrupted data: the data is only as trustworthy as the compressed
+    /// data before the error, which can be corrupted in a way that isn't detected right away.
+    ///
+    /// Returns an empty buffer if no call to `read` has returned an error.
+    pub fn recovered_output(self) -> Vec<u8> {
+        self.recovered_output
+    }
+
+    /// Destroys the inflater and returns the underlying reader.
+    ///
+    /// Once the last block has been read, the reader is positioned right after the compressed
+    /// data. Otherwise it is positioned somewhere in the middle of it.
+    ///
+    /// # Panic
+    ///
+    /// Panics if a previous call to `read` returned an error.
+    pub fn into_inner(self) -> R {
+        let reader = match self.state {
+            Some(InflaterState::BeforeBlockStart { data }) => data.byte_align_unwrap(),
+            Some(InflaterState::UncompressedData { data, .. }) => data,
+            Some(InflaterState::CompressedData { data, .. }) => {
+                data.into_inner().byte_align_unwrap()
+            },
+            Some(InflaterState::Eof { data }) => data,
+            None => panic!("I/O errors in the inflater are unrecoverable"),
+        };
+
+        reader.inner
+    }
 }
 
-impl<R> Read for Inflater<R> where R: Read {
+impl<'a> Inflater<&'a [u8]> {
+    /// Initializes a new inflater that reads the data from a slice, without wrapping it in a
+    /// `Cursor`.
+    pub fn from_slice(data: &'a [u8]) -> Inflater<&'a [u8]> {
+        Inflater::new(data)
+    }
+}
+
+impl<R, W> Read for Inflater<R, W> where R: Read, W: Window {
+    #[cfg(feature = "read_buf")]
+    fn read_buf(&mut self, cursor: ::std::io::BorrowedCursor<'_>) -> IoResult<()> {
+        ::read_buf_chunk(self, cursor)
+    }
+
     fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
-        match self.state.take() {
-            Some(InflaterState::BeforeBlockStart { data }) => {
-                self.state = Some(try!(consume_block_start(data)));
-                self.read(buf)
+        // a read into an empty buffer must not consume anything, nor be mistaken for the end
+        // of the data
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+
+        let result = self.decode(buf);
+
+        #[cfg(feature = "metrics")]
+        self.metrics.add_time(start.elapsed());
+
+        let result = result?;
+        self.total_in = self.counting_reader().map_or(self.total_in, |reader| reader.count);
+        self.total_out += result as u64;
+        Ok(result)
+    }
+}
+
+impl<R, W> Inflater<R, W> where R: Read, W: Window {
+    /// Decodes some data into `buf`. Same as `read`, but without updating the counters.
+    fn decode(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        // the rest of a block started by `next_block` is returned by `read` instead
+        self.pending_block = None;
+
+        // a loop rather than recursion, as a stream can contain any number of empty blocks
+        loop {
+            match self.state.take() {
+                Some(InflaterState::BeforeBlockStart { data }) => {
+                    self.start_block(data)?;
+
+                    if self.sync_boundary && self.stop_at_sync_flush {
+                        return Ok(0);
+                    }
+                },
+
+                Some(state @ InflaterState::UncompressedData { .. }) |
+                Some(state @ InflaterState::CompressedData { .. }) => {
+                    self.state = Some(state);
+
+                    match self.decode_block_data(buf)? {
+                        0 => (),
+                        len => return Ok(len),
+                    }
+                },
+
+                Some(InflaterState::Eof { data }) => {
+                    self.state = Some(InflaterState::Eof { data });
+                    return Ok(0);
+                },
+
+                None => return Err(IoError::new(ErrorKind::InvalidInput,
+                                                "I/O errors in the inflater are unrecoverable")),
+            }
+        }
+    }
+
+    /// Implementation of `read_up_to_blocks`, without updating the counters.
+    fn decode_up_to_blocks(&mut self, max_blocks: usize, buf: &mut [u8]) -> IoResult<usize> {
+        self.pending_block = None;
+        let mut written = 0;
+        let mut ended_blocks = 0;
+
+        while ended_blocks < max_blocks && written < buf.len() {
+            let result = match self.state.take() {
+                Some(InflaterState::BeforeBlockStart { data }) => {
+                    self.start_block(data).map(|()| 0)
+                },
+
+                Some(state @ InflaterState::UncompressedData { .. }) |
+                Some(state @ InflaterState::CompressedData { .. }) => {
+                    self.state = Some(state);
+                    self.decode_block_data(&mut buf[written ..])
+                },
+
+                Some(InflaterState::Eof { data }) => {
+                    self.state = Some(InflaterState::Eof { data });
+                    break;
+                },
+
+                None => Err(IoError::new(ErrorKind::InvalidInput,
+                                         "I/O errors in the inflater are unrecoverable")),
+            };
+
+            match result {
+                Ok(len) => written += len,
+                Err(ref err) if written != 0 && ::is_retryable(err) => break,
+                Err(err) => {
+                    // the data decoded by the previous iterations is lost as well
+                    let mut recovered = buf[.. written].to_vec();
+                    recovered.append(&mut self.recovered_output);
+                    self.recovered_output = recovered;
+                    return Err(err);
+                },
+            }
+
+            match self.state {
+                Some(InflaterState::BeforeBlockStart { .. }) |
+                Some(InflaterState::Eof { .. }) => ended_blocks += 1,
+                _ => (),
+            }
+
+            if self.sync_boundary && self.stop_at_sync_flush {
+                break;
+            }
+        }
+
+        Ok(written)
+    }
+
+    /// Reads the header of the block that starts at the start of `data`, and puts the inflater
+    /// in the corresponding state.
+    fn start_block(&mut self, mut data: BitRead<CountingReader<R>>) -> IoResult<()> {
+        // if the underlying reader returns a retryable error in the middle of the header, we go
+        // back to the start of the block so that it can be read again once there is more data
+        data.checkpoint();
+        let stats = self.block_stats;
+        let bit_position = data.bit_position();
+
+        let result = match data.is_at_eof() {
+            // a stream that stops between two blocks and hasn't had its last block yet is
+            // truncated, even though no block is cut in the middle
+            Ok(true) => Err(DecodeError::UnexpectedEof.into()),
+            Ok(false) => read_block_header(&mut data, &mut self.block_stats, self.check_padding,
+                                           self.accept_incomplete_codes),
+            Err(err) => Err(err),
+        };
+
+        let header = match result {
+            Ok(header) => header,
+            Err(err) => {
+                if ::is_retryable(&err) {
+                    data.rollback();
+                    self.block_stats = stats;
+                    self.state = Some(InflaterState::BeforeBlockStart { data });
+                }
+                return Err(err);
             },
+        };
+
+        if self.record_block_offsets {
+            self.block_offsets.push(BlockOffset { bit_position,
+                                                  block_type: header.block_type() });
+        }
+        #[cfg(feature = "metrics")]
+        self.metrics.start_block(header.block_type());
+
+        let state = enter_block(data, header);
+        if let InflaterState::UncompressedData { len, last_block, .. } = state {
+            self.stored_block_start = Some((len as u16, last_block));
+        }
+
+        // only an empty non-final uncompressed block leads directly to another block
+        self.sync_boundary = matches!(state, InflaterState::BeforeBlockStart { .. });
+        self.state = Some(state);
+        Ok(())
+    }
 
+    /// Decodes some data of the current block into `buf`. Returns 0 once the end of the block
+    /// has been reached, in which case the inflater is either before the next block or at the
+    /// end of the data.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the inflater isn't inside of a block.
+    ///
+    fn decode_block_data(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        match self.state.take() {
             Some(InflaterState::UncompressedData { mut data, len, last_block }) => {
                 assert!(len != 0);
 
-                let result = try!(if buf.len() > len {
+                let result = if buf.len() > len {
                     data.read(&mut buf[..len])
                 } else {
                     data.read(buf)
-                });
+                };
+                let result = match result {
+                    Ok(result) => result,
+                    Err(err) => {
+                        // nothing has been read, so the next call can try again
+                        if ::is_retryable(&err) {
+                            self.state = Some(InflaterState::UncompressedData { data, len,
+                                                                                last_block });
+                        }
+                        return Err(err);
+                    },
+                };
 
-                for b in &buf[..result] {
-                    self.output_cache.push(*b);
-                }
+                self.push_output(&buf[..result]);
 
                 if result == 0 {
                     Err(IoError::new(ErrorKind::InvalidInput,
@@ -93,94 +932,151 @@ impl<R> Read for Inflater<R> where R: Read {
 
                 } else if result == len {
                     if last_block {
-                        self.state = Some(InflaterState::Eof { data: data });
+                        self.state = Some(InflaterState::Eof { data });
                     } else {
                         self.state = Some(InflaterState::BeforeBlockStart {
-                                              data: BitRead::new(data)
+                                              data: aligned_bit_reader(data)
                                           });
                     }
                     Ok(result)
 
                 } else {
-                    self.state = Some(InflaterState::UncompressedData { data: data,
+                    self.state = Some(InflaterState::UncompressedData { data,
                                                                         len: len - result,
-                                                                        last_block: last_block });
+                                                                        last_block });
                     Ok(result)
                 }
             },
 
             Some(InflaterState::CompressedData { mut data, last_block }) => {
-                let result = try!(data.with_previous_data(&self.output_cache).read(buf));
-
-                for b in &buf[..result] {
-                    self.output_cache.push(*b);
-                }
+                let result = data.with_previous_data(&self.output_cache, self.window_size)
+                                 .read(buf);
+                let (literal_bytes, match_bytes) = data.take_output_counts();
+                self.literal_bytes += literal_bytes;
+                self.match_bytes += match_bytes;
+                let result = match result {
+                    Ok(result) => result,
+                    Err(err) => {
+                        if ::is_retryable(&err) {
+                            // the block reader has gone back to the start of the symbol that it
+                            // was decoding, and nothing has been written to `buf`
+                            self.state = Some(InflaterState::CompressedData { data, last_block });
+                        } else {
+                            let written = data.written_before_error();
+                            self.recovered_output.extend_from_slice(&buf[.. written]);
+                        }
+                        return Err(err);
+                    },
+                };
+                self.push_output(&buf[..result]);
 
                 if result == 0 {
                     if last_block {
-                        self.state = Some(InflaterState::Eof {
-                                              data: data.into_inner().byte_align_unwrap()
-                                          });
+                        let data = data.into_inner();
+                        self.end_padding_bits = (8 - data.bit_position() % 8) as u8 % 8;
+                        self.state = Some(InflaterState::Eof { data: data.byte_align_unwrap() });
                     } else {
                         self.state = Some(InflaterState::BeforeBlockStart {
                                               data: data.into_inner()
                                           });
                     }
-
-                    self.read(buf)
-
                 } else {
-                    self.state = Some(InflaterState::CompressedData { data: data,
-                                                                      last_block: last_block });
-                    Ok(result)
+                    self.state = Some(InflaterState::CompressedData { data,
+                                                                      last_block });
                 }
-            },
 
-            Some(InflaterState::Eof { data }) => {
-                self.state = Some(InflaterState::Eof { data: data });
-                return Ok(0);
+                Ok(result)
             },
 
-            None => return Err(IoError::new(ErrorKind::InvalidInput,
-                                            "I/O errors in the inflater are unrecoverable"))
+            _ => panic!("The inflater isn't inside of a block"),
+        }
+    }
+
+    /// Adds decoded data to the cache, and discards the data that is out of the window.
+    fn push_output(&mut self, data: &[u8]) {
+        #[cfg(feature = "metrics")]
+        self.metrics.add_output(data.len());
+
+        self.output_cache.push(data, self.window_size);
+    }
+}
+
+/// Header of a block, read by `read_block_header`.
+pub(crate) enum BlockHeader {
+    Stored {
+        len: u16,
+        last_block: bool,
+    },
+
+    Fixed {
+        last_block: bool,
+    },
+
+    Dynamic {
+        tables: DynamicTables,
+        last_block: bool,
+    },
+}
+
+impl BlockHeader {
+    fn block_type(&self) -> BlockType {
+        match *self {
+            BlockHeader::Stored { .. } => BlockType::Stored,
+            BlockHeader::Fixed { .. } => BlockType::Fixed,
+            BlockHeader::Dynamic { .. } => BlockType::Dynamic,
         }
     }
 }
 
-/// Assumes that a block starts at the start of `bits` and initializes the inflater.
-fn consume_block_start<R>(mut bits: BitRead<R>) -> IoResult<InflaterState<R>> where R: Read {
+/// Reads the header of the block that starts at the current position of `bits`, and counts the
+/// block in `stats`. If `check_padding` is true, the padding bits of an uncompressed block must
+/// be zero, and if `accept_incomplete` is false, the codes of a dynamic block must be complete.
+///
+/// Nothing is read from the underlying reader beyond the header, and `bits` is positioned right
+/// after it. For an uncompressed block, that is the start of the data on a byte boundary.
+pub(crate) fn read_block_header<R>(bits: &mut BitRead<R>, stats: &mut BlockStats,
+                                   check_padding: bool, accept_incomplete: bool)
+                                   -> IoResult<BlockHeader> where R: Read
+{
     // the bfinal bit indicates whether we are at the last block
-    let bfinal = try!(bits.read(1)) != 0;
+    let bfinal = bits.read(1)? != 0;
 
     // the next two bits correspond to the type of block
-    match try!(bits.read(2)) {
+    match bits.read(2)? {
         // dynamic huffman codes
         0b10 => {
+            stats.dynamic += 1;
+
             // the block starts with two huffman table definitions
-            Ok(InflaterState::CompressedData {
-                data: try!(CompressedBlockReader::from_dynamic_tables(bits)),
+            Ok(BlockHeader::Dynamic {
+                tables: DynamicTables::read(bits, accept_incomplete)?,
                 last_block: bfinal,
             })
         },
 
         // fixed huffman codes
         0b01 => {
-            // instead of having the two sets of lengths (see previous section), we use
-            // lengths defined by the RFC
-            Ok(InflaterState::CompressedData {
-                data: CompressedBlockReader::from_fixed_tables(bits),
-                last_block: bfinal,
-            })
+            stats.fixed += 1;
+            Ok(BlockHeader::Fixed { last_block: bfinal })
         },
 
         // block of uncompressed data
         0b00 => {
-            // the rest of the bits must be ignored
-            let mut inner = bits.byte_align_unwrap();
+            stats.stored += 1;
+
+            // the rest of the bits of the current byte must be ignored; the blocks start on a
+            // byte boundary of the bit positions
+            let padding_start = bits.bit_position();
+            let padding = bits.read(((8 - padding_start % 8) % 8) as u8)?;
+            if check_padding && padding != 0 {
+                return Err(DecodeError::NonZeroPadding { at_bit: padding_start }.into());
+            }
 
             // reading the header of the uncompressed data
             let mut header = [0, 0, 0, 0];
-            try!(::read_all(&mut inner, &mut header));
+            for byte in header.iter_mut() {
+                *byte = bits.read(8)?;
+            }
 
             let (len, nlen) = (((header[1] as u16) << 8) | header[0] as u16,
                                ((header[3] as u16) << 8) | header[2] as u16);
@@ -190,11 +1086,7 @@ fn consume_block_start<R>(mut bits: BitRead<R>) -> IoResult<InflaterState<R>> wh
                 return Err(IoError::new(ErrorKind::InvalidInput, "Failed to match nlen and len"));
             }
 
-            Ok(InflaterState::UncompressedData {
-                data: inner,
-                len: len as usize,
-                last_block: bfinal,
-            })
+            Ok(BlockHeader::Stored { len, last_block: bfinal })
         },
 
         // reserved
@@ -203,11 +1095,50 @@ fn consume_block_start<R>(mut bits: BitRead<R>) -> IoResult<InflaterState<R>> wh
     }
 }
 
+/// Builds the state of the inflater at the start of the data of the block whose header has been
+/// read from `bits` by `read_block_header`.
+fn enter_block<R>(bits: BitRead<R>, header: BlockHeader) -> InflaterState<R> where R: Read {
+    match header {
+        BlockHeader::Dynamic { tables, last_block } => InflaterState::CompressedData {
+            data: Box::new(CompressedBlockReader::from_dynamic_tables(bits, tables)),
+            last_block,
+        },
+
+        // instead of having the two sets of lengths, we use lengths defined by the RFC
+        BlockHeader::Fixed { last_block } => InflaterState::CompressedData {
+            data: Box::new(CompressedBlockReader::from_fixed_tables(bits)),
+            last_block,
+        },
+
+        // empty blocks are valid, for example to align the stream on a byte boundary
+        BlockHeader::Stored { len: 0, last_block } => {
+            let header_end = bits.bit_position();
+            let inner = bits.byte_align_unwrap();
+            if last_block {
+                InflaterState::Eof { data: inner }
+            } else {
+                let mut data = BitRead::new(inner);
+                data.set_bit_position(header_end);
+                InflaterState::BeforeBlockStart { data }
+            }
+        },
+
+        BlockHeader::Stored { len, last_block } => InflaterState::UncompressedData {
+            data: bits.byte_align_unwrap(),
+            len: len as usize,
+            last_block,
+        },
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Inflater;
+    use super::{BlockInfo, BlockOffset, BlockStats, BlockType, Inflater, DEFAULT_WINDOW_SIZE};
+    use deflate::Deflater;
+    use error::DecodeError;
     use std::io::Cursor;
-    use std::io::Read;
+    use std::io::{Read, Write};
+    use window::FixedWindow;
 
     #[test]
     fn uncompressed_block() {
@@ -221,6 +1152,52 @@ mod tests {
         assert_eq!(output, b"hello");
     }
 
+    #[test]
+    fn uncompressed_block_from_slice() {
+        let data = [0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c', b'd'];
+        let mut inflater = Inflater::from_slice(&data);
+
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"abc");
+        assert_eq!(inflater.into_inner(), b"d");
+    }
+
+    #[test]
+    fn uncompressed_block_padding() {
+        // the last padding bit of the first byte is set
+        let data = [0x81, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o'];
+
+        let mut output = Vec::new();
+        Inflater::new(&data[..]).read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello");
+
+        let mut inflater = Inflater::new(&data[..]);
+        inflater.set_check_padding(true);
+        let err = inflater.read_to_end(&mut Vec::new()).unwrap_err();
+        let err = err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>());
+        assert_eq!(err, Some(&DecodeError::NonZeroPadding { at_bit: 3 }));
+
+        let data = [0x01, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o'];
+        let mut inflater = Inflater::new(&data[..]);
+        inflater.set_check_padding(true);
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello");
+    }
+
+    #[test]
+    fn empty_uncompressed_blocks() {
+        let data = vec![0x0, 0, 0, 0xff, 0xff, 0x1, 0, 0, 0xff, 0xff];
+        let data = Cursor::new(data);
+
+        let mut inflater = Inflater::new(data);
+
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert!(output.is_empty());
+    }
+
     #[test]
     fn uncompressed_block_too_short() {
         let data = vec![0x1, 5, 0, 0xfa, 0xff, b'h', b'e', b'l'];
@@ -243,6 +1220,40 @@ mod tests {
         assert!(inflater.read_to_end(&mut output).is_err());
     }
 
+    #[test]
+    fn compressed_fixed_block_pointer_small_reads() {
+        // `a` followed by a pointer that repeats it 99 times
+        let data = vec![0x4b, 0x4c, 0xa4, 0x3d, 0x00, 0x00];
+        let data = Cursor::new(data);
+
+        let mut inflater = Inflater::new(data);
+
+        // the pointer is copied over several reads
+        let mut output = Vec::new();
+        let mut buf = [0; 7];
+        loop {
+            match inflater.read(&mut buf).unwrap() {
+                0 => break,
+                len => output.extend_from_slice(&buf[.. len]),
+            }
+        }
+        assert_eq!(output, vec![b'a'; 100]);
+    }
+
+    #[test]
+    fn compressed_dynamic_block() {
+        // a dynamic block whose codes only contain `a`, `b`, `c` and the end of block
+        let data = vec![0x15, 0xc5, 0x31, 0x01, 0x00, 0x00, 0x0c, 0x83, 0x30, 0xad, 0x80, 0x7f,
+                        0x0f, 0xdd, 0xf2, 0x44, 0xc8, 0x38, 0x8a, 0x14, 0x1f, 0x31];
+        let data = Cursor::new(data);
+
+        let mut inflater = Inflater::new(data);
+
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"baacbcaaaabbabaccababaaca");
+    }
+
     #[test]
     fn compressed_fixed_block_distance() {
         let data = vec![0x73, 0x49, 0x4d, 0xcb, 0x49, 0x2c, 0x49, 0x55, 0x00, 0x11, 0x00];
@@ -255,6 +1266,20 @@ mod tests {
         assert_eq!(output, b"Deflate late");
     }
 
+    #[test]
+    fn compressed_fixed_block_maximum_length() {
+        // `a` followed by two pointers with length code 285, which is a length of 258, and a
+        // distance of 1
+        let data = vec![0x4b, 0x1c, 0x05, 0xa3, 0x00, 0x00];
+        let data = Cursor::new(data);
+
+        let mut inflater = Inflater::new(data);
+
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, vec![b'a'; 1 + 2 * 258]);
+    }
+
     #[test]
     fn uncompressed_then_compressed_fixed_block_distance() {
         let data = vec![0x0, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o',
@@ -266,6 +1291,200 @@ mod tests {
         let mut output = Vec::new();
         inflater.read_to_end(&mut output).unwrap();
         assert_eq!(output, b"helloDeflate late");
+        assert_eq!(inflater.block_stats(), &BlockStats { stored: 1, fixed: 1, dynamic: 0 });
+        assert_eq!((inflater.literal_bytes(), inflater.match_bytes()), (8, 4));
+    }
+
+    #[test]
+    fn literal_and_match_bytes() {
+        // `Deflate late`, where `late` is a pointer
+        let data = [0x73, 0x49, 0x4d, 0xcb, 0x49, 0x2c, 0x49, 0x55, 0x00, 0x11, 0x00];
+
+        // the copy of the pointer is split between several reads
+        let mut inflater = Inflater::new(&data[..]);
+        let mut buf = [0; 10];
+        assert_eq!(inflater.read(&mut buf).unwrap(), 10);
+        assert_eq!((inflater.literal_bytes(), inflater.match_bytes()), (8, 2));
+        inflater.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!((inflater.literal_bytes(), inflater.match_bytes()), (8, 4));
+
+        // everything comes from literals and pointers in compressed blocks
+        let data = b"Lorem ipsum dolor sit amet, lorem ipsum dolor sit amet".repeat(100);
+        let mut compressed = Vec::new();
+        let mut deflater = Deflater::new(&mut compressed, 6);
+        deflater.write_all(&data).unwrap();
+        deflater.finish().unwrap();
+
+        let mut inflater = Inflater::new(&compressed[..]);
+        inflater.read_to_end(&mut Vec::new()).unwrap();
+        assert!(inflater.match_bytes() > 9 * inflater.literal_bytes());
+        assert_eq!(inflater.literal_bytes() + inflater.match_bytes(), data.len() as u64);
+    }
+
+    #[test]
+    fn window() {
+        let data = vec![0x0, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o',
+                        0x73, 0x49, 0x4d, 0xcb, 0x49, 0x2c, 0x49, 0x55, 0x00, 0x11, 0x00];
+        let mut inflater = Inflater::new(Cursor::new(data));
+        assert_eq!(inflater.window(), b"");
+
+        let mut buf = [0; 5];
+        assert_eq!(inflater.read(&mut buf).unwrap(), 5);
+        assert_eq!(inflater.window(), b"hello");
+
+        inflater.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(inflater.window(), b"helloDeflate late");
+
+        inflater.set_window_size(4);
+        assert_eq!(inflater.window(), b"late");
+    }
+
+    #[test]
+    fn with_window_size() {
+        // `Deflate late`, where `late` is a pointer 5 bytes back
+        let data = [0x73, 0x49, 0x4d, 0xcb, 0x49, 0x2c, 0x49, 0x55, 0x00, 0x11, 0x00];
+
+        let mut inflater = Inflater::with_window_size(&data[..], 4);
+        let err = inflater.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(matches!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()),
+                         Some(&DecodeError::InvalidBackReference { .. })));
+
+        for &window_size in &[5, DEFAULT_WINDOW_SIZE, 1 << 20] {
+            let mut output = Vec::new();
+            Inflater::with_window_size(&data[..], window_size).read_to_end(&mut output).unwrap();
+            assert_eq!(output, b"Deflate late");
+        }
+    }
+
+    #[test]
+    fn with_window() {
+        // the data starts with a pointer to the end of the window
+        let mut history = vec![0; 40000];
+        history.extend_from_slice(b"Deflate late");
+        let data = b"Deflate late is late";
+        let mut compressed = Vec::new();
+        let mut deflater = Deflater::new(&mut compressed, 6);
+        deflater.set_dictionary(&history);
+        deflater.write_all(data).unwrap();
+        deflater.finish().unwrap();
+
+        let mut inflater = Inflater::with_window(Cursor::new(compressed.clone()), history.clone());
+        assert_eq!(inflater.window().len(), 32768);
+        assert!(history.ends_with(inflater.window()));
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, data);
+
+        let mut inflater = Inflater::new(Cursor::new(compressed));
+        let err = inflater.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(matches!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()),
+                         Some(&DecodeError::InvalidBackReference { .. })));
+    }
+
+    #[test]
+    fn empty_reads() {
+        let data = vec![0x0, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o',
+                        0x73, 0x49, 0x4d, 0xcb, 0x49, 0x2c, 0x49, 0x55, 0x00, 0x11, 0x00];
+        let mut inflater = Inflater::new(Cursor::new(data));
+
+        // before the first block, inside the uncompressed block, between the blocks, and inside
+        // the compressed block
+        for &len in &[0, 2, 3, 2] {
+            inflater.read_exact(&mut vec![0; len]).unwrap();
+            let position = inflater.get_ref().position();
+            assert_eq!(inflater.read(&mut []).unwrap(), 0);
+            assert_eq!(inflater.get_ref().position(), position);
+            assert!(!inflater.is_finished());
+        }
+
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"flate late");
+        assert_eq!(inflater.read(&mut []).unwrap(), 0);
+        assert_eq!(inflater.total_out(), 17);
+    }
+
+    #[test]
+    fn next_block() {
+        let data = vec![0x0, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o',
+                        0x73, 0x49, 0x4d, 0xcb, 0x49, 0x2c, 0x49, 0x55, 0x00, 0x11, 0x00];
+        let mut inflater = Inflater::new(Cursor::new(data));
+
+        assert_eq!(inflater.next_block().unwrap(), Some(BlockInfo {
+            block_type: BlockType::Stored,
+            last_block: false,
+            data: b"hello".to_vec(),
+        }));
+        assert_eq!(inflater.total_out(), 5);
+
+        assert_eq!(inflater.next_block().unwrap(), Some(BlockInfo {
+            block_type: BlockType::Fixed,
+            last_block: true,
+            data: b"Deflate late".to_vec(),
+        }));
+        assert_eq!(inflater.next_block().unwrap(), None);
+        assert_eq!((inflater.total_in(), inflater.total_out()), (21, 17));
+    }
+
+    #[test]
+    fn next_block_after_read() {
+        let data = vec![0x0, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o',
+                        0x73, 0x49, 0x4d, 0xcb, 0x49, 0x2c, 0x49, 0x55, 0x00, 0x11, 0x00];
+        let mut inflater = Inflater::new(Cursor::new(data));
+
+        inflater.read_exact(&mut [0; 2]).unwrap();
+        assert!(inflater.next_block().is_err());
+
+        // the inflater is still usable
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"lloDeflate late");
+    }
+
+    #[test]
+    fn read_up_to_blocks() {
+        let data = vec![0x0, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o',
+                        0x73, 0x49, 0x4d, 0xcb, 0x49, 0x2c, 0x49, 0x55, 0x00, 0x11, 0x00];
+
+        let mut inflater = Inflater::new(Cursor::new(data.clone()));
+        let mut buf = [0; 64];
+        assert_eq!(inflater.read_up_to_blocks(0, &mut buf).unwrap(), 0);
+        assert_eq!(inflater.read_up_to_blocks(1, &mut buf).unwrap(), 5);
+        assert_eq!(&buf[.. 5], b"hello");
+        assert_eq!(inflater.total_out(), 5);
+        assert_eq!(inflater.read_up_to_blocks(1, &mut buf).unwrap(), 12);
+        assert_eq!(&buf[.. 12], b"Deflate late");
+        assert!(inflater.is_finished());
+        assert_eq!(inflater.read_up_to_blocks(1, &mut buf).unwrap(), 0);
+        assert_eq!((inflater.total_in(), inflater.total_out()), (21, 17));
+
+        // a buffer smaller than the block
+        let mut inflater = Inflater::new(Cursor::new(data.clone()));
+        assert_eq!(inflater.read_up_to_blocks(1, &mut buf[.. 3]).unwrap(), 3);
+        assert_eq!(inflater.read_up_to_blocks(1, &mut buf).unwrap(), 2);
+        assert_eq!(&buf[.. 2], b"lo");
+        assert_eq!(inflater.read_up_to_blocks(1, &mut buf).unwrap(), 12);
+
+        let mut inflater = Inflater::new(Cursor::new(data));
+        assert_eq!(inflater.read_up_to_blocks(2, &mut buf).unwrap(), 17);
+        assert_eq!(&buf[.. 17], b"helloDeflate late");
+    }
+
+    #[test]
+    fn read_up_to_blocks_empty_block() {
+        let data = vec![0x0, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o',
+                        0x0, 0, 0, 0xff, 0xff,
+                        0x1, 5, 0, 0xfa, 0xff, b'w', b'o', b'r', b'l', b'd'];
+
+        let mut inflater = Inflater::new(Cursor::new(data));
+        let mut buf = [0; 64];
+        assert_eq!(inflater.read_up_to_blocks(1, &mut buf).unwrap(), 5);
+        assert_eq!(inflater.read_up_to_blocks(1, &mut buf).unwrap(), 0);
+        assert!(inflater.at_sync_boundary());
+        assert!(!inflater.is_finished());
+        assert_eq!(inflater.read_up_to_blocks(1, &mut buf).unwrap(), 5);
+        assert_eq!(&buf[.. 5], b"world");
+        assert!(inflater.is_finished());
     }
 
     #[test]
@@ -280,4 +1499,376 @@ mod tests {
         inflater.read_to_end(&mut output).unwrap();
         assert_eq!(output, b"Deflate latehello");
     }
+
+    #[test]
+    fn sync_flush() {
+        // `hello` in a stored block, a sync flush, then `world` in the final block
+        let data = vec![0x0, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o',
+                        0x0, 0, 0, 0xff, 0xff,
+                        0x1, 5, 0, 0xfa, 0xff, b'w', b'o', b'r', b'l', b'd'];
+
+        let mut inflater = Inflater::new(Cursor::new(data.clone()));
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"helloworld");
+        assert!(!inflater.at_sync_boundary());
+
+        let mut inflater = Inflater::new(Cursor::new(data));
+        inflater.set_stop_at_sync_flush(true);
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello");
+        assert!(inflater.at_sync_boundary());
+        assert!(!inflater.is_finished());
+        assert_eq!(inflater.total_in(), 15);
+
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"world");
+        assert!(!inflater.at_sync_boundary());
+        assert!(inflater.is_finished());
+    }
+
+    #[test]
+    fn end_padding_bits() {
+        // fixed block with the literal `a` then a match of length 3 at distance 1: its 30 bits
+        // are followed by 2 bits of padding
+        let data = [0x4b, 0x04, 0x02, 0x00];
+        let mut inflater = Inflater::new(&data[..]);
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"aaaa");
+        assert_eq!(inflater.end_padding_bits(), 2);
+        assert_eq!(inflater.total_in() * 8 - inflater.end_padding_bits() as u64, 30);
+
+        // an empty fixed block takes 10 bits
+        let mut inflater = Inflater::new(&[0x03, 0x00][..]);
+        assert_eq!(inflater.end_padding_bits(), 0);
+        inflater.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(inflater.end_padding_bits(), 6);
+
+        // uncompressed blocks end on a byte boundary
+        let mut inflater = Inflater::new(&[0x01, 0x01, 0x00, 0xfe, 0xff, b'a'][..]);
+        inflater.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(inflater.end_padding_bits(), 0);
+    }
+
+    #[test]
+    fn reset_window() {
+        // `Hello, permessage-deflate world!` twice, with sync flushes after each, the second
+        // one being a reference to the first one
+        let data = vec![0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0x28, 0x48, 0x2d, 0xca, 0x4d,
+                        0x2d, 0x2e, 0x4e, 0x4c, 0x4f, 0xd5, 0x4d, 0x49, 0x4d, 0xcb, 0x49, 0x2c,
+                        0x49, 0x55, 0x28, 0xcf, 0x2f, 0xca, 0x49, 0x51, 0x04, 0x00, 0x00, 0x00,
+                        0xff, 0xff, 0xf2, 0x20, 0x20, 0x0f, 0x00, 0x00, 0x00, 0xff, 0xff];
+
+        for &reset in &[false, true] {
+            let mut inflater = Inflater::new(Cursor::new(data.clone()));
+            inflater.set_stop_at_sync_flush(true);
+
+            let mut output = Vec::new();
+            inflater.read_to_end(&mut output).unwrap();
+            assert_eq!(output, b"Hello, permessage-deflate world!");
+
+            if reset {
+                inflater.reset_window();
+            }
+
+            let mut output = Vec::new();
+            match inflater.read_to_end(&mut output) {
+                Ok(_) => {
+                    assert!(!reset);
+                    assert_eq!(output, b"Hello, permessage-deflate world!");
+                },
+                Err(err) => {
+                    assert!(reset);
+                    assert!(matches!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()),
+                                     Some(&DecodeError::InvalidBackReference { .. })));
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn total_in_out() {
+        let data = vec![0x0, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o',
+                        0x73, 0x49, 0x4d, 0xcb, 0x49, 0x2c, 0x49, 0x55, 0x00, 0x11, 0x00,
+                        0xaa, 0xbb];
+        let mut inflater = Inflater::new(Cursor::new(data));
+
+        let mut buf = [0; 3];
+        inflater.read_exact(&mut buf).unwrap();
+        assert_eq!((inflater.total_in(), inflater.total_out()), (8, 3));
+
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"loDeflate late");
+        assert_eq!((inflater.total_in(), inflater.total_out()), (21, 17));
+    }
+
+    #[test]
+    fn compressed_fixed_block_reserved_symbols() {
+        // fixed block whose first symbol is the length symbol 286, and fixed block with `a`
+        // followed by a pointer with the distance symbol 30
+        for data in &[vec![0x1b, 0x03], vec![0x4b, 0x04, 0x3e]] {
+            let mut inflater = Inflater::new(Cursor::new(data.clone()));
+
+            let err = inflater.read_to_end(&mut Vec::new()).unwrap_err();
+            assert!(matches!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()),
+                             Some(&DecodeError::InvalidSymbol { .. })));
+        }
+    }
+
+    #[test]
+    fn dynamic_block_invalid_code_length_codes() {
+        // dynamic block with the 4 first code length codes set to 0, and dynamic block with
+        // the 4 first code length codes set to 1
+        for data in &[vec![0x05, 0x00, 0x00, 0x00], vec![0x05, 0x00, 0x92, 0x04]] {
+            let mut inflater = Inflater::new(Cursor::new(data.clone()));
+
+            let err = inflater.read_to_end(&mut Vec::new()).unwrap_err();
+            assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()),
+                       Some(&DecodeError::InvalidHuffmanTable));
+        }
+    }
+
+    #[test]
+    fn dynamic_block_without_distance_codes() {
+        // dynamic block whose only distance code length is 0, containing `aaa`
+        let data = vec![0x05, 0xc0, 0x81, 0x08, 0x00, 0x00, 0x00, 0x00, 0x20, 0xd6, 0xfd, 0x25,
+                        0x0e, 0x01];
+        let mut inflater = Inflater::new(Cursor::new(data));
+
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"aaa");
+    }
+
+    #[test]
+    fn dynamic_block_incomplete_code() {
+        // dynamic block containing `aaa`, whose literal/length code only has the codes `0` and
+        // `10`, and whose only distance code length is 0
+        let data = vec![0x05, 0xc0, 0x81, 0x0c, 0x00, 0x00, 0x00, 0x80, 0x30, 0xd6, 0xfb, 0x4b,
+                        0x34, 0x04];
+
+        let mut inflater = Inflater::new(Cursor::new(data.clone()));
+        let err = inflater.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()),
+                   Some(&DecodeError::InvalidHuffmanTable));
+
+        let mut inflater = Inflater::new(Cursor::new(data));
+        inflater.set_accept_incomplete_codes(true);
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"aaa");
+    }
+
+    #[test]
+    fn dynamic_block_too_many_codes() {
+        // dynamic blocks declaring 287 literal and length codes, and 31 distance codes
+        for data in &[vec![0xf5, 0x00, 0x00, 0x00], vec![0x05, 0x1e, 0x00, 0x00]] {
+            let mut inflater = Inflater::new(Cursor::new(data.clone()));
+
+            let err = inflater.read_to_end(&mut Vec::new()).unwrap_err();
+            assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()),
+                       Some(&DecodeError::InvalidHeader { at_bit: 3 }));
+        }
+    }
+
+    #[test]
+    fn block_offsets() {
+        // uncompressed block containing `hello`, followed by an empty fixed block
+        let data = [0x00, 0x05, 0x00, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o', 0x03, 0x00];
+        let mut inflater = Inflater::new(&data[..]);
+        inflater.set_record_block_offsets(true);
+        assert!(inflater.block_offsets().is_empty());
+        inflater.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(inflater.block_offsets(), &[
+            BlockOffset { bit_position: 0, block_type: BlockType::Stored },
+            BlockOffset { bit_position: 80, block_type: BlockType::Fixed },
+        ]);
+
+        // empty fixed block, followed by an empty uncompressed block that doesn't start on a
+        // byte boundary
+        let data = [0x02, 0x04, 0x00, 0x00, 0xff, 0xff];
+        let mut inflater = Inflater::new(&data[..]);
+        inflater.set_record_block_offsets(true);
+        inflater.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(inflater.block_offsets(), &[
+            BlockOffset { bit_position: 0, block_type: BlockType::Fixed },
+            BlockOffset { bit_position: 10, block_type: BlockType::Stored },
+        ]);
+
+        // nothing is recorded by default
+        let mut inflater = Inflater::new(&data[..]);
+        inflater.read_to_end(&mut Vec::new()).unwrap();
+        assert!(inflater.block_offsets().is_empty());
+    }
+
+    #[test]
+    fn recovered_output() {
+        let data = (0 .. 100_000u32).map(|n| (n.wrapping_mul(n) >> 10) as u8).collect::<Vec<_>>();
+        let mut compressed = Vec::new();
+        let mut deflater = Deflater::new(&mut compressed, 6);
+        deflater.write_all(&data).unwrap();
+        deflater.finish().unwrap();
+
+        // truncating in the middle of a compressed block
+        compressed.truncate(compressed.len() / 2);
+
+        let mut inflater = Inflater::new(Cursor::new(compressed));
+        let mut output = Vec::new();
+        assert!(inflater.read_to_end(&mut output).is_err());
+
+        let recovered = inflater.recovered_output();
+        assert!(!recovered.is_empty());
+        output.extend(recovered);
+        assert!(output.len() > data.len() / 4);
+        assert!(data.starts_with(&output));
+    }
+
+    #[test]
+    fn eof_after_non_final_block() {
+        // non-final uncompressed block containing `abc`, and nothing after it
+        let data = vec![0x00, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c'];
+        let mut inflater = Inflater::new(Cursor::new(data));
+
+        let mut output = Vec::new();
+        let err = inflater.read_to_end(&mut output).unwrap_err();
+        assert_eq!(output, b"abc");
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()),
+                   Some(&DecodeError::UnexpectedEof));
+        assert!(!inflater.is_finished());
+    }
+
+    #[test]
+    fn compressed_fixed_block_pointer_before_start() {
+        // fixed block whose first symbol is a pointer with a distance of 1
+        let data = vec![0x03, 0x02, 0x00];
+        let data = Cursor::new(data);
+
+        let mut inflater = Inflater::new(data);
+
+        let mut output = Vec::new();
+        let err = inflater.read_to_end(&mut output).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()),
+                   Some(&DecodeError::InvalidBackReference { at_bit: 3 }));
+    }
+
+    #[test]
+    fn error_position() {
+        // uncompressed block containing `hello`, followed by a fixed block whose first symbol is
+        // the reserved length symbol 286
+        let data = vec![0x00, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o', 0x1b, 0x03];
+        let mut inflater = Inflater::new(Cursor::new(data));
+
+        let err = inflater.read_to_end(&mut Vec::new()).unwrap_err();
+        let err = err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()).unwrap();
+        assert_eq!(*err, DecodeError::InvalidSymbol { at_bit: 83 });
+        assert_eq!(err.bit_position(), Some(83));
+    }
+
+    #[test]
+    fn fixed_window() {
+        let data: Vec<u8> = (0 .. 100000u64).map(|n| (n * n / 1000 % 251) as u8).collect();
+        let mut deflater = Deflater::new(Vec::new(), 6);
+        deflater.write_all(&data).unwrap();
+        let compressed = deflater.finish().unwrap();
+
+        let window = FixedWindow::new([0; DEFAULT_WINDOW_SIZE]);
+        let mut inflater = Inflater::with_window_storage(&compressed[..], window);
+        let mut output = Vec::new();
+        let mut buf = [0; 1000];
+        loop {
+            match inflater.read(&mut buf).unwrap() {
+                0 => break,
+                n => output.extend_from_slice(&buf[.. n]),
+            }
+        }
+        assert_eq!(output, data);
+
+        // the same with the default storage
+        let mut output = Vec::new();
+        Inflater::new(&compressed[..]).read_to_end(&mut output).unwrap();
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    fn fixed_window_too_small() {
+        // the pointer in `late` has a distance of 5
+        let data = vec![0x73, 0x49, 0x4d, 0xcb, 0x49, 0x2c, 0x49, 0x55, 0x00, 0x11, 0x00];
+
+        for &(len, ok) in &[(5, true), (4, false)] {
+            let mut buffer = [0; 5];
+            let window = FixedWindow::new(&mut buffer[.. len]);
+            let mut inflater = Inflater::with_window_storage(&data[..], window);
+
+            // reading byte by byte, so that the pointer references data of previous reads
+            let mut output = Vec::new();
+            let result = loop {
+                let mut buf = [0];
+                match inflater.read(&mut buf) {
+                    Ok(0) => break Ok(()),
+                    Ok(_) => output.push(buf[0]),
+                    Err(err) => break Err(err),
+                }
+            };
+            assert_eq!(result.is_ok(), ok);
+            if ok {
+                assert_eq!(output, b"Deflate late");
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_window_small_reads() {
+        // the pointers go 10 bytes back, further than the window
+        let compressed = ::deflate(&b"abcdefghij".repeat(31), 6);
+
+        let window = FixedWindow::new([0; 8]);
+        let mut inflater = Inflater::with_window_storage(&compressed[..], window);
+        let result = loop {
+            // the copies don't fit in the output buffer and continue on the next read
+            let mut buf = [0; 16];
+            match inflater.read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(_) => (),
+                Err(err) => break Err(err),
+            }
+        };
+        let err = result.unwrap_err();
+        assert!(matches!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()),
+                         Some(&DecodeError::InvalidBackReference { .. })));
+
+        // a larger window can't be set either
+        let window = FixedWindow::new([0; 8]);
+        let mut inflater = Inflater::with_window_storage(&compressed[..], window);
+        inflater.set_window_size(16);
+        assert!(inflater.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn compressed_fixed_block_overlapping_distance_small_reads() {
+        // 300 times `a` followed by 100 times `bcd`
+        let data = vec![0x4b, 0x4c, 0x1c, 0x05, 0xc4, 0x82, 0xa4, 0xe4, 0x94, 0x51, 0x44, 0x24,
+                        0x02, 0x00];
+        let data = Cursor::new(data);
+
+        let mut inflater = Inflater::new(data);
+
+        let mut output = Vec::new();
+        let mut buf = [0; 7];
+        loop {
+            match inflater.read(&mut buf).unwrap() {
+                0 => break,
+                n => output.extend_from_slice(&buf[..n]),
+            }
+        }
+
+        let mut expected = vec![b'a'; 300];
+        for _ in 0 .. 100 {
+            expected.extend_from_slice(b"bcd");
+        }
+        assert_eq!(output, expected);
+    }
 }
@@ -0,0 +1,189 @@
+//! The I/O traits and error type used by the crate.
+//!
+//! With the `std` feature, which is enabled by default, these are the ones of `std::io`.
+//! Without it, the crate only depends on `core` and `alloc` and this module provides minimal
+//! replacements for them, which the sources and destinations of data must implement.
+
+#[cfg(feature = "std")]
+pub use std::io::{BufRead, Error, ErrorKind, Read, Result, Write};
+
+#[cfg(not(feature = "std"))]
+pub use self::no_std::{BufRead, Error, ErrorKind, Read, Result, Write};
+
+#[cfg(not(feature = "std"))]
+mod no_std {
+    use alloc::vec::Vec;
+    use core::fmt;
+    use core::result;
+    use error::DecodeError;
+
+    /// Result of an I/O operation.
+    pub type Result<T> = result::Result<T, Error>;
+
+    /// A source of bytes.
+    pub trait Read {
+        /// Reads some bytes into `buf` and returns how many were read. Returning 0 means that
+        /// the end of the data has been reached, unless `buf` is empty.
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+    }
+
+    /// A source of bytes that has an internal buffer.
+    pub trait BufRead: Read {
+        /// Returns the content of the internal buffer, reading more data into it if it is
+        /// empty. Returning an empty buffer means that the end of the data has been reached.
+        fn fill_buf(&mut self) -> Result<&[u8]>;
+
+        /// Marks the first `amt` bytes of the internal buffer as read.
+        fn consume(&mut self, amt: usize);
+    }
+
+    /// A destination for bytes.
+    pub trait Write {
+        /// Writes some bytes of `buf` and returns how many were written.
+        fn write(&mut self, buf: &[u8]) -> Result<usize>;
+
+        /// Makes sure that all the data written so far has reached its destination.
+        fn flush(&mut self) -> Result<()>;
+
+        /// Writes the whole `buf`.
+        fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.write(buf)? {
+                    0 => return Err(Error::new(ErrorKind::WriteZero, "Failed to write the data")),
+                    n => buf = &buf[n ..],
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    impl Read for &[u8] {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let len = if buf.len() < self.len() { buf.len() } else { self.len() };
+            buf[.. len].copy_from_slice(&self[.. len]);
+            *self = &self[len ..];
+            Ok(len)
+        }
+    }
+
+    impl<R> Read for &mut R where R: Read + ?Sized {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            (**self).read(buf)
+        }
+    }
+
+    impl BufRead for &[u8] {
+        fn fill_buf(&mut self) -> Result<&[u8]> {
+            Ok(*self)
+        }
+
+        fn consume(&mut self, amt: usize) {
+            *self = &self[amt ..];
+        }
+    }
+
+    impl<R> BufRead for &mut R where R: BufRead + ?Sized {
+        fn fill_buf(&mut self) -> Result<&[u8]> {
+            (**self).fill_buf()
+        }
+
+        fn consume(&mut self, amt: usize) {
+            (**self).consume(amt)
+        }
+    }
+
+    impl Write for Vec<u8> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<W> Write for &mut W where W: Write + ?Sized {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            (**self).write(buf)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            (**self).flush()
+        }
+    }
+
+    /// Category of an I/O error.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum ErrorKind {
+        /// The data is invalid.
+        InvalidInput,
+        /// The data is valid but doesn't have the expected content.
+        InvalidData,
+        /// The data ended before the expected amount of it could be read.
+        UnexpectedEof,
+        /// The destination doesn't accept more data.
+        WriteZero,
+        /// The operation was interrupted and can be retried.
+        Interrupted,
+        /// The operation can't be completed right now, and can be retried later.
+        WouldBlock,
+        /// Any other error.
+        Other,
+    }
+
+    /// An I/O error.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Error {
+        kind: ErrorKind,
+        inner: Inner,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Inner {
+        Message(&'static str),
+        Decode(DecodeError),
+    }
+
+    impl Error {
+        /// Builds a new error from a kind and a message.
+        pub fn new(kind: ErrorKind, message: &'static str) -> Error {
+            Error {
+                kind,
+                inner: Inner::Message(message),
+            }
+        }
+
+        /// Returns the kind of the error.
+        pub fn kind(&self) -> ErrorKind {
+            self.kind
+        }
+
+        /// Returns the `DecodeError` that caused this error, if any.
+        pub fn decode_error(&self) -> Option<&DecodeError> {
+            match self.inner {
+                Inner::Decode(ref err) => Some(err),
+                Inner::Message(_) => None,
+            }
+        }
+    }
+
+    impl From<DecodeError> for Error {
+        fn from(err: DecodeError) -> Error {
+            Error {
+                kind: ErrorKind::InvalidInput,
+                inner: Inner::Decode(err),
+            }
+        }
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+            match self.inner {
+                Inner::Message(message) => write!(fmt, "{}", message),
+                Inner::Decode(ref err) => write!(fmt, "{}", err),
+            }
+        }
+    }
+}
@@ -1,30 +1,509 @@
-use std::io::{self, Read};
+//! Decompression and compression of DEFLATE, zlib and gzip data.
+//!
+//! The `std` feature is enabled by default. Without it, the crate only requires `core` and
+//! `alloc`, and reads and writes data through the traits of the `io` module instead of the ones
+//! of `std::io`.
 
-pub use zlib_decoder::ZlibDecoder;
+#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(feature = "read_buf", feature(read_buf, core_io_borrowed_buf, borrowed_buf_init))]
+
+#[macro_use]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate core;
+#[cfg(feature = "async")]
+extern crate tokio;
+
+use alloc::vec::Vec;
+use io::{Read, Write};
+
+pub use adler32::Adler32;
+#[cfg(feature = "async")]
+pub use async_zlib_decoder::AsyncZlibDecoder;
+pub use auto_decoder::{AutoDecoder, Format};
+pub use content_decoder::ContentDecoder;
+pub use bit::{BitOrder, BitRead, Lsb, Msb};
+pub use chunker::{ChunkedInflater, ChunkerParams};
+pub use compressed_block_reader::Symbol;
+pub use crc32::Crc32;
+#[cfg(feature = "std")]
+pub use decoder_pool::{DecoderPool, PooledDecoder};
+pub use decompressor::Decompressor;
+pub use deflate::{DEFAULT_LEVEL, DeflateStrategy, Deflater, DeflaterBuilder};
+pub use error::DecodeError;
+pub use gzip_decoder::{decompress_gzip, ExtraSubfields, GzipDecoder, GzipHeader, GzipMember,
+                       GzipMembers};
+pub use gzip_encoder::{GzipEncoder, GzipEncoderBuilder};
+#[cfg(feature = "std")]
+pub use indexed_inflater::IndexedInflater;
+pub use inflate::{BlockInfo, BlockOffset, BlockStats, BlockType, Inflater};
+#[cfg(feature = "metrics")]
+pub use metrics::DecodeMetrics;
+pub use parallel_inflater::ParallelInflater;
+pub use permessage_deflate::PermessageDeflate;
+pub use stream::{Status, StreamInput, StreamState};
+pub use symbols::SymbolIter;
+pub use tee::Tee;
+pub use window::{FixedWindow, Window};
+pub use zlib_decoder::{DecodedBytes, ZlibDecoder, ZlibDecoderBuilder, ZlibHeaderInfo};
+pub use zlib_encoder::ZlibEncoder;
+pub use zlib_writer::ZlibWriter;
 
 mod adler32;
+#[cfg(feature = "async")]
+mod async_zlib_decoder;
+mod auto_decoder;
 mod bit;
+mod chunker;
 mod compressed_block_reader;
+mod content_decoder;
+mod crc32;
+#[cfg(feature = "std")]
+mod decoder_pool;
+mod decompressor;
+mod deflate;
+mod error;
+mod gzip_decoder;
+mod gzip_encoder;
 mod huffman;
+#[cfg(feature = "std")]
+mod indexed_inflater;
 mod inflate;
+pub mod io;
+mod lz77;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod optimal;
+mod parallel_inflater;
+mod permessage_deflate;
+mod stream;
+mod symbols;
+mod tee;
+mod window;
 mod zlib_decoder;
+mod zlib_encoder;
+mod zlib_writer;
 
-/// Reads in the whole buffer. If an EOF error happens, returns `InvalidInput`.
-fn read_all<R>(reader: &mut R, mut output: &mut [u8]) -> io::Result<()> where R: Read {
-    debug_assert!(output.len() != 0);
+/// Compresses `data` into raw DEFLATE data with a compression level between 0 and 9. See
+/// `Deflater::new`.
+///
+/// # Panic
+///
+/// Panics if `level` is superior to 9.
+///
+pub fn deflate(data: &[u8], level: u32) -> Vec<u8> {
+    let mut deflater = Deflater::new(Vec::new(), level);
+    deflater.write_all(data).expect("writing to a Vec can't fail");
+    deflater.finish().expect("writing to a Vec can't fail")
+}
 
-    let mut offset = 0;
+/// Decompresses the whole raw DEFLATE data of `data`. See `Inflater`.
+///
+/// The data after the last block, if any, is ignored.
+pub fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut inflater = Inflater::from_slice(data);
+    let mut buf = [0; 32768];
 
     loop {
+        match inflater.read(&mut buf) {
+            Ok(0) => return Ok(output),
+            Ok(len) => output.extend_from_slice(&buf[.. len]),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Compresses `data` into zlib data with a compression level between 0 and 9. See
+/// `ZlibEncoder::new`.
+///
+/// # Panic
+///
+/// Panics if `level` is superior to 9.
+///
+pub fn compress_zlib(data: &[u8], level: u32) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), level);
+    encoder.write_all(data).expect("writing to a Vec can't fail");
+    encoder.finish().expect("writing to a Vec can't fail")
+}
+
+/// Text that the tests of the modules compress and decompress.
+#[cfg(test)]
+const LOREM_IPSUM: &[u8] = b"Lorem ipsum dolor sit amet, lorem ipsum dolor sit amet";
+
+/// Compresses `data` into a gzip member with the default header and level, for the tests of the
+/// modules. See `GzipEncoder::new`.
+#[cfg(test)]
+fn compress_gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzipEncoder::new(Vec::new());
+    encoder.write_all(data).expect("writing to a Vec can't fail");
+    encoder.finish().expect("writing to a Vec can't fail")
+}
+
+/// Returns true if `err` is of a kind after which the same read can be tried again, in which
+/// case the decoders keep their state instead of becoming unusable.
+fn is_retryable(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::Interrupted
+}
+
+/// Reads in the whole buffer. If an EOF error happens, returns `InvalidInput`. Reads that are
+/// interrupted are retried.
+fn read_all<R>(reader: &mut R, output: &mut [u8]) -> io::Result<()> where R: Read {
+    let mut offset = 0;
+
+    while offset != output.len() {
         match reader.read(&mut output[offset..]) {
-            Ok(len) if len == output.len() - offset => {
-                return Ok(());
-            },
             Ok(0) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unexpected EOF")),
-            Ok(len) => {
-                offset += len;
-            },
+            Ok(len) => offset += len,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
             Err(e) => return Err(e),
         }
     }
+
+    Ok(())
+}
+
+/// Reads a 32 bits integer stored in little endian, as the fields of the gzip trailer. See
+/// `read_all`.
+fn read_u32_le<R>(reader: &mut R) -> io::Result<u32> where R: Read {
+    let mut bytes = [0; 4];
+    read_all(reader, &mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Maximum number of bytes that `read_buf_chunk` decodes at once.
+#[cfg(feature = "read_buf")]
+const READ_BUF_CHUNK: usize = 32768;
+
+/// Implementation of `Read::read_buf` for the decoders.
+///
+/// The decoders write to a `&mut [u8]`, so the memory must be initialized. It is only zeroed if
+/// the cursor doesn't already know it is initialized, and this is recorded in the cursor, so that
+/// reading again into the same buffer doesn't zero it again. The cursor can only record that all
+/// of it is initialized, so for cursors larger than what is decoded at once, only the part that
+/// the call to `read` can fill is zeroed, instead of all of it as the default implementation
+/// does.
+#[cfg(feature = "read_buf")]
+fn read_buf_chunk<R>(reader: &mut R, mut cursor: std::io::BorrowedCursor<'_>) -> io::Result<()>
+                     where R: Read + ?Sized
+{
+    let written = if cursor.is_init() || cursor.capacity() <= READ_BUF_CHUNK {
+        let buf = cursor.ensure_init();
+        let len = core::cmp::min(buf.len(), READ_BUF_CHUNK);
+        reader.read(&mut buf[.. len])?
+    } else {
+        let chunk = unsafe { &mut cursor.as_mut()[.. READ_BUF_CHUNK] };
+        chunk.fill(core::mem::MaybeUninit::new(0));
+
+        // the bytes have just been initialized
+        let chunk = unsafe { &mut *(chunk as *mut [core::mem::MaybeUninit<u8>] as *mut [u8]) };
+        reader.read(chunk)?
+    };
+
+    // the bytes that have been written are part of the chunk, which is initialized
+    unsafe { cursor.advance(written); }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Write};
+    use super::{compress_zlib, deflate, inflate, is_retryable, read_all, read_u32_le, AutoDecoder,
+                GzipDecoder, GzipEncoder, Inflater, ZlibDecoder};
+
+    /// Reader that returns the data `chunk` bytes at a time, with an error of kind `error`
+    /// before each chunk if there is one.
+    struct Chunked<'a> {
+        data: &'a [u8],
+        chunk: usize,
+        error: Option<io::ErrorKind>,
+        interrupted: bool,
+    }
+
+    impl<'a> Chunked<'a> {
+        fn new(data: &'a [u8], chunk: usize, error: Option<io::ErrorKind>) -> Chunked<'a> {
+            Chunked { data, chunk, error, interrupted: false }
+        }
+    }
+
+    impl<'a> Read for Chunked<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if let (Some(kind), false) = (self.error, self.interrupted) {
+                self.interrupted = true;
+                return Err(io::Error::new(kind, "interrupted"));
+            }
+
+            self.interrupted = false;
+            let len = *[self.chunk, buf.len(), self.data.len()].iter().min().unwrap();
+            buf[.. len].copy_from_slice(&self.data[.. len]);
+            self.data = &self.data[len ..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn chunked_reads() {
+        for chunk in 1 .. 6 {
+            let mut reader = Chunked::new(b"hello world", chunk, None);
+            let mut output = [0; 5];
+            read_all(&mut reader, &mut output).unwrap();
+            assert_eq!(&output, b"hello");
+            assert_eq!(reader.data, b" world");
+        }
+    }
+
+    #[test]
+    fn interrupted_reads() {
+        let mut reader = Chunked::new(b"hello world", 2, Some(io::ErrorKind::Interrupted));
+        let mut output = [0; 11];
+        read_all(&mut reader, &mut output).unwrap();
+        assert_eq!(&output, b"hello world");
+    }
+
+    #[test]
+    fn unexpected_eof() {
+        let mut reader = Chunked::new(b"hello", 2, Some(io::ErrorKind::Interrupted));
+        let err = read_all(&mut reader, &mut [0; 6]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn empty_buffer() {
+        let mut reader = Chunked::new(b"", 2, None);
+        read_all(&mut reader, &mut []).unwrap();
+    }
+
+    #[test]
+    fn u32_little_endian() {
+        let data = [0x12, 0x34, 0x56, 0x78, 0x9a];
+        let mut reader = Chunked::new(&data, 1, Some(io::ErrorKind::Interrupted));
+        assert_eq!(read_u32_le(&mut reader).unwrap(), 0x78563412);
+        assert_eq!(reader.data, &[0x9a]);
+
+        let err = read_u32_le(&mut Chunked::new(&data[.. 3], 2, None)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    /// Deterministic xorshift generator, so that failures are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        /// Random data made of runs of bytes from a small alphabet, so that it contains matches.
+        fn data(&mut self) -> Vec<u8> {
+            let len = (self.next() % 20_000) as usize;
+            let alphabet = 1 + self.next() % 255;
+            let mut data = Vec::with_capacity(len);
+
+            while data.len() < len {
+                let byte = (self.next() % alphabet) as u8;
+                let run = 1 + (self.next() % 8) as usize;
+                data.extend((0 .. run).map(|_| byte));
+            }
+
+            data
+        }
+    }
+
+    #[test]
+    fn deflate_round_trip() {
+        let mut rng = Rng(0x2545f4914f6cdd1d);
+
+        for _ in 0 .. 4 {
+            let data = rng.data();
+            for level in 0 .. 10 {
+                let mut output = Vec::new();
+                Inflater::new(&deflate(&data, level)[..]).read_to_end(&mut output).unwrap();
+                assert!(output == data, "round-trip failed at level {}", level);
+            }
+        }
+    }
+
+    #[test]
+    fn inflate_function() {
+        let data = Rng(0x2545f4914f6cdd1d).data();
+        for &level in &[0, 1, 6, 9] {
+            assert!(inflate(&deflate(&data, level)).unwrap() == data);
+        }
+
+        assert!(inflate(&[0x03, 0x00]).unwrap().is_empty());
+        assert!(inflate(&deflate(b"hello world, hello world", 6)[.. 3]).is_err());
+    }
+
+    #[test]
+    fn compress_zlib_round_trip() {
+        let mut rng = Rng(0x9e3779b97f4a7c15);
+
+        for _ in 0 .. 4 {
+            let data = rng.data();
+            for level in 0 .. 10 {
+                let mut output = Vec::new();
+                ZlibDecoder::new(&compress_zlib(&data, level)[..]).read_to_end(&mut output)
+                                                                  .unwrap();
+                assert!(output == data, "round-trip failed at level {}", level);
+            }
+        }
+    }
+
+    /// Reads everything from `reader`, trying again after each `WouldBlock` or `Interrupted`
+    /// error as is done with a non-blocking socket.
+    fn read_non_blocking<R>(mut reader: R) -> io::Result<Vec<u8>> where R: Read {
+        let mut output = Vec::new();
+        let mut buf = [0; 100];
+
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => return Ok(output),
+                Ok(n) => output.extend_from_slice(&buf[.. n]),
+                Err(ref e) if is_retryable(e) => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    #[test]
+    fn would_block_reads() {
+        let data = Rng(0xd1b54a32d192ed03).data();
+        let would_block = Some(io::ErrorKind::WouldBlock);
+
+        for &level in &[0, 1, 6] {
+            let raw = deflate(&data, level);
+            let zlib = compress_zlib(&data, level);
+            for &chunk in &[1, 3, 1000] {
+                let inflater = Inflater::new(Chunked::new(&raw, chunk, would_block));
+                assert!(read_non_blocking(inflater).unwrap() == data);
+
+                let decoder = ZlibDecoder::new(Chunked::new(&zlib, chunk, would_block));
+                assert!(read_non_blocking(decoder).unwrap() == data);
+            }
+        }
+    }
+
+    #[test]
+    fn would_block_next_block() {
+        // larger than the 4096 bytes that `next_block` decodes at a time
+        let data: Vec<u8> = (0 .. 20_000u32).map(|n| (n * n / 7 % 251) as u8).collect();
+        let would_block = Some(io::ErrorKind::WouldBlock);
+
+        for &level in &[0, 1, 6] {
+            for &chunk in &[1, 3, 1000] {
+                let raw = deflate(&data, level);
+                let mut inflater = Inflater::new(Chunked::new(&raw, chunk, would_block));
+                let mut output = Vec::new();
+                loop {
+                    match inflater.next_block() {
+                        Ok(Some(block)) => output.extend(block.data),
+                        Ok(None) => break,
+                        Err(ref e) if is_retryable(e) => (),
+                        Err(e) => panic!("{}", e),
+                    }
+                }
+                assert!(output == data, "level {}, chunks of {} bytes", level, chunk);
+                assert_eq!(inflater.total_out(), data.len() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn would_block_zlib_headers() {
+        // `hello world` compressed by zlib with `hello` as the dictionary, twice
+        let stream = [0x78, 0xbb, 0x06, 0x2c, 0x02, 0x15, 0xcb, 0x00, 0x11, 0x0a, 0xe5, 0xf9,
+                      0x45, 0x39, 0x29, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+        let data = [&stream[..], &stream[..]].concat();
+        let would_block = Some(io::ErrorKind::WouldBlock);
+
+        let mut decoder = ZlibDecoder::builder().dictionary(b"hello").allow_multi(true)
+                                                .build(Chunked::new(&data, 1, would_block));
+        assert_eq!(read_non_blocking(&mut decoder).unwrap(), b"hello worldhello world");
+        assert_eq!(decoder.total_in(), data.len() as u64);
+
+        // the error is still returned for data that is truncated
+        let decoder = ZlibDecoder::new(Chunked::new(&stream[.. 17], 1, would_block));
+        assert!(read_non_blocking(decoder).is_err());
+    }
+
+    #[test]
+    fn retryable_errors() {
+        let data = Rng(0x2545f4914f6cdd1d).data();
+        let raw = deflate(&data, 6);
+        let zlib = compress_zlib(&data, 6);
+
+        // two members with headers that contain strings
+        let mut gzip = Vec::new();
+        for name in &["first.txt", "second.txt"] {
+            let mut encoder = GzipEncoder::builder().filename(name).comment("comment")
+                                                    .build(Vec::new());
+            encoder.write_all(&data).unwrap();
+            gzip.extend(encoder.finish().unwrap());
+        }
+        let both = [&data[..], &data[..]].concat();
+
+        for &kind in &[io::ErrorKind::WouldBlock, io::ErrorKind::Interrupted] {
+            for &chunk in &[1, 7, 1000] {
+                let inflater = Inflater::new(Chunked::new(&raw, chunk, Some(kind)));
+                assert!(read_non_blocking(inflater).unwrap() == data);
+
+                let decoder = ZlibDecoder::new(Chunked::new(&zlib, chunk, Some(kind)));
+                assert!(read_non_blocking(decoder).unwrap() == data);
+
+                let decoder = GzipDecoder::new(Chunked::new(&gzip, chunk, Some(kind)));
+                assert!(read_non_blocking(decoder).unwrap() == both);
+
+                let decoder = AutoDecoder::new(Chunked::new(&gzip, chunk, Some(kind)));
+                assert!(read_non_blocking(decoder).unwrap() == both);
+
+                let decoder = AutoDecoder::new(Chunked::new(&raw, chunk, Some(kind)));
+                assert!(read_non_blocking(decoder).unwrap() == data);
+
+                // every other call to `next` returns the error, and the members aren't lost
+                let decoder = GzipDecoder::new(Chunked::new(&gzip, chunk, Some(kind)));
+                let members = decoder.members().filter_map(|member| match member {
+                    Ok(member) => Some(member),
+                    Err(ref e) if is_retryable(e) => None,
+                    Err(e) => panic!("{}", e),
+                }).collect::<Vec<_>>();
+                assert_eq!(members.len(), 2);
+                assert_eq!(members[1].header.filename.as_ref().unwrap(), "second.txt");
+                assert!(members[1].data == data);
+            }
+        }
+    }
+
+    #[test]
+    fn clone_mid_stream() {
+        let data = Rng(0x9e3779b97f4a7c15).data();
+        let raw = deflate(&data, 6);
+        let zlib = compress_zlib(&data, 6);
+
+        let mut inflater = Inflater::new(io::Cursor::new(&raw[..]));
+        let mut start = vec![0; data.len() / 2];
+        inflater.read_exact(&mut start).unwrap();
+        let mut copy = inflater.clone();
+        let mut rest = Vec::new();
+        inflater.read_to_end(&mut rest).unwrap();
+        let mut copy_rest = Vec::new();
+        copy.read_to_end(&mut copy_rest).unwrap();
+        assert!(rest == copy_rest);
+        assert!([start, rest].concat() == data);
+        assert_eq!(copy.total_in(), raw.len() as u64);
+
+        let mut decoder = ZlibDecoder::new(&zlib[..]);
+        let mut start = vec![0; data.len() / 3];
+        decoder.read_exact(&mut start).unwrap();
+        let mut copy = decoder.clone();
+        let mut rest = Vec::new();
+        decoder.read_to_end(&mut rest).unwrap();
+        let mut copy_rest = Vec::new();
+        copy.read_to_end(&mut copy_rest).unwrap();
+        assert!(rest == copy_rest);
+        assert!([start, rest].concat() == data);
+        assert_eq!(copy.checksum_ok(), Some(true));
+    }
 }
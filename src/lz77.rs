@@ -0,0 +1,357 @@
+//! LZ77 match finder used by the compressor.
+//!
+//! Positions are counted from the start of the stream and the data is passed as a window that
+//! contains the bytes starting at a given position. This way, the hash chains never need to be
+//! rebased when the window slides.
+
+use alloc::vec::Vec;
+use core::cmp;
+
+/// Maximum distance of a back-reference.
+pub const WINDOW_SIZE: usize = 32768;
+
+/// Minimum length of a back-reference.
+pub const MIN_MATCH: usize = 3;
+
+/// Maximum length of a back-reference.
+pub const MAX_MATCH: usize = 258;
+
+const HASH_BITS: usize = 15;
+const HASH_SIZE: usize = 1 << HASH_BITS;
+
+/// Value of `head` and `prev` for a hash that has never been seen.
+const NONE: usize = usize::MAX;
+
+/// An element of the output of the match finder.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// A byte to copy as it is.
+    Literal(u8),
+
+    /// A copy of `length` bytes that are `distance` bytes behind.
+    Match {
+        length: u16,
+        distance: u16,
+    },
+}
+
+/// Finds the longest previous occurence of the data at a given position, using hash chains
+/// over the first three bytes.
+pub struct MatchFinder {
+    /// For each hash, the last position that had this hash.
+    head: Vec<usize>,
+
+    /// For each position modulo `WINDOW_SIZE`, the previous position that had the same hash.
+    prev: Vec<usize>,
+
+    /// Maximum number of positions to examine for each match.
+    max_chain: usize,
+
+    /// Stop looking for a longer match once one of this length is found.
+    nice_length: usize,
+
+    /// Matches shorter than this are only used if the next position doesn't have a longer
+    /// match. `0` disables lazy matching.
+    max_lazy: usize,
+
+    /// When looking for a longer match at the next position than a match of at least this
+    /// length, only a quarter of the chain is examined.
+    good_length: usize,
+
+    /// Matches shorter than this are written as literals. See `set_min_length`.
+    min_length: usize,
+}
+
+impl MatchFinder {
+    /// Builds a new match finder.
+    ///
+    /// With lazy matching, a match shorter than `max_lazy` is dropped in favour of a literal if
+    /// the next position has a longer match. If `max_lazy` is 0, the longest match at the
+    /// current position is always used.
+    pub fn new(max_chain: usize, nice_length: usize, max_lazy: usize, good_length: usize)
+               -> MatchFinder
+    {
+        MatchFinder {
+            head: vec![NONE; HASH_SIZE],
+            prev: vec![NONE; WINDOW_SIZE],
+            max_chain: cmp::max(max_chain, 1),
+            nice_length: cmp::min(nice_length, MAX_MATCH),
+            max_lazy,
+            good_length,
+            min_length: MIN_MATCH,
+        }
+    }
+
+    /// Makes `tokenize` write the matches shorter than `min_length` as literals. The default is
+    /// `MIN_MATCH`, which keeps all the matches.
+    pub fn set_min_length(&mut self, min_length: usize) {
+        self.min_length = cmp::max(min_length, MIN_MATCH);
+    }
+
+    /// Turns the data at positions `start .. end` into tokens and appends them to `tokens`.
+    ///
+    /// `window` contains the data starting at position `window_start`, which must include at
+    /// least the `WINDOW_SIZE` bytes before `start` (or all of them if there are fewer) and all
+    /// the data up to `end`. Matches never extend past `end`.
+    pub fn tokenize(&mut self, window: &[u8], window_start: usize, start: usize, end: usize,
+                    tokens: &mut Vec<Token>)
+    {
+        debug_assert!(window_start <= start && end <= window_start + window.len());
+
+        let mut pos = start;
+
+        // match at `pos` that has already been found while looking ahead
+        let mut next_match = None;
+
+        while pos < end {
+            let (length, distance) = match next_match.take() {
+                Some(m) => m,
+                None => self.find(window, window_start, pos, end, self.max_chain),
+            };
+
+            if length >= self.min_length {
+                self.insert(window, window_start, pos, end);
+
+                if length < self.max_lazy && pos + 1 < end {
+                    let chain = if length >= self.good_length {
+                        cmp::max(self.max_chain / 4, 1)
+                    } else {
+                        self.max_chain
+                    };
+
+                    let next = self.find(window, window_start, pos + 1, end, chain);
+                    if next.0 > length {
+                        tokens.push(Token::Literal(window[pos - window_start]));
+                        next_match = Some(next);
+                        pos += 1;
+                        continue;
+                    }
+                }
+
+                tokens.push(Token::Match { length: length as u16, distance: distance as u16 });
+                for p in pos + 1 .. pos + length {
+                    self.insert(window, window_start, p, end);
+                }
+                pos += length;
+            } else {
+                tokens.push(Token::Literal(window[pos - window_start]));
+                self.insert(window, window_start, pos, end);
+                pos += 1;
+            }
+        }
+    }
+
+    /// Adds the positions `start .. end` to the hash chains without producing tokens, so that
+    /// the next calls to `tokenize` can reference this data.
+    pub fn insert_range(&mut self, window: &[u8], window_start: usize, start: usize, end: usize) {
+        for pos in start .. end {
+            self.insert(window, window_start, pos, end);
+        }
+    }
+
+    /// Appends to `matches` the matches for the data at `pos` that are longer than all the
+    /// closer ones, as `(length, distance)` tuples ordered by increasing length and distance.
+    /// For each length, the first of these matches that is at least as long is the closest one.
+    ///
+    /// The position must not have been inserted yet. Examines as many previous positions as
+    /// `tokenize` does.
+    pub fn find_matches(&self, window: &[u8], window_start: usize, pos: usize, end: usize,
+                        matches: &mut Vec<(u16, u16)>)
+    {
+        self.walk_chain(window, window_start, pos, end, self.max_chain, |length, distance| {
+            matches.push((length as u16, distance as u16));
+        });
+    }
+
+    /// Returns the longest match for the data at `pos` as a `(length, distance)` tuple, by
+    /// examining at most `chain` previous positions. The length is 0 if there is no match.
+    fn find(&self, window: &[u8], window_start: usize, pos: usize, end: usize, chain: usize)
+            -> (usize, usize)
+    {
+        let mut best = (0, 0);
+        self.walk_chain(window, window_start, pos, end, chain, |length, distance| {
+            best = (length, distance);
+        });
+        best
+    }
+
+    /// Examines at most `chain` previous positions that have the same hash as `pos`, from the
+    /// closest one, and calls `longer` with the length and distance of each match that is
+    /// longer than the previous ones.
+    fn walk_chain<F>(&self, window: &[u8], window_start: usize, pos: usize, end: usize,
+                     mut chain: usize, mut longer: F) where F: FnMut(usize, usize)
+    {
+        let max_length = cmp::min(MAX_MATCH, end - pos);
+        if max_length < MIN_MATCH {
+            return;
+        }
+
+        let current = &window[pos - window_start .. pos - window_start + max_length];
+
+        let mut best = MIN_MATCH - 1;
+        let mut candidate = self.head[hash(current)];
+
+        while candidate != NONE && candidate < pos && pos - candidate <= WINDOW_SIZE &&
+              candidate >= window_start && chain > 0
+        {
+            let previous = &window[candidate - window_start ..];
+            let length = current.iter().zip(previous).take_while(|&(a, b)| a == b).count();
+
+            if length > best {
+                best = length;
+                longer(length, pos - candidate);
+                if length >= self.nice_length || length == max_length {
+                    break;
+                }
+            }
+
+            let next = self.prev[candidate % WINDOW_SIZE];
+            if next == NONE || next >= candidate {
+                break;
+            }
+            candidate = next;
+            chain -= 1;
+        }
+    }
+
+    /// Adds the position `pos` to the hash chains.
+    pub fn insert(&mut self, window: &[u8], window_start: usize, pos: usize, end: usize) {
+        if pos + MIN_MATCH > end {
+            return;
+        }
+
+        let h = hash(&window[pos - window_start ..]);
+        self.prev[pos % WINDOW_SIZE] = self.head[h];
+        self.head[h] = pos;
+    }
+}
+
+/// Turns the data at positions `start .. end` into literals and matches at distance 1, which
+/// encode the runs of the same byte, and appends them to `tokens`. The parameters are the same
+/// as `MatchFinder::tokenize`, except that only the byte before `start` is needed.
+pub fn tokenize_runs(window: &[u8], window_start: usize, start: usize, end: usize,
+                     tokens: &mut Vec<Token>)
+{
+    debug_assert!(window_start <= start && end <= window_start + window.len());
+
+    let mut pos = start;
+    while pos < end {
+        let run = if pos > window_start {
+            let previous = window[pos - window_start - 1];
+            let max_length = cmp::min(MAX_MATCH, end - pos);
+            window[pos - window_start ..][.. max_length].iter()
+                                                     .take_while(|&&b| b == previous).count()
+        } else {
+            0
+        };
+
+        if run >= MIN_MATCH {
+            tokens.push(Token::Match { length: run as u16, distance: 1 });
+            pos += run;
+        } else {
+            tokens.push(Token::Literal(window[pos - window_start]));
+            pos += 1;
+        }
+    }
+}
+
+/// Hashes the first three bytes of `data`.
+fn hash(data: &[u8]) -> usize {
+    (((data[0] as usize) << 10) ^ ((data[1] as usize) << 5) ^ data[2] as usize) & (HASH_SIZE - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize_runs, MatchFinder, Token};
+
+    #[test]
+    fn repeated_pattern() {
+        let data = b"abcabcabcabcx";
+        let mut finder = MatchFinder::new(128, 258, 0, 0);
+        let mut tokens = Vec::new();
+        finder.tokenize(data, 0, 0, data.len(), &mut tokens);
+
+        assert_eq!(tokens, vec![
+            Token::Literal(b'a'), Token::Literal(b'b'), Token::Literal(b'c'),
+            Token::Match { length: 9, distance: 3 },
+            Token::Literal(b'x'),
+        ]);
+    }
+
+    #[test]
+    fn match_in_history() {
+        let data = b"Deflate late";
+        let mut finder = MatchFinder::new(128, 258, 0, 0);
+        let mut tokens = Vec::new();
+        finder.tokenize(data, 0, 0, 8, &mut tokens);
+        tokens.clear();
+        finder.tokenize(data, 0, 8, data.len(), &mut tokens);
+
+        assert_eq!(tokens, vec![Token::Match { length: 4, distance: 5 }]);
+    }
+
+    #[test]
+    fn lazy_matching() {
+        // greedy matching uses `abc` then has to write `d` as a literal, while lazy matching
+        // writes `a` as a literal and finds `bcde`
+        let data = b"abcxbcdeyabcde";
+
+        let mut finder = MatchFinder::new(128, 258, 0, 0);
+        let mut tokens = Vec::new();
+        finder.tokenize(data, 0, 0, data.len(), &mut tokens);
+        assert_eq!(&tokens[9 ..], &[
+            Token::Match { length: 3, distance: 9 }, Token::Literal(b'd'), Token::Literal(b'e'),
+        ]);
+
+        let mut finder = MatchFinder::new(128, 258, 258, 258);
+        let mut tokens = Vec::new();
+        finder.tokenize(data, 0, 0, data.len(), &mut tokens);
+        assert_eq!(&tokens[9 ..], &[
+            Token::Literal(b'a'), Token::Match { length: 4, distance: 6 },
+        ]);
+    }
+
+    #[test]
+    fn insert_range() {
+        let data = b"Deflate late";
+        let mut finder = MatchFinder::new(128, 258, 0, 0);
+        finder.insert_range(data, 0, 0, 8);
+
+        let mut tokens = Vec::new();
+        finder.tokenize(data, 0, 8, data.len(), &mut tokens);
+        assert_eq!(tokens, vec![Token::Match { length: 4, distance: 5 }]);
+    }
+
+    #[test]
+    fn min_length() {
+        // `abc` is only used if 3 bytes are enough
+        let data = b"abcxabcdefyabcdef";
+        let mut finder = MatchFinder::new(128, 258, 0, 0);
+        finder.set_min_length(6);
+        let mut tokens = Vec::new();
+        finder.tokenize(data, 0, 0, data.len(), &mut tokens);
+        assert_eq!(&tokens[4 ..], &[
+            Token::Literal(b'a'), Token::Literal(b'b'), Token::Literal(b'c'), Token::Literal(b'd'),
+            Token::Literal(b'e'), Token::Literal(b'f'), Token::Literal(b'y'),
+            Token::Match { length: 6, distance: 7 },
+        ]);
+    }
+
+    #[test]
+    fn runs() {
+        let data = b"aaaaaabcbcbcbcddd";
+        let mut tokens = Vec::new();
+        tokenize_runs(data, 0, 0, data.len(), &mut tokens);
+        assert_eq!(tokens, vec![
+            Token::Literal(b'a'), Token::Match { length: 5, distance: 1 },
+            Token::Literal(b'b'), Token::Literal(b'c'), Token::Literal(b'b'), Token::Literal(b'c'),
+            Token::Literal(b'b'), Token::Literal(b'c'), Token::Literal(b'b'), Token::Literal(b'c'),
+            Token::Literal(b'd'), Token::Literal(b'd'), Token::Literal(b'd'),
+        ]);
+
+        // the run can continue the byte before `start`
+        let mut tokens = Vec::new();
+        tokenize_runs(data, 0, 2, 6, &mut tokens);
+        assert_eq!(tokens, vec![Token::Match { length: 4, distance: 1 }]);
+    }
+}
@@ -0,0 +1,95 @@
+//! Measurements of the decoding, collected if the `metrics` feature is enabled.
+
+use std::time::Duration;
+use inflate::BlockType;
+
+/// Measurements of an `Inflater`, returned by `Inflater::metrics`.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct DecodeMetrics {
+    /// Number of bytes read from the underlying reader, same as `Inflater::total_in`.
+    pub bytes_in: u64,
+
+    /// Number of bytes decoded, same as `Inflater::total_out`.
+    pub bytes_out: u64,
+
+    /// Time spent in `read`, including the time spent waiting for the underlying reader.
+    pub time: Duration,
+
+    /// Number of bytes decoded from uncompressed blocks.
+    pub stored_bytes: u64,
+
+    /// Number of bytes decoded from blocks compressed with the fixed Huffman codes.
+    pub fixed_bytes: u64,
+
+    /// Number of bytes decoded from blocks compressed with Huffman codes defined in the block.
+    pub dynamic_bytes: u64,
+}
+
+impl DecodeMetrics {
+    /// Returns the number of megabytes (10^6 bytes) decoded per second spent in `read`, or 0
+    /// if no time has been measured.
+    pub fn throughput_mb_s(&self) -> f64 {
+        let secs = self.time.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+
+        self.bytes_out as f64 / secs / 1_000_000.0
+    }
+}
+
+/// Measurements collected by an `Inflater` as it decodes.
+#[derive(Debug, Clone)]
+pub(crate) struct Recorder {
+    time: Duration,
+    stored_bytes: u64,
+    fixed_bytes: u64,
+    dynamic_bytes: u64,
+
+    /// Type of the block being decoded, which the decoded bytes are counted for.
+    current_block: BlockType,
+}
+
+impl Recorder {
+    /// Builds a recorder with nothing measured, as if an uncompressed block was being decoded.
+    pub(crate) fn new() -> Recorder {
+        Recorder {
+            time: Duration::from_secs(0),
+            stored_bytes: 0,
+            fixed_bytes: 0,
+            dynamic_bytes: 0,
+            current_block: BlockType::Stored,
+        }
+    }
+
+    /// Counts the next decoded bytes for a block of type `block_type`.
+    pub(crate) fn start_block(&mut self, block_type: BlockType) {
+        self.current_block = block_type;
+    }
+
+    /// Adds time spent decoding.
+    pub(crate) fn add_time(&mut self, time: Duration) {
+        self.time += time;
+    }
+
+    /// Counts bytes decoded from the current block.
+    pub(crate) fn add_output(&mut self, len: usize) {
+        match self.current_block {
+            BlockType::Stored => self.stored_bytes += len as u64,
+            BlockType::Fixed => self.fixed_bytes += len as u64,
+            BlockType::Dynamic => self.dynamic_bytes += len as u64,
+        }
+    }
+
+    /// Returns the measurements, with the totals of the inflater.
+    pub(crate) fn snapshot(&self, bytes_in: u64, bytes_out: u64) -> DecodeMetrics {
+        DecodeMetrics {
+            bytes_in,
+            bytes_out,
+            time: self.time,
+            stored_bytes: self.stored_bytes,
+            fixed_bytes: self.fixed_bytes,
+            dynamic_bytes: self.dynamic_bytes,
+        }
+    }
+}
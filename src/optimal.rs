@@ -0,0 +1,179 @@
+//! Optimal parsing, used by the `Optimal` strategy of the compressor.
+//!
+//! All the matches of a block are found first. The tokens are then chosen as the shortest path
+//! from the start to the end of the block, where the cost of each token is its size in bits
+//! with some Huffman codes. The first pass uses the fixed codes of the RFC, and the second one
+//! the codes that the tokens of the first pass would get in a dynamic block.
+
+use alloc::vec::Vec;
+use deflate::{distance_symbol, frequencies, length_symbol};
+use huffman::HuffmanEncoder;
+use lz77::{MatchFinder, Token, MAX_MATCH, MIN_MATCH};
+
+/// Turns the data at positions `start .. end` into tokens and appends them to `tokens`. The
+/// parameters are the same as `MatchFinder::tokenize`.
+pub fn tokenize(finder: &mut MatchFinder, window: &[u8], window_start: usize, start: usize,
+                end: usize, tokens: &mut Vec<Token>)
+{
+    // the matches at position `start + i` are `matches[offsets[i] .. offsets[i + 1]]`
+    let mut matches = Vec::new();
+    let mut offsets = Vec::with_capacity(end - start + 1);
+    for pos in start .. end {
+        offsets.push(matches.len());
+        finder.find_matches(window, window_start, pos, end, &mut matches);
+        finder.insert(window, window_start, pos, end);
+    }
+    offsets.push(matches.len());
+
+    let data = &window[start - window_start .. end - window_start];
+    let first = shortest_path(data, &matches, &offsets, &Costs::fixed());
+    tokens.extend(shortest_path(data, &matches, &offsets, &Costs::from_tokens(&first)));
+}
+
+/// Size in bits of the code of each literal/length symbol and of each distance symbol.
+struct Costs {
+    lit_len: [u32; 286],
+    dist: [u32; 30],
+}
+
+impl Costs {
+    /// Returns the sizes of the fixed codes.
+    fn fixed() -> Costs {
+        let mut lit_len = [8; 286];
+        for cost in &mut lit_len[144 .. 256] {
+            *cost = 9;
+        }
+        for cost in &mut lit_len[256 .. 280] {
+            *cost = 7;
+        }
+
+        Costs { lit_len, dist: [5; 30] }
+    }
+
+    /// Returns the sizes of the codes of a dynamic block containing `tokens`. The symbols that
+    /// the tokens don't use are given the largest size, so that they can still be chosen.
+    fn from_tokens(tokens: &[Token]) -> Costs {
+        let (lit_len_freqs, dist_freqs) = frequencies(tokens);
+        let lit_len = HuffmanEncoder::from_frequencies(&lit_len_freqs, 15);
+        let dist = HuffmanEncoder::from_frequencies(&dist_freqs, 15);
+
+        let mut costs = Costs { lit_len: [15; 286], dist: [15; 30] };
+        for (cost, len) in costs.lit_len.iter_mut().zip(lit_len.lengths()) {
+            if len != 0 {
+                *cost = len as u32;
+            }
+        }
+        for (cost, len) in costs.dist.iter_mut().zip(dist.lengths()) {
+            if len != 0 {
+                *cost = len as u32;
+            }
+        }
+        costs
+    }
+}
+
+/// Returns the tokens of `data` whose total cost is the lowest, given the matches found at each
+/// position. See `tokenize`.
+fn shortest_path(data: &[u8], matches: &[(u16, u16)], offsets: &[usize], costs: &Costs)
+                 -> Vec<Token>
+{
+    // cost of each length, including its extra bits
+    let mut length_costs = [0; MAX_MATCH + 1];
+    for (length, cost) in length_costs.iter_mut().enumerate().skip(MIN_MATCH) {
+        let (symbol, extra_bits, _) = length_symbol(length as u16);
+        *cost = costs.lit_len[257 + symbol as usize] + extra_bits as u32;
+    }
+
+    // lowest cost of the data up to each position, and length and distance of the last token
+    // of the corresponding path, the length being 1 for literals
+    let mut best = vec![(u32::MAX, 0u16, 0u16); data.len() + 1];
+    best[0].0 = 0;
+
+    for (pos, &byte) in data.iter().enumerate() {
+        let cost = best[pos].0;
+
+        let literal = cost + costs.lit_len[byte as usize];
+        if literal < best[pos + 1].0 {
+            best[pos + 1] = (literal, 1, 0);
+        }
+
+        // the lengths between two matches are reached with the distance of the longest one
+        let mut min_length = MIN_MATCH;
+        for &(length, distance) in &matches[offsets[pos] .. offsets[pos + 1]] {
+            let (symbol, extra_bits, _) = distance_symbol(distance);
+            let distance_cost = cost + costs.dist[symbol as usize] + extra_bits as u32;
+
+            for len in min_length ..= length as usize {
+                let total = distance_cost + length_costs[len];
+                if total < best[pos + len].0 {
+                    best[pos + len] = (total, len as u16, distance);
+                }
+            }
+            min_length = length as usize + 1;
+        }
+    }
+
+    let mut tokens = Vec::new();
+    let mut pos = data.len();
+    while pos != 0 {
+        let (_, length, distance) = best[pos];
+        if length == 1 {
+            tokens.push(Token::Literal(data[pos - 1]));
+        } else {
+            tokens.push(Token::Match { length, distance });
+        }
+        pos -= length as usize;
+    }
+
+    tokens.reverse();
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokenize;
+    use lz77::{MatchFinder, Token};
+
+    // rebuilds the data from the tokens
+    fn expand(tokens: &[Token], history: &[u8]) -> Vec<u8> {
+        let mut data = history.to_vec();
+        for token in tokens {
+            match *token {
+                Token::Literal(byte) => data.push(byte),
+                Token::Match { length, distance } => {
+                    for _ in 0 .. length {
+                        let byte = data[data.len() - distance as usize];
+                        data.push(byte);
+                    }
+                },
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn matches_are_valid() {
+        let mut data = Vec::new();
+        for i in 0u32 .. 3000 {
+            data.extend_from_slice(format!("{} {} ", i % 37, i * i % 101).as_bytes());
+        }
+
+        let mut finder = MatchFinder::new(128, 258, 0, 0);
+        let mut tokens = Vec::new();
+        tokenize(&mut finder, &data, 0, 0, data.len(), &mut tokens);
+        assert!(expand(&tokens, &[]) == data);
+        assert!(tokens.len() < data.len() / 4);
+    }
+
+    #[test]
+    fn history() {
+        let data = b"Deflate late";
+        let mut finder = MatchFinder::new(128, 258, 0, 0);
+        finder.insert_range(data, 0, 0, 8);
+
+        let mut tokens = Vec::new();
+        tokenize(&mut finder, data, 0, 8, data.len(), &mut tokens);
+        assert_eq!(tokens, vec![Token::Match { length: 4, distance: 5 }]);
+        assert_eq!(expand(&tokens, b"Deflate "), data);
+    }
+}
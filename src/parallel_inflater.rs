@@ -0,0 +1,273 @@
+//! Decompression of DEFLATE data made of independent parts, like the output of `pigz`.
+
+use alloc::vec::Vec;
+use io::{self, ErrorKind, Read};
+use inflate::Inflater;
+
+/// Empty fixed block with `bfinal` set, appended to each part except the last one so that it
+/// forms a complete DEFLATE stream.
+const FINAL_BLOCK: [u8; 2] = [0x03, 0x00];
+
+/// Decodes raw DEFLATE data that can be split in parts decoded independently of each other.
+///
+/// Each offset passed to `with_offsets` must be the position of a block that starts on a byte
+/// boundary and doesn't reference the data of the previous blocks, which is the case after a
+/// full flush. With the `parallel` feature, the parts are decoded on multiple threads.
+/// Otherwise, or if no offset is known, the data is decoded sequentially.
+pub struct ParallelInflater<'a> {
+    /// The whole DEFLATE data.
+    data: &'a [u8],
+
+    /// Start of each part after the first one, in increasing order.
+    offsets: Vec<usize>,
+
+    /// Maximum number of threads to use.
+    threads: usize,
+}
+
+impl<'a> ParallelInflater<'a> {
+    /// Builds a decoder for DEFLATE data whose parts aren't known. It is decoded sequentially.
+    pub fn new(data: &'a [u8]) -> ParallelInflater<'a> {
+        ParallelInflater::with_offsets(data, &[])
+    }
+
+    /// Builds a decoder for DEFLATE data in which each of the `offsets` starts a new part.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the offsets aren't increasing or are out of the data.
+    ///
+    pub fn with_offsets(data: &'a [u8], offsets: &[usize]) -> ParallelInflater<'a> {
+        assert!(offsets.windows(2).all(|w| w[0] < w[1]), "The offsets must be increasing");
+        assert!(offsets.iter().all(|&offset| offset < data.len()),
+                "The offsets must be inside of the data");
+
+        ParallelInflater {
+            data,
+            offsets: offsets.iter().cloned().filter(|&offset| offset != 0).collect(),
+            threads: default_threads(),
+        }
+    }
+
+    /// Sets the maximum number of threads used to decode the parts. The default is the number of
+    /// CPUs. Without the `parallel` feature, only one thread is ever used.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `threads` is 0.
+    ///
+    pub fn set_threads(&mut self, threads: usize) {
+        assert!(threads != 0);
+        self.threads = threads;
+    }
+
+    /// Decodes all the parts and returns the concatenation of their content.
+    ///
+    /// Returns an error if one of the parts doesn't end exactly at the start of the next one, or
+    /// references the data of a previous part.
+    pub fn decompress(&self) -> io::Result<Vec<u8>> {
+        let parts = self.parts();
+        let mut output = Vec::new();
+
+        for part in self.decode_parts(&parts) {
+            output.extend_from_slice(&part?);
+        }
+
+        Ok(output)
+    }
+
+    /// Splits the data at the offsets.
+    fn parts(&self) -> Vec<&'a [u8]> {
+        let mut parts = Vec::with_capacity(self.offsets.len() + 1);
+        let mut start = 0;
+
+        for &offset in &self.offsets {
+            parts.push(&self.data[start .. offset]);
+            start = offset;
+        }
+
+        parts.push(&self.data[start ..]);
+        parts
+    }
+
+    /// Decodes each part, on multiple threads if possible.
+    #[cfg(feature = "parallel")]
+    fn decode_parts(&self, parts: &[&[u8]]) -> Vec<io::Result<Vec<u8>>> {
+        use std::thread;
+
+        if self.threads == 1 || parts.len() == 1 {
+            return decode_parts(parts, 0, parts.len());
+        }
+
+        // each thread decodes consecutive parts
+        let per_thread = parts.len().div_ceil(self.threads);
+
+        thread::scope(|scope| {
+            let handles = parts.chunks(per_thread).enumerate().map(|(n, chunk)| {
+                scope.spawn(move || decode_parts(chunk, n * per_thread, parts.len()))
+            }).collect::<Vec<_>>();
+
+            handles.into_iter()
+                   .flat_map(|handle| handle.join().expect("A decoding thread panicked"))
+                   .collect()
+        })
+    }
+
+    /// Decodes each part.
+    #[cfg(not(feature = "parallel"))]
+    fn decode_parts(&self, parts: &[&[u8]]) -> Vec<io::Result<Vec<u8>>> {
+        decode_parts(parts, 0, parts.len())
+    }
+}
+
+/// Returns the default number of threads.
+#[cfg(feature = "parallel")]
+fn default_threads() -> usize {
+    ::std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Returns the default number of threads.
+#[cfg(not(feature = "parallel"))]
+fn default_threads() -> usize {
+    1
+}
+
+/// Decodes consecutive parts, the first one being the part number `first` out of `num_parts`.
+fn decode_parts(parts: &[&[u8]], first: usize, num_parts: usize) -> Vec<io::Result<Vec<u8>>> {
+    parts.iter().enumerate().map(|(n, part)| decode_part(part, first + n == num_parts - 1))
+                            .collect()
+}
+
+/// Decodes a part of the data. Every part but the last one must end right after a non-final
+/// block.
+fn decode_part(part: &[u8], last: bool) -> io::Result<Vec<u8>> {
+    let trailer: &[u8] = if last { &[] } else { &FINAL_BLOCK };
+    let mut inflater = Inflater::new(PartReader { part, trailer });
+
+    let mut output = Vec::new();
+    let mut buf = [0; 32768];
+    loop {
+        match inflater.read(&mut buf)? {
+            0 => break,
+            len => output.extend_from_slice(&buf[.. len]),
+        }
+    }
+
+    // the inflater stops right after the last block, which must be the one that we have added
+    if !last && inflater.total_in() != (part.len() + trailer.len()) as u64 {
+        return Err(io::Error::new(ErrorKind::InvalidInput,
+                                  "A part of the DEFLATE data doesn't end at the next offset"));
+    }
+
+    Ok(output)
+}
+
+/// Reads a part of the data followed by a trailer.
+struct PartReader<'a> {
+    part: &'a [u8],
+    trailer: &'a [u8],
+}
+
+impl<'a> Read for PartReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.part.is_empty() {
+            self.trailer.read(buf)
+        } else {
+            self.part.read(buf)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParallelInflater;
+    use deflate::Deflater;
+    use error::DecodeError;
+    use std::io::{Read, Write};
+    use inflate::Inflater;
+
+    /// Compresses each chunk independently and returns the concatenated DEFLATE data with the
+    /// offset of each chunk after the first one.
+    fn compress_parts(chunks: &[&[u8]]) -> (Vec<u8>, Vec<usize>) {
+        let mut data = Vec::new();
+        let mut offsets = Vec::new();
+
+        for (n, chunk) in chunks.iter().enumerate() {
+            if n != 0 {
+                offsets.push(data.len());
+            }
+
+            let mut deflater = Deflater::new(&mut data, 6);
+            deflater.write_all(chunk).unwrap();
+            if n == chunks.len() - 1 {
+                deflater.finish().unwrap();
+            } else {
+                deflater.flush().unwrap();
+            }
+        }
+
+        (data, offsets)
+    }
+
+    fn test_data() -> Vec<u8> {
+        (0 .. 500000u32).map(|n| (n % 251 + n / 7000) as u8).collect()
+    }
+
+    #[test]
+    fn same_as_sequential() {
+        let data = test_data();
+        let chunks = data.chunks(40000).collect::<Vec<_>>();
+        let (compressed, offsets) = compress_parts(&chunks);
+
+        let mut sequential = Vec::new();
+        Inflater::new(&compressed[..]).read_to_end(&mut sequential).unwrap();
+        assert!(sequential == data);
+
+        for &threads in &[1, 2, 3, 16] {
+            let mut inflater = ParallelInflater::with_offsets(&compressed, &offsets);
+            inflater.set_threads(threads);
+            assert!(inflater.decompress().unwrap() == sequential);
+        }
+
+        // the offsets are optional
+        assert!(ParallelInflater::new(&compressed).decompress().unwrap() == sequential);
+        assert!(ParallelInflater::with_offsets(&compressed, &offsets[.. 3]).decompress()
+                                                                           .unwrap() == data);
+    }
+
+    #[test]
+    fn wrong_offset() {
+        let data = test_data();
+        let chunks = data.chunks(100000).collect::<Vec<_>>();
+        let (compressed, offsets) = compress_parts(&chunks);
+
+        // in the middle of a part
+        let inflater = ParallelInflater::with_offsets(&compressed, &[offsets[0] + 100]);
+        assert!(inflater.decompress().is_err());
+    }
+
+    #[test]
+    fn dependent_parts() {
+        // a single stream flushed in the middle, whose second part references the first one
+        let data = b"hello hello hello hello".repeat(100);
+
+        let mut first_part = Vec::new();
+        let mut deflater = Deflater::new(&mut first_part, 6);
+        deflater.write_all(&data).unwrap();
+        deflater.flush().unwrap();
+        drop(deflater);
+
+        let mut compressed = Vec::new();
+        let mut deflater = Deflater::new(&mut compressed, 6);
+        deflater.write_all(&data).unwrap();
+        deflater.flush().unwrap();
+        deflater.write_all(&data).unwrap();
+        deflater.finish().unwrap();
+        assert!(compressed.starts_with(&first_part));
+
+        let inflater = ParallelInflater::with_offsets(&compressed, &[first_part.len()]);
+        let err = inflater.decompress().unwrap_err();
+        assert!(matches!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()),
+                         Some(&DecodeError::InvalidBackReference { .. })));
+    }
+}
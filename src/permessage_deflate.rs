@@ -0,0 +1,165 @@
+//! Decompression of WebSocket messages compressed with the permessage-deflate extension, as
+//! defined by RFC 7692.
+
+use alloc::vec::Vec;
+use core::cmp;
+use io::{self, ErrorKind, Read};
+use io::Error as IoError;
+use inflate::Inflater;
+
+/// Marker removed by the sender at the end of each message, which is the content of the
+/// uncompressed block written by a sync flush.
+const MESSAGE_TRAILER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Decompresses a sequence of permessage-deflate messages.
+///
+/// The messages form a single DEFLATE stream, in which each message ends with a sync flush
+/// whose last four bytes have been removed. The window is kept between messages, so that a
+/// message can reference data of the previous ones ("context takeover").
+pub struct PermessageDeflate {
+    inflater: Inflater<MessageReader>,
+}
+
+/// Source of the inflater, which contains the payload of the message being decompressed.
+struct MessageReader {
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl Read for MessageReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = cmp::min(buf.len(), self.data.len() - self.position);
+        buf[.. len].copy_from_slice(&self.data[self.position .. self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+impl PermessageDeflate {
+    /// Builds a decompressor for the messages of a new WebSocket connection.
+    pub fn new() -> PermessageDeflate {
+        let mut inflater = Inflater::new(MessageReader {
+            data: Vec::new(),
+            position: 0,
+        });
+        inflater.set_stop_at_sync_flush(true);
+
+        PermessageDeflate {
+            inflater,
+        }
+    }
+
+    /// Decompresses the payload of a message, without the `00 00 FF FF` trailer that the sender
+    /// has removed.
+    ///
+    /// The message can also end with a block that has `bfinal` set, in which case the next
+    /// message starts with new blocks and can still reference the data of the previous ones.
+    ///
+    /// The messages must be passed in the order in which they were sent. After an error, the
+    /// next messages can't be decompressed anymore.
+    pub fn decompress(&mut self, payload: &[u8]) -> io::Result<Vec<u8>> {
+        {
+            let reader = self.reader()?;
+            reader.data.clear();
+            reader.data.extend_from_slice(payload);
+            reader.data.extend_from_slice(&MESSAGE_TRAILER);
+            reader.position = 0;
+        }
+
+        let mut output = Vec::new();
+        let mut buf = [0; 4096];
+
+        loop {
+            match self.inflater.read(&mut buf)? {
+                0 => {
+                    let remaining = {
+                        let reader = self.reader()?;
+                        reader.data.len() - reader.position
+                    };
+
+                    if self.inflater.at_sync_boundary() {
+                        // the message can contain other sync flushes before its end
+                        if remaining == 0 {
+                            return Ok(output);
+                        }
+                    } else if remaining == MESSAGE_TRAILER.len() {
+                        // the sender is allowed to end the message with a block that has
+                        // `bfinal` set, in which case the trailer that we have added isn't part
+                        // of the DEFLATE data; the next message continues with new blocks
+                        self.inflater.continue_after_last_block();
+                        return Ok(output);
+                    } else {
+                        return Err(IoError::new(ErrorKind::InvalidInput,
+                                                "Data after the last block of a \
+                                                 permessage-deflate message"));
+                    }
+                },
+                len => output.extend_from_slice(&buf[.. len]),
+            }
+        }
+    }
+
+    /// Returns the source of the inflater, or an error if a previous message has failed.
+    fn reader(&mut self) -> io::Result<&mut MessageReader> {
+        self.inflater.try_get_mut().ok_or_else(|| {
+            IoError::new(ErrorKind::InvalidInput, "I/O errors in the inflater are unrecoverable")
+        })
+    }
+}
+
+impl Default for PermessageDeflate {
+    fn default() -> PermessageDeflate {
+        PermessageDeflate::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PermessageDeflate;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn context_takeover() {
+        // `Hello, permessage-deflate world!` twice, compressed by zlib with sync flushes
+        let first = [0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0x28, 0x48, 0x2d, 0xca, 0x4d,
+                     0x2d, 0x2e, 0x4e, 0x4c, 0x4f, 0xd5, 0x4d, 0x49, 0x4d, 0xcb, 0x49, 0x2c,
+                     0x49, 0x55, 0x28, 0xcf, 0x2f, 0xca, 0x49, 0x51, 0x04, 0x00];
+        let second = [0xf2, 0x20, 0x20, 0x0f, 0x00];
+
+        let mut decompressor = PermessageDeflate::new();
+        assert_eq!(decompressor.decompress(&first).unwrap(), b"Hello, permessage-deflate world!");
+        assert_eq!(decompressor.decompress(&second).unwrap(), b"Hello, permessage-deflate world!");
+
+        // the second message only contains a reference to the first one
+        let mut decompressor = PermessageDeflate::new();
+        assert!(decompressor.decompress(&second).is_err());
+    }
+
+    #[test]
+    fn empty_message() {
+        let mut decompressor = PermessageDeflate::new();
+        assert_eq!(decompressor.decompress(&[0x00]).unwrap(), b"");
+    }
+
+    #[test]
+    fn final_block() {
+        // `final` in a block with `bfinal` set, followed by a message that references it
+        let mut decompressor = PermessageDeflate::new();
+        assert_eq!(decompressor.decompress(&[0x4b, 0xcb, 0xcc, 0x4b, 0xcc, 0x01, 0x00]).unwrap(),
+                   b"final");
+        assert_eq!(decompressor.decompress(&[0x4a, 0x03, 0x11, 0x00, 0x00]).unwrap(), b"final");
+    }
+
+    #[test]
+    fn truncated_message() {
+        let mut decompressor = PermessageDeflate::new();
+        assert!(decompressor.decompress(&[0xf2, 0x48, 0xcd]).is_err());
+    }
+
+    #[test]
+    fn message_after_error() {
+        let mut decompressor = PermessageDeflate::new();
+        assert!(decompressor.decompress(&[0xff, 0xff, 0xff]).is_err());
+        assert_eq!(decompressor.decompress(&[0x00]).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,303 @@
+//! Decoding of DEFLATE data with buffers managed by the caller.
+
+use core::cmp;
+use io::{BufRead, ErrorKind, Read};
+use io::Error as IoError;
+use io::Result as IoResult;
+
+use inflate::Inflater;
+use window::Window;
+
+/// Number of bytes copied from the input at a time by `Inflater::decompress`.
+const CHUNK_SIZE: usize = 256;
+
+/// Reader of an `Inflater` that receives its data from the calls to `decompress`.
+///
+/// It never returns the end of the data. Reading from it once the input given to `decompress`
+/// has been consumed returns an error of kind `WouldBlock`, and the inflater resumes from where
+/// it stopped once more input is provided.
+pub struct StreamInput {
+    chunk: [u8; CHUNK_SIZE],
+    start: usize,
+    end: usize,
+}
+
+/// Returned by `Inflater::decompress`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Status {
+    /// Number of bytes consumed from the input.
+    pub consumed: usize,
+    /// Number of bytes written at the start of the output.
+    pub produced: usize,
+    /// Why `decompress` returned.
+    pub state: StreamState,
+}
+
+/// Reason why `Inflater::decompress` returned.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StreamState {
+    /// All the input has been consumed, and more is needed to continue decoding.
+    NeedsInput,
+    /// The output is full. More decoded data may be available.
+    OutputFull,
+    /// The end of the DEFLATE data has been reached. What follows it in the input hasn't been
+    /// consumed.
+    Finished,
+}
+
+impl StreamInput {
+    /// Builds a reader with no data.
+    pub fn new() -> StreamInput {
+        StreamInput {
+            chunk: [0; CHUNK_SIZE],
+            start: 0,
+            end: 0,
+        }
+    }
+}
+
+impl Default for StreamInput {
+    fn default() -> StreamInput {
+        StreamInput::new()
+    }
+}
+
+impl Read for StreamInput {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.start == self.end {
+            return Err(IoError::new(ErrorKind::WouldBlock, "More input is needed"));
+        }
+
+        let len = cmp::min(buf.len(), self.end - self.start);
+        buf[.. len].copy_from_slice(&self.chunk[self.start .. self.start + len]);
+        self.start += len;
+        Ok(len)
+    }
+}
+
+impl Inflater<StreamInput> {
+    /// Builds an inflater whose data is given to each call to `decompress` instead of being
+    /// read from a reader.
+    ///
+    /// Use `with_window_storage(StreamInput::new(), window)` to store the window elsewhere than
+    /// in a `Vec<u8>`.
+    pub fn new_stream() -> Inflater<StreamInput> {
+        Inflater::new(StreamInput::new())
+    }
+}
+
+impl<W> Inflater<StreamInput, W> where W: Window {
+    /// Decodes the DEFLATE data of `input` into `output`, similar to zlib's `inflate()`.
+    ///
+    /// The inflater doesn't own any buffer for the input and the output. Decoding stops once
+    /// `input` is empty, `output` is full, or the end of the data is reached, and the returned
+    /// `Status` says which one happened and how many bytes have been consumed and produced.
+    /// The consumed bytes are marked as such with `BufRead::consume`. A value that is split
+    /// between two calls, for example a block header, is decoded again once the rest of it is
+    /// available, so the input can be provided in pieces of any size.
+    ///
+    /// An empty `input` means that no more data is available for now, not the end of the data.
+    /// Errors other than those of `input` are unrecoverable, as with `read`.
+    pub fn decompress<I>(&mut self, input: &mut I, output: &mut [u8]) -> IoResult<Status>
+        where I: BufRead
+    {
+        let mut status = Status {
+            consumed: 0,
+            produced: 0,
+            state: StreamState::NeedsInput,
+        };
+
+        loop {
+            if self.is_finished() {
+                status.state = StreamState::Finished;
+                return Ok(status);
+            }
+            if status.produced == output.len() {
+                status.state = StreamState::OutputFull;
+                return Ok(status);
+            }
+
+            let available = match input.fill_buf() {
+                Ok(available) => available,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            let copied = {
+                let stream = match self.try_get_mut() {
+                    Some(stream) => stream,
+                    None => {
+                        return Err(IoError::new(ErrorKind::InvalidInput,
+                                                "I/O errors in the inflater are unrecoverable"));
+                    },
+                };
+
+                let len = cmp::min(available.len(), CHUNK_SIZE);
+                stream.chunk[.. len].copy_from_slice(&available[.. len]);
+                stream.start = 0;
+                stream.end = len;
+                len
+            };
+
+            let result = self.read(&mut output[status.produced ..]);
+
+            // the bytes that haven't been read by the inflater are left in the input
+            let used = match self.try_get_mut() {
+                Some(stream) => {
+                    let used = stream.start;
+                    stream.start = 0;
+                    stream.end = 0;
+                    used
+                },
+                None => copied,
+            };
+            input.consume(used);
+            status.consumed += used;
+
+            match result {
+                Ok(n) => status.produced += n,
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock && copied == 0 => {
+                    return Ok(status);
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StreamInput, StreamState};
+    use std::io::Write;
+    use deflate::Deflater;
+    use inflate::Inflater;
+    use window::FixedWindow;
+
+    fn sample() -> Vec<u8> {
+        let mut data = Vec::new();
+        for i in 0u32 .. 20000 {
+            data.extend_from_slice(format!("{} ", i % 731 * i % 97).as_bytes());
+        }
+        data
+    }
+
+    fn compress(data: &[u8], level: u32) -> Vec<u8> {
+        let mut deflater = Deflater::new(Vec::new(), level);
+        deflater.write_all(data).unwrap();
+        deflater.finish().unwrap()
+    }
+
+    // gives the input `input_step` bytes at a time and reads `output_step` bytes at a time
+    fn decode(compressed: &[u8], input_step: usize, output_step: usize) -> Vec<u8> {
+        let mut inflater = Inflater::new_stream();
+        let mut output = Vec::new();
+        let mut offset = 0;
+
+        loop {
+            let end = (offset + input_step).min(compressed.len());
+            let mut input = &compressed[offset .. end];
+            let mut buf = vec![0; output_step];
+            let status = inflater.decompress(&mut input, &mut buf).unwrap();
+
+            assert_eq!(input.len(), end - offset - status.consumed);
+            offset += status.consumed;
+            output.extend_from_slice(&buf[.. status.produced]);
+
+            match status.state {
+                StreamState::Finished => break,
+                StreamState::NeedsInput => {
+                    assert!(input.is_empty());
+                    assert!(offset < compressed.len());
+                },
+                StreamState::OutputFull => assert_eq!(status.produced, output_step),
+            }
+        }
+
+        assert_eq!(offset, compressed.len());
+        assert_eq!(inflater.total_in(), compressed.len() as u64);
+        output
+    }
+
+    #[test]
+    fn tiny_buffers() {
+        let data = sample();
+        for &level in &[0, 1, 6] {
+            let compressed = compress(&data, level);
+            for &(input_step, output_step) in &[(1, 1), (1, 4096), (3, 7), (4096, 1),
+                                                (1 << 20, 1 << 20)] {
+                assert!(decode(&compressed, input_step, output_step) == data);
+            }
+        }
+    }
+
+    #[test]
+    fn data_after_the_end() {
+        let mut compressed = compress(b"hello world", 6);
+        let len = compressed.len();
+        compressed.extend_from_slice(b"trailer");
+
+        let mut inflater = Inflater::new_stream();
+        let mut input = &compressed[..];
+        let mut output = [0; 64];
+        let status = inflater.decompress(&mut input, &mut output).unwrap();
+        assert_eq!(status.state, StreamState::Finished);
+        assert_eq!(status.consumed, len);
+        assert_eq!(&output[.. status.produced], b"hello world");
+        assert_eq!(input, b"trailer");
+
+        // nothing else is decoded
+        let status = inflater.decompress(&mut input, &mut output).unwrap();
+        assert_eq!((status.consumed, status.produced, status.state),
+                   (0, 0, StreamState::Finished));
+    }
+
+    #[test]
+    fn empty_input() {
+        let mut inflater = Inflater::new_stream();
+        let status = inflater.decompress(&mut &[][..], &mut [0; 16]).unwrap();
+        assert_eq!((status.consumed, status.produced, status.state),
+                   (0, 0, StreamState::NeedsInput));
+
+        // nothing is consumed if there is no room for the output
+        let status = inflater.decompress(&mut &[0x03, 0x00][..], &mut []).unwrap();
+        assert_eq!((status.consumed, status.state), (0, StreamState::OutputFull));
+
+        let status = inflater.decompress(&mut &[0x03, 0x00][..], &mut [0; 16]).unwrap();
+        assert_eq!((status.consumed, status.produced, status.state),
+                   (2, 0, StreamState::Finished));
+    }
+
+    #[test]
+    fn fixed_window() {
+        let data = sample();
+        let compressed = compress(&data, 6);
+
+        let mut inflater = Inflater::with_window_storage(StreamInput::new(),
+                                                         FixedWindow::new([0; 32768]));
+        // one more byte, otherwise the output is full before the end of the last block
+        let mut output = vec![0; data.len() + 1];
+        let mut produced = 0;
+        for chunk in compressed.chunks(10) {
+            let mut input = chunk;
+            let status = inflater.decompress(&mut input, &mut output[produced ..]).unwrap();
+            assert!(input.is_empty());
+            produced += status.produced;
+        }
+        assert!(inflater.is_finished());
+        assert_eq!(produced, data.len());
+        assert!(output[.. produced] == data[..]);
+    }
+
+    #[test]
+    fn error() {
+        // block with the reserved type
+        let mut inflater = Inflater::new_stream();
+        assert!(inflater.decompress(&mut &[0x07][..], &mut [0; 16]).is_err());
+        assert!(inflater.decompress(&mut &[0x03, 0x00][..], &mut [0; 16]).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! Iteration over the symbols of DEFLATE data, for tools that analyze how it is compressed.
+
+use alloc::boxed::Box;
+use io::{Read, Result as IoResult};
+use bit::BitRead;
+use compressed_block_reader::{CompressedBlockReader, Symbol};
+use error::DecodeError;
+use inflate::{read_block_header, BlockHeader, BlockStats};
+
+/// An iterator over the symbols of raw DEFLATE data, in the order of the data.
+///
+/// The pointers to previous data are returned as `Symbol::Match` without being resolved, so
+/// nothing is decoded and their distance isn't checked. The bytes of uncompressed blocks are
+/// returned as `Symbol::Literal`. Each block, including the empty ones, ends with a
+/// `Symbol::EndOfBlock`.
+///
+/// After an error, the iterator doesn't return anything else.
+pub struct SymbolIter<R> where R: Read {
+    state: Option<SymbolIterState<R>>,
+}
+
+enum SymbolIterState<R> where R: Read {
+    BeforeBlockStart {
+        data: BitRead<R>,
+    },
+
+    UncompressedData {
+        data: BitRead<R>,
+        // number of bytes remaining in the block
+        len: u16,
+        last_block: bool,
+    },
+
+    CompressedData {
+        data: Box<CompressedBlockReader<R>>,
+        last_block: bool,
+    },
+
+    Eof,
+}
+
+impl<R> SymbolIter<R> where R: Read {
+    /// Builds an iterator over the symbols of the data read from `reader`.
+    pub fn new(reader: R) -> SymbolIter<R> {
+        SymbolIter {
+            state: Some(SymbolIterState::BeforeBlockStart { data: BitRead::new(reader) }),
+        }
+    }
+
+    /// Returns the next symbol, or `None` at the end of the data or after an error.
+    fn next_symbol(&mut self) -> IoResult<Option<Symbol>> {
+        match self.state.take() {
+            Some(SymbolIterState::BeforeBlockStart { mut data }) => {
+                if data.is_at_eof()? {
+                    return Err(DecodeError::UnexpectedEof.into());
+                }
+
+                let header = read_block_header(&mut data, &mut BlockStats::default(), false,
+                                               false)?;
+                self.state = Some(match header {
+                    BlockHeader::Stored { len, last_block } => {
+                        SymbolIterState::UncompressedData { data, len, last_block }
+                    },
+                    BlockHeader::Fixed { last_block } => SymbolIterState::CompressedData {
+                        data: Box::new(CompressedBlockReader::from_fixed_tables(data)),
+                        last_block,
+                    },
+                    BlockHeader::Dynamic { tables, last_block } => {
+                        SymbolIterState::CompressedData {
+                            data: Box::new(CompressedBlockReader::from_dynamic_tables(data,
+                                                                                      tables)),
+                            last_block,
+                        }
+                    },
+                });
+                self.next_symbol()
+            },
+
+            Some(SymbolIterState::UncompressedData { data, len: 0, last_block }) => {
+                self.state = Some(end_block(data, last_block));
+                Ok(Some(Symbol::EndOfBlock))
+            },
+
+            Some(SymbolIterState::UncompressedData { mut data, len, last_block }) => {
+                let byte = data.read(8)?;
+                self.state = Some(SymbolIterState::UncompressedData { data, len: len - 1,
+                                                                      last_block });
+                Ok(Some(Symbol::Literal(byte)))
+            },
+
+            Some(SymbolIterState::CompressedData { mut data, last_block }) => {
+                let symbol = data.next_symbol()?;
+                self.state = Some(if symbol == Symbol::EndOfBlock {
+                    end_block(data.into_inner(), last_block)
+                } else {
+                    SymbolIterState::CompressedData { data, last_block }
+                });
+                Ok(Some(symbol))
+            },
+
+            Some(SymbolIterState::Eof) => {
+                self.state = Some(SymbolIterState::Eof);
+                Ok(None)
+            },
+
+            None => Ok(None),
+        }
+    }
+}
+
+/// Returns the state after a block that ends at the current position of `data`.
+fn end_block<R>(data: BitRead<R>, last_block: bool) -> SymbolIterState<R> where R: Read {
+    if last_block {
+        SymbolIterState::Eof
+    } else {
+        SymbolIterState::BeforeBlockStart { data }
+    }
+}
+
+impl<R> Iterator for SymbolIter<R> where R: Read {
+    type Item = IoResult<Symbol>;
+
+    fn next(&mut self) -> Option<IoResult<Symbol>> {
+        self.next_symbol().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SymbolIter;
+    use compressed_block_reader::Symbol;
+    use std::io::Write;
+    use deflate::Deflater;
+
+    fn symbols(data: &[u8]) -> Vec<Symbol> {
+        SymbolIter::new(data).collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn deflate_late() {
+        let data = [0x73, 0x49, 0x4d, 0xcb, 0x49, 0x2c, 0x49, 0x55, 0x00, 0x11, 0x00];
+
+        let mut expected = b"Deflate ".iter().map(|&b| Symbol::Literal(b)).collect::<Vec<_>>();
+        expected.push(Symbol::Match { length: 4, distance: 5 });
+        expected.push(Symbol::EndOfBlock);
+        assert_eq!(symbols(&data), expected);
+    }
+
+    #[test]
+    fn uncompressed_blocks() {
+        // `hello`, then an empty final block
+        let data = [0x0, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o', 0x1, 0, 0, 0xff, 0xff];
+
+        let mut expected = b"hello".iter().map(|&b| Symbol::Literal(b)).collect::<Vec<_>>();
+        expected.push(Symbol::EndOfBlock);
+        expected.push(Symbol::EndOfBlock);
+        assert_eq!(symbols(&data), expected);
+    }
+
+    #[test]
+    fn dynamic_blocks() {
+        let data = b"Lorem ipsum dolor sit amet, lorem ipsum dolor sit amet".repeat(20);
+        let mut deflater = Deflater::new(Vec::new(), 6);
+        deflater.write_all(&data).unwrap();
+        let compressed = deflater.finish().unwrap();
+
+        // rebuilding the data from the symbols
+        let mut output: Vec<u8> = Vec::new();
+        for symbol in symbols(&compressed) {
+            match symbol {
+                Symbol::Literal(byte) => output.push(byte),
+                Symbol::Match { length, distance } => {
+                    for _ in 0 .. length {
+                        let byte = output[output.len() - distance as usize];
+                        output.push(byte);
+                    }
+                },
+                Symbol::EndOfBlock => (),
+            }
+        }
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    fn errors() {
+        // truncated in the middle of the block, and empty
+        let data = [0x73, 0x49, 0x4d, 0xcb];
+        let mut iter = SymbolIter::new(&data[..]);
+        assert!(iter.by_ref().any(|symbol| symbol.is_err()));
+        assert!(iter.next().is_none());
+
+        assert!(SymbolIter::new(&[][..]).next().unwrap().is_err());
+    }
+}
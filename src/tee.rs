@@ -0,0 +1,129 @@
+//! Copy of the compressed data that a decoder consumes, for proxies that forward it.
+
+use io::{ErrorKind, Read, Write};
+use io::Error as IoError;
+
+/// A reader that writes all the bytes read from an underlying reader to a sink.
+///
+/// Wrapping the reader of a decoder gives both the decompressed data and the exact compressed
+/// bytes that the decoder has consumed, without buffering the compressed data separately.
+/// The decoders read their input byte by byte and stop at the end of their data, so the sink
+/// doesn't receive what follows. See `Inflater::new_teeing`.
+///
+/// If writing to the sink fails, the error is returned by `read`. The bytes read at that time
+/// are lost, so the next calls to `read` return an error instead of reading anything.
+pub struct Tee<R, S> {
+    inner: R,
+    sink: S,
+
+    /// True if writing to the sink has failed.
+    failed: bool,
+}
+
+impl<R, S> Tee<R, S> where R: Read, S: Write {
+    /// Builds a reader that reads from `inner` and writes what it reads to `sink`.
+    pub fn new(inner: R, sink: S) -> Tee<R, S> {
+        Tee { inner, sink, failed: false }
+    }
+
+    /// Returns a reference to the sink.
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Returns a mutable reference to the sink.
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Destroys the tee and returns the underlying reader and the sink.
+    pub fn into_inner(self) -> (R, S) {
+        (self.inner, self.sink)
+    }
+}
+
+impl<R, S> Read for Tee<R, S> where R: Read, S: Write {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        if self.failed {
+            return Err(IoError::new(ErrorKind::InvalidInput,
+                                    "Writing to the sink of the tee has failed"));
+        }
+
+        let len = self.inner.read(buf)?;
+        if let Err(err) = self.sink.write_all(&buf[.. len]) {
+            // the bytes have been consumed, so nothing can be read anymore
+            self.failed = true;
+            return Err(err);
+        }
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tee;
+    use std::io::{self, ErrorKind, Read, Write};
+    use inflate::Inflater;
+    use zlib_decoder::ZlibDecoder;
+    use LOREM_IPSUM as DATA;
+
+    #[test]
+    fn inflater() {
+        let compressed = ::deflate(&DATA.repeat(100), 6);
+
+        let mut input = compressed.clone();
+        input.extend_from_slice(b"next frame");
+
+        let mut inflater = Inflater::new_teeing(&input[..], Vec::new());
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert!(output == DATA.repeat(100));
+
+        let (rest, teed) = inflater.into_inner().into_inner();
+        assert_eq!(teed, compressed);
+        assert_eq!(rest, b"next frame");
+    }
+
+    #[test]
+    fn zlib() {
+        let compressed = ::compress_zlib(DATA, 6);
+
+        let mut decoder = ZlibDecoder::new(Tee::new(&compressed[..], Vec::new()));
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, DATA);
+        assert_eq!(decoder.into_inner().sink(), &compressed);
+    }
+
+    /// Sink that accepts `remaining` bytes, then fails.
+    struct FailingSink {
+        remaining: usize,
+    }
+
+    impl Write for FailingSink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.remaining == 0 {
+                return Err(io::Error::new(ErrorKind::WouldBlock, "full"));
+            }
+            let len = buf.len().min(self.remaining);
+            self.remaining -= len;
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sink_error() {
+        let mut tee = Tee::new(DATA, FailingSink { remaining: 10 });
+        let mut buf = [0; 8];
+        assert_eq!(tee.read(&mut buf).unwrap(), 8);
+        assert_eq!(tee.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        // the error isn't retryable, as the bytes have been lost
+        assert_eq!(tee.read(&mut buf).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+}
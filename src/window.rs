@@ -0,0 +1,206 @@
+//! Storage of the decoded data that the pointers of DEFLATE data can reference.
+
+use alloc::vec::Vec;
+use core::cmp;
+
+/// Storage of the last bytes decoded by an `Inflater`, which pointers can copy data from.
+///
+/// The default storage is a `Vec<u8>`, which is allocated on the heap. `FixedWindow` stores the
+/// data in a buffer of fixed size instead, for example an array on the stack, so that the
+/// window, which is by far the largest buffer of the inflater, isn't allocated dynamically.
+pub trait Window {
+    /// Returns the number of bytes stored, which pointers can reference.
+    fn len(&self) -> usize;
+
+    /// Returns true if no byte is stored.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum number of bytes that can be stored, which limits the distance of the
+    /// pointers. Unlimited by default.
+    fn capacity(&self) -> usize {
+        usize::MAX
+    }
+
+    /// Appends decoded data. The data that is more than `window_size` bytes back can be
+    /// discarded, but doesn't have to be.
+    fn push(&mut self, data: &[u8], window_size: usize);
+
+    /// Copies to `dest` the stored data that starts `distance` bytes before the end.
+    ///
+    /// `distance` is never superior to `len()`, and the length of `dest` is never superior to
+    /// `distance`.
+    fn copy_back(&self, distance: usize, dest: &mut [u8]);
+
+    /// Discards all the data.
+    fn clear(&mut self);
+}
+
+impl Window for Vec<u8> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn push(&mut self, data: &[u8], window_size: usize) {
+        self.extend_from_slice(data);
+
+        // discarding only once the buffer is twice as large as needed, so that the cost of
+        // moving the data is spread over many bytes
+        if Vec::len(self) >= 2 * cmp::max(window_size, 4096) {
+            let excess = Vec::len(self) - window_size;
+            self.drain(.. excess);
+        }
+    }
+
+    fn copy_back(&self, distance: usize, dest: &mut [u8]) {
+        let start = Vec::len(self) - distance;
+        dest.copy_from_slice(&self[start .. start + dest.len()]);
+    }
+
+    fn clear(&mut self) {
+        Vec::clear(self)
+    }
+}
+
+/// Window that stores the data in a buffer of fixed size, used as a ring buffer.
+///
+/// The buffer can be an array, such as `[u8; 32768]`, or a mutable slice provided by the
+/// caller. Pointers can't go further back than its length, so it must be at least as long as
+/// the window size of the data, which is 32768 bytes for most DEFLATE data. Decoding a pointer
+/// that goes further back returns an error.
+#[derive(Clone)]
+pub struct FixedWindow<B> where B: AsRef<[u8]> + AsMut<[u8]> {
+    buffer: B,
+
+    /// Position in `buffer` where the next byte is written.
+    end: usize,
+
+    /// Number of bytes stored, which is at most the length of `buffer`.
+    len: usize,
+}
+
+impl<B> FixedWindow<B> where B: AsRef<[u8]> + AsMut<[u8]> {
+    /// Builds a window that stores the data in `buffer`. Its content is ignored.
+    pub fn new(buffer: B) -> FixedWindow<B> {
+        FixedWindow {
+            buffer,
+            end: 0,
+            len: 0,
+        }
+    }
+
+    /// Destroys the window and returns the buffer.
+    pub fn into_inner(self) -> B {
+        self.buffer
+    }
+}
+
+impl<B> Window for FixedWindow<B> where B: AsRef<[u8]> + AsMut<[u8]> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn capacity(&self) -> usize {
+        self.buffer.as_ref().len()
+    }
+
+    fn push(&mut self, data: &[u8], _: usize) {
+        let buffer = self.buffer.as_mut();
+        if buffer.is_empty() {
+            return;
+        }
+
+        // only the end of the data fits in the buffer
+        if data.len() >= buffer.len() {
+            let start = data.len() - buffer.len();
+            buffer.copy_from_slice(&data[start ..]);
+            self.end = 0;
+            self.len = buffer.len();
+            return;
+        }
+
+        let first = cmp::min(data.len(), buffer.len() - self.end);
+        buffer[self.end .. self.end + first].copy_from_slice(&data[.. first]);
+        buffer[.. data.len() - first].copy_from_slice(&data[first ..]);
+        self.end = (self.end + data.len()) % buffer.len();
+        self.len = cmp::min(self.len + data.len(), buffer.len());
+    }
+
+    fn copy_back(&self, distance: usize, dest: &mut [u8]) {
+        let buffer = self.buffer.as_ref();
+        let start = (self.end + buffer.len() - distance) % buffer.len();
+        let first = cmp::min(dest.len(), buffer.len() - start);
+        let (dest_start, dest_end) = dest.split_at_mut(first);
+        dest_start.copy_from_slice(&buffer[start .. start + first]);
+        dest_end.copy_from_slice(&buffer[.. dest_end.len()]);
+    }
+
+    fn clear(&mut self) {
+        self.end = 0;
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FixedWindow, Window};
+
+    fn back<W>(window: &W, distance: usize, len: usize) -> Vec<u8> where W: Window {
+        let mut dest = vec![0; len];
+        window.copy_back(distance, &mut dest);
+        dest
+    }
+
+    #[test]
+    fn vec() {
+        // `Vec` has inherent methods with the same names
+        let mut window = Vec::new();
+        Window::push(&mut window, b"hello ", 8);
+        Window::push(&mut window, b"world", 8);
+        assert_eq!(Window::len(&window), 11);
+        assert_eq!(Window::capacity(&window), usize::MAX);
+        assert_eq!(back(&window, 11, 5), b"hello");
+        assert_eq!(back(&window, 5, 5), b"world");
+
+        Window::clear(&mut window);
+        assert!(Window::is_empty(&window));
+
+        // the data that is out of the window is discarded once there is too much of it
+        Window::push(&mut window, &[0; 8192], 8);
+        assert_eq!(Window::len(&window), 8);
+    }
+
+    #[test]
+    fn fixed() {
+        let mut window = FixedWindow::new([0; 8]);
+        assert_eq!(window.capacity(), 8);
+        window.push(b"hello ", 8);
+        assert_eq!(window.len(), 6);
+        assert_eq!(back(&window, 6, 6), b"hello ");
+
+        // wraps around the end of the buffer
+        window.push(b"world", 8);
+        assert_eq!(window.len(), 8);
+        assert_eq!(back(&window, 8, 8), b"lo world");
+        assert_eq!(back(&window, 7, 3), b"o w");
+
+        window.push(b"0123456789", 8);
+        assert_eq!(back(&window, 8, 8), b"23456789");
+
+        window.clear();
+        assert!(window.is_empty());
+    }
+
+    #[test]
+    fn fixed_caller_buffer() {
+        let mut buffer = [0; 4];
+        {
+            let mut window = FixedWindow::new(&mut buffer[..]);
+            window.push(b"abc", 4);
+            window.push(b"def", 4);
+            assert_eq!(back(&window, 4, 4), b"cdef");
+        }
+        assert_eq!(&buffer, b"efcd");
+    }
+}
@@ -1,12 +1,87 @@
-use std::io::{ErrorKind, Read};
-use std::io::Error as IoError;
-use inflate::Inflater;
+use alloc::vec::Vec;
+use core::cmp;
+use io::{ErrorKind, Read};
+use io::Error as IoError;
+use adler32::Adler32;
+use error::DecodeError;
+use inflate::{BlockStats, Inflater, DEFAULT_WINDOW_SIZE};
 
 /// A reader that decodes zlib data from an underlying reader.
+///
+/// As with `Inflater`, the decoder can be cloned if the reader can, which only makes sense for
+/// in-memory readers such as `&[u8]` or a `Cursor`.
+#[derive(Clone)]
 pub struct ZlibDecoder<R> where R: Read {
     state: Option<ZlibDecoderState<R>>,
+
+    /// Preset dictionary passed by the user, if any.
+    dictionary: Option<Vec<u8>>,
+
+    /// Information about the header, once it has been read.
+    header: Option<ZlibHeaderInfo>,
+
+    /// Number of bytes read from the reader, not counting the ones read by the current
+    /// inflater.
+    total_in: u64,
+
+    /// Number of bytes decoded so far, not counting the ones decoded by the current inflater.
+    total_out: u64,
+
+    /// Statistics of the inflaters that have been destroyed.
+    block_stats: BlockStats,
+
+    /// If true, another zlib stream can follow the trailer. See `new_multi`.
+    multi: bool,
+
+    /// Buffer to use for the decoded data of the next inflater, if any. See
+    /// `with_output_cache`.
+    output_cache: Option<Vec<u8>>,
+    /// If true, a wrong or missing trailer isn't an error. See `new_lenient`.
+    lenient: bool,
+
+    /// Whether the trailers that have been read so far were correct.
+    checksum_ok: Option<bool>,
+
+    /// Maximum number of bytes to decode, if any. See `ZlibDecoderBuilder::size_limit`.
+    size_limit: Option<u64>,
+
+    /// Bytes of the header or of the trailer being read, which are kept if the reader returns
+    /// `WouldBlock` or `Interrupted` in the middle of them.
+    pending: [u8; 6],
+
+    /// Number of bytes in `pending`.
+    pending_len: usize,
+}
+
+/// Builds a `ZlibDecoder` with several options at once.
+#[derive(Debug, Clone, Default)]
+pub struct ZlibDecoderBuilder {
+    dictionary: Option<Vec<u8>>,
+    size_limit: Option<u64>,
+    lenient: bool,
+    multi: bool,
 }
 
+/// Information found in the header of zlib data.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ZlibHeaderInfo {
+    /// Compression method. Always `8`, which is DEFLATE.
+    pub compression_method: u8,
+
+    /// Size in bytes of the window used by the compressor, between 256 and 32768.
+    pub window_size: u32,
+
+    /// Value of the `FLEVEL` field. See `ZlibDecoder::compression_level_hint`.
+    pub compression_level: u8,
+
+    /// Adler32 checksum of the preset dictionary that the data requires, if any.
+    pub dictionary_id: Option<u32>,
+}
+
+// The inflater is much larger than the other states, but it is the state that the decoder is in
+// almost all the time, and boxing it would only add an allocation.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone)]
 enum ZlibDecoderState<R> where R: Read {
     // we haven't started doing anything yet
     Start {
@@ -14,13 +89,44 @@ enum ZlibDecoderState<R> where R: Read {
         reader: R,
     },
 
+    // we have read the header but not started decompressing
+    Header {
+        // naked reader, positioned right after the header
+        reader: R,
+
+        // identifier of the preset dictionary required by the data, if any
+        dictionary_id: Option<u32>,
+    },
+
     // we are currently reading compressed data
     CompressedData {
         // reader wrapper around the inflater
         reader: Inflater<R>,
+
+        // checksum of the data decompressed so far
+        adler: Adler32,
     },
 
-    Checksum,
+    // we have decoded the data and are reading the trailer
+    Trailer {
+        // naked reader, positioned right after the compressed data or in the trailer
+        reader: R,
+
+        // checksum of the decompressed data
+        checksum: u32,
+    },
+
+    // we have read a trailer and are checking whether another zlib stream follows
+    NextHeader {
+        // naked reader, positioned right after the trailer or in the next header
+        reader: R,
+    },
+
+    // we have read and checked the trailer
+    Eof {
+        // naked reader, positioned right after the trailer
+        reader: R,
+    },
 }
 
 impl<R> ZlibDecoder<R> where R: Read {
@@ -28,89 +134,762 @@ impl<R> ZlibDecoder<R> where R: Read {
     pub fn new(reader: R) -> ZlibDecoder<R> {
         ZlibDecoder {
             state: Some(ZlibDecoderState::Start {
-                reader: reader,
-            })
+                reader,
+            }),
+            dictionary: None,
+            header: None,
+            total_in: 0,
+            total_out: 0,
+            block_stats: BlockStats::default(),
+            multi: false,
+            output_cache: None,
+            lenient: false,
+            checksum_ok: None,
+            size_limit: None,
+            pending: [0; 6],
+            pending_len: 0,
+        }
+    }
+
+    /// Same as `new`, but reads and checks the header right away, so that data that isn't zlib
+    /// data is detected before anything else is done with the decoder.
+    ///
+    /// `new` doesn't read anything until the first call to `read`, which is needed if the header
+    /// may not be available yet. If the data requires a preset dictionary, its identifier is
+    /// read as part of the header, and the dictionary can then be provided with
+    /// `set_dictionary`.
+    pub fn try_new(reader: R) -> Result<ZlibDecoder<R>, IoError> {
+        let mut decoder = ZlibDecoder::new(reader);
+        decoder.read_header()?;
+        Ok(decoder)
+    }
+
+    /// Same as `new`, but decodes multiple zlib streams that follow each other, like the result
+    /// of `cat a.zlib b.zlib`. Their decompressed content is concatenated.
+    ///
+    /// After the trailer of each stream, the next two bytes are checked to find out whether they
+    /// form a valid zlib header. If they don't, or if the data ends, the decoding stops there
+    /// without an error, and these bytes are lost. Note that some data can happen to start like
+    /// a zlib header without being one, in which case decoding it will return an error.
+    ///
+    /// `total_in` doesn't include the bytes that have been checked and aren't a header.
+    pub fn new_multi(reader: R) -> ZlibDecoder<R> {
+        let mut decoder = ZlibDecoder::new(reader);
+        decoder.multi = true;
+        decoder
+    }
+
+    /// Same as `new`, but a wrong Adler32 checksum in the trailer, or a trailer that is cut
+    /// short by the end of the data, isn't an error. The decoding then ends normally, and
+    /// `checksum_ok` returns `Some(false)`.
+    ///
+    /// This is meant for data produced by broken encoders, whose content is correct but not
+    /// the trailer. The data is still checked while it is being decoded.
+    pub fn new_lenient(reader: R) -> ZlibDecoder<R> {
+        let mut decoder = ZlibDecoder::new(reader);
+        decoder.lenient = true;
+        decoder
+    }
+
+    /// Same as `new`, but provides the preset dictionary that was used to compress the data.
+    /// See `ZlibEncoder::new_with_dictionary`.
+    ///
+    /// Decoding data that requires a dictionary fails without one. The dictionary is ignored if
+    /// the data doesn't require one.
+    pub fn with_dictionary(reader: R, dictionary: &[u8]) -> ZlibDecoder<R> {
+        let mut decoder = ZlibDecoder::new(reader);
+        decoder.dictionary = Some(dictionary.to_vec());
+        decoder
+    }
+
+    /// Same as `new`, but the inflater reuses `output_cache` to store the decoded data instead of
+    /// allocating a new buffer. See `take_output_cache`.
+    #[cfg(feature = "std")]
+    pub(crate) fn with_output_cache(reader: R, output_cache: Vec<u8>) -> ZlibDecoder<R> {
+        let mut decoder = ZlibDecoder::new(reader);
+        decoder.output_cache = Some(output_cache);
+        decoder
+    }
+
+    /// Takes the buffer where the decoded data is stored, if the decoder has allocated one, so
+    /// that another decoder can reuse it. The decoder must not be used afterwards.
+    #[cfg(feature = "std")]
+    pub(crate) fn take_output_cache(&mut self) -> Option<Vec<u8>> {
+        match self.state {
+            Some(ZlibDecoderState::CompressedData { ref mut reader, .. }) => {
+                Some(reader.take_output_cache())
+            },
+            _ => self.output_cache.take(),
+        }
+    }
+
+    /// Decodes all the zlib data of `reader` and checks its trailer, without keeping the
+    /// decompressed data. Returns the length of the decompressed data.
+    ///
+    /// This is meant to check that the data is valid without allocating a buffer for the
+    /// decompressed data, which can be much larger than the compressed one.
+    pub fn validate(reader: R) -> Result<u64, IoError> {
+        let mut decoder = ZlibDecoder::new(reader);
+        let mut buf = [0; 4096];
+
+        loop {
+            match decoder.read(&mut buf) {
+                Ok(0) => return Ok(decoder.total_out()),
+                Ok(_) => (),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
         }
     }
+
+    /// Returns the identifier of the preset dictionary that the data requires, which is its
+    /// Adler32 checksum, or `None` if the data doesn't require one or if the header hasn't
+    /// been read yet.
+    ///
+    /// Decoding such data without a dictionary returns a `DecodeError::DictionaryRequired`
+    /// error containing the same identifier, after which the dictionary can be provided with
+    /// `set_dictionary`.
+    pub fn required_dictionary_id(&self) -> Option<u32> {
+        self.header.and_then(|header| header.dictionary_id)
+    }
+
+    /// Provides the preset dictionary once the header has shown that the data requires one,
+    /// for when the dictionary is chosen according to `required_dictionary_id`.
+    ///
+    /// This can only be called between the moment the header has been read, with `read_header`
+    /// or by a call to `read` that returned a `DecodeError::DictionaryRequired` error, and the
+    /// moment decoding starts. Otherwise, or if the data doesn't require a dictionary,
+    /// `DecodeError::UnexpectedDictionary` is returned. If the Adler32 checksum of `dictionary`
+    /// isn't the one of the header, `DecodeError::WrongDictionary` is returned and the
+    /// dictionary can be set again.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<(), DecodeError> {
+        let expected = match self.state {
+            Some(ZlibDecoderState::Header { dictionary_id: Some(id), .. }) => id,
+            _ => return Err(DecodeError::UnexpectedDictionary),
+        };
+
+        let mut adler = Adler32::new();
+        adler.feed(dictionary);
+        if adler.checksum() != expected {
+            return Err(DecodeError::WrongDictionary { expected, found: adler.checksum() });
+        }
+
+        self.dictionary = Some(dictionary.to_vec());
+        Ok(())
+    }
+
+    /// Returns the value of the `FLEVEL` field of the zlib header, or `None` if the header hasn't
+    /// been read yet.
+    ///
+    /// This is a hint given by the compressor: `0` means that the fastest algorithm was used,
+    /// `1` a fast algorithm, `2` the default algorithm and `3` the slowest algorithm with the
+    /// maximum compression. It isn't needed to decode the data.
+    pub fn compression_level_hint(&self) -> Option<u8> {
+        self.header.map(|header| header.compression_level)
+    }
+
+    /// Returns whether the Adler32 checksum of the trailer matches the decoded data, or `None`
+    /// if the trailer hasn't been read yet. For multiple streams, this is false if one of the
+    /// trailers is wrong.
+    ///
+    /// Unless the decoder has been built with `new_lenient`, a wrong checksum is an error and
+    /// this is never `Some(false)`.
+    pub fn checksum_ok(&self) -> Option<bool> {
+        self.checksum_ok
+    }
+
+    /// Returns true if all the data has been decoded and the trailer has been checked.
+    ///
+    /// This is the case once `read` has returned 0. If it is false after a call to `read` has
+    /// returned an error, the data is incomplete or invalid.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, Some(ZlibDecoderState::Eof { .. }))
+    }
+
+    /// Returns the number of bytes read from the underlying reader so far, including the header
+    /// and the trailer.
+    ///
+    /// The data is read byte by byte, so once `read` has returned 0 this is exactly the length
+    /// of the zlib data.
+    pub fn total_in(&self) -> u64 {
+        match self.state {
+            Some(ZlibDecoderState::CompressedData { ref reader, .. }) => {
+                self.total_in + reader.total_in()
+            },
+            _ => self.total_in,
+        }
+    }
+
+    /// Returns the number of bytes decoded so far.
+    pub fn total_out(&self) -> u64 {
+        match self.state {
+            Some(ZlibDecoderState::CompressedData { ref reader, .. }) => {
+                self.total_out + reader.total_out()
+            },
+            _ => self.total_out,
+        }
+    }
+
+    /// Returns the number of DEFLATE blocks of each type encountered so far.
+    pub fn block_stats(&self) -> BlockStats {
+        match self.state {
+            Some(ZlibDecoderState::CompressedData { ref reader, .. }) => {
+                let stats = reader.block_stats();
+                BlockStats {
+                    stored: self.block_stats.stored + stats.stored,
+                    fixed: self.block_stats.fixed + stats.fixed,
+                    dynamic: self.block_stats.dynamic + stats.dynamic,
+                }
+            },
+            _ => self.block_stats,
+        }
+    }
+
+    /// Reads the zlib header if it hasn't been read yet, and returns the information it
+    /// contains.
+    ///
+    /// Calling this is optional, as `read` reads the header when needed. Does not start
+    /// decompressing the data and doesn't check whether the required preset dictionary, if
+    /// any, has been provided.
+    pub fn read_header(&mut self) -> Result<ZlibHeaderInfo, IoError> {
+        match self.state.take() {
+            Some(ZlibDecoderState::Start { mut reader }) => {
+                let header = match self.consume_header(&mut reader, false) {
+                    Ok(header) => header.expect("only the next streams are optional"),
+                    Err(err) => {
+                        if ::is_retryable(&err) {
+                            self.state = Some(ZlibDecoderState::Start { reader });
+                        }
+                        return Err(err);
+                    },
+                };
+
+                self.state = Some(ZlibDecoderState::Header {
+                    reader,
+                    dictionary_id: header.dictionary_id,
+                });
+                Ok(header)
+            },
+
+            Some(state) => {
+                self.state = Some(state);
+                Ok(self.header.expect("the header is read before anything else"))
+            },
+
+            None => {
+                Err(IoError::new(ErrorKind::InvalidInput,
+                                 "I/O errors in the inflater are unrecoverable"))
+            }
+        }
+    }
+
+    /// Decodes the whole data into `buf`, whose length must be exactly the length of the
+    /// decompressed data. The trailer is checked as well.
+    ///
+    /// Returns an error of kind `UnexpectedEof` if the data is shorter than `buf`, and of kind
+    /// `InvalidData` if it is longer. In both cases, the content of `buf` is unspecified.
+    pub fn decode_exact(&mut self, buf: &mut [u8]) -> Result<(), IoError> {
+        let mut filled = 0;
+        while filled != buf.len() {
+            match self.read(&mut buf[filled ..]) {
+                Ok(0) => return Err(IoError::new(ErrorKind::UnexpectedEof,
+                                                 "Decompressed data is shorter than the buffer")),
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        // reading until the end, which also checks the trailer
+        loop {
+            match self.read(&mut [0]) {
+                Ok(0) => return Ok(()),
+                Ok(_) => return Err(IoError::new(ErrorKind::InvalidData,
+                                                 "Trailing data after the end of the buffer")),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Reads a zlib header into `self.header` and returns it. If `next_stream` is true, this is
+    /// the header of a stream that follows another one, and `None` is returned if the data ends
+    /// or if there is no valid header.
+    ///
+    /// If the reader returns `WouldBlock` or `Interrupted`, what has been read so far is kept in
+    /// `self.pending` and the next call continues from there.
+    fn consume_header(&mut self, reader: &mut R, next_stream: bool)
+                      -> Result<Option<ZlibHeaderInfo>, IoError>
+    {
+        if self.fill_pending(reader, 2)? < 2 {
+            self.pending_len = 0;
+            if next_stream {
+                return Ok(None);
+            }
+            return Err(IoError::new(ErrorKind::InvalidInput, "Unexpected EOF"));
+        }
+
+        let (cmf, flg) = (self.pending[0], self.pending[1]);
+        if next_stream && !is_zlib_header(cmf, flg) {
+            self.pending_len = 0;
+            return Ok(None);
+        }
+        let mut header = check_zlib_header(cmf, flg)?;
+
+        // if the `fdict` flag is set, there is the Adler32 of the dictionary afterwards here
+        if header.dictionary_id.is_some() {
+            if self.fill_pending(reader, 6)? < 6 {
+                return Err(IoError::new(ErrorKind::InvalidInput, "Unexpected EOF"));
+            }
+            let id = [self.pending[2], self.pending[3], self.pending[4], self.pending[5]];
+            header.dictionary_id = Some(u32::from_be_bytes(id));
+        }
+
+        self.total_in += self.pending_len as u64;
+        self.pending_len = 0;
+        self.header = Some(header);
+        Ok(Some(header))
+    }
+
+    /// Reads from `reader` until `self.pending` contains at least `len` bytes or the data ends,
+    /// and returns the number of bytes in `self.pending`. Reads that are interrupted are retried.
+    fn fill_pending(&mut self, reader: &mut R, len: usize) -> Result<usize, IoError> {
+        while self.pending_len < len {
+            match reader.read(&mut self.pending[self.pending_len .. len]) {
+                Ok(0) => break,
+                Ok(n) => self.pending_len += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(self.pending_len)
+    }
+
+    /// Returns a mutable reference to the underlying reader, or `None` if a previous call to
+    /// `read` has returned an error that can't be recovered from.
+    pub(crate) fn try_get_mut(&mut self) -> Option<&mut R> {
+        match self.state {
+            Some(ZlibDecoderState::Start { ref mut reader }) => Some(reader),
+            Some(ZlibDecoderState::Header { ref mut reader, .. }) => Some(reader),
+            Some(ZlibDecoderState::CompressedData { ref mut reader, .. }) => reader.try_get_mut(),
+            Some(ZlibDecoderState::Trailer { ref mut reader, .. }) => Some(reader),
+            Some(ZlibDecoderState::NextHeader { ref mut reader }) => Some(reader),
+            Some(ZlibDecoderState::Eof { ref mut reader }) => Some(reader),
+            None => None,
+        }
+    }
+
+    /// Destroys the decoder and returns the underlying reader.
+    ///
+    /// Once `read` has returned 0, the reader is positioned right after the Adler32 trailer of
+    /// the zlib data: the decoder reads the data byte by byte and never consumes anything past
+    /// the trailer, so any data that follows can be read from the reader. Otherwise it is
+    /// positioned somewhere in the middle of the zlib data.
+    ///
+    /// # Panic
+    ///
+    /// Panics if a previous call to `read` returned an error.
+    ///
+    pub fn into_inner(self) -> R {
+        match self.state {
+            Some(ZlibDecoderState::Start { reader }) => reader,
+            Some(ZlibDecoderState::Header { reader, .. }) => reader,
+            Some(ZlibDecoderState::CompressedData { reader, .. }) => reader.into_inner(),
+            Some(ZlibDecoderState::Trailer { reader, .. }) => reader,
+            Some(ZlibDecoderState::NextHeader { reader }) => reader,
+            Some(ZlibDecoderState::Eof { reader }) => reader,
+            None => panic!("I/O errors in the inflater are unrecoverable"),
+        }
+    }
+
+    /// Reads and checks the Adler32 trailer if it hasn't been read yet, then destroys the
+    /// decoder and returns the underlying reader, positioned right after the trailer.
+    ///
+    /// Contrary to `into_inner`, this tells for sure that the data is complete and valid, even
+    /// if `read` has never returned 0, for example after a `read_exact` that ended with the
+    /// last byte of the data.
+    ///
+    /// Returns an error of kind `InvalidInput` if some of the decompressed data hasn't been
+    /// read yet. The errors of the reader are returned as well, including `WouldBlock`, in
+    /// which case the reader is lost.
+    pub fn finish(mut self) -> Result<R, IoError> {
+        // the data has been fully read if decoding more of it doesn't return anything
+        match self.read(&mut [0])? {
+            0 => Ok(self.into_inner()),
+            _ => Err(IoError::new(ErrorKind::InvalidInput,
+                                  "The zlib data hasn't been fully read")),
+        }
+    }
+
+    /// Turns the decoder into an iterator over the decompressed bytes.
+    ///
+    /// The data is decompressed in chunks, so this is much faster than calling `bytes()`.
+    /// The first error is returned by the iterator, which then ends.
+    pub fn decoded_bytes(self) -> DecodedBytes<R> {
+        DecodedBytes {
+            decoder: self,
+            buffer: vec![0; DECODED_BYTES_BUFFER_LEN],
+            pos: 0,
+            len: 0,
+            finished: false,
+        }
+    }
+}
+
+// The reader is only passed to `ZlibDecoderBuilder::build`, so `builder` is only defined for a
+// single type of reader in order for `ZlibDecoder::builder()` to compile.
+impl ZlibDecoder<&'static [u8]> {
+    /// Returns a builder that allows setting several options of the decoder.
+    pub fn builder() -> ZlibDecoderBuilder {
+        ZlibDecoderBuilder::default()
+    }
+}
+
+impl<'a> ZlibDecoder<&'a [u8]> {
+    /// Builds a new zlib decoder that reads the data from a slice, without wrapping it in a
+    /// `Cursor`.
+    ///
+    /// Once `read` has returned 0, `into_inner` returns the part of `data` that follows the zlib
+    /// stream.
+    pub fn from_slice(data: &'a [u8]) -> ZlibDecoder<&'a [u8]> {
+        ZlibDecoder::new(data)
+    }
 }
 
 impl<R> Read for ZlibDecoder<R> where R: Read {
+    #[cfg(feature = "read_buf")]
+    fn read_buf(&mut self, cursor: ::std::io::BorrowedCursor<'_>) -> Result<(), IoError> {
+        ::read_buf_chunk(self, cursor)
+    }
+
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // a loop rather than recursion, as the data can contain any number of empty streams
+        loop {
+            if let Some(len) = self.read_step(buf)? {
+                return Ok(len);
+            }
+        }
+    }
+}
+
+impl<R> ZlibDecoder<R> where R: Read {
+    /// Advances the decoder by one state. Returns `None` if nothing has been decoded yet, in
+    /// which case the decoder must be called again with the same buffer.
+    fn read_step(&mut self, buf: &mut [u8]) -> Result<Option<usize>, IoError> {
         match self.state.take() {
-            Some(ZlibDecoderState::Start { mut reader }) => {
-                try!(consume_zlib_header(&mut reader));
+            Some(state @ ZlibDecoderState::Start { .. }) => {
+                self.state = Some(state);
+                self.read_header()?;
+                Ok(None)
+            },
+
+            Some(ZlibDecoderState::Header { reader, dictionary_id }) => {
+                let output_cache = self.output_cache.take().unwrap_or_else(|| {
+                    Vec::with_capacity(2 * DEFAULT_WINDOW_SIZE)
+                });
+
+                let mut inflater = match dictionary_id {
+                    None => Inflater::with_output_cache(reader, output_cache, &[]),
+                    Some(id) => {
+                        // the decoder stays before the data, so that `set_dictionary` can
+                        // still be called
+                        let dictionary = match self.dictionary {
+                            Some(ref d) => d,
+                            None => {
+                                self.output_cache = Some(output_cache);
+                                self.state = Some(ZlibDecoderState::Header {
+                                    reader,
+                                    dictionary_id,
+                                });
+                                return Err(DecodeError::DictionaryRequired { id }.into());
+                            },
+                        };
+
+                        let mut adler = Adler32::new();
+                        adler.feed(dictionary);
+                        if adler.checksum() != id {
+                            self.output_cache = Some(output_cache);
+                            self.state = Some(ZlibDecoderState::Header {
+                                reader,
+                                dictionary_id,
+                            });
+                            return Err(IoError::new(ErrorKind::InvalidInput,
+                                                    "Wrong preset dictionary for the zlib data"));
+                        }
+
+                        Inflater::with_output_cache(reader, output_cache, dictionary)
+                    },
+                };
+
+                let header = self.header.expect("the header is read before anything else");
+                inflater.set_window_size(header.window_size as usize);
+
                 self.state = Some(ZlibDecoderState::CompressedData {
-                    reader: Inflater::new(reader),
+                    reader: inflater,
+                    adler: Adler32::new(),
                 });
-                self.read(buf)
+                Ok(None)
             },
 
-            Some(ZlibDecoderState::CompressedData { mut reader }) => {
-                let result = try!(reader.read(buf));
+            Some(ZlibDecoderState::CompressedData { mut reader, mut adler }) => {
+                // decoding one byte more than allowed tells if the data is too long, and a limit
+                // of `u64::MAX` can't be exceeded anyway
+                let allowed = self.size_limit.map(|limit| {
+                    limit.saturating_sub(self.total_out + reader.total_out()).saturating_add(1)
+                });
+                let len = match allowed {
+                    Some(allowed) => cmp::min(buf.len() as u64, allowed) as usize,
+                    None => buf.len(),
+                };
+
+                let result = match reader.read(&mut buf[.. len]) {
+                    Ok(result) => result,
+                    Err(err) => {
+                        // the inflater continues from where it stopped once there is more data
+                        if ::is_retryable(&err) {
+                            self.state = Some(ZlibDecoderState::CompressedData { reader, adler });
+                        }
+                        return Err(err);
+                    },
+                };
+
+                if allowed == Some(result as u64) {
+                    return Err(IoError::new(ErrorKind::InvalidData,
+                                            "Decompressed data exceeds the size limit"));
+                }
 
                 if result == 0 {
-                    self.state = Some(ZlibDecoderState::Checksum);
-                    self.read(buf)
+                    self.total_in += reader.total_in();
+                    self.total_out += reader.total_out();
+                    let stats = reader.block_stats();
+                    self.block_stats.stored += stats.stored;
+                    self.block_stats.fixed += stats.fixed;
+                    self.block_stats.dynamic += stats.dynamic;
+                    self.output_cache = Some(reader.take_output_cache());
+                    self.state = Some(ZlibDecoderState::Trailer {
+                        reader: reader.into_inner(),
+                        checksum: adler.checksum(),
+                    });
+                    Ok(None)
 
                 } else {
-                    self.state = Some(ZlibDecoderState::CompressedData { reader: reader });
-                    Ok(result)
+                    adler.feed(&buf[.. result]);
+                    self.state = Some(ZlibDecoderState::CompressedData { reader, adler });
+                    Ok(Some(result))
+                }
+            },
+
+            Some(ZlibDecoderState::Trailer { mut reader, checksum }) => {
+                let trailer_len = match self.fill_pending(&mut reader, 4) {
+                    Ok(len) => len,
+                    Err(err) => {
+                        if ::is_retryable(&err) {
+                            self.state = Some(ZlibDecoderState::Trailer { reader, checksum });
+                        }
+                        return Err(err);
+                    },
+                };
+                let trailer = [self.pending[0], self.pending[1], self.pending[2], self.pending[3]];
+                self.pending_len = 0;
+                self.total_in += trailer_len as u64;
+
+                // the Adler32 is stored in big endian, contrary to the CRC32 of gzip
+                let ok = trailer_len == trailer.len() && u32::from_be_bytes(trailer) == checksum;
+                if !ok && !self.lenient {
+                    return Err(if trailer_len == trailer.len() {
+                        IoError::new(ErrorKind::InvalidInput,
+                                     "Wrong Adler32 checksum in zlib trailer")
+                    } else {
+                        IoError::new(ErrorKind::InvalidInput, "Unexpected EOF")
+                    });
+                }
+                self.checksum_ok = Some(self.checksum_ok.unwrap_or(true) && ok);
+
+                // a truncated trailer is the end of the data
+                if trailer_len != trailer.len() || !self.multi {
+                    self.state = Some(ZlibDecoderState::Eof { reader });
+                    return Ok(Some(0));
+                }
+
+                self.state = Some(ZlibDecoderState::NextHeader { reader });
+                Ok(None)
+            },
+
+            Some(ZlibDecoderState::NextHeader { mut reader }) => {
+                match self.consume_header(&mut reader, true) {
+                    Ok(Some(header)) => {
+                        self.state = Some(ZlibDecoderState::Header {
+                            reader,
+                            dictionary_id: header.dictionary_id,
+                        });
+                        Ok(None)
+                    },
+                    Ok(None) => {
+                        self.state = Some(ZlibDecoderState::Eof { reader });
+                        Ok(Some(0))
+                    },
+                    Err(err) => {
+                        if ::is_retryable(&err) {
+                            self.state = Some(ZlibDecoderState::NextHeader { reader });
+                        }
+                        Err(err)
+                    },
                 }
             },
 
-            Some(ZlibDecoderState::Checksum) => {
-                // FIXME: check checksum
-                Ok(0)
+            Some(ZlibDecoderState::Eof { reader }) => {
+                self.state = Some(ZlibDecoderState::Eof { reader });
+                Ok(Some(0))
             },
 
             None => {
-                return Err(IoError::new(ErrorKind::InvalidInput,
-                                        "I/O errors in the inflater are unrecoverable"));
+                Err(IoError::new(ErrorKind::InvalidInput,
+                                 "I/O errors in the inflater are unrecoverable"))
             }
         }
     }
 }
 
-/// Consumes the Zlib header from the reader and checks that nothing is wrong with it.
-fn consume_zlib_header<R>(reader: &mut R) -> Result<(), IoError> where R: Read {
-    let (cmf, flg) = {
-        let mut header = [0, 0];
-        try!(::read_all(reader, &mut header));
-        (header[0], header[1])
-    };
+impl ZlibDecoderBuilder {
+    /// Sets the preset dictionary that was used to compress the data. See
+    /// `ZlibDecoder::with_dictionary`.
+    pub fn dictionary(mut self, dictionary: &[u8]) -> ZlibDecoderBuilder {
+        self.dictionary = Some(dictionary.to_vec());
+        self
+    }
+
+    /// Sets the maximum number of bytes to decode. Once more bytes than that have been decoded,
+    /// `read` returns an error of kind `InvalidData` instead of the extra bytes.
+    ///
+    /// This protects against data that decompresses to a huge size. There is no limit by
+    /// default.
+    pub fn size_limit(mut self, limit: u64) -> ZlibDecoderBuilder {
+        self.size_limit = Some(limit);
+        self
+    }
+
+    /// If true, a wrong or missing trailer isn't an error. See `ZlibDecoder::new_lenient`.
+    pub fn lenient_checksum(mut self, lenient: bool) -> ZlibDecoderBuilder {
+        self.lenient = lenient;
+        self
+    }
+
+    /// If true, decodes multiple zlib streams that follow each other. See
+    /// `ZlibDecoder::new_multi`.
+    pub fn allow_multi(mut self, multi: bool) -> ZlibDecoderBuilder {
+        self.multi = multi;
+        self
+    }
+
+    /// Builds the decoder by taking ownership of the reader where the data will be read from.
+    pub fn build<R>(self, reader: R) -> ZlibDecoder<R> where R: Read {
+        let mut decoder = ZlibDecoder::new(reader);
+        decoder.dictionary = self.dictionary;
+        decoder.size_limit = self.size_limit;
+        decoder.lenient = self.lenient;
+        decoder.multi = self.multi;
+        decoder
+    }
+}
+
+/// Iterator over the decompressed bytes of a `ZlibDecoder`. See `ZlibDecoder::decoded_bytes`.
+pub struct DecodedBytes<R> where R: Read {
+    decoder: ZlibDecoder<R>,
+
+    /// Data that has been decompressed but not returned yet is `buffer[pos .. len]`.
+    buffer: Vec<u8>,
+    pos: usize,
+    len: usize,
+
+    /// True if the end of the data or an error has been reached.
+    finished: bool,
+}
+
+/// Number of bytes that `DecodedBytes` decompresses at once.
+const DECODED_BYTES_BUFFER_LEN: usize = 8192;
+
+impl<R> Iterator for DecodedBytes<R> where R: Read {
+    type Item = Result<u8, IoError>;
+
+    fn next(&mut self) -> Option<Result<u8, IoError>> {
+        if self.pos == self.len {
+            if self.finished {
+                return None;
+            }
+
+            match self.decoder.read(&mut self.buffer) {
+                Ok(0) => {
+                    self.finished = true;
+                    return None;
+                },
+                Ok(len) => {
+                    self.pos = 0;
+                    self.len = len;
+                },
+                Err(err) => {
+                    self.finished = true;
+                    return Some(Err(err));
+                },
+            }
+        }
 
-    // checking compression method
-    if (cmf & 0b1111) != 8 {
-        return Err(IoError::new(ErrorKind::InvalidInput, "Unsupported zlib compression method"));
+        let byte = self.buffer[self.pos];
+        self.pos += 1;
+        Some(Ok(byte))
     }
+}
+
+/// Returns true if `cmf` and `flg` are the first two bytes of a valid zlib header.
+pub(crate) fn is_zlib_header(cmf: u8, flg: u8) -> bool {
+    check_zlib_header(cmf, flg).is_ok()
+}
 
-    // checking cinfo
-    if ((cmf >> 4) & 0b1111) != 7 {
-        return Err(IoError::new(ErrorKind::InvalidInput, "Unsupported value for CInfo in \
-                                                          zlib header"));
+/// Checks the first two bytes of a zlib header, `cmf` and `flg`. Returns the information they
+/// contain, with `dictionary_id` set to `Some(0)` if the header is followed by the identifier
+/// of a dictionary and `None` otherwise.
+///
+/// All the values of `FLEVEL` and `FDICT` are valid. The checksum is checked first, as data
+/// that isn't a zlib header most likely fails all the checks.
+fn check_zlib_header(cmf: u8, flg: u8) -> Result<ZlibHeaderInfo, DecodeError> {
+    if !((cmf as u16) * 256 + (flg as u16)).is_multiple_of(31) {
+        return Err(DecodeError::InvalidZlibHeaderChecksum);
     }
 
-    // checking the value of `fcheck`
-    if ((cmf as u16) * 256 + (flg as u16)) % 31 != 0 {
-        return Err(IoError::new(ErrorKind::InvalidInput, "Wrong value for zlib header checksum"));
+    let method = cmf & 0b1111;
+    if method != 8 {
+        return Err(DecodeError::UnsupportedCompressionMethod { method });
     }
 
-    // if the `fdict` flag is set, there is a dictionnary ID afterwards here
-    let fdict = (flg & 0b00100000) != 0;
-    if fdict {
-        let mut dict = [0, 0, 0, 0];
-        try!(::read_all(reader, &mut dict));
-        // TODO: is there something to do with this dictionnary? not sure
+    // cinfo is the base-2 logarithm of the window size minus eight
+    let cinfo = cmf >> 4;
+    if cinfo > 7 {
+        return Err(DecodeError::InvalidWindowSize { cinfo });
     }
 
-    Ok(())
+    Ok(ZlibHeaderInfo {
+        compression_method: method,
+        window_size: 256 << cinfo,
+        // the compression level is only informative
+        compression_level: flg >> 6,
+        dictionary_id: if (flg & 0b00100000) != 0 { Some(0) } else { None },
+    })
 }
 
 #[cfg(test)]
 mod tests {
-    use super::ZlibDecoder;
-    use std::io::Cursor;
-    use std::io::Read;
+    use super::{check_zlib_header, ZlibDecoder, ZlibHeaderInfo};
+    use error::DecodeError;
+    use adler32::Adler32;
+    use deflate::Deflater;
+    use inflate::BlockStats;
+    use std::fs;
+    use std::io::{Cursor, ErrorKind};
+    use std::io::{Read, Write};
 
     #[test]
     fn hello_world() {
@@ -124,4 +903,608 @@ mod tests {
         inflater.read_to_end(&mut output).unwrap();
         assert_eq!(output, b"hello world");
     }
+
+    #[test]
+    fn empty_reads() {
+        let data = [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca, 0x49,
+                    0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+        let mut decoder = ZlibDecoder::new(&data[..]);
+        assert_eq!(decoder.read(&mut []).unwrap(), 0);
+        assert_eq!(decoder.total_in(), 0);
+
+        let mut buf = [0; 5];
+        decoder.read_exact(&mut buf).unwrap();
+        assert_eq!(decoder.read(&mut []).unwrap(), 0);
+        assert!(!decoder.is_finished());
+
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b" world");
+        assert_eq!(decoder.read(&mut []).unwrap(), 0);
+    }
+
+    #[test]
+    fn hello_world_from_slice() {
+        let data = [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca, 0x49,
+                    0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d, 0xaa, 0xbb];
+
+        let mut inflater = ZlibDecoder::from_slice(&data);
+
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+        assert_eq!(inflater.into_inner(), &[0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn dictionary() {
+        // `hello world` compressed by zlib with `hello` as the dictionary
+        let data = vec![0x78, 0xbb, 0x06, 0x2c, 0x02, 0x15, 0xcb, 0x00, 0x11, 0x0a, 0xe5, 0xf9,
+                        0x45, 0x39, 0x29, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+
+        let mut decoder = ZlibDecoder::with_dictionary(Cursor::new(data.clone()), b"hello");
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+
+        let mut decoder = ZlibDecoder::new(Cursor::new(data.clone()));
+        assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+
+        let mut decoder = ZlibDecoder::with_dictionary(Cursor::new(data), b"world");
+        assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn compression_level_hint() {
+        for &(flg, level) in &[(0x01, 0), (0x5e, 1), (0x9c, 2), (0xda, 3)] {
+            // empty data with each value of `FLEVEL`
+            let data = vec![0x78, flg, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01];
+
+            let mut decoder = ZlibDecoder::new(Cursor::new(data));
+            assert_eq!(decoder.compression_level_hint(), None);
+            decoder.read_to_end(&mut Vec::new()).unwrap();
+            assert_eq!(decoder.compression_level_hint(), Some(level));
+        }
+    }
+
+    #[test]
+    fn read_header() {
+        let data = fs::read("tests/fixture/8").unwrap();
+        let expected = fs::read("tests/fixture/8r").unwrap();
+
+        let mut decoder = ZlibDecoder::new(Cursor::new(data));
+        let header = decoder.read_header().unwrap();
+        assert_eq!(header, ZlibHeaderInfo {
+            compression_method: 8,
+            window_size: 32768,
+            compression_level: 2,
+            dictionary_id: None,
+        });
+        assert_eq!(decoder.read_header().unwrap(), header);
+
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(decoder.read_header().unwrap(), header);
+    }
+
+    #[test]
+    fn read_header_dictionary() {
+        // `hello world` compressed by zlib with `hello` as the dictionary
+        let data = vec![0x78, 0xbb, 0x06, 0x2c, 0x02, 0x15, 0xcb, 0x00, 0x11, 0x0a, 0xe5, 0xf9,
+                        0x45, 0x39, 0x29, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+
+        let mut decoder = ZlibDecoder::new(Cursor::new(data));
+        let header = decoder.read_header().unwrap();
+        assert_eq!(header.dictionary_id, Some(0x062c0215));
+        assert_eq!(decoder.required_dictionary_id(), Some(0x062c0215));
+        assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn dictionary_required() {
+        // `hello world` compressed by zlib with `hello` as the dictionary
+        let data = [0x78, 0xbb, 0x06, 0x2c, 0x02, 0x15, 0xcb, 0x00, 0x11, 0x0a, 0xe5, 0xf9, 0x45,
+                    0x39, 0x29, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+
+        let mut decoder = ZlibDecoder::new(&data[..]);
+        assert_eq!(decoder.required_dictionary_id(), None);
+        let err = decoder.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()),
+                   Some(&DecodeError::DictionaryRequired { id: 0x062c0215 }));
+        assert_eq!(decoder.required_dictionary_id(), Some(0x062c0215));
+
+        let mut adler = Adler32::new();
+        adler.feed(b"hello");
+        assert_eq!(adler.checksum(), 0x062c0215);
+
+        // `hello world` compressed without a dictionary
+        let data = [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca, 0x49,
+                    0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+        let mut decoder = ZlibDecoder::new(&data[..]);
+        decoder.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(decoder.required_dictionary_id(), None);
+    }
+
+    #[test]
+    fn try_new() {
+        // `hello world` compressed without a dictionary
+        let data = [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca, 0x49,
+                    0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+        let mut decoder = ZlibDecoder::try_new(&data[..]).unwrap();
+        assert_eq!(decoder.compression_level_hint(), Some(2));
+        assert_eq!(decoder.total_in(), 2);
+
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+
+        let err = ZlibDecoder::try_new(&[0x78, 0x9d, 0xcb, 0x48][..]).err().unwrap();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()),
+                   Some(&DecodeError::InvalidZlibHeaderChecksum));
+        assert!(ZlibDecoder::try_new(&[0x78][..]).is_err());
+
+        // the identifier of the dictionary is part of the header
+        let data = [0x78, 0xbb, 0x06, 0x2c, 0x02, 0x15, 0xcb, 0x00, 0x11, 0x0a, 0xe5, 0xf9, 0x45,
+                    0x39, 0x29, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+        let mut decoder = ZlibDecoder::try_new(&data[..]).unwrap();
+        assert_eq!(decoder.required_dictionary_id(), Some(0x062c0215));
+        assert_eq!(decoder.total_in(), 6);
+        decoder.set_dictionary(b"hello").unwrap();
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+    }
+
+    #[test]
+    fn set_dictionary() {
+        // `hello world` compressed by zlib with `hello` as the dictionary
+        let data = [0x78, 0xbb, 0x06, 0x2c, 0x02, 0x15, 0xcb, 0x00, 0x11, 0x0a, 0xe5, 0xf9, 0x45,
+                    0x39, 0x29, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+
+        let mut decoder = ZlibDecoder::new(&data[..]);
+        assert_eq!(decoder.set_dictionary(b"hello"), Err(DecodeError::UnexpectedDictionary));
+        decoder.read_header().unwrap();
+        let id = decoder.required_dictionary_id().unwrap();
+        assert_eq!(id, 0x062c0215);
+        assert_eq!(decoder.set_dictionary(b"world"),
+                   Err(DecodeError::WrongDictionary { expected: id, found: 0x06a60229 }));
+        decoder.set_dictionary(b"hello").unwrap();
+
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+        assert_eq!(decoder.set_dictionary(b"hello"), Err(DecodeError::UnexpectedDictionary));
+
+        // the dictionary can also be set after decoding returned that it is required
+        let mut decoder = ZlibDecoder::new(&data[..]);
+        assert!(decoder.read(&mut [0; 16]).is_err());
+        decoder.set_dictionary(b"hello").unwrap();
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+
+        // `hello world` compressed without a dictionary
+        let data = [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca, 0x49,
+                    0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+        let mut decoder = ZlibDecoder::new(&data[..]);
+        decoder.read_header().unwrap();
+        assert_eq!(decoder.set_dictionary(b"hello"), Err(DecodeError::UnexpectedDictionary));
+    }
+
+    #[test]
+    fn small_window() {
+        // compressed by zlib with a window of 8 kiB
+        let data = vec![0x58, 0x85, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0x75,
+                        0x14, 0x8a, 0x73, 0x13, 0x73, 0x72, 0x14, 0xca, 0x33, 0xf3, 0x52, 0xf2,
+                        0xcb, 0x01, 0x04, 0xc1, 0x0d, 0xce];
+
+        let mut decoder = ZlibDecoder::new(Cursor::new(data));
+        assert_eq!(decoder.read_header().unwrap().window_size, 8192);
+
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello hello hello hello, small window");
+    }
+
+    #[test]
+    fn pointer_out_of_window() {
+        // 10000 bytes repeated twice, so that the data contains pointers with a distance of 10000
+        let mut seed = 1u32;
+        let mut data = (0 .. 10000).map(|_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as u8
+        }).collect::<Vec<_>>();
+        data.extend(data.clone());
+
+        let mut deflater = Deflater::new(Vec::new(), 6);
+        deflater.write_all(&data).unwrap();
+        let deflated = deflater.finish().unwrap();
+
+        let mut adler = Adler32::new();
+        adler.feed(&data);
+        let checksum = adler.checksum();
+
+        // headers with a window of 32 kiB and 8 kiB
+        for &(header, ok) in &[([0x78, 0x9c], true), ([0x58, 0x85], false)] {
+            let mut compressed = header.to_vec();
+            compressed.extend_from_slice(&deflated);
+            compressed.extend_from_slice(&[(checksum >> 24) as u8, (checksum >> 16) as u8,
+                                           (checksum >> 8) as u8, checksum as u8]);
+
+            let mut decoder = ZlibDecoder::new(Cursor::new(compressed));
+            let mut output = Vec::new();
+            assert_eq!(decoder.read_to_end(&mut output).is_ok(), ok);
+            if ok {
+                assert_eq!(output, data);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_window_size() {
+        // empty data with a CINFO of 8
+        let data = vec![0x88, 0x1c, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01];
+
+        let mut decoder = ZlibDecoder::new(Cursor::new(data));
+        let err = decoder.read_header().unwrap_err();
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()),
+                   Some(&DecodeError::InvalidWindowSize { cinfo: 8 }));
+    }
+
+    #[test]
+    fn header_space() {
+        for cmf in 0 ..= 255u8 {
+            for flg in 0 ..= 255u8 {
+                let result = check_zlib_header(cmf, flg);
+
+                if !((cmf as u16) * 256 + flg as u16).is_multiple_of(31) {
+                    assert_eq!(result, Err(DecodeError::InvalidZlibHeaderChecksum));
+                } else if cmf & 0b1111 != 8 {
+                    assert_eq!(result, Err(DecodeError::UnsupportedCompressionMethod {
+                        method: cmf & 0b1111,
+                    }));
+                } else if cmf >> 4 > 7 {
+                    assert_eq!(result, Err(DecodeError::InvalidWindowSize { cinfo: cmf >> 4 }));
+                } else {
+                    // every `FLEVEL` and `FDICT` is accepted
+                    let header = result.unwrap();
+                    assert_eq!(header.window_size, 256 << (cmf >> 4));
+                    assert_eq!(header.compression_level, flg >> 6);
+                    assert_eq!(header.dictionary_id.is_some(), flg & 0b00100000 != 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn header_errors() {
+        // method 7 with a valid checksum, and method 7 with a wrong checksum
+        for &(header, ref expected) in &[
+            ([0x77, 0x09], DecodeError::UnsupportedCompressionMethod { method: 7 }),
+            ([0x77, 0x0a], DecodeError::InvalidZlibHeaderChecksum),
+        ] {
+            let mut decoder = ZlibDecoder::new(Cursor::new(header.to_vec()));
+            let err = decoder.read_header().unwrap_err();
+            assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()), Some(expected));
+        }
+    }
+
+    #[test]
+    fn total_in_out() {
+        let mut data = fs::read("tests/fixture/8").unwrap();
+        let expected = fs::read("tests/fixture/8r").unwrap();
+        let len = data.len() as u64;
+        data.extend_from_slice(b"trailing data");
+
+        let mut decoder = ZlibDecoder::new(Cursor::new(data));
+        assert_eq!((decoder.total_in(), decoder.total_out()), (0, 0));
+
+        let mut buf = [0; 100];
+        decoder.read_exact(&mut buf).unwrap();
+        assert!(decoder.total_in() > 2 && decoder.total_in() < len);
+        assert_eq!(decoder.total_out(), 100);
+
+        let mut output = buf.to_vec();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(decoder.total_in(), len);
+        assert_eq!(decoder.total_out(), expected.len() as u64);
+    }
+
+    #[test]
+    fn block_stats() {
+        let data = vec![0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca,
+                        0x49, 0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+
+        let mut decoder = ZlibDecoder::new(Cursor::new(data));
+        assert_eq!(decoder.block_stats(), BlockStats::default());
+        decoder.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(decoder.block_stats(), BlockStats { stored: 0, fixed: 1, dynamic: 0 });
+    }
+
+    #[test]
+    fn multiple_streams() {
+        let hello_world = [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca,
+                           0x49, 0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+        let data = [&hello_world[..], &hello_world[..]].concat();
+
+        let mut decoder = ZlibDecoder::new_multi(Cursor::new(data.clone()));
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello worldhello world");
+        assert_eq!(decoder.total_in(), data.len() as u64);
+        assert_eq!(decoder.block_stats().fixed, 2);
+
+        // only the first stream is decoded by default
+        let mut decoder = ZlibDecoder::new(Cursor::new(data));
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+    }
+
+    #[test]
+    fn multiple_streams_followed_by_other_data() {
+        let hello_world = [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca,
+                           0x49, 0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+
+        for trailing in &[&b"not zlib"[..], &b"x"[..]] {
+            let data = [&hello_world[..], trailing].concat();
+
+            let mut decoder = ZlibDecoder::new_multi(Cursor::new(data));
+            let mut output = Vec::new();
+            decoder.read_to_end(&mut output).unwrap();
+            assert_eq!(output, b"hello world");
+            assert!(decoder.is_finished());
+            assert_eq!(decoder.total_in(), hello_world.len() as u64);
+        }
+    }
+
+    #[test]
+    fn is_finished() {
+        let data = vec![0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca,
+                        0x49, 0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+
+        let mut decoder = ZlibDecoder::new(Cursor::new(data.clone()));
+        assert!(!decoder.is_finished());
+        decoder.read_exact(&mut [0; 11]).unwrap();
+        assert!(!decoder.is_finished());
+        assert_eq!(decoder.read(&mut [0; 11]).unwrap(), 0);
+        assert!(decoder.is_finished());
+
+        // missing the last byte of the trailer
+        let mut decoder = ZlibDecoder::new(Cursor::new(&data[.. data.len() - 1]));
+        decoder.read_exact(&mut [0; 11]).unwrap();
+        assert!(decoder.read(&mut [0; 11]).is_err());
+        assert!(!decoder.is_finished());
+    }
+
+    #[test]
+    fn decode_exact() {
+        let data = vec![0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca,
+                        0x49, 0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+
+        let mut buf = [0; 11];
+        let mut decoder = ZlibDecoder::new(Cursor::new(data.clone()));
+        decoder.decode_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello world");
+        assert!(decoder.is_finished());
+
+        let mut decoder = ZlibDecoder::new(Cursor::new(data.clone()));
+        let err = decoder.decode_exact(&mut [0; 12]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        let mut decoder = ZlibDecoder::new(Cursor::new(data));
+        let err = decoder.decode_exact(&mut [0; 10]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn lenient_trailer() {
+        let good = [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca, 0x49,
+                    0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+        let mut wrong = good;
+        wrong[18] ^= 0xff;
+        let truncated = &good[.. 17];
+
+        for &(data, ok) in &[(&good[..], true), (&wrong[..], false), (truncated, false)] {
+            let mut decoder = ZlibDecoder::new_lenient(data);
+            assert_eq!(decoder.checksum_ok(), None);
+            let mut output = Vec::new();
+            decoder.read_to_end(&mut output).unwrap();
+            assert_eq!(output, b"hello world");
+            assert_eq!(decoder.checksum_ok(), Some(ok));
+            assert!(decoder.is_finished());
+            assert_eq!(decoder.total_in(), data.len() as u64);
+
+            let mut decoder = ZlibDecoder::new(data);
+            let result = decoder.read_to_end(&mut Vec::new());
+            assert_eq!(result.is_ok(), ok);
+            assert_eq!(decoder.checksum_ok(), if ok { Some(true) } else { None });
+        }
+    }
+
+    #[test]
+    fn builder() {
+        // `hello world` compressed by zlib with `hello` as the dictionary, with a wrong trailer
+        let first = [0x78, 0xbb, 0x06, 0x2c, 0x02, 0x15, 0xcb, 0x00, 0x11, 0x0a, 0xe5, 0xf9,
+                     0x45, 0x39, 0x29, 0x00, 0x1a, 0x0b, 0x04, 0x00];
+        let second = [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca,
+                      0x49, 0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+        let data = [&first[..], &second[..]].concat();
+
+        let builder = ZlibDecoder::builder()
+            .dictionary(b"hello")
+            .lenient_checksum(true)
+            .allow_multi(true);
+
+        let mut decoder = builder.clone().size_limit(22).build(&data[..]);
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello worldhello world");
+        assert_eq!(decoder.checksum_ok(), Some(false));
+
+        let mut decoder = builder.clone().size_limit(21).build(&data[..]);
+        let err = decoder.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        // the largest limit doesn't overflow
+        let mut decoder = builder.size_limit(u64::MAX).build(&data[..]);
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello worldhello world");
+
+        // the default configuration is the same as `new`
+        let mut decoder = ZlibDecoder::builder().build(&data[..]);
+        assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn validate() {
+        let mut data = fs::read("tests/fixture/8").unwrap();
+        let expected = fs::read("tests/fixture/8r").unwrap();
+        assert_eq!(ZlibDecoder::validate(&data[..]).unwrap(), expected.len() as u64);
+
+        // wrong checksum
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert!(ZlibDecoder::validate(&data[..]).is_err());
+
+        // truncated data
+        assert!(ZlibDecoder::validate(&data[.. last / 2]).is_err());
+    }
+
+    #[test]
+    fn decoded_bytes() {
+        let data = fs::read("tests/fixture/8").unwrap();
+        let expected = fs::read("tests/fixture/8r").unwrap();
+
+        let decoder = ZlibDecoder::new(Cursor::new(data));
+        let output = decoder.decoded_bytes().collect::<Result<Vec<u8>, _>>().unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn decoded_bytes_error() {
+        let data = vec![0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf];
+
+        let mut bytes = ZlibDecoder::new(Cursor::new(data)).decoded_bytes();
+        let mut results = Vec::new();
+        for result in &mut bytes {
+            results.push(result);
+        }
+
+        assert!(results.last().unwrap().is_err());
+        assert!(results[.. results.len() - 1].iter().all(|r| r.is_ok()));
+        assert!(bytes.next().is_none());
+    }
+
+    #[test]
+    fn wrong_checksum() {
+        let data = vec![0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca,
+                        0x49, 0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5e];
+        let mut decoder = ZlibDecoder::new(Cursor::new(data));
+
+        assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn trailer_endianness() {
+        let mut data = vec![0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f,
+                            0xca, 0x49, 0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+        let mut adler = Adler32::new();
+        adler.feed(b"hello world");
+        assert_eq!(adler.checksum(), 0x1a0b045d);
+
+        let mut decoder = ZlibDecoder::new(&data[..]);
+        decoder.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(decoder.checksum_ok(), Some(true));
+
+        // the same checksum in little endian
+        data[15 ..].reverse();
+        let err = ZlibDecoder::new(&data[..]).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "Wrong Adler32 checksum in zlib trailer");
+    }
+
+    #[test]
+    fn empty() {
+        // a fixed block with only the end of block symbol, and a final empty uncompressed block
+        let fixed = [0x78, 0x9c, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01];
+        let stored = [0x78, 0x01, 0x01, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01];
+        assert_eq!(Adler32::new().checksum(), 1);
+
+        for data in &[&fixed[..], &stored[..]] {
+            let mut decoder = ZlibDecoder::new(*data);
+            let mut output = Vec::new();
+            assert_eq!(decoder.read_to_end(&mut output).unwrap(), 0);
+            assert_eq!(decoder.checksum_ok(), Some(true));
+            assert!(decoder.is_finished());
+            assert_eq!(decoder.total_out(), 0);
+
+            // the checksum of the empty data is still checked
+            let mut data = data.to_vec();
+            *data.last_mut().unwrap() = 0;
+            assert!(ZlibDecoder::new(&data[..]).read_to_end(&mut Vec::new()).is_err());
+        }
+    }
+
+    #[test]
+    fn into_inner_after_trailer() {
+        let mut data = vec![0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f,
+                            0xca, 0x49, 0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+        data.extend_from_slice(b"next frame");
+
+        let mut decoder = ZlibDecoder::new(Cursor::new(data));
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+
+        let mut reader = decoder.into_inner();
+        let mut trailing = Vec::new();
+        reader.read_to_end(&mut trailing).unwrap();
+        assert_eq!(trailing, b"next frame");
+    }
+
+    #[test]
+    fn finish() {
+        let mut data = vec![0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f,
+                            0xca, 0x49, 0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+        data.extend_from_slice(b"next frame");
+
+        let mut decoder = ZlibDecoder::new(Cursor::new(&data));
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+        let mut trailing = Vec::new();
+        decoder.finish().unwrap().read_to_end(&mut trailing).unwrap();
+        assert_eq!(trailing, b"next frame");
+
+        // `read` hasn't returned 0, so the trailer hasn't been read yet
+        let mut decoder = ZlibDecoder::new(Cursor::new(&data));
+        let mut output = [0; 11];
+        decoder.read_exact(&mut output).unwrap();
+        let mut trailing = Vec::new();
+        decoder.finish().unwrap().read_to_end(&mut trailing).unwrap();
+        assert_eq!(trailing, b"next frame");
+
+        // the trailer is still checked
+        data[18] ^= 1;
+        let mut decoder = ZlibDecoder::new(Cursor::new(&data));
+        decoder.read_exact(&mut output).unwrap();
+        assert_eq!(decoder.finish().unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn finish_partially_read() {
+        let data = [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca, 0x49,
+                    0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+
+        let mut decoder = ZlibDecoder::new(&data[..]);
+        decoder.read_exact(&mut [0; 5]).unwrap();
+        assert_eq!(decoder.finish().unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        // nothing has been read
+        let err = ZlibDecoder::new(&data[..]).finish().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
 }
@@ -0,0 +1,208 @@
+use alloc::vec::Vec;
+use io::{self, Write};
+use adler32::Adler32;
+use deflate::Deflater;
+
+/// A writer that compresses data in the zlib format to an underlying writer.
+///
+/// `finish` must be called once all the data has been written in order to write the end of the
+/// compressed data and the checksum.
+pub struct ZlibEncoder<W> where W: Write {
+    /// Compresses the data and writes the header.
+    deflater: Deflater<W>,
+
+    /// Checksum of the uncompressed data written so far.
+    adler: Adler32,
+}
+
+impl<W> ZlibEncoder<W> where W: Write {
+    /// Builds a new zlib encoder by taking ownership of the writer where the data will be
+    /// written to. The compression level is between 0 and 9, see `Deflater::new`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `level` is superior to 9.
+    ///
+    pub fn new(writer: W, level: u32) -> ZlibEncoder<W> {
+        ZlibEncoder {
+            deflater: Deflater::with_header(writer, level, &zlib_header(level, None)),
+            adler: Adler32::new(),
+        }
+    }
+
+    /// Same as `new`, but the compressed data can reference the content of `dictionary`. This
+    /// improves the compression of small messages that have a lot in common, like the fields of
+    /// a protocol.
+    ///
+    /// The same dictionary must be passed to the decoder, see `ZlibDecoder::with_dictionary`.
+    /// Only the last 32kiB of the dictionary are used.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `level` is superior to 9.
+    ///
+    pub fn new_with_dictionary(writer: W, level: u32, dictionary: &[u8]) -> ZlibEncoder<W> {
+        let mut adler = Adler32::new();
+        adler.feed(dictionary);
+        let header = zlib_header(level, Some(adler.checksum()));
+
+        let mut deflater = Deflater::with_header(writer, level, &header);
+        deflater.set_dictionary(dictionary);
+
+        ZlibEncoder {
+            deflater,
+            adler: Adler32::new(),
+        }
+    }
+
+    /// Writes the end of the compressed data and the checksum, and returns the underlying
+    /// writer.
+    pub fn finish(self) -> io::Result<W> {
+        let mut writer = self.deflater.finish()?;
+
+        let adler = self.adler.checksum();
+        writer.write_all(&[(adler >> 24) as u8, (adler >> 16) as u8, (adler >> 8) as u8,
+                           adler as u8])?;
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+impl<W> Write for ZlibEncoder<W> where W: Write {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.deflater.write(buf)?;
+        self.adler.feed(&buf[.. written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.deflater.flush()
+    }
+}
+
+/// Returns the zlib header for a compression level, including the identifier of the dictionary
+/// if there is one.
+fn zlib_header(level: u32, dictionary_id: Option<u32>) -> Vec<u8> {
+    // compression method 8 with a window of 32kiB
+    let cmf = 0x78;
+
+    // the same values as the reference implementation for the `flevel` field
+    let flevel = match level {
+        0 | 1 => 0,
+        2 ..= 5 => 1,
+        6 => 2,
+        _ => 3,
+    };
+
+    let fdict = if dictionary_id.is_some() { 0b00100000 } else { 0 };
+
+    // `fcheck` makes the two bytes a multiple of 31
+    let flg = (flevel << 6) | fdict;
+    let fcheck = 31 - ((cmf as u16) * 256 + flg as u16) % 31;
+    let mut header = vec![cmf, flg | (fcheck % 31) as u8];
+
+    if let Some(id) = dictionary_id {
+        header.extend_from_slice(&[(id >> 24) as u8, (id >> 16) as u8, (id >> 8) as u8,
+                                   id as u8]);
+    }
+
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ZlibEncoder;
+    use adler32::Adler32;
+    use zlib_decoder::ZlibDecoder;
+    use std::io::{Cursor, Read, Write};
+
+    fn encode(data: &[u8], level: u32) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), level);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn hello_world() {
+        // same as what zlib produces
+        assert_eq!(encode(b"hello world", 6),
+                   [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca,
+                    0x49, 0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d]);
+    }
+
+    #[test]
+    fn headers() {
+        for level in 0 .. 10 {
+            let compressed = encode(b"", level);
+            assert_eq!(compressed[0], 0x78);
+            assert_eq!((compressed[0] as u16 * 256 + compressed[1] as u16) % 31, 0);
+        }
+
+        assert_eq!(&encode(b"", 1)[.. 2], [0x78, 0x01]);
+        assert_eq!(&encode(b"", 5)[.. 2], [0x78, 0x5e]);
+        assert_eq!(&encode(b"", 9)[.. 2], [0x78, 0xda]);
+    }
+
+    #[test]
+    fn trailer() {
+        let data = b"Deflate late".repeat(1000);
+        let compressed = encode(&data, 6);
+
+        let mut adler = Adler32::new();
+        adler.feed(&data);
+        let adler = adler.checksum();
+        assert_eq!(&compressed[compressed.len() - 4 ..],
+                   [(adler >> 24) as u8, (adler >> 16) as u8, (adler >> 8) as u8, adler as u8]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = (0 .. 100000u32).map(|n| (n % 7 + n / 1000) as u8).collect::<Vec<_>>();
+
+        for level in 0 .. 10 {
+            let compressed = encode(&data, level);
+
+            let mut decoder = ZlibDecoder::new(Cursor::new(compressed));
+            let mut output = Vec::new();
+            decoder.read_to_end(&mut output).unwrap();
+            assert_eq!(output, data);
+        }
+    }
+
+    #[test]
+    fn dictionary() {
+        let dictionary = b"GET /index.html HTTP/1.1\r\nHost: example.com\r\n\
+                           User-Agent: flate3\r\nAccept: */*\r\n\r\n";
+        let message = b"GET /index.html HTTP/1.1\r\nHost: example.org\r\n\
+                        User-Agent: flate3\r\nAccept: text/html\r\n\r\n";
+
+        let mut encoder = ZlibEncoder::new_with_dictionary(Vec::new(), 6, dictionary);
+        encoder.write_all(message).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert!(compressed.len() < encode(message, 6).len() / 2);
+
+        let mut decoder = ZlibDecoder::with_dictionary(Cursor::new(compressed), dictionary);
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(&output[..], &message[..]);
+    }
+
+    #[test]
+    fn dictionary_header() {
+        let mut adler = Adler32::new();
+        adler.feed(b"dictionary");
+        let adler = adler.checksum();
+
+        let compressed = {
+            let encoder = ZlibEncoder::new_with_dictionary(Vec::new(), 6, b"dictionary");
+            encoder.finish().unwrap()
+        };
+
+        assert_eq!(compressed[0], 0x78);
+        assert!(compressed[1] & 0b00100000 != 0);
+        assert_eq!((compressed[0] as u16 * 256 + compressed[1] as u16) % 31, 0);
+        assert_eq!(&compressed[2 .. 6],
+                   [(adler >> 24) as u8, (adler >> 16) as u8, (adler >> 8) as u8, adler as u8]);
+    }
+}
@@ -0,0 +1,221 @@
+use alloc::vec::Vec;
+use core::cmp;
+use io::{self, ErrorKind, Read, Write};
+use zlib_decoder::ZlibDecoder;
+
+/// A writer that decodes the zlib data written to it, and writes the decompressed data to an
+/// underlying writer.
+///
+/// The compressed data is decoded as it is written. The decoder stops when it runs out of data,
+/// for example in the middle of a symbol, and continues from there once more data is written.
+/// `finish` must be called once all the data has been written, in order to check that the data
+/// isn't truncated and to flush the underlying writer.
+pub struct ZlibWriter<W> where W: Write {
+    /// Decodes the data written so far.
+    decoder: ZlibDecoder<PendingInput>,
+
+    /// Where the decompressed data is written.
+    writer: W,
+
+    /// True if the decoder has reached the end of the zlib data.
+    finished: bool,
+}
+
+/// Source of the decoder, which contains the compressed data that hasn't been decoded yet.
+///
+/// Returns `WouldBlock` when all the data has been read, unless `finish` has been called, so that
+/// the decoder continues from where it stopped on the next write.
+struct PendingInput {
+    data: Vec<u8>,
+    position: usize,
+
+    /// True once `finish` has been called, in which case no more data will be written.
+    complete: bool,
+}
+
+/// Number of bytes decoded by each call to the decoder.
+const DECODE_CHUNK_LEN: usize = 4096;
+
+impl Read for PendingInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.data.len() && !self.complete && !buf.is_empty() {
+            return Err(io::Error::new(ErrorKind::WouldBlock,
+                                      "The zlib data hasn't been written yet"));
+        }
+
+        let len = cmp::min(buf.len(), self.data.len() - self.position);
+        buf[.. len].copy_from_slice(&self.data[self.position .. self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+impl<W> ZlibWriter<W> where W: Write {
+    /// Builds a new zlib writer by taking ownership of the writer where the decompressed data
+    /// will be written to.
+    pub fn new(writer: W) -> ZlibWriter<W> {
+        ZlibWriter {
+            decoder: ZlibDecoder::new(PendingInput {
+                data: Vec::new(),
+                position: 0,
+                complete: false,
+            }),
+            writer,
+            finished: false,
+        }
+    }
+
+    /// Decodes the rest of the data, checks the trailer, and returns the underlying writer.
+    ///
+    /// Returns an error if the data is truncated or if data has been written after the end of
+    /// the zlib data.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.input()?.complete = true;
+        self.decode()?;
+
+        if !self.finished {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "Unexpected EOF in zlib data"));
+        }
+
+        // the decoder never reads past the end of the zlib data
+        let input = self.input()?;
+        if input.position != input.data.len() {
+            return Err(io::Error::new(ErrorKind::InvalidInput,
+                                      "Data after the end of the zlib data"));
+        }
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Returns the buffered data, or an error if the decoder has failed.
+    fn input(&mut self) -> io::Result<&mut PendingInput> {
+        self.decoder.try_get_mut().ok_or_else(|| {
+            io::Error::new(ErrorKind::InvalidInput, "I/O errors in the inflater are unrecoverable")
+        })
+    }
+
+    /// Decodes the buffered data and writes it to the underlying writer.
+    fn decode(&mut self) -> io::Result<()> {
+        let mut buf = [0; DECODE_CHUNK_LEN];
+
+        while !self.finished {
+            match self.decoder.read(&mut buf) {
+                Ok(0) => self.finished = true,
+                Ok(len) => self.writer.write_all(&buf[.. len])?,
+                // all the buffered data has been read
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        // discarding the data that has been decoded once it makes up most of the buffer
+        let input = self.input()?;
+        if input.position >= input.data.len() / 2 {
+            input.data.drain(.. input.position);
+            input.position = 0;
+        }
+
+        Ok(())
+    }
+}
+
+impl<W> Write for ZlibWriter<W> where W: Write {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.input()?.data.extend_from_slice(buf);
+        self.decode()?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.decode()?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ZlibWriter;
+    use zlib_encoder::ZlibEncoder;
+    use std::io::{ErrorKind, Write};
+
+    #[test]
+    fn hello_world() {
+        let mut writer = ZlibWriter::new(Vec::new());
+        writer.write_all(&[0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f,
+                           0xca, 0x49, 0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d]).unwrap();
+        assert_eq!(writer.finish().unwrap(), b"hello world");
+    }
+
+    #[test]
+    fn round_trip_chunks() {
+        let data = (0 .. 300000u32).map(|n| (n % 7 + n / 1000 + n.wrapping_mul(n) % 13) as u8)
+                                   .collect::<Vec<_>>();
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), 6);
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        for &chunk_len in &[1, 7, 1000, 65536, compressed.len()] {
+            let mut writer = ZlibWriter::new(Vec::new());
+            for chunk in compressed.chunks(chunk_len) {
+                writer.write_all(chunk).unwrap();
+            }
+            let output = writer.finish().unwrap();
+            assert!(output == data);
+        }
+    }
+
+    #[test]
+    fn partial_output() {
+        // data that is decompressed before `finish` is called
+        let data = vec![0; 1000000];
+        let mut encoder = ZlibEncoder::new(Vec::new(), 0);
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut writer = ZlibWriter::new(Vec::new());
+        writer.write_all(&compressed).unwrap();
+        assert!(writer.writer.len() >= data.len() - 40000);
+        assert_eq!(writer.finish().unwrap().len(), data.len());
+    }
+
+    #[test]
+    fn truncated() {
+        let mut writer = ZlibWriter::new(Vec::new());
+        writer.write_all(&[0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9]).unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn trailing_data() {
+        let mut writer = ZlibWriter::new(Vec::new());
+        writer.write_all(&[0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f,
+                           0xca, 0x49, 0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d, 0x00]).unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn empty_blocks() {
+        // a lot of input that doesn't produce any output
+        let mut compressed = vec![0x78, 0x01];
+        compressed.extend_from_slice(&[0x00, 0x00, 0x00, 0xff, 0xff].repeat(7000));
+        compressed.extend_from_slice(&[0x01, 0x02, 0x00, 0xfd, 0xff, b'h', b'i',
+                                       0x01, 0x3b, 0x00, 0xd2]);
+
+        let (first, second) = compressed.split_at(compressed.len() - 20);
+        let mut writer = ZlibWriter::new(Vec::new());
+        writer.write_all(first).unwrap();
+        writer.write_all(second).unwrap();
+        assert_eq!(writer.finish().unwrap(), b"hi");
+    }
+
+    #[test]
+    fn write_after_error() {
+        let mut writer = ZlibWriter::new(Vec::new());
+        assert!(writer.write_all(&[0x78, 0x9c, 0xff, 0xff]).is_err());
+        assert_eq!(writer.write(&[0x00]).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert!(writer.flush().is_err());
+        assert!(writer.finish().is_err());
+    }
+}
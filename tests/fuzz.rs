@@ -0,0 +1,152 @@
+//! Feeds random and mutated data to the decoders, which must return an error instead of
+//! panicking.
+
+#![cfg(feature = "std")]
+
+extern crate flate3;
+
+use std::fs;
+use std::io::Read;
+use std::panic;
+use std::path::Path;
+
+/// Deterministic xorshift generator, so that failures are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, max: usize) -> usize {
+        (self.next() % max as u64) as usize
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0 .. len).map(|_| self.next() as u8).collect()
+    }
+}
+
+/// Decodes `data` as zlib and as gzip, and panics with the data if one of the decoders panics.
+fn decode(data: &[u8]) {
+    let result = panic::catch_unwind(|| {
+        let _ = flate3::ZlibDecoder::new(data).read_to_end(&mut Vec::new());
+        let _ = flate3::GzipDecoder::new(data).read_to_end(&mut Vec::new());
+    });
+
+    if result.is_err() {
+        panic!("Decoding panicked with input {:?}", data);
+    }
+}
+
+fn corpus() -> Vec<Vec<u8>> {
+    let mut corpus = Vec::new();
+
+    for dir in &["tests/fixture", "tests/gzip"] {
+        for file in fs::read_dir(Path::new(dir)).unwrap() {
+            let file = file.unwrap().path();
+            if !format!("{}", file.display()).ends_with("r") {
+                corpus.push(fs::read(&file).unwrap());
+            }
+        }
+    }
+
+    corpus
+}
+
+#[test]
+fn mutated_inputs() {
+    let mut rng = Rng(0x2545f4914f6cdd1d);
+    let corpus = corpus();
+
+    for _ in 0 .. 5000 {
+        let mut data = corpus[rng.below(corpus.len())].clone();
+
+        for _ in 0 .. 1 + rng.below(4) {
+            let pos = rng.below(data.len());
+            match rng.below(5) {
+                0 => data[pos] ^= 1 << rng.below(8),
+                1 => data[pos] = rng.next() as u8,
+                2 => data.truncate(pos + 1),
+                3 => {
+                    let len = rng.below(16);
+                    let bytes = rng.bytes(len);
+                    data.splice(pos .. pos, bytes);
+                },
+                _ => {
+                    let end = pos + rng.below(data.len() - pos);
+                    let copy = data[pos .. end].to_vec();
+                    data.splice(pos .. pos, copy);
+                },
+            }
+        }
+
+        decode(&data);
+    }
+}
+
+#[test]
+fn random_inputs() {
+    let mut rng = Rng(0x9e3779b97f4a7c15);
+    let headers: &[&[u8]] = &[&[], &[0x78, 0x9c], &[0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00,
+                                                   0x00, 0x00, 0x03]];
+
+    for _ in 0 .. 5000 {
+        let len = 1 + rng.below(300);
+        let mut body = rng.bytes(len);
+
+        // making dynamic blocks more likely, as their headers are the most complex part
+        if rng.below(2) == 0 {
+            body[0] = (body[0] & !0b111) | 0b101;
+        }
+
+        let mut data = headers[rng.below(headers.len())].to_vec();
+        data.extend(body);
+
+        decode(&data);
+    }
+}
+
+/// Raw DEFLATE data made of `count` empty stored blocks followed by a final block with "hi".
+fn empty_stored_blocks(count: usize) -> Vec<u8> {
+    let mut data = [0x00, 0x00, 0x00, 0xff, 0xff].repeat(count);
+    data.extend_from_slice(&[0x01, 0x02, 0x00, 0xfd, 0xff, b'h', b'i']);
+    data
+}
+
+#[test]
+fn many_empty_blocks() {
+    // each empty block used to recurse once in the decoders, which overflowed the stack
+    let deflate = empty_stored_blocks(100_000);
+
+    let mut output = Vec::new();
+    flate3::Inflater::new(&deflate[..]).read_to_end(&mut output).unwrap();
+    assert_eq!(output, b"hi");
+
+    let mut adler = flate3::Adler32::new();
+    adler.feed(b"hi");
+    let mut zlib = vec![0x78, 0x01];
+    zlib.extend_from_slice(&deflate);
+    zlib.extend_from_slice(&adler.checksum().to_be_bytes());
+
+    let mut output = Vec::new();
+    flate3::ZlibDecoder::new(&zlib[..]).read_to_end(&mut output).unwrap();
+    assert_eq!(output, b"hi");
+}
+
+#[test]
+fn many_empty_members() {
+    let zlib = [0x78, 0x9c, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01].repeat(100_000);
+    let mut output = Vec::new();
+    flate3::ZlibDecoder::new_multi(&zlib[..]).read_to_end(&mut output).unwrap();
+    assert!(output.is_empty());
+
+    let gzip = [0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x03, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00].repeat(100_000);
+    let mut output = Vec::new();
+    flate3::GzipDecoder::new(&gzip[..]).read_to_end(&mut output).unwrap();
+    assert!(output.is_empty());
+}
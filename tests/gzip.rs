@@ -0,0 +1,54 @@
+#![cfg(feature = "std")]
+
+extern crate flate3;
+
+use std::fs;
+use std::io::{Cursor, Read, Write};
+
+#[test]
+fn concatenated_members() {
+    let mut compressed = fs::read("tests/gzip/6.gz").unwrap();
+    compressed.extend(fs::read("tests/gzip/7.gz").unwrap());
+
+    let mut expected = fs::read("tests/fixture/6r").unwrap();
+    expected.extend(fs::read("tests/fixture/7r").unwrap());
+
+    let mut decoder = flate3::GzipDecoder::new(Cursor::new(compressed));
+    let mut result = Vec::new();
+    decoder.read_to_end(&mut result).unwrap();
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn header() {
+    let compressed = fs::read("tests/gzip/6.gz").unwrap();
+
+    let mut decoder = flate3::GzipDecoder::new(Cursor::new(compressed));
+    let mut result = Vec::new();
+    decoder.read_to_end(&mut result).unwrap();
+
+    let header = decoder.header().unwrap();
+    assert_eq!(header.filename.as_ref().map(|s| &s[..]), Some("6r"));
+    assert_eq!(header.comment, None);
+    assert_eq!(header.mtime, 1234567890);
+}
+
+#[test]
+fn encode_then_decode() {
+    let data = fs::read("tests/fixture/8r").unwrap();
+
+    let mut encoder = flate3::GzipEncoder::builder().filename("8r").mtime(1234567890)
+                                                    .build(Vec::new());
+    encoder.write_all(&data).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let mut decoder = flate3::GzipDecoder::new(Cursor::new(compressed));
+    let mut result = Vec::new();
+    decoder.read_to_end(&mut result).unwrap();
+
+    assert_eq!(result, data);
+    let header = decoder.header().unwrap();
+    assert_eq!(header.filename.as_ref().map(|s| &s[..]), Some("8r"));
+    assert_eq!(header.mtime, 1234567890);
+}
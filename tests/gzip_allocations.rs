@@ -0,0 +1,65 @@
+//! Compares the number of allocations made by `decompress_gzip`, which uses the size stored in
+//! the trailer, with the ones made by `read_to_end`.
+
+#![cfg(feature = "std")]
+
+extern crate flate3;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts the allocations and reallocations.
+struct CountingAllocator;
+
+// The counter is shared by the whole process, and the tests of a file run in parallel, so this
+// file only contains a single test, which doesn't count the allocations of other ones.
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the result of `f` and the number of allocations it made.
+fn count_allocations<T, F>(f: F) -> (T, usize) where F: FnOnce() -> T {
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    let result = f();
+    (result, ALLOCATIONS.load(Ordering::SeqCst) - before)
+}
+
+#[test]
+fn isize_hint_allocations() {
+    // a few megabytes of text
+    let data = fs::read("tests/fixture/8r").unwrap().repeat(1000);
+    let mut encoder = flate3::GzipEncoder::builder().build(Vec::new());
+    encoder.write_all(&data).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let (output, without_hint) = count_allocations(|| {
+        let mut output = Vec::new();
+        flate3::GzipDecoder::new(&compressed[..]).read_to_end(&mut output).unwrap();
+        output
+    });
+    assert!(output == data);
+
+    let (output, with_hint) = count_allocations(|| flate3::decompress_gzip(&compressed).unwrap());
+    assert!(output == data);
+
+    assert!(with_hint < without_hint);
+}
@@ -0,0 +1,45 @@
+//! Checks the measurements collected with the `metrics` feature.
+
+#![cfg(feature = "metrics")]
+
+extern crate flate3;
+
+use std::fs;
+use std::io::Read;
+
+#[test]
+fn fixture_metrics() {
+    for name in &["6", "11", "12", "13", "14"] {
+        let compressed = fs::read(format!("tests/fixture/{}", name)).unwrap();
+        let expected = fs::read(format!("tests/fixture/{}r", name)).unwrap();
+
+        // the fixtures are zlib data, with a 2 bytes header and a 4 bytes trailer
+        let mut inflater = flate3::Inflater::new(&compressed[2 ..]);
+        let mut output = Vec::new();
+        inflater.read_to_end(&mut output).unwrap();
+        assert!(output == expected);
+
+        let metrics = inflater.metrics();
+        assert_eq!(metrics.bytes_out, output.len() as u64);
+        assert_eq!(metrics.bytes_in, compressed.len() as u64 - 6);
+        assert_eq!(metrics.stored_bytes + metrics.fixed_bytes + metrics.dynamic_bytes,
+                   metrics.bytes_out);
+
+        // only the types of blocks that the data contains have bytes
+        let stats = inflater.block_stats();
+        assert_eq!(metrics.stored_bytes != 0, stats.stored != 0);
+        assert_eq!(metrics.fixed_bytes != 0, stats.fixed != 0);
+        assert_eq!(metrics.dynamic_bytes != 0, stats.dynamic != 0);
+
+        assert!(metrics.time.as_secs() < 60);
+        assert!(metrics.throughput_mb_s() >= 0.0);
+        assert!(metrics.throughput_mb_s().is_finite());
+    }
+}
+
+#[test]
+fn no_time_measured() {
+    let metrics = flate3::Inflater::new(&[][..]).metrics();
+    assert_eq!(metrics, flate3::DecodeMetrics::default());
+    assert_eq!(metrics.throughput_mb_s(), 0.0);
+}
@@ -0,0 +1,71 @@
+//! Compares the number of allocations made by decoding many small zlib payloads with new
+//! decoders and with the decoders of a `DecoderPool`.
+
+#![cfg(feature = "std")]
+
+extern crate flate3;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts the allocations and reallocations.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the number of allocations made by `f`.
+fn count_allocations<F>(f: F) -> usize where F: FnOnce() {
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    f();
+    ALLOCATIONS.load(Ordering::SeqCst) - before
+}
+
+#[test]
+fn pool_allocations() {
+    const PAYLOADS: usize = 1000;
+
+    let payloads = (0 .. PAYLOADS).map(|n| {
+        let mut encoder = flate3::ZlibEncoder::new(Vec::new(), 6);
+        write!(encoder, "message number {}", n).unwrap();
+        encoder.finish().unwrap()
+    }).collect::<Vec<_>>();
+
+    let mut output = [0; 64];
+
+    let without_pool = count_allocations(|| {
+        for payload in &payloads {
+            let mut decoder = flate3::ZlibDecoder::new(&payload[..]);
+            while decoder.read(&mut output).unwrap() != 0 {}
+        }
+    });
+
+    let pool = flate3::DecoderPool::new();
+    let with_pool = count_allocations(|| {
+        for payload in &payloads {
+            let mut decoder = pool.get(&payload[..]);
+            while decoder.read(&mut output).unwrap() != 0 {}
+        }
+    });
+
+    assert!(with_pool < without_pool);
+}
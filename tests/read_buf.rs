@@ -0,0 +1,84 @@
+//! Compares the output of `read_buf`, which decodes into uninitialized memory, with the one of
+//! `read`.
+
+#![cfg(feature = "read_buf")]
+#![feature(read_buf, core_io_borrowed_buf, borrowed_buf_init)]
+
+extern crate flate3;
+
+use std::fs;
+use std::io::{BorrowedBuf, Read};
+use std::mem::MaybeUninit;
+
+/// Decodes everything with `read_buf`, `buffer_len` bytes of uninitialized memory at a time.
+fn read_buf_to_end<R>(mut reader: R, buffer_len: usize) -> Vec<u8> where R: Read {
+    let mut output = Vec::new();
+    let mut buffer = vec![MaybeUninit::uninit(); buffer_len];
+
+    loop {
+        let mut buf = BorrowedBuf::from(&mut buffer[..]);
+        reader.read_buf(buf.unfilled()).unwrap();
+        if buf.len() == 0 {
+            return output;
+        }
+        output.extend_from_slice(buf.filled());
+    }
+}
+
+#[test]
+fn zlib_fixture() {
+    let compressed = fs::read("tests/fixture/11").unwrap();
+    let mut expected = Vec::new();
+    flate3::ZlibDecoder::new(&compressed[..]).read_to_end(&mut expected).unwrap();
+    assert!(expected.len() > 65536);
+
+    for &buffer_len in &[1, 1000, 1 << 20] {
+        let output = read_buf_to_end(flate3::ZlibDecoder::new(&compressed[..]), buffer_len);
+        assert!(output == expected);
+    }
+}
+
+#[test]
+fn raw_fixture() {
+    // the fixture is zlib data, with a 2 bytes header
+    let compressed = fs::read("tests/fixture/13").unwrap();
+    let mut expected = Vec::new();
+    flate3::Inflater::new(&compressed[2 ..]).read_to_end(&mut expected).unwrap();
+
+    for &buffer_len in &[7, 1 << 20] {
+        let output = read_buf_to_end(flate3::Inflater::new(&compressed[2 ..]), buffer_len);
+        assert!(output == expected);
+    }
+
+    // `read_to_end` uses `read_buf`
+    let mut output = Vec::new();
+    let mut inflater = flate3::Inflater::new(&compressed[2 ..]);
+    inflater.read_to_end(&mut output).unwrap();
+    assert!(output == expected);
+}
+
+#[test]
+fn initialized_memory() {
+    let compressed = fs::read("tests/fixture/11").unwrap();
+
+    // memory that is known to be initialized isn't zeroed again
+    let hello_world = [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca,
+                       0x49, 0x01, 0x00, 0x1a, 0x0b, 0x04, 0x5d];
+    let mut buffer = vec![0xaa; 65536];
+    {
+        let mut buf = BorrowedBuf::from(&mut buffer[..]);
+        flate3::ZlibDecoder::new(&hello_world[..]).read_buf(buf.unfilled()).unwrap();
+        assert_eq!(buf.filled(), b"hello world");
+    }
+    assert!(buffer[11 ..].iter().all(|&b| b == 0xaa));
+
+    // the initialization of a buffer is recorded, so that it is only done once
+    let mut buffer = vec![MaybeUninit::uninit(); 4096];
+    let mut buf = BorrowedBuf::from(&mut buffer[..]);
+    let mut decoder = flate3::ZlibDecoder::new(&compressed[..]);
+    decoder.read_buf(buf.unfilled()).unwrap();
+    assert!(buf.is_init());
+    buf.clear();
+    decoder.read_buf(buf.unfilled()).unwrap();
+    assert!(buf.is_init() && buf.len() != 0);
+}
@@ -1,30 +1,79 @@
+#![cfg(feature = "std")]
+
 extern crate flate3;
 
 use std::fs;
-use std::path::Path;
-use std::io::Read;
+use std::path::PathBuf;
+use std::io::{Read, Write};
+
+/// Returns the compressed fixtures of `tests/fixture` with their expected output.
+///
+/// The expected output of the fixture `name` is in the file `{name}r`. Panics if a fixture
+/// doesn't have one.
+fn fixtures_with_output() -> Vec<(PathBuf, Vec<u8>, Vec<u8>)> {
+    let mut files = fs::read_dir("tests/fixture").unwrap()
+                                                .map(|file| file.unwrap().path())
+                                                .collect::<Vec<_>>();
+    files.sort();
+
+    // a file is an expected output if there is a fixture with the same name minus the `r`
+    let is_output = |file: &PathBuf| {
+        let name = file.to_str().unwrap();
+        name.ends_with('r') && files.iter().any(|f| f.to_str() == Some(&name[.. name.len() - 1]))
+    };
+
+    files.iter().filter(|file| !is_output(file)).map(|file| {
+        let output = PathBuf::from(format!("{}r", file.display()));
+        if !output.exists() {
+            panic!("The fixture {} doesn't have an expected output, which should be in {}",
+                   file.display(), output.display());
+        }
+
+        (file.clone(), fs::read(file).unwrap(), fs::read(&output).unwrap())
+    }).collect()
+}
 
 #[test]
 fn fixtures() {
-    let path = Path::new("tests/fixture");
-    for file in fs::read_dir(&path).unwrap() {
-        let file = file.unwrap().path();
+    for (file, compressed, expected) in fixtures_with_output() {
+        println!("Testing {:?}", file);
 
-        let name = format!("{}", file.display());
-        if !name.ends_with("r") {
-            println!("Testing {:?}", name);
+        let mut decoder = flate3::ZlibDecoder::new(&compressed[..]);
+        let mut result = Vec::new();
+        decoder.read_to_end(&mut result).unwrap();
 
-            let compressed = fs::File::open(&file).unwrap();
-            let mut decompressed = fs::File::open(format!("{}r", name)).unwrap();
+        assert!(result == expected, "Wrong output for {}", file.display());
+    }
+}
 
-            let mut decoder = flate3::ZlibDecoder::new(compressed);
+#[test]
+fn fixtures_block_types() {
+    // the fixtures must cover all the types of blocks
+    let mut stats = flate3::BlockStats::default();
+    for (_, compressed, _) in fixtures_with_output() {
+        let mut decoder = flate3::ZlibDecoder::new(&compressed[..]);
+        decoder.read_to_end(&mut Vec::new()).unwrap();
 
-            let mut result = Vec::new();
-            decoder.read_to_end(&mut result).unwrap();
-            let mut expected = Vec::new();
-            decompressed.read_to_end(&mut expected).unwrap();
+        let file_stats = decoder.block_stats();
+        stats.stored += file_stats.stored;
+        stats.fixed += file_stats.fixed;
+        stats.dynamic += file_stats.dynamic;
+    }
 
-            assert_eq!(result, expected);
-        }
+    assert!(stats.stored > 0 && stats.fixed > 0 && stats.dynamic > 1, "{:?}", stats);
+}
+
+#[test]
+fn encode_fixtures() {
+    for (_, _, data) in fixtures_with_output() {
+        let mut encoder = flate3::ZlibEncoder::new(Vec::new(), flate3::DEFAULT_LEVEL);
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut decoder = flate3::ZlibDecoder::new(&compressed[..]);
+        let mut result = Vec::new();
+        decoder.read_to_end(&mut result).unwrap();
+
+        assert!(result == data);
     }
 }